@@ -1,7 +1,9 @@
 use blink_pairs::parser::{
     indent::indent_levels,
     languages::{Rust, C},
-    parse_filetype, tokenize, Matcher, State,
+    parse_filetype, tokenize,
+    tokenize::{MemchrTokenizer, SwarTokenizer},
+    Matcher, State, Tokenizer,
 };
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
@@ -21,7 +23,7 @@ fn criterion_benches(c: &mut Criterion) {
 
     c.bench_function("tokenize simd - c", |b| {
         b.iter(|| {
-            tokenize::<64>(black_box(c_text), black_box(C::TOKENS)).for_each(|c| {
+            tokenize::<64>(black_box(c_text.as_bytes()), black_box(C::TOKENS)).for_each(|c| {
                 black_box(c);
             })
         })
@@ -29,12 +31,52 @@ fn criterion_benches(c: &mut Criterion) {
 
     c.bench_function("tokenize simd - rust", |b| {
         b.iter(|| {
-            tokenize::<64>(black_box(rust_text), black_box(Rust::TOKENS)).for_each(|c| {
+            tokenize::<64>(black_box(rust_text.as_bytes()), black_box(Rust::TOKENS)).for_each(|c| {
                 black_box(c);
             })
         })
     });
 
+    c.bench_function("tokenize memchr - c", |b| {
+        b.iter(|| {
+            MemchrTokenizer
+                .tokenize(black_box(c_text.as_bytes()), black_box(C::TOKENS))
+                .for_each(|c| {
+                    black_box(c);
+                })
+        })
+    });
+
+    c.bench_function("tokenize memchr - rust", |b| {
+        b.iter(|| {
+            MemchrTokenizer
+                .tokenize(black_box(rust_text.as_bytes()), black_box(Rust::TOKENS))
+                .for_each(|c| {
+                    black_box(c);
+                })
+        })
+    });
+
+    c.bench_function("tokenize swar - c", |b| {
+        b.iter(|| {
+            SwarTokenizer
+                .tokenize(black_box(c_text.as_bytes()), black_box(C::TOKENS))
+                .for_each(|c| {
+                    black_box(c);
+                })
+        })
+    });
+
+    c.bench_function("tokenize swar - rust", |b| {
+        b.iter(|| {
+            SwarTokenizer
+                .tokenize(black_box(rust_text.as_bytes()), black_box(Rust::TOKENS))
+                .for_each(|c| {
+                    black_box(c);
+                })
+        })
+    });
+
     c.bench_function("parse simd - c", |b| {
         b.iter(|| parse_filetype("c", 4, black_box(&c_lines), State::Normal))
     });
@@ -0,0 +1,10 @@
+#![no_main]
+
+use blink_pairs::fuzz::FuzzCase;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|case: FuzzCase| {
+    if let Err(divergence) = case.check() {
+        panic!("{divergence:?} in {case:?}");
+    }
+});
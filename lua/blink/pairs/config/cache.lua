@@ -0,0 +1,24 @@
+--- @class (exact) blink.pairs.CacheConfig
+--- @field enabled boolean Store the parsed buffers on disk, so that reopening large files restores the highlights without parsing
+--- @field dir string Directory of the cache entries
+--- @field min_lines number Buffers with fewer lines are never cached
+
+local validate = require('blink.pairs.config.utils').validate
+local cache = {
+  --- @type blink.pairs.CacheConfig
+  default = {
+    enabled = false,
+    dir = vim.fn.stdpath('cache') .. '/blink.pairs',
+    min_lines = 10000,
+  },
+}
+
+function cache.validate(config)
+  validate('cache', {
+    enabled = { config.enabled, 'boolean' },
+    dir = { config.dir, 'string' },
+    min_lines = { config.min_lines, 'number' },
+  }, config)
+end
+
+return cache
@@ -5,13 +5,23 @@
 --- @field unmatched_group string Highlight group for unmatched pairs
 --- @field priority number
 --- @field ns integer
+--- @field inner blink.pairs.InnerHighlightsConfig
+--- @field doc_code blink.pairs.DocCodeHighlightsConfig
 --- @field matchparen blink.pairs.MatchparenConfig
 
+--- @class (exact) blink.pairs.InnerHighlightsConfig
+--- @field enabled boolean Highlight the pairs inside strings and comments, i.e. brackets in doc comments, which are paired separately from the code
+--- @field groups string[] Highlight groups for matched pairs inside strings and comments, in order that they'll appear based on depth
+
+--- @class (exact) blink.pairs.DocCodeHighlightsConfig
+--- @field enabled boolean Highlight the pairs of code blocks in doc comments, i.e. Rust's `///` and JSDoc, parsed as the buffer's filetype. Parses the whole buffer after each change
+
 --- @class (exact) blink.pairs.MatchparenConfig
 --- @field enabled boolean
 --- @field cmdline boolean Requires `require('vim._extui').enable({})`. Disabled by default due to only showing matchparen when moving the cursor, and not when typing.
 --- @field group string Highlight group for the matching pair
 --- @field priority number Priority of the highlight
+--- @field insert_adjacency blink.pairs.Adjacency Delimiter to highlight the pair of in insert mode when the cursor isn't on one, i.e. `'before'` for the `)` right before the cursor
 
 local validate = require('blink.pairs.config.utils').validate
 local highlights = {
@@ -27,11 +37,23 @@ local highlights = {
     unmatched_group = 'BlinkPairsUnmatched',
     priority = 200,
     ns = vim.api.nvim_create_namespace('blink.pairs'),
+    inner = {
+      enabled = false,
+      groups = {
+        'BlinkPairsOrange',
+        'BlinkPairsPurple',
+        'BlinkPairsBlue',
+      },
+    },
+    doc_code = {
+      enabled = false,
+    },
     matchparen = {
       enabled = true,
       cmdline = false,
       group = 'MatchParen',
       priority = 250,
+      insert_adjacency = 'exact',
     },
   },
 }
@@ -44,14 +66,30 @@ function highlights.validate(config)
     groups = { config.groups, 'table' },
     priority = { config.priority, 'number' },
     ns = { config.ns, 'number' },
+    inner = { config.inner, 'table', true },
+    doc_code = { config.doc_code, 'table', true },
     matchparen = { config.matchparen, 'table', true },
   }, config)
 
+  validate('highlights.inner', {
+    enabled = { config.inner.enabled, 'boolean' },
+    groups = { config.inner.groups, 'table' },
+  }, config.inner)
+
+  validate('highlights.doc_code', {
+    enabled = { config.doc_code.enabled, 'boolean' },
+  }, config.doc_code)
+
   validate('highlights.matchparen', {
     enabled = { config.matchparen.enabled, 'boolean' },
     cmdline = { config.cmdline, 'boolean' },
     group = { config.matchparen.group, 'string' },
     priority = { config.matchparen.priority, 'number' },
+    insert_adjacency = {
+      config.matchparen.insert_adjacency,
+      function(adjacency) return vim.tbl_contains({ 'exact', 'before', 'after', 'nearest_on_line' }, adjacency) end,
+      "one of 'exact', 'before', 'after' or 'nearest_on_line'",
+    },
   }, config.matchparen)
 end
 
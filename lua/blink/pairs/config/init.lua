@@ -1,6 +1,7 @@
 --- @class (exact) blink.pairs.ConfigStrict
 --- @field mappings blink.pairs.MappingsConfig
 --- @field highlights blink.pairs.HighlightsConfig
+--- @field cache blink.pairs.CacheConfig
 --- @field debug boolean
 
 local validate = require('blink.pairs.config.utils').validate
@@ -8,6 +9,7 @@ local validate = require('blink.pairs.config.utils').validate
 local config = {
   mappings = require('blink.pairs.config.mappings').default,
   highlights = require('blink.pairs.config.highlights').default,
+  cache = require('blink.pairs.config.cache').default,
   debug = false,
 }
 
@@ -21,11 +23,13 @@ function M.validate(cfg)
   validate('config', {
     mappings = { cfg.mappings, 'table' },
     highlights = { cfg.highlights, 'table' },
+    cache = { cfg.cache, 'table' },
     debug = { cfg.debug, 'boolean' },
   }, cfg)
 
   require('blink.pairs.config.mappings').validate(cfg.mappings)
   require('blink.pairs.config.highlights').validate(cfg.highlights)
+  require('blink.pairs.config.cache').validate(cfg.cache)
 end
 
 --- @param user_config blink.pairs.Config
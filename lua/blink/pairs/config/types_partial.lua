@@ -1,10 +1,19 @@
 --- @class (exact) blink.pairs.Config : blink.pairs.ConfigStrict, {}
 --- @field mappings? blink.pairs.MappingsConfigPartial
 --- @field highlights? blink.pairs.HighlightsConfigPartial
+--- @field cache? blink.pairs.CacheConfigPartial
 
 --- @class (exact) blink.pairs.MappingsConfigPartial : blink.pairs.MappingsConfig, {}
 
 --- @class (exact) blink.pairs.HighlightsConfigPartial : blink.pairs.HighlightsConfig, {}
+--- @field inner? blink.pairs.InnerHighlightsConfigPartial
+--- @field doc_code? blink.pairs.DocCodeHighlightsConfigPartial
 --- @field matchparen? blink.pairs.MatchparenConfigPartial
 
+--- @class (exact) blink.pairs.InnerHighlightsConfigPartial : blink.pairs.InnerHighlightsConfig, {}
+
+--- @class (exact) blink.pairs.DocCodeHighlightsConfigPartial : blink.pairs.DocCodeHighlightsConfig, {}
+
 --- @class (exact) blink.pairs.MatchparenConfigPartial : blink.pairs.MatchparenConfig, {}
+
+--- @class (exact) blink.pairs.CacheConfigPartial : blink.pairs.CacheConfig, {}
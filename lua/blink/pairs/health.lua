@@ -0,0 +1,33 @@
+--- Checks for `:checkhealth blink.pairs`
+local health = {}
+
+function health.check()
+  vim.health.start('blink.pairs')
+
+  local ok, rust = pcall(require, 'blink.pairs.rust')
+  if not ok then
+    vim.health.error('Failed to load the native library', {
+      'Build it with `cargo build --release` or install `blink.download` for prebuilt binaries',
+      tostring(rust),
+    })
+    return
+  end
+  vim.health.ok('Loaded the native library')
+
+  local tokenizer = rust.get_tokenizer_health()
+  if #tokenizer.unsupported_simd_features > 0 then
+    vim.health.warn(
+      string.format(
+        'The CPU lacks the SIMD features of the build (%s), which may crash Neovim',
+        table.concat(tokenizer.unsupported_simd_features, ', ')
+      ),
+      { 'Build from source with `cargo build --release` on this machine' }
+    )
+  elseif tokenizer.backend == 'simd' then
+    vim.health.ok(string.format('Using the simd tokenizer with %d byte lanes', tokenizer.simd_lanes))
+  else
+    vim.health.ok('Using the ' .. tokenizer.backend .. ' tokenizer')
+  end
+end
+
+return health
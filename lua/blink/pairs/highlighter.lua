@@ -1,22 +1,90 @@
 local highlighter = {}
 
+--- Matched pairs inside strings and comments of the window being drawn, by line
+--- @type table<number, blink.pairs.MatchWithLine[]>
+local inner_matches_by_line = {}
+
+--- @param bufnr number
+--- @param toprow number
+--- @param botrow number
+local function update_inner_matches(bufnr, toprow, botrow)
+  inner_matches_by_line = {}
+  local lines = vim.api.nvim_buf_get_lines(bufnr, toprow, botrow + 1, false)
+  local filetype = require('blink.pairs.watcher').get_filetype(bufnr)
+  for _, match in ipairs(require('blink.pairs.rust').get_inner_matches(bufnr, filetype, lines, toprow)) do
+    if match.stack_height ~= nil then
+      inner_matches_by_line[match.line] = inner_matches_by_line[match.line] or {}
+      table.insert(inner_matches_by_line[match.line], match)
+    end
+  end
+end
+
+--- Pairs of the code blocks in doc comments, by line, for each buffer
+--- @type table<number, { changedtick: number, matches_by_line: table<number, blink.pairs.MatchWithLine[]> }>
+local doc_code_by_bufnr = {}
+
+--- Parses the code blocks in the doc comments when the buffer changed
+--- @param bufnr number
+local function update_doc_code_matches(bufnr)
+  local changedtick = vim.b[bufnr].changedtick
+  if doc_code_by_bufnr[bufnr] ~= nil and doc_code_by_bufnr[bufnr].changedtick == changedtick then return end
+
+  local matches_by_line = {}
+  local lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false)
+  local filetype = require('blink.pairs.watcher').get_filetype(bufnr)
+  local tab_width = require('blink.pairs.utils').get_tab_width(bufnr)
+  for _, match in ipairs(require('blink.pairs.rust').get_doc_code_matches(filetype, tab_width, lines)) do
+    matches_by_line[match.line] = matches_by_line[match.line] or {}
+    table.insert(matches_by_line[match.line], match)
+  end
+  doc_code_by_bufnr[bufnr] = { changedtick = changedtick, matches_by_line = matches_by_line }
+end
+
 --- @param config blink.pairs.HighlightsConfig
 function highlighter.register(config)
   vim.api.nvim_set_decoration_provider(config.ns, {
-    on_win = function(_, _, bufnr)
+    on_win = function(_, _, bufnr, toprow, botrow)
       if not config.cmdline and vim.api.nvim_get_mode().mode:match('c') then return false end
 
       vim.api.nvim_buf_clear_namespace(bufnr, config.ns, 0, -1)
-      return require('blink.pairs.watcher').attach(bufnr)
+      if not require('blink.pairs.watcher').attach(bufnr) then return false end
+
+      -- Fetch the highlights of the whole window at once, where the level after the last group is unmatched
+      local highlights = require('blink.pairs.rust').get_highlights(bufnr, toprow, botrow + 1, #config.groups)
+      for i = 1, #highlights, 4 do
+        local line, col, len, level = highlights[i], highlights[i + 1], highlights[i + 2], highlights[i + 3]
+        vim.api.nvim_buf_set_extmark(bufnr, config.ns, line, col, {
+          end_col = col + len,
+          hl_group = config.groups[level + 1] or config.unmatched_group,
+          hl_mode = 'combine',
+          priority = config.priority,
+        })
+      end
+
+      if config.inner.enabled then update_inner_matches(bufnr, toprow, botrow) end
+      if config.doc_code.enabled then update_doc_code_matches(bufnr) end
+      return true
     end,
     on_line = function(_, _, bufnr, line_number)
-      for _, match in ipairs(require('blink.pairs.rust').get_line_matches(bufnr, line_number)) do
-        local hl_group = match.stack_height == nil and config.unmatched_group
-          or config.groups[match.stack_height % #config.groups + 1]
+      if config.doc_code.enabled and doc_code_by_bufnr[bufnr] ~= nil then
+        for _, match in ipairs(doc_code_by_bufnr[bufnr].matches_by_line[line_number] or {}) do
+          local hl_group = match.stack_height == nil and config.unmatched_group
+            or config.groups[match.stack_height % #config.groups + 1]
+
+          vim.api.nvim_buf_set_extmark(bufnr, config.ns, line_number, match.col, {
+            end_col = match.col + match.len,
+            hl_group = hl_group,
+            hl_mode = 'combine',
+            priority = config.priority,
+          })
+        end
+      end
 
+      if not config.inner.enabled then return end
+      for _, match in ipairs(inner_matches_by_line[line_number] or {}) do
         vim.api.nvim_buf_set_extmark(bufnr, config.ns, line_number, match.col, {
-          end_col = match.col + match[1]:len(),
-          hl_group = hl_group,
+          end_col = match.col + match.len,
+          hl_group = config.inner.groups[match.stack_height % #config.inner.groups + 1],
           hl_mode = 'combine',
           priority = config.priority,
         })
@@ -24,6 +92,15 @@ function highlighter.register(config)
     end,
   })
 
+  if config.doc_code.enabled then
+    vim.api.nvim_create_autocmd('BufWipeout', {
+      callback = function(args) doc_code_by_bufnr[args.buf] = nil end,
+    })
+  end
+  -- Parse the buffers of a restored session together instead of one by one on their first draw
+  vim.api.nvim_create_autocmd('SessionLoadPost', {
+    callback = function() require('blink.pairs.watcher').attach_many(vim.api.nvim_list_bufs()) end,
+  })
   if config.matchparen.enabled then require('blink.pairs.matchparen').setup(config) end
 end
 
@@ -16,9 +16,65 @@ local function set_highlights()
   vim.api.nvim_set_hl(0, 'BlinkPairsMatchParen', { link = 'MatchParen', default = true })
 end
 
+local function format_metrics(metrics)
+  local mb_per_sec = metrics.micros > 0 and metrics.bytes / metrics.micros or 0
+  return string.format(
+    '%d lines, %d bytes, %d stored matches in %.2fms (%.1f MB/s)',
+    metrics.lines,
+    metrics.bytes,
+    metrics.stored_matches,
+    metrics.micros / 1000,
+    mb_per_sec
+  )
+end
+
+local function create_commands()
+  vim.api.nvim_create_user_command('BlinkPairsStats', function()
+    local bufnr = vim.api.nvim_get_current_buf()
+    local metrics = require('blink.pairs.rust').get_buffer_metrics(bufnr)
+    if metrics == nil then
+      vim.notify('[blink.pairs] Buffer has not been parsed', vim.log.levels.WARN, { title = 'blink.pairs' })
+      return
+    end
+
+    vim.notify(
+      table.concat({
+        'Last parse: ' .. format_metrics(metrics.last),
+        'Total (' .. metrics.parses .. ' parses): ' .. format_metrics(metrics.total),
+      }, '\n'),
+      vim.log.levels.INFO,
+      { title = 'blink.pairs' }
+    )
+  end, { desc = 'Show parsing performance metrics for the current buffer' })
+
+  vim.api.nvim_create_user_command('BlinkPairsUnmatched', function(opts)
+    local token = opts.args ~= '' and opts.args or nil
+    local items = {}
+    for _, match in ipairs(require('blink.pairs.rust').find_unmatched_across_buffers(token)) do
+      if vim.api.nvim_buf_is_valid(match.bufnr) then
+        table.insert(items, {
+          bufnr = match.bufnr,
+          lnum = match.line + 1,
+          col = match.col + 1,
+          end_col = match.col + match.len + 1,
+          text = 'Unmatched ' .. match.text,
+        })
+      end
+    end
+
+    vim.fn.setqflist({}, ' ', { title = 'Unmatched delimiters', items = items })
+    if #items == 0 then
+      vim.notify('[blink.pairs] No unmatched delimiters', vim.log.levels.INFO, { title = 'blink.pairs' })
+      return
+    end
+    vim.cmd.copen()
+  end, { nargs = '?', desc = 'List the unmatched delimiters of all parsed buffers in the quickfix list' })
+end
+
 --- @param user_config blink.pairs.Config
 function pairs.setup(user_config)
   set_highlights()
+  create_commands()
 
   local config = require('blink.pairs.config')
   config.merge_with(user_config)
@@ -58,6 +114,48 @@ function pairs.download_if_available(callback)
   }, callback)
 end
 
+--- Indentation of the line `v:lnum` derived from the pairs, for bracket-structured languages, i.e.
+--- `vim.bo.indentexpr = "v:lua.require'blink.pairs'.indentexpr()"`. Keeps the current indentation
+--- when the buffer hasn't been parsed or the line starts inside of a string or comment
+--- @return number
+function pairs.indentexpr()
+  local bufnr = vim.api.nvim_get_current_buf()
+  local tab_width = require('blink.pairs.utils').get_tab_width(bufnr)
+  return require('blink.pairs.rust').get_suggested_indent(bufnr, vim.v.lnum - 1, tab_width) or -1
+end
+
+--- Fold levels of the lines of the last buffer passed to `foldexpr`
+local fold_cache = { bufnr = nil, changedtick = nil, exprs = {} }
+
+--- Fold level of the line `v:lnum` derived from the pairs spanning at least two lines, i.e.
+--- `vim.wo.foldexpr = "v:lua.require'blink.pairs'.foldexpr()"` with `vim.wo.foldmethod = 'expr'`
+--- @return string
+function pairs.foldexpr()
+  local bufnr = vim.api.nvim_get_current_buf()
+  local changedtick = vim.b[bufnr].changedtick
+  if fold_cache.bufnr ~= bufnr or fold_cache.changedtick ~= changedtick then
+    local levels = {}
+    local starts = {}
+    for _, range in ipairs(require('blink.pairs.rust').get_fold_ranges(bufnr, 2)) do
+      for line = range.start_line, range.end_line do
+        levels[line] = math.max(levels[line] or 0, range.level)
+      end
+      starts[range.start_line] = math.max(starts[range.start_line] or 0, range.level)
+    end
+
+    local exprs = {}
+    for line = 0, vim.api.nvim_buf_line_count(bufnr) - 1 do
+      if starts[line] ~= nil then
+        exprs[line] = '>' .. starts[line]
+      elseif levels[line] ~= nil then
+        exprs[line] = tostring(levels[line])
+      end
+    end
+    fold_cache = { bufnr = bufnr, changedtick = changedtick, exprs = exprs }
+  end
+  return fold_cache.exprs[vim.v.lnum - 1] or '0'
+end
+
 -- Get match at a given position in a buffer
 function pairs.get_match_at(bufnr, row, col)
   local ok, blink_pairs = pcall(require, 'blink_pairs')
@@ -121,6 +121,9 @@ function mappings.open_pair(ctx, key, rule, offset)
   -- \| -> \(|
   if ctx.is_escaped then return key end
 
+  -- don| -> don'|
+  if mappings.is_excluded(ctx, rule) then return key end
+
   -- |) -> (|)
   if
     ctx.parser.get_unmatched_closing_after(ctx.bufnr, rule.opening, rule.closing, ctx.cursor.row - 1, ctx.cursor.col)
@@ -133,6 +136,15 @@ function mappings.open_pair(ctx, key, rule, offset)
   return rule.opening:sub(offset or 0) .. rule.closing .. mappings.shift_keycode(-#rule.closing)
 end
 
+--- Whether the filetype excludes auto-pairing the rule's opening at the cursor, see `add_auto_pair_exclusion`
+--- @param ctx blink.pairs.Context
+--- @param rule blink.pairs.Rule
+--- @return boolean
+function mappings.is_excluded(ctx, rule)
+  local filetype = require('blink.pairs.watcher').get_filetype(ctx.bufnr)
+  return ctx.parser.is_auto_pair_excluded(ctx.bufnr, filetype, rule.opening, ctx.cursor.row - 1, ctx.cursor.col)
+end
+
 --- @param ctx blink.pairs.Context
 --- @param key string
 --- @param rule blink.pairs.Rule
@@ -144,6 +156,7 @@ function mappings.close_pair(ctx, key, rule)
     ctx.parser.get_unmatched_opening_before(ctx.bufnr, rule.opening, rule.closing, ctx.cursor.row - 1, ctx.cursor.col)
     ~= nil
   then
+    mappings.notify_stale_indent(ctx.bufnr, ctx.cursor.row - 1, ctx.cursor.col)
     return rule.closing
   end
 
@@ -153,6 +166,7 @@ function mappings.close_pair(ctx, key, rule)
   -- | ) ->  )|
   if ctx:text_after_cursor(2) == ' ' .. rule.closing then return mappings.shift_keycode(2) end
 
+  mappings.notify_stale_indent(ctx.bufnr, ctx.cursor.row - 1, ctx.cursor.col)
   return rule.closing
 end
 
@@ -171,6 +185,9 @@ function mappings.open_or_close_pair(ctx, key, rule)
   -- |' -> '|
   if ctx:is_after_cursor(pair) then return mappings.shift_keycode(#pair) end
 
+  -- don| -> don'|
+  if mappings.is_excluded(ctx, rule) then return key end
+
   -- Multiple character open
   -- '|' -> '''|'''
   if #rule.opening > 1 then
@@ -200,10 +217,32 @@ function mappings.backspace(rules)
     if surrounding_space then return '<Del><BS>' end
 
     -- (|) -> |
+    mappings.notify_stale_indent(ctx.bufnr, ctx.cursor.row - 1, ctx.cursor.col - #rule.opening)
     return mappings.shift_keycode(#rule.closing) .. string.rep('<BS>', #rule.opening + #rule.closing)
   end
 end
 
+--- Fires the `User BlinkPairsStaleIndent` autocmd with the lines whose indentation no longer
+--- matches the pairs, after the delimiter inserted or removed at the position has been applied
+--- @param bufnr number
+--- @param row number 0-indexed
+--- @param col number
+function mappings.notify_stale_indent(bufnr, row, col)
+  vim.schedule(function()
+    if not vim.api.nvim_buf_is_valid(bufnr) then return end
+
+    local lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false)
+    local range =
+      require('blink.pairs.rust').get_stale_indent_range(bufnr, lines, utils.get_tab_width(bufnr), row, col)
+    if range == nil then return end
+
+    vim.api.nvim_exec_autocmds('User', {
+      pattern = 'BlinkPairsStaleIndent',
+      data = { bufnr = bufnr, start_line = range.start_line, end_line = range.end_line },
+    })
+  end)
+end
+
 --- @param rules blink.pairs.Rule[]
 function mappings.enter(rules)
   return function()
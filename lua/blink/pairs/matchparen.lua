@@ -33,7 +33,8 @@ function M.setup(config)
       local cursor = { ctx.cursor.row, ctx.cursor.col + prompt_len }
       local buf = ctx.bufnr
       -- TODO: returns nil in cmdline mode due to the autocmd running before the watcher
-      local pair = rust.get_match_pair(buf, cursor[1] - 1, cursor[2])
+      local adjacency = mode:match('i') and config.matchparen.insert_adjacency or 'exact'
+      local pair = rust.get_match_pair(buf, cursor[1] - 1, cursor[2], adjacency)
 
       -- Clear extmarks
       if last_buf and vim.api.nvim_buf_is_valid(last_buf) then vim.api.nvim_buf_clear_namespace(last_buf, ns, 0, -1) end
@@ -42,9 +43,9 @@ function M.setup(config)
       if pair == nil then return end
 
       -- Highlight matches
-      for i, match in ipairs(pair) do
+      for _, match in ipairs(pair) do
         vim.api.nvim_buf_set_extmark(buf, ns, match.line, match.col, {
-          end_col = match.col + (match[i] or match[1]):len(),
+          end_col = match.col + match.len,
           hl_group = config.matchparen.group,
           hl_mode = 'combine',
           priority = config.matchparen.priority,
@@ -39,6 +39,20 @@ function M.is_in_span(span_name)
   return span_at == span_name
 end
 
+--- Checks if the cursor is inside of a comment
+--- @return boolean
+function M.is_in_comment()
+  local cursor = vim.api.nvim_win_get_cursor(0)
+  return require('blink.pairs.rust').is_in_comment(vim.api.nvim_get_current_buf(), cursor[1] - 1, cursor[2])
+end
+
+--- Checks if the cursor is inside of a string
+--- @return boolean
+function M.is_in_string()
+  local cursor = vim.api.nvim_win_get_cursor(0)
+  return require('blink.pairs.rust').is_in_string(vim.api.nvim_get_current_buf(), cursor[1] - 1, cursor[2])
+end
+
 --- Takes a table of user friendly rule definitions and converts it to a table of rules
 --- @param definitions blink.pairs.RuleDefinitions
 --- @return blink.pairs.RulesByKey
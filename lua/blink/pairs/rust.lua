@@ -18,23 +18,207 @@ package.cpath = package.cpath
 
 --- @class blink.pairs.Parser
 --- @field parse_buffer fun(bufnr: number?, shiftwidth: number, filetype: string, lines: string[], start_line: number?, old_end_line: number?, new_end_line: number?): boolean
+--- @field parse_buffers fun(buffers: blink.pairs.BufferToParse[]): number[]
+--- @field load_cached_buffer fun(bufnr: number, cache_dir: string, path: string, filetype: string, shiftwidth: number, lines: string[]): boolean Restores the buffer from the on-disk cache when parsed from the same lines
+--- @field store_cached_buffer fun(bufnr: number, cache_dir: string, path: string, filetype: string, shiftwidth: number, lines: string[]): boolean
+--- @field get_buffer_metrics fun(bufnr: number): blink.pairs.BufferMetrics?
+--- @field checkpoint_buffer fun(bufnr: number): boolean
+--- @field restore_buffer fun(bufnr: number): boolean
+--- @field get_token_sets fun(filetype: string): blink.pairs.TokenSets? Texts of the filetype's delimiters, strings, comments and spans, i.e. for choosing a comment string
 --- @field get_line_matches fun(bufnr: number, line_number: number, token_type: number?): blink.pairs.Match[]
+--- @field get_line_matches_in fun(bufnr: number, line_number: number, start_col: number, end_col: number): blink.pairs.Match[] Matches overlapping the columns, including tokens starting before them but ending inside
+--- @field get_highlights fun(bufnr: number, start_line: number, end_line: number, num_colors: number): number[] Flat list of `line, col, len, level` for the delimiters within the lines, end exclusive, where the level is the stack height modulo `num_colors`, or `num_colors` when unmatched
+--- @field get_inner_matches fun(bufnr: number, filetype: string, lines: string[], start_line: number): blink.pairs.MatchWithLine[] Delimiters inside strings and comments of the given lines, starting at `start_line`, paired on a stack separate from the code's
+--- @field get_doc_code_matches fun(filetype: string, shiftwidth: number, lines: string[]): blink.pairs.MatchWithLine[] Pairs of the code blocks in doc comments, parsed as the filetype, given the buffer's lines
+--- @field query_matches fun(bufnr: number, query: blink.pairs.Query, filetype?: string, lines?: string[]): blink.pairs.MatchWithLine[] Delimiters inside strings and comments are only included when given the filetype and all of the buffer's lines
 --- @field get_span_at fun(bufnr: number, row: number, col: number): string?
---- @field get_match_at fun(bufnr: number, row: number, col: number): blink.pairs.Match?
---- @field get_match_pair fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine[]?
+--- @field get_span_kind_at fun(bufnr: number, filetype: string, row: number, col: number): blink.pairs.SpanKind? Kind of the string, comment or span at the position, i.e. for allowing auto pairs in docstrings but not in regexes
+--- @field is_in_comment fun(bufnr: number, row: number, col: number): boolean Whether the position is inside of a comment, excluding its delimiters
+--- @field is_in_string fun(bufnr: number, row: number, col: number): boolean Whether the position is inside of a string, excluding its delimiters and interpolations
+--- @field get_match_at fun(bufnr: number, row: number, col: number): (blink.pairs.Match | { offset: number })? Token covering the position, with the byte of the token the position is on as `offset`
+--- @field get_match_pair fun(bufnr: number, row: number, col: number, adjacency: blink.pairs.Adjacency?): blink.pairs.MatchWithLine[]? Pair of the delimiter at the position, or next to it depending on the adjacency, `'exact'` by default
+--- @field get_span_pair fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine[]? Opening and closing of the string, block comment or span at or around the position
+--- @field get_pairs_intersecting_point fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine[]? Innermost pair whose delimiters or contents contain the position, i.e. under the mouse
+--- @field get_enclosing_pair fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine[]? Innermost pair surrounding the position, excluding the pair whose delimiter is at the position
+--- @field analyze_paste fun(bufnr: number, filetype: string, row: number, col: number, lines: string[]): blink.pairs.PasteAnalysis? Delimiters left unpaired by pasting the lines at the position, with their positions after pasting
+--- @field add_auto_pair_exclusion fun(filetype: string, opening: string, context: blink.pairs.ExclusionContext) Stops auto-pairing the opening in the context, in addition to the filetype's built-in exclusions
+--- @field is_auto_pair_excluded fun(bufnr: number, filetype: string, opening: string, row: number, col: number): boolean Whether typing the opening at the position shouldn't insert its closing
+--- @field is_safe_insertion_point fun(bufnr: number, row: number, col: number): boolean? Whether inserting text at the position keeps the strings, comments and tokens around it intact, i.e. for validating snippets. Nil when the buffer isn't parsed
+--- @field get_pairs_in_range fun(bufnr: number, start_line: number, end_line: number): blink.pairs.MatchWithLine[][] Matched pairs with the opening or closing within the lines, end exclusive, including the partners outside of them
+--- @field get_enclosing_pairs fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine[][] Pairs surrounding the position from the innermost to the outermost, i.e. for expanding the selection or breadcrumbs
+--- @field get_pairs_containing fun(bufnr: number, start_line: number, start_col: number, end_line: number, end_col: number): blink.pairs.MatchWithLine[][] Pairs whose range including the delimiters contains the range, from the innermost to the outermost, end exclusive
+--- @field get_pending_closings fun(bufnr: number, row: number, col: number): blink.pairs.PendingClosing[] Closings which would balance the unmatched openings before the position, innermost first, for ranking completions
+--- @field get_cycle_pair fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine?
+--- @field get_construct_ranges fun(bufnr: number, row: number, col: number): blink.pairs.TokenRange[]?
+--- @field get_opening_context fun(bufnr: number, row: number, col: number): blink.pairs.TokenRange? Line of the opening for the closing at the position, with its text range excluding the surrounding whitespace
+--- @field get_closing_annotations fun(bufnr: number, lines: string[], start_line: number, end_line: number, min_lines: number, max_len: number): blink.pairs.ClosingAnnotation[] Opening line snippets for the closings of pairs spanning at least `min_lines` lines, given the buffer's lines
+--- @field get_sortable_ranges fun(bufnr: number, lines: string[], row: number, col: number): blink.pairs.SourceRange[]? Elements inside the innermost pair at the position, given the buffer's lines
+--- @field get_trailing_comma fun(bufnr: number, lines: string[], row: number, col: number): { line: number, col: number }? Comma after the last element of the pair whose delimiter is at the position, i.e. in `[a, b,]`, given the buffer's lines
 --- @field get_unmatched_opening_before fun(bufnr: number, opening: string, closing: string, row: number, col: number): blink.pairs.MatchWithLine?
 --- @field get_unmatched_closing_after fun(bufnr: number, opening: string, closing: string, row: number, col: number): blink.pairs.MatchWithLine?
+--- @field get_next_unmatched_after fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine? Nearest unmatched delimiter of any token after the position
+--- @field get_prev_unmatched_before fun(bufnr: number, row: number, col: number): blink.pairs.MatchWithLine? Nearest unmatched delimiter of any token before the position
+--- @field get_join_adjustment fun(bufnr: number, row: number, line: string, next_line: string): blink.pairs.JoinAdjustment? Edits for joining the line with the next, given the text of both lines. Nil when the line ends inside of a string or comment
+--- @field get_textobject_at fun(bufnr: number, row: number, col: number, around: boolean): blink.pairs.SourceRange? Range inside of the pair at the position, or including its delimiters when `around`, end exclusive
+--- @field get_expanded_selection fun(bufnr: number, start_line: number, start_col: number, end_line: number, end_col: number): blink.pairs.SourceRange? Smallest range of a pair, inside of or including its delimiters, strictly containing the range, end exclusive
+--- @field get_shrunk_selection fun(bufnr: number, start_line: number, start_col: number, end_line: number, end_col: number, cursor_line: number, cursor_col: number): blink.pairs.SourceRange? Largest range of a pair strictly inside of the range, preferring the pair closest to the cursor, end exclusive
+--- @field get_span_range_at fun(bufnr: number, row: number, col: number, around: boolean): blink.pairs.SourceRange? Range of the string, comment or span at the position, inside of its delimiters or including them when `around`
+--- @field get_surroundings_at fun(bufnr: number, row: number, col: number): blink.pairs.Surrounding[] Pairs, strings, comments and spans around the position or with a delimiter at it, from the innermost to the outermost
+--- @field get_top_level_blocks fun(bufnr: number): blink.pairs.SourceRange[] Pairs at the top level spanning multiple lines including their delimiters, i.e. for an outline
+--- @field get_structure_hash fun(bufnr: number): integer? Digest of the pairs and their nesting ignoring the text around them, which only changes when folds and outlines need to be recomputed
+--- @field get_sibling_block fun(bufnr: number, row: number, col: number, direction: 'forward' | 'backward'): blink.pairs.MatchWithLine[]? Next or previous pair at the same depth within the same parent, i.e. the following block or argument
+--- @field get_fold_ranges fun(bufnr: number, min_lines: number): { start_line: number, end_line: number, level: number }[] Lines of the pairs spanning at least `min_lines` lines, end inclusive, with their fold level starting from 1
+--- @field get_next_opening fun(bufnr: number, opening: string, row: number, col: number): blink.pairs.MatchWithLine? Nearest opening delimiter with the text after the position, outside of strings and comments
+--- @field get_prev_closing fun(bufnr: number, closing: string, row: number, col: number): blink.pairs.MatchWithLine? Nearest closing delimiter with the text before the position, outside of strings and comments
+--- @field get_line_depth_profile fun(bufnr: number, line: number): blink.pairs.DepthProfile? Stack heights at the start and end of the line and the highest one within, i.e. for drawing depth changes in the gutter
 --- @field get_indent_levels fun(bufnr: number, start_line: number, end_line: number): number[]
+--- @field get_depth_by_line fun(bufnr: number, start_line: number, end_line: number): number[] Nesting depth at the start of each line, excluding end_line
+--- @field get_virtual_indent_levels fun(bufnr: number, start_line: number, end_line: number): number[] Blank lines get the higher indent level of the nearest non-blank lines
+--- @field get_indent_changes fun(bufnr: number): { start_line: number, end_line: number }? Lines whose indent levels changed in the last parse, end exclusive
+--- @field get_suggested_indent fun(bufnr: number, row: number, tab_width: number): number? Indentation of the line in columns derived from the pairs, or nil inside of a string or comment
+--- @field get_stale_indent_range fun(bufnr: number, lines: string[], tab_width: number, row: number, col: number): { start_line: number, end_line: number }? Lines whose indentation no longer matches the pairs after a delimiter was inserted or removed at the position, end exclusive
+--- @field find_unmatched_across_buffers fun(token: string?): blink.pairs.UnmatchedDelimiter[] Unmatched delimiters of all parsed buffers, optionally only those with the opening or closing text
+--- @field get_diagnostics fun(bufnr: number): blink.pairs.Diagnostic[] Unmatched delimiters and unterminated strings, comments and spans, in order
+--- @field get_stats fun(bufnr: number): blink.pairs.PairStats? Matched pairs and unmatched delimiters of each delimiter type, i.e. for the statusline
+--- @field get_nesting_regions fun(bufnr: number): blink.pairs.NestingRegion[]
+--- @field get_out_of_scope_ranges fun(bufnr: number, row: number, col: number): { start_line: number, end_line: number }[] Lines outside of the scope at the position, end exclusive
+--- @field detect_indent fun(lines: string[]): blink.pairs.DetectedIndent?
+--- @field set_tokenizer_backend fun(backend: 'simd' | 'memchr' | 'swar')
+--- @field get_tokenizer_health fun(): { backend: 'simd' | 'memchr' | 'swar', simd_lanes: number, unsupported_simd_features: string[] } Tokenizer used for parsing, the SIMD lanes picked for the CPU and the SIMD features enabled at build time which the CPU lacks
+--- @field set_depth_limit fun(bufnr: number, tab_width: number, max_depth: number, overflow: 'clamp' | 'unmatched'): boolean Pairs of the parsed buffer nested deeper than `max_depth` (256 by default) get a stack height of `max_depth` when clamped, or are marked as unmatched. Returns false when the buffer isn't parsed
+
+--- @alias blink.pairs.Adjacency 'exact' | 'before' | 'after' | 'nearest_on_line'
+
+--- @alias blink.pairs.ExclusionContext 'after_word' | 'after_whitespace' | 'before_word' | 'in_string' | 'in_comment'
+
+--- @class blink.pairs.SpanKind
+--- @field kind 'string' | 'docstring' | 'regex' | 'comment' | 'span'
+--- @field span string? Name of the span
+
+--- @class blink.pairs.Surrounding
+--- @field kind 'pair' | 'string' | 'comment' | 'span'
+--- @field span string? Name of the span
+--- @field range blink.pairs.SourceRange Including the delimiters, end exclusive
+
+--- @class blink.pairs.TokenSets
+--- @field delimiters string[][] Opening and closing of each delimiter
+--- @field strings string[] Strings closed by the same text, including chars and regexes
+--- @field block_strings string[][]
+--- @field regexes string[] Strings which are regexes, also in `strings`
+--- @field docstrings string[] Openings of the block strings which are docstrings when they start their line
+--- @field line_comments string[]
+--- @field block_comments string[][]
+--- @field inline_spans { [1]: string, [2]: string, span: string }[]
+--- @field block_spans { [1]: string, [2]: string, span: string }[]
+
+--- @class blink.pairs.BufferToParse
+--- @field bufnr number
+--- @field tab_width number
+--- @field filetype string
+--- @field lines string[]
+
+--- @class blink.pairs.DepthProfile
+--- @field entry number
+--- @field exit number
+--- @field max number
 
 --- @class blink.pairs.Match
 --- @field [1] string
 --- @field [2] string?
 --- @field span string?
 --- @field col number
+--- @field len number Length of the matched source text, which may differ from the token's text for keywords
+--- @field end_col number Column after the last byte of the token
 --- @field stack_height number?
 
 --- @class blink.pairs.MatchWithLine : blink.pairs.Match
 --- @field line number
+--- @field middle string? Text of the middle token, i.e. `else`, when between the opening and closing
+
+--- @class blink.pairs.TokenRange
+--- @field line number
+--- @field start_col number
+--- @field end_col number Exclusive
+
+--- @class blink.pairs.SourceRange
+--- @field start_line number
+--- @field start_col number
+--- @field end_line number
+--- @field end_col number Exclusive
+
+--- @class blink.pairs.ClosingAnnotation
+--- @field line number
+--- @field col number Column after the closing
+--- @field opening_line number
+--- @field text string Text of the opening line before the opening, i.e. `impl Foo`
+
+--- @class blink.pairs.PendingClosing
+--- @field closing string
+--- @field opening_line number
+--- @field opening_col number
+--- @field depth number Number of closings which must be typed before this one, 0 for the innermost
+
+--- @class blink.pairs.PasteAnalysis
+--- @field balanced boolean
+--- @field changes_state boolean Whether the pasted text leaves a string or comment open, or closes the one at the position
+--- @field extra_closings blink.pairs.MatchWithLine[] Closings which would close the pairs around the position
+--- @field unclosed_openings blink.pairs.MatchWithLine[]
+
+--- @class blink.pairs.JoinAdjustment
+--- @field trailing_comma number? Column of the comma at the end of the line which becomes redundant before the closing starting the next line
+--- @field line_end number End of the line's text, after which the whitespace is removed
+--- @field next_line_start number Start of the next line's text, before which the whitespace is removed
+--- @field separator string Text between the lines
+
+--- @class blink.pairs.Diagnostic
+--- @field line number
+--- @field col number
+--- @field len number
+--- @field text string
+--- @field message 'unmatched opening' | 'unmatched closing' | 'unterminated string' | 'unterminated comment' | 'unterminated span'
+
+--- @class blink.pairs.UnmatchedDelimiter
+--- @field bufnr number
+--- @field line number
+--- @field col number
+--- @field len number
+--- @field text string
+
+--- @class blink.pairs.DetectedIndent
+--- @field style 'tabs' | 'spaces'
+--- @field width number Characters per indent level, always 1 for tabs
+--- @field confidence number Share of the indented lines which follow the style, from 0 to 1
+--- @field deviating_lines number[] Indented lines which don't follow the style
+
+--- @class blink.pairs.PairStats
+--- @field unmatched number Total number of unmatched delimiters
+--- @field delimiters { opening: string, closing: string, matched: number, unmatched: number }[]
+--- @field max_depth number
+
+--- @class blink.pairs.NestingRegion
+--- @field start_line number
+--- @field end_line number
+--- @field max_depth number
+
+--- @class blink.pairs.Metrics
+--- @field lines number
+--- @field bytes number
+--- @field stored_matches number Excludes the delimiters inside strings and comments
+--- @field micros number
+
+--- @class blink.pairs.BufferMetrics
+--- @field last blink.pairs.Metrics
+--- @field total blink.pairs.Metrics
+--- @field parses number
+
+--- @class blink.pairs.Query
+--- @field token string? Opening text of the token, i.e. `(`
+--- @field token_type number?
+--- @field matched boolean? `true` for matched, `false` for unmatched
+--- @field in_comments boolean? Matches inside comments are only found when `query_matches` is given the lines
+--- @field in_strings boolean? Matches inside strings are only found when `query_matches` is given the lines
+--- @field start_line number?
+--- @field end_line number? Exclusive
 
 --- @type blink.pairs.Parser
 return require('blink_pairs')
@@ -5,6 +5,68 @@ local watcher = {
   watched_bufnrs = {},
 }
 
+--- @param bufnr number
+--- @return string
+function watcher.get_filetype(bufnr)
+  -- TODO: use 'lua' filetype for cmd buffers with := and :lua
+  local ft = vim.bo[bufnr].filetype
+  -- map cmdline's 'cmd' filetype to 'vim'
+  if ft == 'cmd' then ft = 'vim' end
+  -- fixed-form fortran has column 1 comments, see `:h ft-fortran-syntax`
+  if ft == 'fortran' and vim.b[bufnr].fortran_fixed_source == 1 then ft = 'fortran_fixed' end
+  return ft
+end
+
+--- Gets the path of the buffer's file when the buffer should be cached
+--- @param bufnr number
+--- @return string?
+local function get_cache_path(bufnr)
+  local config = require('blink.pairs.config').cache
+  if not config.enabled or vim.bo[bufnr].buftype ~= '' then return end
+  if vim.api.nvim_buf_line_count(bufnr) < config.min_lines then return end
+
+  local path = vim.api.nvim_buf_get_name(bufnr)
+  if path == '' then return end
+  return path
+end
+
+--- Restores the parsed buffer from the on-disk cache when its lines haven't changed
+--- @param bufnr number
+--- @return boolean did_load
+local function load_from_cache(bufnr)
+  local path = get_cache_path(bufnr)
+  if path == nil then return false end
+
+  local lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false)
+  local ok, ret = pcall(
+    require('blink.pairs.rust').load_cached_buffer,
+    bufnr,
+    require('blink.pairs.config').cache.dir,
+    path,
+    watcher.get_filetype(bufnr),
+    utils.get_tab_width(bufnr),
+    lines
+  )
+  return ok and ret
+end
+
+--- @param bufnr number
+local function store_in_cache(bufnr)
+  local path = get_cache_path(bufnr)
+  if path == nil then return end
+
+  local lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false)
+  pcall(
+    require('blink.pairs.rust').store_cached_buffer,
+    bufnr,
+    require('blink.pairs.config').cache.dir,
+    path,
+    watcher.get_filetype(bufnr),
+    utils.get_tab_width(bufnr),
+    lines
+  )
+end
+
 --- Runs a full parse on the buffer when start_line, old_end_line, and new_end_line are not provided.
 --- Otherwise, incrementally parses the buffer.
 --- @param bufnr number
@@ -18,11 +80,7 @@ local function parse_buffer(bufnr, start_line, old_end_line, new_end_line)
   local lines = vim.api.nvim_buf_get_lines(bufnr, start_line or 0, new_end_line or -1, false)
 
   local rust = require('blink.pairs.rust')
-
-  -- TODO: use 'lua' filetype for cmd buffers with := and :lua
-  local ft = vim.bo[bufnr].filetype
-  -- map cmdline's 'cmd' filetype to 'vim'
-  if ft == 'cmd' then ft = 'vim' end
+  local ft = watcher.get_filetype(bufnr)
 
   local ok, ret =
     pcall(rust.parse_buffer, bufnr, utils.get_tab_width(bufnr), ft, lines, start_line, old_end_line, new_end_line)
@@ -35,18 +93,19 @@ local function parse_buffer(bufnr, start_line, old_end_line, new_end_line)
   return did_parse
 end
 
---- Runs an initial parse on the buffer and attaches via nvim_buf_attach
---- for incremental parsing
+--- Attaches to the parsed buffer via nvim_buf_attach for incremental parsing
 --- @param bufnr number
---- @return boolean is_attached Whether the buffer is parseable and attached
-function watcher.attach(bufnr)
-  if watcher.watched_bufnrs[bufnr] ~= nil then return true end
-
-  local did_parse = parse_buffer(bufnr)
-  if not did_parse then return false end
-
+local function watch(bufnr)
   watcher.watched_bufnrs[bufnr] = true
 
+  vim.api.nvim_create_autocmd('BufWritePost', {
+    buffer = bufnr,
+    callback = function()
+      if not watcher.watched_bufnrs[bufnr] then return true end
+      store_in_cache(bufnr)
+    end,
+  })
+
   local last_changedtick = 0
   vim.api.nvim_buf_attach(bufnr, false, {
     on_detach = function() watcher.watched_bufnrs[bufnr] = nil end,
@@ -74,8 +133,53 @@ function watcher.attach(bufnr)
       end
     end,
   })
+end
 
+--- Runs an initial parse on the buffer and attaches via nvim_buf_attach
+--- for incremental parsing
+--- @param bufnr number
+--- @return boolean is_attached Whether the buffer is parseable and attached
+function watcher.attach(bufnr)
+  if watcher.watched_bufnrs[bufnr] ~= nil then return true end
+
+  if load_from_cache(bufnr) then
+    -- Verify the cached result after the first redraw
+    vim.schedule(function()
+      if watcher.watched_bufnrs[bufnr] and vim.api.nvim_buf_is_valid(bufnr) then parse_buffer(bufnr) end
+    end)
+  else
+    local did_parse = parse_buffer(bufnr)
+    if not did_parse then return false end
+    store_in_cache(bufnr)
+  end
+
+  watch(bufnr)
   return true
 end
 
+--- Runs the initial parse of the loaded buffers in parallel and attaches to the parseable ones,
+--- i.e. after restoring a session, rather than parsing each buffer on the UI thread when it's first drawn
+--- @param bufnrs number[]
+function watcher.attach_many(bufnrs)
+  local buffers = {}
+  for _, bufnr in ipairs(bufnrs) do
+    if watcher.watched_bufnrs[bufnr] == nil and vim.api.nvim_buf_is_loaded(bufnr) then
+      table.insert(buffers, {
+        bufnr = bufnr,
+        tab_width = utils.get_tab_width(bufnr),
+        filetype = watcher.get_filetype(bufnr),
+        lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false),
+      })
+    end
+  end
+  if #buffers == 0 then return end
+
+  local ok, parsed_bufnrs = pcall(require('blink.pairs.rust').parse_buffers, buffers)
+  if not ok then return end
+  for _, bufnr in ipairs(parsed_bufnrs) do
+    store_in_cache(bufnr)
+    watch(bufnr)
+  end
+end
+
 return watcher
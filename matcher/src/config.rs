@@ -1,7 +1,7 @@
 use syn::parse::{Parse, ParseStream};
 use syn::token::{Colon, Comma, FatArrow};
 use syn::{braced, bracketed, Result};
-use syn::{Ident, LitStr};
+use syn::{Ident, LitStr, Path};
 
 use std::collections::{HashMap, HashSet};
 
@@ -9,12 +9,24 @@ pub struct MatcherDef {
     pub name: Ident,
     pub delimiters: Vec<(String, String)>,
     pub line_comments: Vec<String>,
+    pub line_start_comments: Vec<String>,
+    pub not_comments: Vec<String>,
     pub block_comments: Vec<(String, String)>,
     pub strings: Vec<String>,
+    pub doubled_strings: Vec<String>,
+    /// (string delimiter, opening, closing), i.e. `"\"": "{$" => "}"`
+    pub interpolations: Vec<(String, String, String)>,
     pub chars: Vec<String>,
+    pub regexes: Vec<String>,
+    pub char_prefixes: Vec<String>,
     pub block_strings: Vec<(String, String)>,
+    /// Openings of block strings which are docstrings when they start their line, i.e. `"""`
+    /// in Python
+    pub docstrings: Vec<String>,
     pub inline_spans: HashMap<String, (String, String)>,
     pub block_spans: HashMap<String, (String, String)>,
+    pub keywords: Option<Path>,
+    pub auto_pair_exclusions: Option<Path>,
 }
 
 // Parse the incoming macro definition into a MatcherDef struct
@@ -26,12 +38,21 @@ impl Parse for MatcherDef {
 
         let mut delimiters = Vec::new();
         let mut line_comments = Vec::new();
+        let mut line_start_comments = Vec::new();
+        let mut not_comments = Vec::new();
         let mut block_comments = Vec::new();
         let mut strings = Vec::new();
+        let mut doubled_strings = Vec::new();
+        let mut interpolations = Vec::new();
         let mut chars = Vec::new();
+        let mut regexes = Vec::new();
+        let mut char_prefixes = Vec::new();
         let mut block_strings = Vec::new();
+        let mut docstrings = Vec::new();
         let mut inline_spans = HashMap::new();
         let mut block_spans = HashMap::new();
+        let mut keywords = None;
+        let mut auto_pair_exclusions = None;
 
         fn get_single_char(token: LitStr) -> Result<String> {
             let value = token.value();
@@ -50,10 +71,28 @@ impl Parse for MatcherDef {
             let section_name = content.parse::<Ident>()?;
             content.parse::<Colon>()?;
 
+            // Path to a `&[Keyword]` const
+            if section_name == "keywords" {
+                keywords = Some(content.parse::<Path>()?);
+                if !content.is_empty() {
+                    content.parse::<Comma>()?;
+                }
+                continue;
+            }
+            // Path to a `&[AutoPairExclusion]` const
+            if section_name == "auto_pair_exclusions" {
+                auto_pair_exclusions = Some(content.parse::<Path>()?);
+                if !content.is_empty() {
+                    content.parse::<Comma>()?;
+                }
+                continue;
+            }
+
             let section_content;
             match section_name.to_string().as_str() {
-                "delimiters" | "line_comment" | "block_comment" | "string" | "char"
-                | "block_string" => {
+                "delimiters" | "line_comment" | "line_start_comment" | "not_comment"
+                | "block_comment" | "string" | "doubled_string" | "interpolation" | "regex"
+                | "char" | "char_prefix" | "block_string" | "docstring" => {
                     bracketed!(section_content in content);
                 }
                 "inline_span" | "block_span" => {
@@ -65,9 +104,11 @@ impl Parse for MatcherDef {
             match section_name.to_string().as_str() {
                 "delimiters" => {
                     while !section_content.is_empty() {
-                        let open = get_single_char(section_content.parse::<LitStr>()?)?;
+                        // Multi-character delimiters, such as `{{` in templates, must be
+                        // listed before their single character prefixes
+                        let open = section_content.parse::<LitStr>()?.value();
                         section_content.parse::<FatArrow>()?;
-                        let close = get_single_char(section_content.parse::<LitStr>()?)?;
+                        let close = section_content.parse::<LitStr>()?.value();
                         delimiters.push((open, close));
 
                         if !section_content.is_empty() {
@@ -83,6 +124,22 @@ impl Parse for MatcherDef {
                         }
                     }
                 }
+                "line_start_comment" => {
+                    while !section_content.is_empty() {
+                        line_start_comments.push(section_content.parse::<LitStr>()?.value());
+                        if !section_content.is_empty() {
+                            section_content.parse::<Comma>()?;
+                        }
+                    }
+                }
+                "not_comment" => {
+                    while !section_content.is_empty() {
+                        not_comments.push(section_content.parse::<LitStr>()?.value());
+                        if !section_content.is_empty() {
+                            section_content.parse::<Comma>()?;
+                        }
+                    }
+                }
                 "block_comment" => {
                     while !section_content.is_empty() {
                         let open = section_content.parse::<LitStr>()?.value();
@@ -103,6 +160,38 @@ impl Parse for MatcherDef {
                         }
                     }
                 }
+                "doubled_string" => {
+                    while !section_content.is_empty() {
+                        let delim = get_single_char(section_content.parse::<LitStr>()?)?;
+                        doubled_strings.push(delim);
+                        if !section_content.is_empty() {
+                            section_content.parse::<Comma>()?;
+                        }
+                    }
+                }
+                "interpolation" => {
+                    while !section_content.is_empty() {
+                        let string = section_content.parse::<LitStr>()?.value();
+                        section_content.parse::<Colon>()?;
+                        let open = section_content.parse::<LitStr>()?.value();
+                        section_content.parse::<FatArrow>()?;
+                        let close = get_single_char(section_content.parse::<LitStr>()?)?;
+                        interpolations.push((string, open, close));
+
+                        if !section_content.is_empty() {
+                            section_content.parse::<Comma>()?;
+                        }
+                    }
+                }
+                "regex" => {
+                    while !section_content.is_empty() {
+                        let delim = get_single_char(section_content.parse::<LitStr>()?)?;
+                        regexes.push(delim);
+                        if !section_content.is_empty() {
+                            section_content.parse::<Comma>()?;
+                        }
+                    }
+                }
                 "char" => {
                     while !section_content.is_empty() {
                         let delim = get_single_char(section_content.parse::<LitStr>()?)?;
@@ -112,6 +201,14 @@ impl Parse for MatcherDef {
                         }
                     }
                 }
+                "char_prefix" => {
+                    while !section_content.is_empty() {
+                        char_prefixes.push(section_content.parse::<LitStr>()?.value());
+                        if !section_content.is_empty() {
+                            section_content.parse::<Comma>()?;
+                        }
+                    }
+                }
                 "block_string" => {
                     while !section_content.is_empty() {
                         let open = section_content.parse::<LitStr>()?.value();
@@ -124,6 +221,21 @@ impl Parse for MatcherDef {
                         }
                     }
                 }
+                "docstring" => {
+                    while !section_content.is_empty() {
+                        let token = section_content.parse::<LitStr>()?;
+                        if !block_strings.iter().any(|(open, _)| *open == token.value()) {
+                            return Err(syn::Error::new(
+                                token.span(),
+                                "Docstring must be the opening of a block string",
+                            ));
+                        }
+                        docstrings.push(token.value());
+                        if !section_content.is_empty() {
+                            section_content.parse::<Comma>()?;
+                        }
+                    }
+                }
                 "inline_span" => {
                     while !section_content.is_empty() {
                         let name = section_content.parse::<Ident>()?.to_string();
@@ -164,12 +276,21 @@ impl Parse for MatcherDef {
             name,
             delimiters,
             line_comments,
+            line_start_comments,
+            not_comments,
             block_comments,
             strings,
+            doubled_strings,
+            interpolations,
             chars,
+            regexes,
+            char_prefixes,
             block_strings,
+            docstrings,
             inline_spans,
             block_spans,
+            keywords,
+            auto_pair_exclusions,
         })
     }
 }
@@ -212,13 +333,32 @@ pub fn collect_tokens(def: &MatcherDef) -> Vec<u8> {
         }
     }
 
-    for s in &def.strings {
+    for s in def.strings.iter().chain(&def.doubled_strings) {
+        for c in s.bytes() {
+            all_tokens.insert(c);
+        }
+    }
+
+    for (_, open, close) in &def.interpolations {
+        for c in open.bytes().chain(close.bytes()) {
+            all_tokens.insert(c);
+        }
+    }
+
+    for comment in def.line_start_comments.iter().chain(&def.not_comments) {
+        for c in comment.bytes() {
+            all_tokens.insert(c);
+        }
+    }
+
+    for s in def.chars.iter().chain(&def.char_prefixes) {
         for c in s.bytes() {
             all_tokens.insert(c);
         }
     }
 
-    for s in &def.chars {
+    for s in &def.regexes {
+        all_tokens.extend(*b"[]");
         for c in s.bytes() {
             all_tokens.insert(c);
         }
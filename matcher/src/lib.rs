@@ -2,6 +2,8 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::parse_macro_input;
 
+use std::collections::HashMap;
+
 mod config;
 mod lookahead;
 mod matcher;
@@ -118,6 +120,13 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
         match_arms.push(close_arm.build());
     }
 
+    // Patterns which would otherwise start a line comment, i.e. `#[` in PHP. Skips the first
+    // byte so that the rest is matched as usual
+    for pattern in &def.not_comments {
+        let arm = MatchArm::builder(pattern.to_string(), max_lookahead).body(quote! { state });
+        match_arms.push(arm.build());
+    }
+
     // 4. Line comment patterns
     for comment in &def.line_comments {
         let arm = MatchArm::builder(comment.to_string(), max_lookahead)
@@ -134,6 +143,87 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
         match_arms.push(arm.build());
     }
 
+    // Line comments which must start in the first column, i.e. `C` in fixed-form Fortran
+    for comment in &def.line_start_comments {
+        let arm = MatchArm::builder(comment.to_string(), max_lookahead)
+            .if_condition(quote! { token.col == 0 })
+            .body(quote! {
+                matches.push(Match::line_comment(#comment, token.col));
+                // Skip tokens based on length of pattern
+                for _ in 1..#comment.len() {
+                    tokens.next();
+                }
+                State::InLineComment
+            });
+        match_arms.push(arm.build());
+    }
+
+    // Interpolation in strings, i.e. `{$expr}` in PHP, where the braces are matched as
+    // delimiters
+    for (string, open, close) in &def.interpolations {
+        let Some((delim_open, delim_close)) = def.delimiters.iter().find(|(_, c)| c == close)
+        else {
+            panic!("Interpolation closing {close:?} must be a delimiter");
+        };
+        let Some(delim_offset) = open.find(delim_open.as_str()) else {
+            panic!("Interpolation opening {open:?} must contain {delim_open:?}");
+        };
+
+        let open_arm = MatchArm::builder(open.to_string(), max_lookahead)
+            .ignore_escaped()
+            .input_state(quote! { State::InString(#string) })
+            .body(quote! {
+                matches.push(Match::new(Kind::Opening, Token::Delimiter(#delim_open, #delim_close), token.col + #delim_offset));
+                // Skip tokens based on length of pattern
+                for _ in 1..#open.len() {
+                    tokens.next();
+                }
+                State::InInterpolation(#string)
+            });
+        match_arms.push(open_arm.build());
+
+        let close_arm = MatchArm::builder(close.to_string(), max_lookahead)
+            .input_state(quote! { State::InInterpolation(#string) })
+            .body(quote! {
+                matches.push(Match::new(Kind::Closing, Token::Delimiter(#delim_open, #delim_close), token.col));
+                State::InString(#string)
+            });
+        match_arms.push(close_arm.build());
+    }
+
+    // Regex literals, i.e. `/[a-z]+/` in JavaScript, which are matched as strings when the
+    // code before the `/` can't end an expression
+    for delim in &def.regexes {
+        let open_arm = MatchArm::builder(delim.to_string(), max_lookahead)
+            .if_condition(quote! { regex_allowed(&line[..token.col]) })
+            .body(quote! {
+                matches.push(Match::new(Kind::Opening, Token::String(#delim), token.col));
+                State::InString(#delim)
+            });
+        match_arms.push(open_arm.build());
+
+        let class_open_arm = MatchArm::builder("[".to_string(), max_lookahead)
+            .ignore_escaped()
+            .input_state(quote! { State::InString(#delim) })
+            .body(quote! { State::InRegexClass(#delim) });
+        match_arms.push(class_open_arm.build());
+
+        let class_close_arm = MatchArm::builder("]".to_string(), max_lookahead)
+            .ignore_escaped()
+            .input_state(quote! { State::InRegexClass(#delim) })
+            .body(quote! { State::InString(#delim) });
+        match_arms.push(class_close_arm.build());
+
+        let close_arm = MatchArm::builder(delim.to_string(), max_lookahead)
+            .ignore_escaped()
+            .input_state(quote! { State::InString(#delim) })
+            .body(quote! {
+                matches.push(Match::new(Kind::Closing, Token::String(#delim), token.col));
+                State::Normal
+            });
+        match_arms.push(close_arm.build());
+    }
+
     // 5. String patterns
     for delim in &def.strings {
         // Opening string
@@ -164,6 +254,32 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
         match_arms.push(close_arm.build());
     }
 
+    // Strings which escape the delimiter by doubling it (`'it''s'`), rather than a backslash
+    for delim in &def.doubled_strings {
+        let open_arm = MatchArm::builder(delim.to_string(), max_lookahead).body(quote! {
+            matches.push(Match::new(Kind::Opening, Token::String(#delim), token.col));
+            State::InString(#delim)
+        });
+        match_arms.push(open_arm.build());
+
+        // Doubled delimiter, stay in the string
+        let escape_arm = MatchArm::builder(delim.repeat(2), max_lookahead)
+            .input_state(quote! { State::InString(#delim) })
+            .body(quote! {
+                tokens.next();
+                state
+            });
+        match_arms.push(escape_arm.build());
+
+        let close_arm = MatchArm::builder(delim.to_string(), max_lookahead)
+            .input_state(quote! { State::InString(#delim) })
+            .body(quote! {
+                matches.push(Match::new(Kind::Closing, Token::String(#delim), token.col));
+                State::Normal
+            });
+        match_arms.push(close_arm.build());
+    }
+
     // 6. Character literal patterns
     for delim in &def.chars {
         // TODO: handle escaped
@@ -192,6 +308,22 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
         match_arms.push(arm.build());
     }
 
+    // Prefixes which make the next character a literal, i.e. `0'(` in Prolog
+    for prefix in &def.char_prefixes {
+        let char_distance = prefix.len();
+        let next_token = quote::format_ident!("token_{}_distance", prefix.len());
+        let arm = MatchArm::builder(prefix.to_string(), max_lookahead).body(quote! {
+            for _ in 1..#prefix.len() {
+                tokens.next();
+            }
+            if #next_token == #char_distance {
+                tokens.next();
+            }
+            state
+        });
+        match_arms.push(arm.build());
+    }
+
     // 7. Inline span patterns
     for (name, (open, close)) in &def.inline_spans {
         let arm = MatchArm::builder(open.to_string(), max_lookahead).body(quote! {
@@ -222,6 +354,10 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
         // Opening delimiter
         let open_arm = MatchArm::builder(open.to_string(), max_lookahead).body(quote! {
             matches.push(Match::new(Kind::Opening, Token::Delimiter(#open, #close), token.col));
+            // Skip tokens based on length of pattern
+            for _ in 1..#open.len() {
+                tokens.next();
+            }
             State::Normal
         });
         match_arms.push(open_arm.build());
@@ -229,11 +365,40 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
         // Closing delimiter
         let close_arm = MatchArm::builder(close.to_string(), max_lookahead).body(quote! {
             matches.push(Match::new(Kind::Closing, Token::Delimiter(#open, #close), token.col));
+            // Skip tokens based on length of pattern
+            for _ in 1..#close.len() {
+                tokens.next();
+            }
             State::Normal
         });
         match_arms.push(close_arm.build());
     }
 
+    // 9. Delimiters inside strings and comments, which are kept apart from the matches so
+    // that they don't affect the stack heights of the code around them
+    let inner_state = quote! {
+        State::InString(_) | State::InBlockString(_) | State::InLineComment | State::InBlockComment(_)
+    };
+    for (open, close) in &def.delimiters {
+        for (kind, pattern) in [
+            (quote! { Kind::Opening }, open),
+            (quote! { Kind::Closing }, close),
+        ] {
+            let arm = MatchArm::builder(pattern.to_string(), max_lookahead)
+                .ignore_escaped()
+                .input_state(inner_state.clone())
+                .body(quote! {
+                    inner_matches.push(Match::new(#kind, Token::Delimiter(#open, #close), token.col));
+                    // Skip tokens based on length of pattern
+                    for _ in 1..#pattern.len() {
+                        tokens.next();
+                    }
+                    state
+                });
+            match_arms.push(arm.build());
+        }
+    }
+
     // Add fallback pattern
     let fallback_arm = quote! { _ => state };
     match_arms.push(fallback_arm);
@@ -247,6 +412,41 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
     };
 
     let name = &def.name;
+    let keywords = def
+        .keywords
+        .as_ref()
+        .map(|keywords| quote! { const KEYWORDS: &[Keyword] = #keywords; });
+    let auto_pair_exclusions = def.auto_pair_exclusions.as_ref().map(|exclusions| {
+        quote! { const AUTO_PAIR_EXCLUSIONS: &[AutoPairExclusion] = #exclusions; }
+    });
+
+    let pairs = |pairs: &[(String, String)]| {
+        let pairs = pairs.iter().map(|(open, close)| quote! { (#open, #close) });
+        quote! { &[#(#pairs),*] }
+    };
+    // Sorted by name since the spans are in hash maps
+    let spans = |spans: &HashMap<String, (String, String)>| {
+        let mut spans = spans.iter().collect::<Vec<_>>();
+        spans.sort();
+        let spans = spans
+            .into_iter()
+            .map(|(name, (open, close))| quote! { (#name, #open, #close) });
+        quote! { &[#(#spans),*] }
+    };
+    let delimiters = pairs(&def.delimiters);
+    let strings = def
+        .strings
+        .iter()
+        .chain(&def.doubled_strings)
+        .chain(&def.chars)
+        .chain(&def.regexes);
+    let regexes = &def.regexes;
+    let block_strings = pairs(&def.block_strings);
+    let docstrings = &def.docstrings;
+    let line_comments = def.line_comments.iter().chain(&def.line_start_comments);
+    let block_comments = pairs(&def.block_comments);
+    let inline_spans = spans(&def.inline_spans);
+    let block_spans = spans(&def.block_spans);
 
     // Generate the full implementation
     let expanded = quote! {
@@ -254,11 +454,25 @@ pub fn define_matcher(input: TokenStream) -> TokenStream {
 
         impl Matcher for #name {
             const TOKENS: &[u8] = &[#(#token_literals),*];
+            #keywords
+            #auto_pair_exclusions
+            const TOKEN_SETS: TokenSets = TokenSets {
+                delimiters: #delimiters,
+                strings: &[#(#strings),*],
+                block_strings: #block_strings,
+                regexes: &[#(#regexes),*],
+                docstrings: &[#(#docstrings),*],
+                line_comments: &[#(#line_comments),*],
+                block_comments: #block_comments,
+                inline_spans: #inline_spans,
+                block_spans: #block_spans,
+            };
 
             fn call<I>(
                 &mut self,
-                matches_by_line: &mut Vec<Vec<Match>>,
-                matches: &mut Vec<Match>,
+                matches: &mut ::alloc::vec::Vec<Match>,
+                inner_matches: &mut ::alloc::vec::Vec<Match>,
+                line: &[u8],
                 tokens: &mut MultiPeek<I>,
                 state: State,
                 token: CharPos,
@@ -26,12 +26,30 @@ pub fn calculate_max_lookahead(def: &MatcherDef) -> usize {
         max_len = max_len.max(s.len());
     }
 
+    for (_, open, _) in &def.interpolations {
+        max_len = max_len.max(open.len());
+    }
+
+    for comment in def.not_comments.iter().chain(&def.line_start_comments) {
+        max_len = max_len.max(comment.len());
+    }
+
+    for s in &def.doubled_strings {
+        // Need to check for the second `'` in `''`
+        max_len = max_len.max(s.len() * 2);
+    }
+
     for s in &def.chars {
         // Always need to lookahead 2 extra bytes ahead for single-char strings
         // So we can check for the second `'` in `'{'`
         max_len = max_len.max(s.len() + 2);
     }
 
+    for s in &def.char_prefixes {
+        // Need to check for the character after the prefix
+        max_len = max_len.max(s.len() + 1);
+    }
+
     for (open, close) in &def.block_strings {
         max_len = max_len.max(open.len());
         max_len = max_len.max(close.len());
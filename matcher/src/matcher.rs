@@ -128,6 +128,9 @@ impl MatchArm {
                 condition.extend(Self::adjacent_if_condition(&self.pattern));
             }
             if let Some(if_condition) = self._if_condition {
+                if self.adjacent {
+                    condition.extend(quote! { && });
+                }
                 condition.extend(if_condition);
             }
         }
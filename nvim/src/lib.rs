@@ -0,0 +1,1217 @@
+use mlua::{prelude::*, BString};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, MutexGuard};
+
+use blink_pairs::buffer::{
+    Adjacency, Checkpoint, ClosingAnnotation, DepthLimit, DepthOverflow, DepthProfile, Diagnostic,
+    Direction, JoinAdjustment, NestingRegion, PairStats, ParsedBuffer, PasteAnalysis,
+    PendingClosing, SourceRange, SpanKind, Surrounding, TokenRange,
+};
+use blink_pairs::cache::ParseCache;
+use blink_pairs::doc_code::doc_code_matches;
+use blink_pairs::metrics::Metrics;
+use blink_pairs::parser::indent::{self, DetectedIndent};
+use blink_pairs::parser::matcher::TokenType;
+use blink_pairs::parser::{
+    filetype_token_sets, simd_lanes, unsupported_simd_features, Backend, ExclusionContext,
+    TokenSets,
+};
+use blink_pairs::parser::{Match, MatchWithLine};
+use blink_pairs::query::Query;
+
+static PARSED_BUFFERS: LazyLock<Mutex<HashMap<usize, ParsedBuffer>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static CHECKPOINTS: LazyLock<Mutex<HashMap<usize, Checkpoint>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Auto-pair exclusions registered at runtime, as the opening and context by filetype
+type ExclusionsByFiletype = HashMap<String, Vec<(String, ExclusionContext)>>;
+static AUTO_PAIR_EXCLUSIONS: LazyLock<Mutex<ExclusionsByFiletype>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static METRICS: LazyLock<Mutex<HashMap<usize, BufferMetrics>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Metrics of the last parse and the sum of all parses for a buffer
+#[derive(Debug, Clone, Copy, Default)]
+struct BufferMetrics {
+    last: Metrics,
+    total: Metrics,
+    parses: usize,
+}
+
+impl BufferMetrics {
+    fn record(&mut self, metrics: Metrics) {
+        self.last = metrics;
+        self.total += metrics;
+        self.parses += 1;
+    }
+}
+
+impl IntoLua for BufferMetrics {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("last", self.last)?;
+        table.set("total", self.total)?;
+        table.set("parses", self.parses)?;
+        (&table).into_lua(lua)
+    }
+}
+
+fn lock_or_reset<T: Default>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            // Reset the mutex
+            mutex.clear_poison();
+            let mut value = mutex.lock().unwrap();
+            *value = T::default();
+            value
+        }
+    }
+}
+
+fn get_parsed_buffers<'a>() -> MutexGuard<'a, HashMap<usize, ParsedBuffer>> {
+    lock_or_reset(&PARSED_BUFFERS)
+}
+
+fn get_checkpoints<'a>() -> MutexGuard<'a, HashMap<usize, Checkpoint>> {
+    lock_or_reset(&CHECKPOINTS)
+}
+
+fn get_metrics<'a>() -> MutexGuard<'a, HashMap<usize, BufferMetrics>> {
+    lock_or_reset(&METRICS)
+}
+
+#[allow(clippy::type_complexity)]
+fn parse_buffer(
+    _lua: &Lua,
+    (bufnr, tab_width, filetype, lines, start_line, old_end_line, new_end_line): (
+        usize,
+        u8,
+        String,
+        Vec<BString>,
+        Option<usize>,
+        Option<usize>,
+        Option<usize>,
+    ),
+) -> LuaResult<bool> {
+    let mut parsed_buffers = get_parsed_buffers();
+
+    // Incremental parse
+    if let Some(parsed_buffer) = parsed_buffers.get_mut(&bufnr) {
+        let (reparsed, metrics) = parsed_buffer.reparse_range_bytes_with_metrics(
+            &filetype,
+            tab_width,
+            &lines,
+            start_line,
+            old_end_line,
+            new_end_line,
+        );
+        if reparsed {
+            get_metrics().entry(bufnr).or_default().record(metrics);
+        }
+        Ok(reparsed)
+    }
+    // Full parse
+    else if let Some((parsed_buffer, metrics)) =
+        ParsedBuffer::parse_bytes_with_metrics(&filetype, tab_width, &lines)
+    {
+        parsed_buffers.insert(bufnr, parsed_buffer);
+        get_metrics().entry(bufnr).or_default().record(metrics);
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Runs the initial parse of the buffers in parallel, i.e. when restoring a session, where
+/// each buffer is a table of `bufnr`, `tab_width`, `filetype` and `lines`. Returns the buffers
+/// which were parsed
+fn parse_buffers(_lua: &Lua, buffers: Vec<LuaTable>) -> LuaResult<Vec<usize>> {
+    let buffers = buffers
+        .into_iter()
+        .map(|buffer| {
+            Ok((
+                buffer.get::<usize>("bufnr")?,
+                buffer.get::<u8>("tab_width")?,
+                buffer.get::<String>("filetype")?,
+                buffer.get::<Vec<BString>>("lines")?,
+            ))
+        })
+        .collect::<LuaResult<Vec<_>>>()?;
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let parsed = ParsedBuffer::parse_many(
+        &buffers
+            .iter()
+            .map(|(_, tab_width, filetype, lines)| {
+                (filetype.as_str(), *tab_width, lines.as_slice())
+            })
+            .collect::<Vec<_>>(),
+        threads,
+    );
+
+    let mut parsed_buffers = get_parsed_buffers();
+    let mut metrics_by_bufnr = get_metrics();
+    Ok(buffers
+        .iter()
+        .zip(parsed)
+        .filter_map(|((bufnr, ..), parsed)| {
+            let (parsed_buffer, metrics) = parsed?;
+            parsed_buffers.insert(*bufnr, parsed_buffer);
+            metrics_by_bufnr.entry(*bufnr).or_default().record(metrics);
+            Some(*bufnr)
+        })
+        .collect())
+}
+
+/// Restores the buffer from the cache when it was parsed from the same lines before, in which
+/// case it should still be reparsed later to verify it
+fn load_cached_buffer(
+    _lua: &Lua,
+    (bufnr, cache_dir, path, filetype, tab_width, lines): (
+        usize,
+        String,
+        String,
+        String,
+        u8,
+        Vec<BString>,
+    ),
+) -> LuaResult<bool> {
+    let cache = ParseCache::new(cache_dir);
+    let Some(parsed_buffer) = cache.load(path.as_ref(), &filetype, tab_width, &lines) else {
+        return Ok(false);
+    };
+    get_parsed_buffers().insert(bufnr, parsed_buffer);
+    Ok(true)
+}
+
+fn store_cached_buffer(
+    _lua: &Lua,
+    (bufnr, cache_dir, path, filetype, tab_width, lines): (
+        usize,
+        String,
+        String,
+        String,
+        u8,
+        Vec<BString>,
+    ),
+) -> LuaResult<bool> {
+    let parsed_buffers = get_parsed_buffers();
+    let Some(parsed_buffer) = parsed_buffers.get(&bufnr) else {
+        return Ok(false);
+    };
+    ParseCache::new(cache_dir)
+        .store(path.as_ref(), &filetype, tab_width, &lines, parsed_buffer)
+        .map_err(LuaError::external)?;
+    Ok(true)
+}
+
+fn get_buffer_metrics(_lua: &Lua, bufnr: usize) -> LuaResult<Option<BufferMetrics>> {
+    Ok(get_metrics().get(&bufnr).copied())
+}
+
+fn checkpoint_buffer(_lua: &Lua, bufnr: usize) -> LuaResult<bool> {
+    let Some(checkpoint) = get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.checkpoint())
+    else {
+        return Ok(false);
+    };
+    get_checkpoints().insert(bufnr, checkpoint);
+    Ok(true)
+}
+
+fn restore_buffer(_lua: &Lua, bufnr: usize) -> LuaResult<bool> {
+    let Some(checkpoint) = get_checkpoints().remove(&bufnr) else {
+        return Ok(false);
+    };
+    let mut parsed_buffers = get_parsed_buffers();
+    let Some(parsed_buffer) = parsed_buffers.get_mut(&bufnr) else {
+        return Ok(false);
+    };
+    parsed_buffer.restore(checkpoint);
+    Ok(true)
+}
+
+fn get_token_sets(_lua: &Lua, filetype: String) -> LuaResult<Option<TokenSets>> {
+    Ok(filetype_token_sets(&filetype))
+}
+
+fn get_line_matches(
+    _lua: &Lua,
+    (bufnr, line_number, token_type): (usize, usize, Option<u8>),
+) -> LuaResult<Vec<Match>> {
+    let parsed_buffers = get_parsed_buffers();
+    let token_type = token_type
+        // TODO: don't ignore the error
+        .and_then(|token_type| token_type.try_into().ok())
+        .unwrap_or(TokenType::Delimiter);
+
+    if let Some(parsed_buffer) = parsed_buffers.get(&bufnr) {
+        if let Some(line_matches) = parsed_buffer.line_matches_ref(line_number) {
+            return Ok(line_matches
+                .iter()
+                .filter(|m| token_type.matches(&m.token))
+                .cloned()
+                .collect());
+        }
+    }
+
+    Ok(Vec::new())
+}
+
+fn get_line_matches_in(
+    _lua: &Lua,
+    (bufnr, line_number, start_col, end_col): (usize, usize, usize, usize),
+) -> LuaResult<Vec<Match>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| {
+            parsed_buffer
+                .matches_in(line_number, start_col..end_col)
+                .to_vec()
+        })
+        .unwrap_or_default())
+}
+
+fn get_highlights(
+    _lua: &Lua,
+    (bufnr, start_line, end_line, num_colors): (usize, usize, usize, usize),
+) -> LuaResult<Vec<usize>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.highlights(start_line..end_line, num_colors))
+        .unwrap_or_default())
+}
+
+fn get_inner_matches(
+    _lua: &Lua,
+    (bufnr, filetype, lines, start_line): (usize, String, Vec<BString>, usize),
+) -> LuaResult<Vec<MatchWithLine>> {
+    let inner_matches = get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.inner_matches(&filetype, &lines, start_line));
+    Ok(inner_matches
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .flat_map(|(line, matches)| {
+            matches
+                .into_iter()
+                .map(move |match_| match_.with_line(start_line + line))
+        })
+        .collect())
+}
+
+fn get_doc_code_matches(
+    _lua: &Lua,
+    (filetype, tab_width, lines): (String, u8, Vec<BString>),
+) -> LuaResult<Vec<MatchWithLine>> {
+    Ok(doc_code_matches(&filetype, tab_width, &lines)
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .flat_map(|(line, matches)| {
+            matches
+                .into_iter()
+                .map(move |match_| match_.with_line(line))
+        })
+        .collect())
+}
+
+fn query_matches(
+    _lua: &Lua,
+    (bufnr, query, filetype, lines): (usize, Query, Option<String>, Option<Vec<BString>>),
+) -> LuaResult<Vec<MatchWithLine>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| match (filetype, lines) {
+            (Some(filetype), Some(lines)) => {
+                parsed_buffer.query_with_inner(&filetype, &lines, &query)
+            }
+            _ => Some(parsed_buffer.query(&query).collect()),
+        })
+        .unwrap_or_default())
+}
+
+fn is_in_comment(_lua: &Lua, (bufnr, row, col): (usize, usize, usize)) -> LuaResult<bool> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .is_some_and(|parsed_buffer| parsed_buffer.is_in_comment(row, col)))
+}
+
+fn is_in_string(_lua: &Lua, (bufnr, row, col): (usize, usize, usize)) -> LuaResult<bool> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .is_some_and(|parsed_buffer| parsed_buffer.is_in_string(row, col)))
+}
+
+fn get_span_at(_lua: &Lua, (bufnr, row, col): (usize, usize, usize)) -> LuaResult<Option<String>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.span_at(row, col)))
+}
+
+fn get_span_kind_at(
+    lua: &Lua,
+    (bufnr, filetype, row, col): (usize, String, usize, usize),
+) -> LuaResult<Option<SpanKind>> {
+    let mut error = None;
+    let kind = get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
+        parsed_buffer.span_kind_at(&filetype, row, col, |line| {
+            buf_line(lua, bufnr, line).unwrap_or_else(|err| {
+                error = Some(err);
+                None
+            })
+        })
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(kind),
+    }
+}
+
+fn get_match_at(
+    lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<LuaTable>> {
+    let Some((match_, offset)) = get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.match_at_with_offset(row, col))
+    else {
+        return Ok(None);
+    };
+    let table = LuaTable::from_lua(match_.into_lua(lua)?, lua)?;
+    table.set("offset", offset)?;
+    Ok(Some(table))
+}
+
+fn get_match_pair(
+    _lua: &Lua,
+    (bufnr, row, col, adjacency): (usize, usize, usize, Option<Adjacency>),
+) -> LuaResult<Option<Vec<MatchWithLine>>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| {
+            parsed_buffer.match_pair_adjacent(row, col, adjacency.unwrap_or_default())
+        })
+        .map(|(open, close)| vec![open, close]))
+}
+
+fn get_span_pair(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<Vec<MatchWithLine>>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.span_pair(row, col))
+        .map(|(open, close)| vec![open, close]))
+}
+
+fn get_pairs_intersecting_point(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<Vec<MatchWithLine>>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.pairs_intersecting_point(row, col))
+        .map(|(open, close)| vec![open, close]))
+}
+
+fn get_enclosing_pair(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<Vec<MatchWithLine>>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.enclosing_pair(row, col))
+        .map(|(open, close)| vec![open, close]))
+}
+
+fn analyze_paste(
+    _lua: &Lua,
+    (bufnr, filetype, row, col, lines): (usize, String, usize, usize, Vec<BString>),
+) -> LuaResult<Option<PasteAnalysis>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.analyze_paste(&filetype, row, col, &lines)))
+}
+
+fn add_auto_pair_exclusion(
+    _lua: &Lua,
+    (filetype, opening, context): (String, String, String),
+) -> LuaResult<()> {
+    let context = ExclusionContext::try_from(context.as_str())
+        .map_err(|_| LuaError::runtime(format!("unknown auto-pair exclusion: {context}")))?;
+    lock_or_reset(&AUTO_PAIR_EXCLUSIONS)
+        .entry(filetype)
+        .or_default()
+        .push((opening, context));
+    Ok(())
+}
+
+fn is_auto_pair_excluded(
+    lua: &Lua,
+    (bufnr, filetype, opening, row, col): (usize, String, String, usize, usize),
+) -> LuaResult<bool> {
+    let Some(line) = buf_line(lua, bufnr, row)? else {
+        return Ok(false);
+    };
+    let extra = lock_or_reset(&AUTO_PAIR_EXCLUSIONS)
+        .get(&filetype)
+        .map(|exclusions| {
+            exclusions
+                .iter()
+                .filter(|(excluded, _)| *excluded == opening)
+                .map(|(_, context)| *context)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .is_some_and(|parsed_buffer| {
+            parsed_buffer.is_auto_pair_excluded(&filetype, &opening, row, &line, col, &extra)
+        }))
+}
+
+fn is_safe_insertion_point(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<bool>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.is_safe_insertion_point(row, col)))
+}
+
+fn get_pairs_in_range(
+    _lua: &Lua,
+    (bufnr, start_line, end_line): (usize, usize, usize),
+) -> LuaResult<Vec<Vec<MatchWithLine>>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| {
+            parsed_buffer
+                .pairs_in_range(start_line..end_line)
+                .into_iter()
+                .map(|(open, close)| vec![open, close])
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+fn get_fold_ranges(lua: &Lua, (bufnr, min_lines): (usize, usize)) -> LuaResult<Vec<LuaTable>> {
+    let ranges = get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.fold_ranges(min_lines))
+        .unwrap_or_default();
+    ranges
+        .into_iter()
+        .map(|(start_line, end_line, level)| {
+            let table = lua.create_table()?;
+            table.set("start_line", start_line)?;
+            table.set("end_line", end_line)?;
+            table.set("level", level)?;
+            Ok(table)
+        })
+        .collect()
+}
+
+fn get_join_adjustment(
+    _lua: &Lua,
+    (bufnr, row, line, next_line): (usize, usize, BString, BString),
+) -> LuaResult<Option<JoinAdjustment>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.join_adjustment(row, &line, &next_line)))
+}
+
+fn get_sibling_block(
+    _lua: &Lua,
+    (bufnr, row, col, direction): (usize, usize, usize, Direction),
+) -> LuaResult<Option<Vec<MatchWithLine>>> {
+    Ok(get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
+        parsed_buffer
+            .sibling_block(row, col, direction)
+            .map(|(open, close)| vec![open, close])
+    }))
+}
+
+fn get_textobject_at(
+    _lua: &Lua,
+    (bufnr, row, col, around): (usize, usize, usize, bool),
+) -> LuaResult<Option<SourceRange>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.textobject_at(row, col, around)))
+}
+
+fn get_expanded_selection(
+    _lua: &Lua,
+    (bufnr, start_line, start_col, end_line, end_col): (usize, usize, usize, usize, usize),
+) -> LuaResult<Option<SourceRange>> {
+    Ok(get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
+        parsed_buffer.expand_selection(SourceRange {
+            start_line,
+            start_col,
+            end_line,
+            end_col,
+        })
+    }))
+}
+
+fn get_shrunk_selection(
+    _lua: &Lua,
+    (bufnr, start_line, start_col, end_line, end_col, cursor_line, cursor_col): (
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+    ),
+) -> LuaResult<Option<SourceRange>> {
+    Ok(get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
+        parsed_buffer.shrink_selection(
+            SourceRange {
+                start_line,
+                start_col,
+                end_line,
+                end_col,
+            },
+            (cursor_line, cursor_col),
+        )
+    }))
+}
+
+fn get_span_range_at(
+    lua: &Lua,
+    (bufnr, row, col, around): (usize, usize, usize, bool),
+) -> LuaResult<Option<SourceRange>> {
+    let Some(line) = buf_line(lua, bufnr, row)? else {
+        return Ok(None);
+    };
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.span_range_at(row, col, line.len(), around)))
+}
+
+fn get_surroundings_at(
+    lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Vec<Surrounding>> {
+    let Some(line) = buf_line(lua, bufnr, row)? else {
+        return Ok(vec![]);
+    };
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.surroundings_at(row, col, line.len()))
+        .unwrap_or_default())
+}
+
+fn get_top_level_blocks(_lua: &Lua, bufnr: usize) -> LuaResult<Vec<SourceRange>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.top_level_blocks())
+        .unwrap_or_default())
+}
+
+fn get_structure_hash(_lua: &Lua, bufnr: usize) -> LuaResult<Option<i64>> {
+    // Reinterpreted as an integer since Lua numbers can't represent every u64
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.structure_hash() as i64))
+}
+
+fn get_enclosing_pairs(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Vec<Vec<MatchWithLine>>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| {
+            parsed_buffer
+                .enclosing_pairs(row, col)
+                .map(|(open, close)| vec![open, close])
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+fn get_pairs_containing(
+    _lua: &Lua,
+    (bufnr, start_line, start_col, end_line, end_col): (usize, usize, usize, usize, usize),
+) -> LuaResult<Vec<Vec<MatchWithLine>>> {
+    let range = SourceRange {
+        start_line,
+        start_col,
+        end_line,
+        end_col,
+    };
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| {
+            parsed_buffer
+                .pairs_containing(range)
+                .map(|(open, close)| vec![open, close])
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+fn get_pending_closings(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Vec<PendingClosing>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.pending_closings(row, col))
+        .unwrap_or_default())
+}
+
+fn get_cycle_pair(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<MatchWithLine>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.cycle_pair(row, col)))
+}
+
+fn get_construct_ranges(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<Vec<TokenRange>>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.construct_ranges(row, col)))
+}
+
+/// Reads a line of the buffer through the Neovim API
+fn buf_line(lua: &Lua, bufnr: usize, line: usize) -> LuaResult<Option<BString>> {
+    let get_lines: LuaFunction = lua
+        .globals()
+        .get::<LuaTable>("vim")?
+        .get::<LuaTable>("api")?
+        .get("nvim_buf_get_lines")?;
+    let lines: Vec<BString> = get_lines.call((bufnr, line, line + 1, false))?;
+    Ok(lines.into_iter().next())
+}
+
+fn get_suggested_indent(
+    lua: &Lua,
+    (bufnr, row, tab_width): (usize, usize, u8),
+) -> LuaResult<Option<usize>> {
+    let mut error = None;
+    let indent = get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
+        parsed_buffer.suggested_indent(row, tab_width, |line| {
+            buf_line(lua, bufnr, line).unwrap_or_else(|err| {
+                error = Some(err);
+                None
+            })
+        })
+    });
+    if let Some(err) = error {
+        return Err(err);
+    }
+    Ok(indent)
+}
+
+fn get_opening_context(
+    lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<LuaTable>> {
+    let mut error = None;
+    let context = get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
+        let closing = parsed_buffer.match_at(row, col)?.with_line(row);
+        parsed_buffer.opening_context(&closing, |line| {
+            buf_line(lua, bufnr, line).unwrap_or_else(|err| {
+                error = Some(err);
+                None
+            })
+        })
+    });
+    if let Some(err) = error {
+        return Err(err);
+    }
+
+    let Some((line, range)) = context else {
+        return Ok(None);
+    };
+    let table = lua.create_table()?;
+    table.set("line", line)?;
+    table.set("start_col", range.start)?;
+    table.set("end_col", range.end)?;
+    Ok(Some(table))
+}
+
+#[allow(clippy::type_complexity)]
+fn get_closing_annotations(
+    _lua: &Lua,
+    (bufnr, lines, start_line, end_line, min_lines, max_len): (
+        usize,
+        Vec<BString>,
+        usize,
+        usize,
+        usize,
+        usize,
+    ),
+) -> LuaResult<Vec<ClosingAnnotation>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| {
+            parsed_buffer.closing_annotations(&lines, start_line, end_line, min_lines, max_len)
+        })
+        .unwrap_or_default())
+}
+
+fn get_sortable_ranges(
+    _lua: &Lua,
+    (bufnr, lines, row, col): (usize, Vec<BString>, usize, usize),
+) -> LuaResult<Option<Vec<SourceRange>>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.sortable_ranges(&lines, row, col)))
+}
+
+fn get_line_depth_profile(
+    _lua: &Lua,
+    (bufnr, line): (usize, usize),
+) -> LuaResult<Option<DepthProfile>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.line_depth_profile(line)))
+}
+
+fn get_trailing_comma(
+    lua: &Lua,
+    (bufnr, lines, row, col): (usize, Vec<BString>, usize, usize),
+) -> LuaResult<Option<LuaTable>> {
+    let Some((line, col)) = get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.trailing_comma_position(&lines, row, col))
+    else {
+        return Ok(None);
+    };
+    let table = lua.create_table()?;
+    table.set("line", line)?;
+    table.set("col", col)?;
+    Ok(Some(table))
+}
+
+fn get_unmatched_opening_before(
+    _lua: &Lua,
+    (bufnr, opening, closing, row, col): (usize, String, String, usize, usize),
+) -> LuaResult<Option<MatchWithLine>> {
+    Ok(get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
+        parsed_buffer.unmatched_opening_before(&opening, &closing, row, col)
+    }))
+}
+
+fn get_unmatched_closing_after(
+    _lua: &Lua,
+    (bufnr, opening, closing, row, col): (usize, String, String, usize, usize),
+) -> LuaResult<Option<MatchWithLine>> {
+    Ok(get_parsed_buffers().get(&bufnr).and_then(|parsed_buffer| {
+        parsed_buffer.unmatched_closing_after(&opening, &closing, row, col)
+    }))
+}
+
+fn get_next_unmatched_after(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<MatchWithLine>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.next_unmatched_after(row, col)))
+}
+
+fn get_prev_unmatched_before(
+    _lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Option<MatchWithLine>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.prev_unmatched_before(row, col)))
+}
+
+fn get_next_opening(
+    _lua: &Lua,
+    (bufnr, opening, row, col): (usize, String, usize, usize),
+) -> LuaResult<Option<MatchWithLine>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.next_opening(&opening, row, col)))
+}
+
+fn get_prev_closing(
+    _lua: &Lua,
+    (bufnr, closing, row, col): (usize, String, usize, usize),
+) -> LuaResult<Option<MatchWithLine>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.prev_closing(&closing, row, col)))
+}
+
+fn get_indent_levels(
+    _lua: &Lua,
+    (bufnr, start_line, end_line): (usize, usize, usize),
+) -> LuaResult<Vec<u8>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.get_indent_levels(start_line, end_line))
+        .unwrap_or_default())
+}
+
+fn get_depth_by_line(
+    _lua: &Lua,
+    (bufnr, start_line, end_line): (usize, usize, usize),
+) -> LuaResult<Vec<usize>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| {
+            let depths = parsed_buffer.depth_by_line();
+            let end_line = end_line.min(depths.len());
+            depths[start_line.min(end_line)..end_line].to_vec()
+        })
+        .unwrap_or_default())
+}
+
+fn get_virtual_indent_levels(
+    _lua: &Lua,
+    (bufnr, start_line, end_line): (usize, usize, usize),
+) -> LuaResult<Vec<u8>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.get_virtual_indent_levels(start_line, end_line))
+        .unwrap_or_default())
+}
+
+fn get_indent_changes(lua: &Lua, bufnr: usize) -> LuaResult<Option<LuaTable>> {
+    let Some(changes) = get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.indent_changes())
+    else {
+        return Ok(None);
+    };
+    let table = lua.create_table()?;
+    table.set("start_line", changes.start)?;
+    table.set("end_line", changes.end)?;
+    Ok(Some(table))
+}
+
+fn get_stale_indent_range(
+    lua: &Lua,
+    (bufnr, lines, tab_width, row, col): (usize, Vec<BString>, u8, usize, usize),
+) -> LuaResult<Option<LuaTable>> {
+    let Some(range) = get_parsed_buffers()
+        .get(&bufnr)
+        .and_then(|parsed_buffer| parsed_buffer.stale_indent_range(&lines, tab_width, row, col))
+    else {
+        return Ok(None);
+    };
+    let table = lua.create_table()?;
+    table.set("start_line", range.start)?;
+    table.set("end_line", range.end)?;
+    Ok(Some(table))
+}
+
+fn get_out_of_scope_ranges(
+    lua: &Lua,
+    (bufnr, row, col): (usize, usize, usize),
+) -> LuaResult<Vec<LuaTable>> {
+    let ranges = get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.out_of_scope_ranges(row, col))
+        .unwrap_or_default();
+    ranges
+        .into_iter()
+        .map(|range| {
+            let table = lua.create_table()?;
+            table.set("start_line", range.start)?;
+            table.set("end_line", range.end)?;
+            Ok(table)
+        })
+        .collect()
+}
+
+/// Gets the unmatched delimiters of all the parsed buffers, optionally only those with the
+/// given opening or closing text, for filling the quickfix list
+fn find_unmatched_across_buffers(lua: &Lua, token: Option<String>) -> LuaResult<Vec<LuaTable>> {
+    let parsed_buffers = get_parsed_buffers();
+    let mut bufnrs = parsed_buffers.keys().copied().collect::<Vec<_>>();
+    bufnrs.sort();
+
+    let mut unmatched = vec![];
+    for bufnr in bufnrs {
+        for match_ in parsed_buffers[&bufnr].unmatched_delimiters() {
+            if token.as_deref().is_some_and(|token| {
+                token != match_.token.opening() && Some(token) != match_.token.closing()
+            }) {
+                continue;
+            }
+            let table = lua.create_table()?;
+            table.set("bufnr", bufnr)?;
+            table.set("line", match_.line)?;
+            table.set("col", match_.col)?;
+            table.set("len", match_.len())?;
+            table.set("text", match_.text())?;
+            unmatched.push(table);
+        }
+    }
+    Ok(unmatched)
+}
+
+fn get_diagnostics(_lua: &Lua, bufnr: usize) -> LuaResult<Vec<Diagnostic>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.diagnostics())
+        .unwrap_or_default())
+}
+
+fn get_stats(_lua: &Lua, bufnr: usize) -> LuaResult<Option<PairStats>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.stats()))
+}
+
+fn get_nesting_regions(_lua: &Lua, bufnr: usize) -> LuaResult<Vec<NestingRegion>> {
+    Ok(get_parsed_buffers()
+        .get(&bufnr)
+        .map(|parsed_buffer| parsed_buffer.nesting_regions())
+        .unwrap_or_default())
+}
+
+fn detect_indent(_lua: &Lua, lines: Vec<BString>) -> LuaResult<Option<DetectedIndent>> {
+    Ok(indent::detect_indent(&lines))
+}
+
+fn set_tokenizer_backend(_lua: &Lua, backend: String) -> LuaResult<()> {
+    let backend = Backend::try_from(backend.as_str())
+        .map_err(|_| LuaError::runtime(format!("unknown tokenizer backend: {backend}")))?;
+    Backend::set(backend);
+    Ok(())
+}
+
+fn get_tokenizer_health(lua: &Lua, _: ()) -> LuaResult<LuaTable> {
+    let table = lua.create_table()?;
+    table.set("backend", Backend::current().name())?;
+    table.set("simd_lanes", simd_lanes())?;
+    table.set("unsupported_simd_features", unsupported_simd_features())?;
+    Ok(table)
+}
+
+fn set_depth_limit(
+    _lua: &Lua,
+    (bufnr, tab_width, max_depth, overflow): (usize, u8, usize, String),
+) -> LuaResult<bool> {
+    let overflow = DepthOverflow::try_from(overflow.as_str())
+        .map_err(|_| LuaError::runtime(format!("unknown depth overflow policy: {overflow}")))?;
+    let mut parsed_buffers = get_parsed_buffers();
+    let Some(parsed_buffer) = parsed_buffers.get_mut(&bufnr) else {
+        return Ok(false);
+    };
+    parsed_buffer.set_depth_limit(
+        DepthLimit {
+            max_depth,
+            overflow,
+        },
+        tab_width,
+    );
+    Ok(true)
+}
+
+// NOTE: skip_memory_check greatly improves performance
+// https://github.com/mlua-rs/mlua/issues/318
+#[mlua::lua_module(skip_memory_check)]
+fn blink_pairs(lua: &Lua) -> LuaResult<LuaTable> {
+    let exports = lua.create_table()?;
+    exports.set("parse_buffer", lua.create_function(parse_buffer)?)?;
+    exports.set("parse_buffers", lua.create_function(parse_buffers)?)?;
+    exports.set(
+        "load_cached_buffer",
+        lua.create_function(load_cached_buffer)?,
+    )?;
+    exports.set(
+        "store_cached_buffer",
+        lua.create_function(store_cached_buffer)?,
+    )?;
+    exports.set(
+        "get_buffer_metrics",
+        lua.create_function(get_buffer_metrics)?,
+    )?;
+    exports.set("checkpoint_buffer", lua.create_function(checkpoint_buffer)?)?;
+    exports.set("restore_buffer", lua.create_function(restore_buffer)?)?;
+    exports.set("get_token_sets", lua.create_function(get_token_sets)?)?;
+    exports.set("get_line_matches", lua.create_function(get_line_matches)?)?;
+    exports.set(
+        "get_line_matches_in",
+        lua.create_function(get_line_matches_in)?,
+    )?;
+    exports.set("get_highlights", lua.create_function(get_highlights)?)?;
+    exports.set("get_inner_matches", lua.create_function(get_inner_matches)?)?;
+    exports.set(
+        "get_doc_code_matches",
+        lua.create_function(get_doc_code_matches)?,
+    )?;
+    exports.set("query_matches", lua.create_function(query_matches)?)?;
+    exports.set("get_span_at", lua.create_function(get_span_at)?)?;
+    exports.set("get_span_kind_at", lua.create_function(get_span_kind_at)?)?;
+    exports.set("is_in_comment", lua.create_function(is_in_comment)?)?;
+    exports.set("is_in_string", lua.create_function(is_in_string)?)?;
+    exports.set("get_match_at", lua.create_function(get_match_at)?)?;
+    exports.set("get_match_pair", lua.create_function(get_match_pair)?)?;
+    exports.set("get_span_pair", lua.create_function(get_span_pair)?)?;
+    exports.set(
+        "get_pairs_intersecting_point",
+        lua.create_function(get_pairs_intersecting_point)?,
+    )?;
+    exports.set(
+        "get_enclosing_pair",
+        lua.create_function(get_enclosing_pair)?,
+    )?;
+    exports.set("analyze_paste", lua.create_function(analyze_paste)?)?;
+    exports.set(
+        "add_auto_pair_exclusion",
+        lua.create_function(add_auto_pair_exclusion)?,
+    )?;
+    exports.set(
+        "is_auto_pair_excluded",
+        lua.create_function(is_auto_pair_excluded)?,
+    )?;
+    exports.set(
+        "is_safe_insertion_point",
+        lua.create_function(is_safe_insertion_point)?,
+    )?;
+    exports.set(
+        "get_pairs_in_range",
+        lua.create_function(get_pairs_in_range)?,
+    )?;
+    exports.set(
+        "get_enclosing_pairs",
+        lua.create_function(get_enclosing_pairs)?,
+    )?;
+    exports.set(
+        "get_pairs_containing",
+        lua.create_function(get_pairs_containing)?,
+    )?;
+    exports.set(
+        "get_pending_closings",
+        lua.create_function(get_pending_closings)?,
+    )?;
+    exports.set("get_cycle_pair", lua.create_function(get_cycle_pair)?)?;
+    exports.set(
+        "get_construct_ranges",
+        lua.create_function(get_construct_ranges)?,
+    )?;
+    exports.set(
+        "get_opening_context",
+        lua.create_function(get_opening_context)?,
+    )?;
+    exports.set(
+        "get_closing_annotations",
+        lua.create_function(get_closing_annotations)?,
+    )?;
+    exports.set(
+        "get_sortable_ranges",
+        lua.create_function(get_sortable_ranges)?,
+    )?;
+    exports.set(
+        "get_trailing_comma",
+        lua.create_function(get_trailing_comma)?,
+    )?;
+    exports.set(
+        "get_line_depth_profile",
+        lua.create_function(get_line_depth_profile)?,
+    )?;
+    exports.set(
+        "get_unmatched_opening_before",
+        lua.create_function(get_unmatched_opening_before)?,
+    )?;
+    exports.set(
+        "get_unmatched_closing_after",
+        lua.create_function(get_unmatched_closing_after)?,
+    )?;
+    exports.set(
+        "get_next_unmatched_after",
+        lua.create_function(get_next_unmatched_after)?,
+    )?;
+    exports.set(
+        "get_prev_unmatched_before",
+        lua.create_function(get_prev_unmatched_before)?,
+    )?;
+    exports.set(
+        "get_join_adjustment",
+        lua.create_function(get_join_adjustment)?,
+    )?;
+    exports.set("get_textobject_at", lua.create_function(get_textobject_at)?)?;
+    exports.set(
+        "get_expanded_selection",
+        lua.create_function(get_expanded_selection)?,
+    )?;
+    exports.set(
+        "get_shrunk_selection",
+        lua.create_function(get_shrunk_selection)?,
+    )?;
+    exports.set("get_span_range_at", lua.create_function(get_span_range_at)?)?;
+    exports.set(
+        "get_surroundings_at",
+        lua.create_function(get_surroundings_at)?,
+    )?;
+    exports.set(
+        "get_top_level_blocks",
+        lua.create_function(get_top_level_blocks)?,
+    )?;
+    exports.set(
+        "get_structure_hash",
+        lua.create_function(get_structure_hash)?,
+    )?;
+    exports.set("get_sibling_block", lua.create_function(get_sibling_block)?)?;
+    exports.set("get_fold_ranges", lua.create_function(get_fold_ranges)?)?;
+    exports.set("get_next_opening", lua.create_function(get_next_opening)?)?;
+    exports.set("get_prev_closing", lua.create_function(get_prev_closing)?)?;
+    exports.set("get_indent_levels", lua.create_function(get_indent_levels)?)?;
+    exports.set("get_depth_by_line", lua.create_function(get_depth_by_line)?)?;
+    exports.set(
+        "get_virtual_indent_levels",
+        lua.create_function(get_virtual_indent_levels)?,
+    )?;
+    exports.set(
+        "get_indent_changes",
+        lua.create_function(get_indent_changes)?,
+    )?;
+    exports.set(
+        "get_suggested_indent",
+        lua.create_function(get_suggested_indent)?,
+    )?;
+    exports.set(
+        "get_stale_indent_range",
+        lua.create_function(get_stale_indent_range)?,
+    )?;
+    exports.set(
+        "find_unmatched_across_buffers",
+        lua.create_function(find_unmatched_across_buffers)?,
+    )?;
+    exports.set("get_diagnostics", lua.create_function(get_diagnostics)?)?;
+    exports.set("get_stats", lua.create_function(get_stats)?)?;
+    exports.set(
+        "get_nesting_regions",
+        lua.create_function(get_nesting_regions)?,
+    )?;
+    exports.set(
+        "get_out_of_scope_ranges",
+        lua.create_function(get_out_of_scope_ranges)?,
+    )?;
+    exports.set("detect_indent", lua.create_function(detect_indent)?)?;
+    exports.set(
+        "set_tokenizer_backend",
+        lua.create_function(set_tokenizer_backend)?,
+    )?;
+    exports.set(
+        "get_tokenizer_health",
+        lua.create_function(get_tokenizer_health)?,
+    )?;
+    exports.set("set_depth_limit", lua.create_function(set_depth_limit)?)?;
+    Ok(exports)
+}
@@ -1,702 +1,5449 @@
-use crate::parser::{parse_filetype, Kind, Match, MatchWithLine, State, Token};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+use core::ops::Range;
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+#[cfg(feature = "lua")]
+use mlua::{FromLua, IntoLua};
+
+use crate::column::byte_to_display_col;
+use crate::hash::FxHasher;
+use crate::history::{History, Replaced};
+#[cfg(feature = "std")]
+use crate::metrics::Metrics;
+use crate::parser::indent::{blank_indent, indent_levels_after};
+use crate::parser::{
+    filetype_auto_pair_exclusions, filetype_token_sets, parse_filetype, parse_filetype_inner,
+    parse_filetype_iter, ExclusionContext, Kind, Match, MatchWithLine, State, Token,
+};
+#[cfg(feature = "std")]
+use crate::parser::{filetype_tokens, indent::indent_levels};
+
+/// With the `serde` feature, serializes everything but the history of recent reparses, so that
+/// deserialized buffers still reparse incrementally. Deserializing fails for buffers whose
+/// lines disagree, see [`SerializedBuffer`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SerializedBuffer"))]
 pub struct ParsedBuffer {
+    /// Matches of each line, always sorted by column
     pub matches_by_line: Vec<Vec<Match>>,
     pub state_by_line: Vec<State>,
     pub indent_levels: Vec<u8>,
+    /// Width of the whitespace of each line which is entirely whitespace, in which case its
+    /// indent level is inherited from the previous line
+    pub(crate) blank_indents: Vec<Option<u8>>,
+    /// Lines whose indent levels changed in the last parse, see
+    /// [`ParsedBuffer::indent_changes`]
+    pub(crate) indent_changes: Range<usize>,
+    /// Hash of each line with the state at its start, see [`line_hashes`]
+    pub(crate) line_hashes: Vec<LineHash>,
+    /// Stack height at the start of each line, for [`ParsedBuffer::stack_height_at`] without
+    /// scanning the matches of other lines
+    pub(crate) depth_at_line_start: Vec<usize>,
+    /// Cap on the nesting depth used when calculating the stack heights, see
+    /// [`ParsedBuffer::set_depth_limit`]
+    pub(crate) depth_limit: DepthLimit,
+    /// Lines replaced by recent reparses, for restoring them on undo
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) history: History,
 }
 
-impl ParsedBuffer {
-    pub fn parse(filetype: &str, tab_width: u8, lines: &[&str]) -> Option<Self> {
-        let mut parsed = parse_filetype(filetype, tab_width, lines, State::Normal)?;
-        parsed.calculate_stack_heights(tab_width);
-        Some(parsed)
-    }
+/// Same as [`ParsedBuffer`] without the history, checked before converting into one like the
+/// [`crate::cache`] entries
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "ParsedBuffer")]
+struct SerializedBuffer {
+    matches_by_line: Vec<Vec<Match>>,
+    state_by_line: Vec<State>,
+    indent_levels: Vec<u8>,
+    blank_indents: Vec<Option<u8>>,
+    indent_changes: Range<usize>,
+    line_hashes: Vec<LineHash>,
+    depth_at_line_start: Vec<usize>,
+    depth_limit: DepthLimit,
+}
 
-    pub fn reparse_range(
-        &mut self,
-        filetype: &str,
-        tab_width: u8,
-        lines: &[&str],
-        start_line: Option<usize>,
-        old_end_line: Option<usize>,
-        new_end_line: Option<usize>,
-    ) -> bool {
-        let max_line = self.matches_by_line.len();
-        let start_line = start_line.unwrap_or(0).min(max_line);
-        let old_end_line = old_end_line.unwrap_or(max_line).min(max_line);
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedBuffer> for ParsedBuffer {
+    type Error = &'static str;
 
-        let initial_state = if start_line > 0 {
-            self.state_by_line
-                .get(start_line - 1)
-                .cloned()
-                .unwrap_or(State::Normal)
-        } else {
-            State::Normal
+    fn try_from(buffer: SerializedBuffer) -> Result<Self, Self::Error> {
+        let line_count = buffer.matches_by_line.len();
+        if buffer.state_by_line.len() != line_count
+            || buffer.indent_levels.len() != line_count
+            || buffer.blank_indents.len() != line_count
+            || buffer.line_hashes.len() != line_count
+            || buffer.depth_at_line_start.len() != line_count
+        {
+            return Err("lines of the buffer have different lengths");
+        }
+        if buffer.indent_changes.start > buffer.indent_changes.end
+            || buffer.indent_changes.end > line_count
+        {
+            return Err("indent changes out of the buffer");
+        }
+        if !buffer
+            .matches_by_line
+            .iter()
+            .all(|matches| matches.is_sorted_by_key(|match_| match_.col))
+        {
+            return Err("matches not sorted by column");
+        }
+        // Matches never sit deeper than the stack, and closings and middles need an opening.
+        // Pairs clamped to the depth limit share a stack height, so it's only an upper bound
+        let mut depth = 0;
+        for match_ in buffer.matches_by_line.iter().flatten() {
+            let within_stack = match (match_.stack_height, match_.kind) {
+                (Some(stack_height), Kind::Opening) => stack_height <= depth,
+                (Some(stack_height), _) => depth > 0 && stack_height <= depth,
+                (None, _) => true,
+            };
+            if !within_stack {
+                return Err("stack heights of the matches are out of the stack");
+            }
+            depth = depth_after(depth, match_);
+        }
+
+        let mut parsed = ParsedBuffer {
+            matches_by_line: buffer.matches_by_line,
+            state_by_line: buffer.state_by_line,
+            indent_levels: buffer.indent_levels,
+            blank_indents: buffer.blank_indents,
+            indent_changes: buffer.indent_changes,
+            line_hashes: buffer.line_hashes,
+            depth_at_line_start: vec![],
+            depth_limit: buffer.depth_limit,
+            history: History::default(),
         };
+        parsed.calculate_line_depths();
+        if parsed.depth_at_line_start != buffer.depth_at_line_start {
+            return Err("stack heights at the start of the lines disagree with the matches");
+        }
+        Ok(parsed)
+    }
+}
 
-        if let Some(new) = parse_filetype(filetype, tab_width, lines, initial_state) {
-            let new_end_line = new_end_line.unwrap_or(start_line + new.matches_by_line.len());
-            let length = new_end_line - start_line;
+/// What happens to the pairs nested deeper than [`DepthLimit::max_depth`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DepthOverflow {
+    /// The openings and closings get a stack height of `max_depth`, so overflowing openings
+    /// are never marked as unmatched
+    Clamp,
+    /// The openings and closings are marked as unmatched, without a stack height
+    Unmatched,
+}
 
-            self.matches_by_line.splice(
-                start_line..old_end_line,
-                new.matches_by_line[0..length].to_vec(),
-            );
-            self.state_by_line.splice(
-                start_line..old_end_line,
-                new.state_by_line[0..length].to_vec(),
-            );
-            self.indent_levels.splice(
-                start_line..old_end_line.min(self.indent_levels.len()),
-                new.indent_levels[0..length].to_vec(),
-            );
+/// Cap on the number of pairs tracked at once when calculating the stack heights, which keeps
+/// pathological inputs such as thousands of `(` from growing the stack without bound. Set per
+/// buffer via [`ParsedBuffer::set_depth_limit`].
+///
+/// Delimiters past the limit are only counted, so each closing past the limit closes the last
+/// opening past the limit regardless of its token. Strings and comments past the limit are
+/// matched as usual
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DepthLimit {
+    pub max_depth: usize,
+    pub overflow: DepthOverflow,
+}
+
+impl Default for DepthLimit {
+    fn default() -> Self {
+        DepthLimit::DEFAULT
+    }
+}
 
-            self.calculate_stack_heights(tab_width);
+impl DepthLimit {
+    pub const DEFAULT: DepthLimit = DepthLimit {
+        max_depth: 256,
+        overflow: DepthOverflow::Clamp,
+    };
+}
 
-            true
-        } else {
-            false
+impl TryFrom<&str> for DepthOverflow {
+    type Error = ();
+
+    fn try_from(overflow: &str) -> Result<Self, Self::Error> {
+        match overflow {
+            "clamp" => Ok(DepthOverflow::Clamp),
+            "unmatched" => Ok(DepthOverflow::Unmatched),
+            _ => Err(()),
         }
     }
+}
 
-    fn calculate_stack_heights(&mut self, tab_width: u8) {
-        let mut unmatched_openings: Vec<(usize, usize)> = vec![];
-        let mut stack = vec![];
-
-        // Get stack heights for all openings using a traditional stack
-        // This results in matching on the closest pairs when there are mismatched
-        // openings/closings
-        // [ ( ( [] (  ) ]
-        // 0     11 1  1 0
-        for (line, matches) in self.matches_by_line.iter_mut().enumerate() {
-            'outer: for match_ in matches.iter_mut() {
-                // Opening delimiter
-                if match_.kind == Kind::Opening {
-                    stack.push((line, match_));
-                }
-                // Closing delimiter
-                else {
-                    for (i, (_, opening)) in stack.iter().enumerate().rev() {
-                        if opening.token == match_.token {
-                            // Mark all skipped matches as unmatched
-                            for (unmatched_line, unmatched_opening) in
-                                stack.splice((i + 1).., vec![])
-                            {
-                                unmatched_openings.push((unmatched_line, unmatched_opening.col));
-                            }
+/// Snapshot of a [`ParsedBuffer`], created by [`ParsedBuffer::checkpoint`]
+#[derive(Debug, Clone)]
+pub struct Checkpoint(ParsedBuffer);
 
-                            // Update stack height
-                            let (_, opening) = stack.pop().unwrap();
-                            opening.stack_height = Some(stack.len());
-                            match_.stack_height = Some(stack.len());
-                            continue 'outer;
-                        }
-                    }
+/// Lines between the buffer returning to the top level (stack height 0), such as a function,
+/// and the deepest nesting within
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NestingRegion {
+    pub start_line: usize,
+    pub end_line: usize,
+    /// Number of pairs enclosing the deepest delimiter, including the top level pair
+    pub max_depth: usize,
+}
 
-                    // No match found, mark as unmatched
-                    match_.stack_height = None;
-                }
-            }
-        }
+#[cfg(feature = "lua")]
+impl IntoLua for NestingRegion {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("start_line", self.start_line)?;
+        table.set("end_line", self.end_line)?;
+        table.set("max_depth", self.max_depth)?;
+        (&table).into_lua(lua)
+    }
+}
 
-        // Remaining items in stack must be unmatched
-        for (line, match_) in stack.into_iter() {
-            unmatched_openings.push((line, match_.col));
-        }
-        unmatched_openings.sort();
+/// Number of matched pairs and unmatched delimiters of a delimiter type, see
+/// [`ParsedBuffer::stats`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelimiterStats {
+    pub opening: &'static str,
+    pub closing: &'static str,
+    pub matched: usize,
+    /// Number of unmatched openings and closings
+    pub unmatched: usize,
+}
 
-        // Remove stack heights for unmatched openings
-        for (line, col) in unmatched_openings.iter() {
-            let match_ = self.match_at_mut(*line, *col).unwrap();
-            match_.stack_height = None;
-        }
+/// Pair statistics of a buffer, i.e. for showing the unbalanced delimiters in the statusline
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PairStats {
+    /// Delimiter types in the order of their first occurrence
+    pub delimiters: Vec<DelimiterStats>,
+    /// Number of pairs enclosing the deepest delimiter, including its own pair
+    pub max_depth: usize,
+}
 
-        // Prefer matching on the furthest pair for mismatched openings
-        // As is, we have matched like so:
-        // [ ( ( [] (  ) ]
-        // 0     11 1  1 0
-        // but we want to match like:
-        // [ ( ( [] (  ) ]
-        // 0 1   22    1 0
-        for (line, col) in unmatched_openings.into_iter().rev() {
-            self.rematch_by_indent_recursive(line, col, tab_width);
-        }
+impl PairStats {
+    /// Total number of unmatched delimiters
+    pub fn unmatched(&self) -> usize {
+        self.delimiters.iter().map(|stats| stats.unmatched).sum()
     }
+}
 
-    /// Gets the indent level of the line, rounded down to the nearest tab width
-    pub fn rounded_indent_level(&self, line: usize, tab_width: u8) -> u8 {
-        self.indent_levels[line].div_floor(tab_width) * tab_width
+#[cfg(feature = "lua")]
+impl IntoLua for DelimiterStats {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("opening", self.opening)?;
+        table.set("closing", self.closing)?;
+        table.set("matched", self.matched)?;
+        table.set("unmatched", self.unmatched)?;
+        (&table).into_lua(lua)
     }
+}
 
-    /// Given an unmatched opening's position, attempts to find a matching opening/closing pair
-    /// where the closing ident level matches the unmatched opening.
-    /// Performed recursively until the match cannot be moved further down the stack.
-    ///
-    /// ```rust
-    /// if some_example {
-    ///     //          ^ unmatched
-    ///     if no_closing_on_this {
-    ///         //       matched  ^
-    /// }
-    /// ```
-    /// becomes
-    /// ```rust
-    /// if some_example {
-    ///     //  matched ^
-    ///     if no_closing_on_this {
-    ///         //      unmatched ^
-    ///     }
-    /// }
-    /// ```
-    pub fn rematch_by_indent_recursive(&mut self, line: usize, col: usize, tab_width: u8) {
-        let indent_level = self.rounded_indent_level(line, tab_width);
-        let token = self.match_at(line, col).unwrap().token;
-        let stack_height = self.stack_height_at(line, col);
+#[cfg(feature = "lua")]
+impl IntoLua for PairStats {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("unmatched", self.unmatched())?;
+        table.set("delimiters", self.delimiters)?;
+        table.set("max_depth", self.max_depth)?;
+        (&table).into_lua(lua)
+    }
+}
 
-        // Find the first matched opening that has the same stack height and token
-        let matched_pair = self
-            .iter_from(line, col + 1)
-            .take_while(|match_| {
-                match_
-                    .stack_height
-                    .map(|sh| sh >= stack_height.saturating_add(1))
-                    .unwrap_or(true)
-            })
-            .filter(|match_| match_.token == token.clone())
-            .flat_map(|match_| self.match_pair(match_.line, match_.col))
-            .find(|(open, close)| {
-                self.rounded_indent_level(close.line, tab_width) == indent_level
-                    && self.rounded_indent_level(close.line, tab_width)
-                        != self.rounded_indent_level(open.line, tab_width)
-            });
+/// Stack heights at the start and end of a line and the highest one within, see
+/// [`ParsedBuffer::line_depth_profile`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthProfile {
+    pub entry: usize,
+    pub exit: usize,
+    pub max: usize,
+}
 
-        if let Some((matched_opening_with_line, matched_closing_with_line)) = matched_pair {
-            // Mark matched opening as unmatched
-            let matched_opening = self
-                .match_at_mut(
-                    matched_opening_with_line.line,
-                    matched_opening_with_line.col,
-                )
-                .unwrap();
-            matched_opening.stack_height = None;
+#[cfg(feature = "lua")]
+impl IntoLua for DepthProfile {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("entry", self.entry)?;
+        table.set("exit", self.exit)?;
+        table.set("max", self.max)?;
+        (&table).into_lua(lua)
+    }
+}
 
-            // Mark unmatched opening as matched, using the stack height - 1 as unmatched
-            // openings lead to incorrect stack heights for the matches after them
-            // For example:
-            // [ ( ( ) ]
-            // 0   2 2 0
-            // When it should be:
-            // [ ( ( ) ]
-            // 0   1 1 0
-            // But since we're now matching on the unmatched opening, we end up with:
-            // [ ( ( ) ]
-            // 0 1   1 0
-            let unmatched_opening = self.match_at_mut(line, col).unwrap();
-            unmatched_opening.stack_height = Some(stack_height);
+/// Source range of a single token, where the columns are byte offsets into the line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenRange {
+    pub line: usize,
+    pub start_col: usize,
+    /// Exclusive
+    pub end_col: usize,
+}
 
-            let matched_closing = self
-                .match_at_mut(
-                    matched_closing_with_line.line,
-                    matched_closing_with_line.col,
-                )
-                .unwrap();
-            matched_closing.stack_height = Some(stack_height);
+#[cfg(feature = "lua")]
+impl IntoLua for TokenRange {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("line", self.line)?;
+        table.set("start_col", self.start_col)?;
+        table.set("end_col", self.end_col)?;
+        (&table).into_lua(lua)
+    }
+}
 
-            // All matches after the closing match are now 1 stack height shallower,
-            // For example, starting with:
-            // [ ( ( ) { } ]
-            // 0   2 2 2 2 0
-            // After the previous step, we have:
-            // [ ( ( ) { } ]
-            // 0 1   1 2 2 0
-            // So we update the "{ }" stack height by 1
-            // [ ( ( ) { } ]
-            // 0 1   1 1 1 0
-            for match_ in self.matches_by_line[matched_closing_with_line.line..]
-                .iter_mut()
-                .enumerate()
-                .flat_map(|(line_idx, matches)| {
-                    matches.iter_mut().filter(move |match_| {
-                        line_idx != 0 || match_.col > matched_closing_with_line.col
-                    })
-                })
-            {
-                if match_.stack_height == Some(stack_height) && match_.kind == Kind::Closing {
-                    break;
-                }
-                match_.stack_height = match_
-                    .stack_height
-                    .map(|stack_height| stack_height.saturating_sub(1));
-            }
+/// Source range which may span multiple lines, where the columns are byte offsets into the
+/// lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceRange {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    /// Exclusive
+    pub end_col: usize,
+}
 
-            self.rematch_by_indent_recursive(
-                matched_opening_with_line.line,
-                matched_opening_with_line.col,
-                tab_width,
-            );
+impl SourceRange {
+    /// Range of the pair inside of its delimiters, or including them when `around`
+    fn of_pair(opening: &MatchWithLine, closing: &MatchWithLine, around: bool) -> Self {
+        match around {
+            true => SourceRange {
+                start_line: opening.line,
+                start_col: opening.col,
+                end_line: closing.line,
+                end_col: closing.end_col(),
+            },
+            false => SourceRange {
+                start_line: opening.line,
+                start_col: opening.end_col(),
+                end_line: closing.line,
+                end_col: closing.col,
+            },
         }
     }
 
-    pub fn line_matches(&self, line_number: usize) -> Option<Vec<Match>> {
-        self.matches_by_line.get(line_number).cloned()
+    /// Whether the other range is within this one, including when they're the same
+    pub fn contains(&self, other: &SourceRange) -> bool {
+        (self.start_line, self.start_col) <= (other.start_line, other.start_col)
+            && (other.end_line, other.end_col) <= (self.end_line, self.end_col)
     }
+}
 
-    pub fn get_indent_levels(&self, start_line: usize, end_line: usize) -> Vec<u8> {
-        let start_idx = start_line.min(self.indent_levels.len());
-        let end_idx = end_line.min(self.indent_levels.len());
+#[cfg(feature = "lua")]
+impl IntoLua for SourceRange {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("start_line", self.start_line)?;
+        table.set("start_col", self.start_col)?;
+        table.set("end_line", self.end_line)?;
+        table.set("end_col", self.end_col)?;
+        (&table).into_lua(lua)
+    }
+}
 
-        if start_idx >= end_idx {
-            return vec![];
-        }
+/// Kind of the string, comment or span at a position, see [`ParsedBuffer::span_kind_at`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpanKind {
+    String,
+    /// Block string starting its line, which the filetype uses for documentation, i.e.
+    /// `"""` in Python
+    Docstring,
+    Regex,
+    Comment,
+    /// With the name of the span
+    Span(&'static str),
+}
 
-        self.indent_levels[start_idx..end_idx].to_vec()
+#[cfg(feature = "lua")]
+impl IntoLua for SpanKind {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        let kind = match self {
+            SpanKind::String => "string",
+            SpanKind::Docstring => "docstring",
+            SpanKind::Regex => "regex",
+            SpanKind::Comment => "comment",
+            SpanKind::Span(span) => {
+                table.set("span", span)?;
+                "span"
+            }
+        };
+        table.set("kind", kind)?;
+        (&table).into_lua(lua)
     }
+}
 
-    pub fn iter_from(
-        &self,
-        line_number: usize,
-        col: usize,
-    ) -> impl Iterator<Item = MatchWithLine> + '_ {
-        self.matches_by_line[line_number.max(0)..]
-            .iter()
-            .enumerate()
-            .flat_map(move |(offset, matches)| {
-                let current_line = line_number + offset;
-                matches
-                    .iter()
-                    .filter(move |match_| current_line != line_number || match_.col >= col)
-                    .map(move |match_| match_.with_line(current_line))
-            })
-    }
+/// Kind of a construct surrounding a position, see [`ParsedBuffer::surroundings_at`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SurroundingKind {
+    Pair,
+    String,
+    Comment,
+    /// With the name of the span
+    Span(&'static str),
+}
 
-    pub fn iter_to(
-        &self,
-        line_number: usize,
-        col: usize,
-    ) -> impl Iterator<Item = MatchWithLine> + '_ {
-        self.matches_by_line[0..=line_number.min(self.matches_by_line.len() - 1)]
-            .iter()
-            .enumerate()
-            .rev()
-            .flat_map(move |(current_line, matches)| {
-                matches
-                    .iter()
-                    .filter(move |match_| current_line != line_number || match_.col < col)
-                    .map(move |match_| match_.with_line(current_line))
-            })
+/// Construct surrounding a position, with its range including the delimiters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surrounding {
+    pub kind: SurroundingKind,
+    pub range: SourceRange,
+}
+
+#[cfg(feature = "lua")]
+impl IntoLua for Surrounding {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        let kind = match self.kind {
+            SurroundingKind::Pair => "pair",
+            SurroundingKind::String => "string",
+            SurroundingKind::Comment => "comment",
+            SurroundingKind::Span(span) => {
+                table.set("span", span)?;
+                "span"
+            }
+        };
+        table.set("kind", kind)?;
+        table.set("range", self.range)?;
+        (&table).into_lua(lua)
     }
+}
 
-    pub fn span_at(&self, line_number: usize, col: usize) -> Option<String> {
-        let line_matches = self.matches_by_line.get(line_number)?;
-        let line_state = self.state_by_line.get(line_number)?;
+/// Snippet of the opening line to show after the closing of a long pair, i.e. as virtual text
+/// `} // impl Foo`, see [`ParsedBuffer::closing_annotations`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosingAnnotation {
+    pub line: usize,
+    /// Column after the closing
+    pub col: usize,
+    pub opening_line: usize,
+    pub text: String,
+}
 
-        // Look for spans starting in the current line before the desired column
+#[cfg(feature = "lua")]
+impl IntoLua for ClosingAnnotation {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("line", self.line)?;
+        table.set("col", self.col)?;
+        table.set("opening_line", self.opening_line)?;
+        table.set("text", self.text)?;
+        (&table).into_lua(lua)
+    }
+}
 
-        let matching_span = line_matches
-            .iter()
-            .rev()
-            // Get all opening matches before the cursor on the current line
-            .filter(|match_| match_.kind == Kind::Opening && match_.col <= col)
-            // Find closing match on the same line or no match (overflows to next line)
-            .find_map(|opening| {
-                match opening.token {
-                    Token::InlineSpan(span, _, _) | Token::BlockSpan(span, _, _) => {
-                        let closing = line_matches.iter().find(|closing| {
-                            closing.kind == Kind::Closing
-                                && closing.col > opening.col
-                                && closing.token == opening.token
-                                && closing.stack_height == opening.stack_height
-                        });
+/// Closing which would balance an unmatched opening before the cursor, for completion sources
+/// to rank or append closings, see [`ParsedBuffer::pending_closings`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingClosing {
+    pub closing: &'static str,
+    pub opening_line: usize,
+    pub opening_col: usize,
+    /// Number of closings which must be typed before this one, i.e. 0 for the innermost
+    pub depth: usize,
+}
 
-                        match closing {
-                            // Ends before desired column
-                            Some(closing) if closing.col < col => None,
-                            // Extends to end of line or found closing after desired column
-                            _ => Some(span),
-                        }
-                    }
-                    _ => None,
-                }
-            });
+#[cfg(feature = "lua")]
+impl IntoLua for PendingClosing {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("closing", self.closing)?;
+        table.set("opening_line", self.opening_line)?;
+        table.set("opening_col", self.opening_col)?;
+        table.set("depth", self.depth)?;
+        (&table).into_lua(lua)
+    }
+}
 
-        if let Some(span) = matching_span {
-            return Some(span.to_string());
-        }
+/// Imbalance which pasting text would bring, see [`ParsedBuffer::analyze_paste`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasteAnalysis {
+    /// Closings without an opening in the pasted text, which would close the pairs around the
+    /// insertion point instead, at their positions in the buffer after pasting
+    pub extra_closings: Vec<MatchWithLine>,
+    /// Openings without a closing in the pasted text, at their positions in the buffer after
+    /// pasting
+    pub unclosed_openings: Vec<MatchWithLine>,
+    /// State at the insertion point
+    pub start_state: State,
+    /// State at the end of the pasted text, which differs from the start state when the text
+    /// leaves a string or block comment open
+    pub end_state: State,
+}
 
-        // Look for spans that started before the current line
-        match line_state {
-            // TODO: check that the span doesn't end before the cursor
-            State::InInlineSpan(span) | State::InBlockSpan(span) => Some(span.to_string()),
-            _ => None,
+impl PasteAnalysis {
+    pub fn is_balanced(&self) -> bool {
+        self.extra_closings.is_empty()
+            && self.unclosed_openings.is_empty()
+            && self.start_state == self.end_state
+    }
+}
+
+#[cfg(feature = "lua")]
+impl IntoLua for PasteAnalysis {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("balanced", self.is_balanced())?;
+        table.set("changes_state", self.start_state != self.end_state)?;
+        table.set("extra_closings", self.extra_closings)?;
+        table.set("unclosed_openings", self.unclosed_openings)?;
+        (&table).into_lua(lua)
+    }
+}
+
+/// Edits for joining a line with the next, see [`ParsedBuffer::join_adjustment`]. The joined
+/// line is the line up to `line_end` without the trailing comma, the separator, and the next
+/// line from `next_line_start`
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinAdjustment {
+    /// Column of the comma at the end of the line which becomes redundant before the closing
+    /// starting the next line, i.e. `[a,` followed by `]`
+    pub trailing_comma: Option<usize>,
+    /// End of the line's text, after which the whitespace is removed
+    pub line_end: usize,
+    /// Start of the next line's text, before which the whitespace is removed
+    pub next_line_start: usize,
+    /// Text between the lines, empty after an opening or before a closing apart from braces
+    pub separator: &'static str,
+}
+
+#[cfg(feature = "lua")]
+impl IntoLua for JoinAdjustment {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("trailing_comma", self.trailing_comma)?;
+        table.set("line_end", self.line_end)?;
+        table.set("next_line_start", self.next_line_start)?;
+        table.set("separator", self.separator)?;
+        (&table).into_lua(lua)
+    }
+}
+
+/// Kind of bracket error, see [`ParsedBuffer::diagnostics`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    UnmatchedOpening,
+    UnmatchedClosing,
+    UnterminatedString,
+    UnterminatedComment,
+    UnterminatedSpan,
+}
+
+impl DiagnosticKind {
+    pub fn message(&self) -> &'static str {
+        match self {
+            DiagnosticKind::UnmatchedOpening => "unmatched opening",
+            DiagnosticKind::UnmatchedClosing => "unmatched closing",
+            DiagnosticKind::UnterminatedString => "unterminated string",
+            DiagnosticKind::UnterminatedComment => "unterminated comment",
+            DiagnosticKind::UnterminatedSpan => "unterminated span",
         }
     }
+}
 
-    pub fn match_at(&self, line_number: usize, col: usize) -> Option<Match> {
-        self.matches_by_line
-            .get(line_number)?
-            .iter()
-            .find(|match_| (match_.col..(match_.col + match_.len())).contains(&col))
-            .cloned()
+/// Unmatched delimiter or unterminated string, comment or span
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+    /// Text of the token, i.e. `(` or `"`
+    pub text: &'static str,
+    pub kind: DiagnosticKind,
+}
+
+#[cfg(feature = "lua")]
+impl IntoLua for Diagnostic {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("line", self.line)?;
+        table.set("col", self.col)?;
+        table.set("len", self.len)?;
+        table.set("text", self.text)?;
+        table.set("message", self.kind.message())?;
+        (&table).into_lua(lua)
     }
+}
 
-    pub fn match_at_mut(&mut self, line_number: usize, col: usize) -> Option<&mut Match> {
-        self.matches_by_line
-            .get_mut(line_number)?
-            .iter_mut()
-            .find(|match_| (match_.col..(match_.col + match_.len())).contains(&col))
+/// Direction to search in from a position, see [`ParsedBuffer::sibling_block`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+#[cfg(feature = "lua")]
+impl FromLua for Direction {
+    fn from_lua(value: mlua::Value, lua: &mlua::Lua) -> mlua::Result<Self> {
+        match String::from_lua(value, lua)?.as_str() {
+            "forward" => Ok(Direction::Forward),
+            "backward" => Ok(Direction::Backward),
+            _ => Err(mlua::Error::runtime("invalid direction")),
+        }
     }
+}
 
-    pub fn match_pair(
-        &self,
-        line_number: usize,
-        col: usize,
-    ) -> Option<(MatchWithLine, MatchWithLine)> {
-        let match_at_pos = self.match_at(line_number, col)?.with_line(line_number);
+/// Which delimiter to pair relative to a position which isn't on one, see
+/// [`ParsedBuffer::match_pair_adjacent`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Adjacency {
+    /// Only the delimiter at the position
+    #[default]
+    Exact,
+    /// The delimiter ending right before the position, i.e. after typing `)` in insert mode
+    Before,
+    /// The delimiter starting right after the position
+    After,
+    /// The closest delimiter on the line, preferring the one before the position when tied
+    NearestOnLine,
+}
 
-        // Ignore unmatched delimiter
-        if matches!(match_at_pos.token, Token::Delimiter(_, _))
-            && match_at_pos.stack_height.is_none()
-        {
-            return None;
+#[cfg(feature = "lua")]
+impl FromLua for Adjacency {
+    fn from_lua(value: mlua::Value, lua: &mlua::Lua) -> mlua::Result<Self> {
+        match String::from_lua(value, lua)?.as_str() {
+            "exact" => Ok(Adjacency::Exact),
+            "before" => Ok(Adjacency::Before),
+            "after" => Ok(Adjacency::After),
+            "nearest_on_line" => Ok(Adjacency::NearestOnLine),
+            _ => Err(mlua::Error::runtime("invalid adjacency")),
         }
+    }
+}
 
-        // Opening match
-        if match_at_pos.kind == Kind::Opening {
-            let closing_match = self.matches_by_line[line_number..]
-                .iter()
-                .enumerate()
-                .map(|(matches_line_number, matches)| (matches_line_number + line_number, matches))
-                .find_map(|(matches_line_number, matches)| {
-                    matches
-                        .iter()
-                        .find(|match_| {
-                            (line_number != matches_line_number || match_.col > col)
-                                && match_at_pos.token == match_.token
-                                && match_at_pos.stack_height == match_.stack_height
-                        })
-                        .map(|match_| match_.with_line(matches_line_number))
-                })?;
+/// Columns of long lines to keep the matches of, see [`ParsedBuffer::clip_long_lines`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnWindow {
+    /// Byte columns to keep the matches within, i.e. the horizontal viewport
+    pub cols: Range<usize>,
+    /// Lines shorter than this many bytes keep all of their matches
+    pub min_line_len: usize,
+}
 
-            Some((match_at_pos, closing_match))
+/// Number of matches dropped from a long line outside of the [`ColumnWindow`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClippedLine {
+    pub line: usize,
+    pub dropped_before: usize,
+    pub dropped_after: usize,
+}
+
+/// Result of [`ParsedBuffer::parse_with_budget`]
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub enum BudgetedParse {
+    Done(ParsedBuffer),
+    /// The budget ran out, call [`ParseContinuation::resume`] with the same lines to continue
+    Pending(ParseContinuation),
+}
+
+/// Progress of a parse which ran out of time, see [`ParsedBuffer::parse_with_budget`]
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct ParseContinuation {
+    filetype: String,
+    tab_width: u8,
+    matches_by_line: Vec<Vec<Match>>,
+    state_by_line: Vec<State>,
+}
+
+#[cfg(feature = "std")]
+impl ParseContinuation {
+    /// Continues parsing within the budget, where the lines must be the same as the ones
+    /// passed to [`ParsedBuffer::parse_with_budget`]
+    pub fn resume<L: AsRef<[u8]>>(
+        mut self,
+        lines: &[L],
+        budget: std::time::Duration,
+    ) -> BudgetedParse {
+        // Check the clock every few lines since it's relatively expensive
+        const LINES_PER_CHECK: usize = 64;
+
+        let start_time = std::time::Instant::now();
+        let start_line = self.state_by_line.len().min(lines.len());
+        let initial_state = self.state_by_line.last().copied().unwrap_or(State::Normal);
+        let Some(parsed_lines) =
+            parse_filetype_iter(&self.filetype, &lines[start_line..], initial_state)
+        else {
+            return BudgetedParse::Pending(self);
+        };
+
+        for (i, (line_matches, state)) in parsed_lines.enumerate() {
+            self.matches_by_line.push(line_matches);
+            self.state_by_line.push(state);
+
+            if (i + 1) % LINES_PER_CHECK == 0 && start_time.elapsed() >= budget {
+                return BudgetedParse::Pending(self);
+            }
         }
-        // Closing match
-        else if match_at_pos.kind == Kind::Closing {
-            let opening_match = self.matches_by_line[0..=line_number]
+
+        let mut parsed = ParsedBuffer {
+            line_hashes: line_hashes(lines, State::Normal, &self.state_by_line),
+            history: History::default(),
+            matches_by_line: self.matches_by_line,
+            state_by_line: self.state_by_line,
+            indent_levels: indent_levels(lines, self.tab_width),
+            blank_indents: lines
                 .iter()
-                .enumerate()
-                .rev()
-                .find_map(|(matches_line_number, matches)| {
-                    matches
-                        .iter()
-                        .rev()
-                        .find(|match_| {
-                            (line_number != matches_line_number || match_.col < col)
-                                && match_at_pos.token == match_.token
-                                && match_at_pos.stack_height == match_.stack_height
-                        })
-                        .map(|match_| match_.with_line(matches_line_number))
-                })?;
+                .map(|line| blank_indent(line, self.tab_width))
+                .collect(),
+            indent_changes: 0..lines.len(),
+            depth_at_line_start: vec![],
+            depth_limit: DepthLimit::DEFAULT,
+        };
+        parsed.calculate_stack_heights(self.tab_width);
+        BudgetedParse::Done(parsed)
+    }
 
-            Some((opening_match, match_at_pos))
-        } else {
-            None
-        }
+    /// Number of lines parsed so far
+    pub fn parsed_lines(&self) -> usize {
+        self.state_by_line.len()
     }
+}
 
-    pub fn stack_height_at_forward(&self, line_number: usize, col: usize) -> Option<usize> {
-        let mut unmatched_opening_count: usize = 0;
-        self.iter_from(line_number, col)
-            .find_map(|match_| match match_.stack_height {
-                Some(stack_height) => Some(
-                    stack_height
-                        .saturating_add(if match_.kind == Kind::Closing { 1 } else { 0 })
-                        .saturating_sub(unmatched_opening_count),
-                ),
-                None => {
-                    if matches!(match_.token, Token::Delimiter(_, _)) {
-                        match match_.kind {
-                            Kind::Opening => {
-                                unmatched_opening_count = unmatched_opening_count.saturating_add(1)
-                            }
-                            Kind::Closing => {
-                                unmatched_opening_count = unmatched_opening_count.saturating_sub(1)
-                            }
-                            Kind::NonPair => {}
-                        };
+/// Gets the stack height after the match, given the one before it. Unmatched delimiters don't
+/// change the stack height
+fn depth_after(depth: usize, match_: &Match) -> usize {
+    match (match_.stack_height, match_.kind) {
+        (Some(stack_height), Kind::Opening | Kind::Middle(_)) => stack_height + 1,
+        (Some(stack_height), _) => stack_height,
+        (None, _) => depth,
+    }
+}
+
+/// Removes the pairs which open and close within the matches, along with the matches which
+/// don't pair, leaving the openings and closings paired outside of them. Line comments are
+/// kept too, since they decide the state for the rest of the line, see
+/// [`ParsedBuffer::state_at`]
+fn unpaired_matches(matches: Vec<Match>) -> Vec<Match> {
+    let mut unpaired: Vec<Match> = Vec::new();
+    // Indices of the openings in `unpaired`
+    let mut openings: Vec<usize> = Vec::new();
+    for match_ in matches {
+        match match_.kind {
+            Kind::Opening => {
+                openings.push(unpaired.len());
+                unpaired.push(match_);
+            }
+            Kind::Closing => {
+                let opening = openings
+                    .iter()
+                    .rposition(|&idx| unpaired[idx].token == match_.token);
+                match opening {
+                    Some(opening) => {
+                        let idx = openings[opening];
+                        openings.truncate(opening);
+                        unpaired.truncate(idx);
                     }
-                    None
+                    None => unpaired.push(match_),
                 }
-            })
+            }
+            Kind::NonPair if matches!(match_.token, Token::LineComment(_)) => unpaired.push(match_),
+            Kind::NonPair | Kind::Middle(_) => {}
+        }
     }
+    unpaired
+}
 
-    pub fn stack_height_at_backward(&self, line_number: usize, col: usize) -> Option<usize> {
-        let mut unmatched_opening_count: usize = 0;
-        self.iter_to(line_number, col)
-            .find_map(|match_| match match_.stack_height {
-                Some(stack_height) => Some(
-                    stack_height
-                        .saturating_add(if match_.kind == Kind::Opening { 1 } else { 0 })
-                        .saturating_sub(unmatched_opening_count),
-                ),
-                None => {
-                    if matches!(match_.token, Token::Delimiter(_, _)) {
-                        match match_.kind {
-                            Kind::Opening => {
-                                unmatched_opening_count = unmatched_opening_count.saturating_add(1)
-                            }
-                            Kind::Closing => {
-                                unmatched_opening_count = unmatched_opening_count.saturating_sub(1)
-                            }
-                            Kind::NonPair => {}
-                        };
-                    }
-                    None
+/// Hashes each line along with the state at its start, which together determine the line's
+/// matches and end state, so that reparsing identical lines may be skipped. The lines aren't
+/// kept, so a line is only known to be identical when its length and 64-bit hash match,
+/// leaving a negligible chance of keeping stale matches for a changed line
+pub(crate) fn line_hashes<L: AsRef<[u8]>>(
+    lines: &[L],
+    initial_state: State,
+    state_by_line: &[State],
+) -> Vec<LineHash> {
+    let start_states = core::iter::once(initial_state).chain(state_by_line.iter().copied());
+    lines
+        .iter()
+        .zip(start_states)
+        .map(|(line, state)| line_hash(line.as_ref(), state))
+        .collect()
+}
+
+/// Hashes of a line with the state at its start, see [`line_hashes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct LineHash {
+    /// Hash of the whole line
+    pub line: u64,
+    /// Length of the whole line in bytes
+    pub len: usize,
+    /// Hash of the line without its leading and trailing whitespace, for detecting changes to
+    /// only the whitespace
+    pub content: u64,
+    /// Length of the line without its leading and trailing whitespace in bytes
+    pub content_len: usize,
+    /// Length of the leading whitespace in bytes
+    pub indent_len: usize,
+}
+
+fn line_hash(line: &[u8], state: State) -> LineHash {
+    let hash = |text: &[u8]| {
+        let mut hasher = FxHasher::default();
+        state.hash(&mut hasher);
+        hasher.write(text);
+        hasher.finish()
+    };
+    let content = line.trim_ascii();
+    LineHash {
+        line: hash(line),
+        len: line.len(),
+        content: hash(content),
+        content_len: content.len(),
+        indent_len: line.len() - line.trim_ascii_start().len(),
+    }
+}
+
+/// Gets the index of the match containing the column, relying on the matches being sorted
+fn match_idx(matches: &[Match], col: usize) -> Option<usize> {
+    let idx = matches
+        .partition_point(|match_| match_.col <= col)
+        .checked_sub(1)?;
+    (col < matches[idx].end_col()).then_some(idx)
+}
+
+/// Moves the start and end positions inward past whitespace and line breaks, returning `None`
+/// when only whitespace is between them
+fn trim_whitespace<'a>(
+    line: &impl Fn(usize) -> &'a [u8],
+    mut start: (usize, usize),
+    mut end: (usize, usize),
+) -> Option<SourceRange> {
+    while start < end {
+        match line(start.0).get(start.1) {
+            Some(byte) if !byte.is_ascii_whitespace() => break,
+            Some(_) => start.1 += 1,
+            None => start = (start.0 + 1, 0),
+        }
+    }
+    while start < end {
+        match end.1.checked_sub(1) {
+            Some(col) if !line(end.0).get(col).is_some_and(u8::is_ascii_whitespace) => break,
+            Some(col) => end.1 = col,
+            None => end = (end.0 - 1, line(end.0 - 1).len()),
+        }
+    }
+    (start < end).then_some(SourceRange {
+        start_line: start.0,
+        start_col: start.1,
+        end_line: end.0,
+        end_col: end.1,
+    })
+}
+
+/// Gets the range of the text without the leading and trailing whitespace
+fn trimmed_range(text: &[u8]) -> Range<usize> {
+    let start = text.len() - text.trim_ascii_start().len();
+    start..start.max(text.trim_ascii_end().len())
+}
+
+/// Truncates the text to the number of characters, ending with `…` when truncated
+fn truncate(text: &str, max_len: usize) -> String {
+    match text.char_indices().nth(max_len) {
+        Some(_) => {
+            let end = text
+                .char_indices()
+                .nth(max_len.saturating_sub(1))
+                .map_or(0, |(idx, _)| idx);
+            let mut text = text[..end].trim_end().to_string();
+            text.push('…');
+            text
+        }
+        None => text.to_string(),
+    }
+}
+
+fn is_unmatched_delimiter(kind: Kind, token: &Token, stack_height: Option<usize>) -> bool {
+    matches!(token, Token::Delimiter(_, _))
+        && matches!(kind, Kind::Opening | Kind::Closing)
+        && stack_height.is_none()
+}
+
+/// Sets the stack heights of the openings and closings by pairing each closing with the
+/// closest opening, leaving the skipped openings unmatched. Unlike
+/// [`ParsedBuffer::calculate_stack_heights`], pairs aren't moved by indentation
+fn pair_closest(matches_by_line: &mut [Vec<Match>]) {
+    let mut stack: Vec<(usize, usize)> = vec![];
+    for line in 0..matches_by_line.len() {
+        for idx in 0..matches_by_line[line].len() {
+            let match_ = &matches_by_line[line][idx];
+            match match_.kind {
+                Kind::Opening => {
+                    stack.push((line, idx));
+                    continue;
                 }
-            })
+                Kind::Closing => {}
+                Kind::NonPair | Kind::Middle(_) => continue,
+            }
+            let Some(i) = stack.iter().rposition(|&(opening_line, opening_idx)| {
+                matches_by_line[opening_line][opening_idx].token == match_.token
+            }) else {
+                continue;
+            };
+
+            stack.truncate(i + 1);
+            let (opening_line, opening_idx) = stack.pop().unwrap();
+            matches_by_line[opening_line][opening_idx].stack_height = Some(stack.len());
+            matches_by_line[line][idx].stack_height = Some(stack.len());
+        }
     }
+}
 
-    pub fn stack_height_at(&self, line_number: usize, col: usize) -> usize {
-        self.stack_height_at_forward(line_number, col)
-            .or_else(|| self.stack_height_at_backward(line_number, col))
-            .unwrap_or(0)
+fn as_bytes<S: AsRef<str>>(lines: &[S]) -> Vec<&[u8]> {
+    lines.iter().map(|line| line.as_ref().as_bytes()).collect()
+}
+
+impl ParsedBuffer {
+    /// Parses the lines, which may be any slice of string-likes such as `&[&str]`,
+    /// `&[String]` or `&[Cow<str>]`
+    pub fn parse<S: AsRef<str>>(filetype: &str, tab_width: u8, lines: &[S]) -> Option<Self> {
+        Self::parse_bytes(filetype, tab_width, &as_bytes(lines))
     }
 
-    pub fn unmatched_opening_before(
-        &self,
-        opening: &str,
-        closing: &str,
-        line_number: usize,
-        col: usize,
-    ) -> Option<MatchWithLine> {
-        let cursor_stack_height = self.stack_height_at(line_number, col);
-        let mut lowest_stack_height = cursor_stack_height;
-        let mut current_stack_height = cursor_stack_height;
+    /// Same as [`ParsedBuffer::parse`] but skips UTF-8 validation by accepting the lines as
+    /// bytes. Columns are byte offsets in both cases
+    pub fn parse_bytes<L: AsRef<[u8]>>(filetype: &str, tab_width: u8, lines: &[L]) -> Option<Self> {
+        let mut parsed = parse_filetype(filetype, tab_width, lines, State::Normal)?;
+        parsed.calculate_stack_heights(tab_width);
+        Some(parsed)
+    }
 
-        for match_ in self
-            .iter_to(line_number, col)
-            .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
-        {
-            if let Some(stack_height) = match_.stack_height {
-                // Stack height higher than cursor
-                if stack_height < lowest_stack_height {
-                    // For example: ( [] ( | )
-                    // Stack:            ^   ^
-                    // Cursor stack height: 1
-                    // We can close the outer pair by adding a closing pair at the cursor
-                    if match_.kind == Kind::Opening
-                        && match_.token.closing() == Some(closing)
-                        && match_.token.opening() == opening
+    /// Same as [`ParsedBuffer::parse_bytes`] but drops the matches of long lines outside of
+    /// the column window, see [`ParsedBuffer::clip_long_lines`]
+    pub fn parse_bytes_with_column_window<L: AsRef<[u8]>>(
+        filetype: &str,
+        tab_width: u8,
+        lines: &[L],
+        window: &ColumnWindow,
+    ) -> Option<(Self, Vec<ClippedLine>)> {
+        let mut parsed = Self::parse_bytes(filetype, tab_width, lines)?;
+        let clipped_lines = parsed.clip_long_lines(lines, window);
+        Some((parsed, clipped_lines))
+    }
+
+    /// Drops the matches outside of the column window on lines longer than
+    /// [`ColumnWindow::min_line_len`], i.e. in minified files, to save memory. Openings and
+    /// closings paired with a match on the other side of the window are kept, along with line
+    /// comments, so stack heights and states within the window and on other lines are
+    /// unaffected, even after a reparse.
+    ///
+    /// The lines must be the ones the buffer was parsed from. Returns the number of matches
+    /// dropped on each clipped line
+    pub fn clip_long_lines<L: AsRef<[u8]>>(
+        &mut self,
+        lines: &[L],
+        window: &ColumnWindow,
+    ) -> Vec<ClippedLine> {
+        let mut clipped_lines = vec![];
+        for (line, matches) in self.matches_by_line.iter_mut().enumerate() {
+            if lines
+                .get(line)
+                .is_none_or(|text| text.as_ref().len() < window.min_line_len)
+            {
+                continue;
+            }
+
+            let total = matches.len();
+            let start = matches.partition_point(|match_| match_.col < window.cols.start);
+            let end = matches
+                .partition_point(|match_| match_.col < window.cols.end)
+                .max(start);
+            let after = unpaired_matches(matches.split_off(end));
+            let within = matches.split_off(start);
+            let before = unpaired_matches(core::mem::take(matches));
+
+            let clipped_line = ClippedLine {
+                line,
+                dropped_before: start - before.len(),
+                dropped_after: total - end - after.len(),
+            };
+            matches.extend(before);
+            matches.extend(within);
+            matches.extend(after);
+            clipped_lines.push(clipped_line);
+        }
+        clipped_lines
+    }
+
+    /// Same as [`ParsedBuffer::parse_bytes`] but stops after roughly the given duration,
+    /// returning a continuation which may be resumed later, e.g. on the next tick of the
+    /// event loop
+    #[cfg(feature = "std")]
+    pub fn parse_with_budget<L: AsRef<[u8]>>(
+        filetype: &str,
+        tab_width: u8,
+        lines: &[L],
+        budget: std::time::Duration,
+    ) -> Option<BudgetedParse> {
+        filetype_tokens(filetype)?;
+        let continuation = ParseContinuation {
+            filetype: filetype.to_string(),
+            tab_width,
+            matches_by_line: Vec::with_capacity(lines.len()),
+            state_by_line: Vec::with_capacity(lines.len()),
+        };
+        Some(continuation.resume(lines, budget))
+    }
+
+    /// Parses the buffers on up to `threads` threads, i.e. when restoring a session with many
+    /// buffers, where each buffer is its filetype, tab width and lines. Returns the same as
+    /// [`ParsedBuffer::parse_bytes_with_metrics`] for each buffer, in the same order
+    #[cfg(feature = "std")]
+    pub fn parse_many<L: AsRef<[u8]> + Sync>(
+        buffers: &[(&str, u8, &[L])],
+        threads: usize,
+    ) -> Vec<Option<(Self, Metrics)>> {
+        let next = AtomicUsize::new(0);
+        let parse_next = || {
+            let mut parsed = vec![];
+            loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&(filetype, tab_width, lines)) = buffers.get(idx) else {
+                    return parsed;
+                };
+                parsed.push((
+                    idx,
+                    Self::parse_bytes_with_metrics(filetype, tab_width, lines),
+                ));
+            }
+        };
+
+        let mut results = vec![None; buffers.len()];
+        std::thread::scope(|scope| {
+            let workers = (1..threads.clamp(1, buffers.len().max(1)))
+                .map(|_| scope.spawn(parse_next))
+                .collect::<Vec<_>>();
+            // The current thread takes part in parsing too
+            let parsed = parse_next();
+            for (idx, result) in workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .chain(parsed)
+            {
+                results[idx] = result;
+            }
+        });
+        results
+    }
+
+    /// Same as [`ParsedBuffer::parse`] but collects the lines from an iterator first
+    pub fn parse_lines<I>(filetype: &str, tab_width: u8, lines: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let lines = lines.into_iter().collect::<Vec<_>>();
+        Self::parse(filetype, tab_width, &lines)
+    }
+
+    pub fn reparse_range<S: AsRef<str>>(
+        &mut self,
+        filetype: &str,
+        tab_width: u8,
+        lines: &[S],
+        start_line: Option<usize>,
+        old_end_line: Option<usize>,
+        new_end_line: Option<usize>,
+    ) -> bool {
+        self.reparse_range_bytes(
+            filetype,
+            tab_width,
+            &as_bytes(lines),
+            start_line,
+            old_end_line,
+            new_end_line,
+        )
+    }
+
+    /// Same as [`ParsedBuffer::reparse_range`] but accepts the lines as bytes
+    pub fn reparse_range_bytes<L: AsRef<[u8]>>(
+        &mut self,
+        filetype: &str,
+        tab_width: u8,
+        lines: &[L],
+        start_line: Option<usize>,
+        old_end_line: Option<usize>,
+        new_end_line: Option<usize>,
+    ) -> bool {
+        let max_line = self.matches_by_line.len();
+        let start_line = start_line.unwrap_or(0).min(max_line);
+        let old_end_line = old_end_line.unwrap_or(max_line).min(max_line);
+
+        let initial_state = if start_line > 0 {
+            self.state_by_line
+                .get(start_line - 1)
+                .cloned()
+                .unwrap_or(State::Normal)
+        } else {
+            State::Normal
+        };
+
+        // Lines spliced in unchanged, i.e. by undo or a formatter, give the same result
+        let new_len = new_end_line.map_or(lines.len(), |end| end.saturating_sub(start_line));
+        if new_len > 0
+            && new_len == old_end_line - start_line
+            && self.is_unchanged(
+                &lines[..new_len.min(lines.len())],
+                start_line,
+                initial_state,
+            )
+        {
+            self.indent_changes = start_line..start_line;
+            return true;
+        }
+
+        // Lines brought back by an undo or redo are restored from the history, and lines which
+        // were only reindented, i.e. by a formatter, reuse their matches
+        let restored = match new_len > 0 && new_len <= lines.len() {
+            true => self
+                .history
+                .take(&lines[..new_len], initial_state)
+                .or_else(|| match new_len == old_end_line - start_line {
+                    true => {
+                        self.shift_whitespace_changes(&lines[..new_len], start_line, initial_state)
+                    }
+                    false => None,
+                }),
+            false => None,
+        };
+        let new = match restored {
+            Some(restored) => restored,
+            None => {
+                let Some(new) = parse_filetype(filetype, tab_width, lines, initial_state) else {
+                    return false;
+                };
+                let length = new_end_line.map_or(new.matches_by_line.len(), |_| new_len);
+                Replaced {
+                    matches_by_line: new.matches_by_line[0..length].to_vec(),
+                    state_by_line: new.state_by_line[0..length].to_vec(),
+                    line_hashes: new.line_hashes[0..length].to_vec(),
+                }
+            }
+        };
+        let length = new.matches_by_line.len();
+
+        let replaced = Replaced {
+            matches_by_line: self
+                .matches_by_line
+                .splice(start_line..old_end_line, new.matches_by_line)
+                .collect(),
+            state_by_line: self
+                .state_by_line
+                .splice(start_line..old_end_line, new.state_by_line)
+                .collect(),
+            line_hashes: self
+                .line_hashes
+                .splice(start_line..old_end_line, new.line_hashes)
+                .collect(),
+        };
+        self.history.push(replaced);
+        self.reindent(&lines[0..length], tab_width, start_line, old_end_line);
+
+        self.calculate_stack_heights(tab_width);
+
+        true
+    }
+
+    /// Whether the lines starting at `start_line` have the same lengths and hashes as the
+    /// existing ones when parsed from the initial state, see [`line_hashes`]
+    fn is_unchanged<L: AsRef<[u8]>>(
+        &self,
+        lines: &[L],
+        start_line: usize,
+        initial_state: State,
+    ) -> bool {
+        let Some(hashes) = self.line_hashes.get(start_line..start_line + lines.len()) else {
+            return false;
+        };
+        let start_states =
+            core::iter::once(initial_state).chain(self.state_by_line[start_line..].iter().copied());
+        lines
+            .iter()
+            .zip(start_states)
+            .zip(hashes)
+            .all(|((line, state), &hash)| line_hash(line.as_ref(), state) == hash)
+    }
+
+    /// When the lines starting at `start_line` only differ from the existing ones in their
+    /// leading and trailing whitespace, gets the existing matches moved by the change in the
+    /// leading whitespace, without tokenizing the lines again. Lines gaining or losing all of
+    /// their leading whitespace, or ending with an escape, may be tokenized differently, i.e.
+    /// due to comments which must start in the first column, so they're never reused
+    fn shift_whitespace_changes<L: AsRef<[u8]>>(
+        &self,
+        lines: &[L],
+        start_line: usize,
+        initial_state: State,
+    ) -> Option<Replaced> {
+        let end_line = start_line + lines.len();
+        let old_hashes = self.line_hashes.get(start_line..end_line)?;
+        let state_by_line = self.state_by_line.get(start_line..end_line)?;
+        let line_hashes = line_hashes(lines, initial_state, state_by_line);
+        let is_shiftable =
+            lines
+                .iter()
+                .zip(old_hashes)
+                .zip(&line_hashes)
+                .all(|((line, old), new)| {
+                    old.content == new.content
+                        && old.content_len == new.content_len
+                        && (old.indent_len == 0) == (new.indent_len == 0)
+                        && !line.as_ref().trim_ascii_end().ends_with(b"\\")
+                });
+        if !is_shiftable {
+            return None;
+        }
+
+        let matches_by_line = self.matches_by_line[start_line..end_line]
+            .iter()
+            .zip(old_hashes.iter().zip(&line_hashes))
+            .map(|(matches, (old, new))| {
+                matches
+                    .iter()
+                    .map(|match_| Match {
+                        col: match_.col + new.indent_len - old.indent_len,
+                        ..match_.clone()
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(Replaced {
+            matches_by_line,
+            state_by_line: state_by_line.to_vec(),
+            line_hashes,
+        })
+    }
+
+    /// Updates the indent levels of the reparsed lines, which replaced the lines from
+    /// `start_line` to `old_end_line`, and of the blank lines after them since those inherit
+    /// their indent level. Records the lines whose indent levels changed
+    fn reindent<L: AsRef<[u8]>>(
+        &mut self,
+        lines: &[L],
+        tab_width: u8,
+        start_line: usize,
+        old_end_line: usize,
+    ) {
+        let old_end_line = old_end_line.min(self.indent_levels.len());
+        let new_end_line = start_line + lines.len();
+        let last_indent = start_line
+            .checked_sub(1)
+            .and_then(|line| self.indent_levels.get(line))
+            .copied();
+        let new_indent_levels = indent_levels_after(lines, tab_width, last_indent);
+
+        // When the line count stayed the same, only report the lines which changed
+        let mut changes = start_line..new_end_line;
+        if old_end_line - start_line == lines.len() {
+            let old_indent_levels = &self.indent_levels[start_line..old_end_line];
+            let differs = |(old, new): (&u8, &u8)| old != new;
+            let mut pairs = old_indent_levels.iter().zip(&new_indent_levels);
+            changes = match pairs.clone().position(differs) {
+                Some(first) => {
+                    let last = pairs.rposition(differs).unwrap_or(first);
+                    start_line + first..start_line + last + 1
+                }
+                None => new_end_line..new_end_line,
+            };
+        }
+
+        self.indent_levels
+            .splice(start_line..old_end_line, new_indent_levels);
+        self.blank_indents.splice(
+            start_line..old_end_line,
+            lines.iter().map(|line| blank_indent(line, tab_width)),
+        );
+
+        // Blank lines after the range follow the indent level of the last reparsed line
+        let mut last_indent = new_end_line
+            .checked_sub(1)
+            .and_then(|line| self.indent_levels.get(line))
+            .copied();
+        let mut line = new_end_line;
+        while let Some(&Some(blank_indent)) = self.blank_indents.get(line) {
+            let indent = *last_indent.get_or_insert(blank_indent);
+            if self.indent_levels[line] == indent {
+                break;
+            }
+            self.indent_levels[line] = indent;
+            line += 1;
+        }
+        if line > new_end_line {
+            if changes.is_empty() {
+                changes.start = new_end_line;
+            }
+            changes.end = line;
+        }
+
+        self.indent_changes = changes;
+    }
+
+    /// Gets the lines whose indent levels changed in the last parse or reparse, so that
+    /// indent guides may be redrawn incrementally. Covers every line after a full parse
+    pub fn indent_changes(&self) -> Range<usize> {
+        self.indent_changes.clone()
+    }
+
+    /// Number of reparses since the full parse, including the ones restored from the history
+    pub fn generation(&self) -> u64 {
+        self.history.generation()
+    }
+
+    /// Snapshots the parsed state so that it may be restored later, i.e. when a large edit
+    /// is undone, without reparsing the buffer
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.clone())
+    }
+
+    /// Replaces the parsed state with the one from the checkpoint
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        *self = checkpoint.0;
+    }
+
+    /// Gets the cap on the nesting depth, see [`ParsedBuffer::set_depth_limit`]
+    pub fn depth_limit(&self) -> DepthLimit {
+        self.depth_limit
+    }
+
+    /// Sets the cap on the nesting depth used for this buffer, [`DepthLimit::DEFAULT`] unless
+    /// set, and recalculates the stack heights with it
+    pub fn set_depth_limit(&mut self, limit: DepthLimit, tab_width: u8) {
+        self.depth_limit = limit;
+        self.calculate_stack_heights(tab_width);
+    }
+
+    fn calculate_stack_heights(&mut self, tab_width: u8) {
+        let limit = self.depth_limit;
+        debug_assert!(
+            self.matches_by_line
+                .iter()
+                .all(|matches| matches.is_sorted_by_key(|match_| match_.col)),
+            "matches must be sorted by column"
+        );
+
+        let mut unmatched_openings: Vec<(usize, usize)> = vec![];
+        // Openings skipped by a closing of another type, i.e. the `(`s in `[ ( ( ) ]`
+        let mut skipped_openings: Vec<(usize, usize)> = vec![];
+        // Openings which don't count towards the stack heights of the matches after them, see
+        // [`ParsedBuffer::recount_stack_heights`]
+        let mut uncounted_openings: Vec<(usize, usize)> = vec![];
+        let mut stack = vec![];
+
+        // Get stack heights for all openings using a traditional stack
+        // This results in matching on the closest pairs when there are mismatched
+        // openings/closings
+        // [ ( ( [] (  ) ]
+        // 0     11 1  1 0
+        // Positions of middles and their openings, which share the opening's stack height
+        let mut middles = vec![];
+        // Number of openings past the depth limit which haven't been closed, which aren't
+        // pushed onto the stack
+        let mut overflowed = 0usize;
+        let overflow_height = match limit.overflow {
+            DepthOverflow::Clamp => Some(limit.max_depth),
+            DepthOverflow::Unmatched => None,
+        };
+
+        for (line, matches) in self.matches_by_line.iter_mut().enumerate() {
+            'outer: for match_ in matches.iter_mut() {
+                // Past the depth limit, where only the delimiters are counted so that strings
+                // and comments get their stack heights as usual
+                if matches!(match_.token, Token::Delimiter(_, _))
+                    && (overflowed > 0
+                        || (match_.kind == Kind::Opening && stack.len() >= limit.max_depth))
+                {
+                    match match_.kind {
+                        Kind::Opening => {
+                            overflowed += 1;
+                            uncounted_openings.push((line, match_.col));
+                        }
+                        Kind::Closing => overflowed -= 1,
+                        _ => {}
+                    }
+                    match_.stack_height = overflow_height;
+                    continue;
+                }
+                // Opening delimiter
+                if match_.kind == Kind::Opening {
+                    stack.push((line, match_));
+                }
+                // Middle, such as `else`, belonging to the closest opening
+                else if let Kind::Middle(_) = match_.kind {
+                    match_.stack_height = None;
+                    if let Some((opening_line, opening)) = stack
+                        .iter()
+                        .rev()
+                        .find(|(_, opening)| opening.token == match_.token)
                     {
-                        lowest_stack_height = stack_height;
+                        middles.push(((line, match_.col), (*opening_line, opening.col)));
                     }
-                    // In this example: ( [ ( | ) ] )
-                    // Stack:             ^ ^   ^ ^
-                    // Cursor stack height: 2
-                    // Inserting a closing pair would not close the outer pair, so we exit
-                    else {
-                        return None;
+                }
+                // Closing delimiter
+                else {
+                    for (i, (_, opening)) in stack.iter().enumerate().rev() {
+                        if opening.token == match_.token {
+                            // Mark all skipped matches as unmatched
+                            for (unmatched_line, unmatched_opening) in
+                                stack.splice((i + 1).., vec![])
+                            {
+                                unmatched_openings.push((unmatched_line, unmatched_opening.col));
+                                skipped_openings.push((unmatched_line, unmatched_opening.col));
+                            }
+
+                            // Update stack height
+                            let (_, opening) = stack.pop().unwrap();
+                            opening.stack_height = Some(stack.len());
+                            match_.stack_height = Some(stack.len());
+                            continue 'outer;
+                        }
                     }
+
+                    // No match found, mark as unmatched
+                    match_.stack_height = None;
                 }
+            }
+        }
+
+        // Remaining items in stack must be unmatched
+        for (line, match_) in stack.into_iter() {
+            unmatched_openings.push((line, match_.col));
+        }
+        unmatched_openings.sort();
+
+        // Remove stack heights for unmatched openings
+        for (line, col) in unmatched_openings.iter() {
+            let match_ = self.match_at_mut(*line, *col).unwrap();
+            match_.stack_height = None;
+        }
+
+        // Prefer matching on the pairs whose closing is indented like an unmatched opening
+        for (line, col) in unmatched_openings.into_iter().rev() {
+            self.rematch_by_indent_recursive(line, col, tab_width);
+        }
+
+        // Then prefer matching on the furthest pair for the remaining skipped openings
+        // As is, we have matched like so:
+        // [ ( ( [] (  ) ]
+        // 0     11 1  1 0
+        // but we want to match like:
+        // [ ( ( [] (  ) ]
+        // 0 1   22    1 0
+        skipped_openings.sort();
+        for &(line, col) in skipped_openings.iter().rev() {
+            self.rematch_furthest_recursive(line, col);
+        }
+
+        // Skipped openings stayed on the stack while the pairs after them were matched, so
+        // count the stack heights again without them
+        uncounted_openings.extend(skipped_openings);
+        uncounted_openings.sort();
+        self.recount_stack_heights(limit, &uncounted_openings);
+
+        for ((line, col), (opening_line, opening_col)) in middles {
+            let stack_height = self
+                .match_at(opening_line, opening_col)
+                .and_then(|opening| opening.stack_height);
+            self.match_at_mut(line, col).unwrap().stack_height = stack_height;
+        }
+
+        self.calculate_line_depths();
+    }
+
+    /// Indexes the stack height at the start of each line, see
+    /// [`ParsedBuffer::stack_height_at`]
+    pub(crate) fn calculate_line_depths(&mut self) {
+        let mut depth = 0;
+        self.depth_at_line_start = self
+            .matches_by_line
+            .iter()
+            .map(|matches| {
+                let line_start = depth;
+                depth = matches.iter().fold(depth, depth_after);
+                line_start
+            })
+            .collect();
+    }
+
+    /// Gets the indent level of the line, rounded down to the nearest tab width
+    pub fn rounded_indent_level(&self, line: usize, tab_width: u8) -> u8 {
+        self.indent_levels[line].div_floor(tab_width) * tab_width
+    }
+
+    /// Given an unmatched opening's position, attempts to find a matching opening/closing pair
+    /// where the closing ident level matches the unmatched opening.
+    /// Performed recursively until the match cannot be moved further down the stack.
+    ///
+    /// ```text
+    /// if some_example {
+    ///     //          ^ unmatched
+    ///     if no_closing_on_this {
+    ///         //       matched  ^
+    /// }
+    /// ```
+    /// becomes
+    /// ```text
+    /// if some_example {
+    ///     //  matched ^
+    ///     if no_closing_on_this {
+    ///         //      unmatched ^
+    ///     }
+    /// }
+    /// ```
+    pub fn rematch_by_indent_recursive(&mut self, line: usize, col: usize, tab_width: u8) {
+        let indent_level = self.rounded_indent_level(line, tab_width);
+        let token = self.match_at(line, col).unwrap().token;
+        // The index isn't valid until the stack heights are calculated
+        let stack_height = self.scan_stack_height_at(line, col);
+
+        // Find the first matched opening that has the same stack height and token
+        let matched_pair = self
+            .iter_from(line, col + 1)
+            .take_while(|match_| {
+                match_
+                    .stack_height
+                    .map(|sh| sh >= stack_height.saturating_add(1))
+                    .unwrap_or(true)
+            })
+            .filter(|match_| match_.token == token.clone())
+            .flat_map(|match_| self.match_pair(match_.line, match_.col))
+            .find(|(open, close)| {
+                self.rounded_indent_level(close.line, tab_width) == indent_level
+                    && self.rounded_indent_level(close.line, tab_width)
+                        != self.rounded_indent_level(open.line, tab_width)
+            });
+
+        if let Some((matched_opening_with_line, matched_closing_with_line)) = matched_pair {
+            // Mark matched opening as unmatched
+            let matched_opening = self
+                .match_at_mut(
+                    matched_opening_with_line.line,
+                    matched_opening_with_line.col,
+                )
+                .unwrap();
+            matched_opening.stack_height = None;
+
+            // Mark unmatched opening as matched, using the stack height - 1 as unmatched
+            // openings lead to incorrect stack heights for the matches after them
+            // For example:
+            // [ ( ( ) ]
+            // 0   2 2 0
+            // When it should be:
+            // [ ( ( ) ]
+            // 0   1 1 0
+            // But since we're now matching on the unmatched opening, we end up with:
+            // [ ( ( ) ]
+            // 0 1   1 0
+            let unmatched_opening = self.match_at_mut(line, col).unwrap();
+            unmatched_opening.stack_height = Some(stack_height);
+
+            let matched_closing = self
+                .match_at_mut(
+                    matched_closing_with_line.line,
+                    matched_closing_with_line.col,
+                )
+                .unwrap();
+            matched_closing.stack_height = Some(stack_height);
+
+            // All matches after the closing match are now 1 stack height shallower,
+            // For example, starting with:
+            // [ ( ( ) { } ]
+            // 0   2 2 2 2 0
+            // After the previous step, we have:
+            // [ ( ( ) { } ]
+            // 0 1   1 2 2 0
+            // So we update the "{ }" stack height by 1
+            // [ ( ( ) { } ]
+            // 0 1   1 1 1 0
+            for match_ in self.matches_by_line[matched_closing_with_line.line..]
+                .iter_mut()
+                .enumerate()
+                .flat_map(|(line_idx, matches)| {
+                    matches.iter_mut().filter(move |match_| {
+                        line_idx != 0 || match_.col > matched_closing_with_line.col
+                    })
+                })
+            {
+                if match_.stack_height == Some(stack_height) && match_.kind == Kind::Closing {
+                    break;
+                }
+                match_.stack_height = match_
+                    .stack_height
+                    .map(|stack_height| stack_height.saturating_sub(1));
+            }
+
+            self.rematch_by_indent_recursive(
+                matched_opening_with_line.line,
+                matched_opening_with_line.col,
+                tab_width,
+            );
+        }
+    }
+
+    /// Given an unmatched opening's position, moves the closing of the first pair of the same
+    /// type directly inside of it onto the unmatched opening, leaving the pair's opening
+    /// unmatched instead. Performed recursively on the pair's opening, so that the closing
+    /// ends up matching the furthest opening.
+    ///
+    /// ```text
+    /// [ ( ( ) ]
+    /// 0     1 0
+    /// ```
+    /// becomes
+    /// ```text
+    /// [ ( ( ) ]
+    /// 0 1   1 0
+    /// ```
+    ///
+    /// Stack heights aren't updated, other than marking the openings as matched or unmatched,
+    /// see [`ParsedBuffer::recount_stack_heights`]
+    fn rematch_furthest_recursive(&mut self, line: usize, col: usize) {
+        let opening = self.match_at(line, col).unwrap();
+        // Already rematched by indent
+        if opening.stack_height.is_some() {
+            return;
+        }
+
+        // Find the first pair of the same type directly inside of the opening, which ends at
+        // the first closing which isn't nested inside of another pair
+        let mut inner_opening = None;
+        let mut depth = 0usize;
+        let mut closing = None;
+        for match_ in self
+            .iter_from(line, col + 1)
+            .filter(|match_| match_.stack_height.is_some())
+        {
+            match match_.kind {
+                Kind::Opening if depth == 0 && inner_opening.is_none() => {
+                    if match_.token == opening.token {
+                        inner_opening = Some(match_);
+                    } else {
+                        depth += 1;
+                    }
+                }
+                Kind::Opening => depth += 1,
+                Kind::Closing if depth == 0 => {
+                    if inner_opening.is_some() {
+                        closing = Some(match_);
+                    }
+                    break;
+                }
+                Kind::Closing => depth -= 1,
+                Kind::NonPair | Kind::Middle(_) => {}
+            }
+        }
+        let (Some(inner_opening), Some(closing)) = (inner_opening, closing) else {
+            return;
+        };
+
+        self.match_at_mut(inner_opening.line, inner_opening.col)
+            .unwrap()
+            .stack_height = None;
+        self.match_at_mut(line, col).unwrap().stack_height = closing.stack_height;
+
+        self.rematch_furthest_recursive(inner_opening.line, inner_opening.col);
+    }
+
+    /// Sets the stack heights of the matched openings and closings to the number of openings
+    /// around them, clamped to the depth limit. Unmatched openings count until a closing
+    /// around them, as if they were still on the stack, unless they're in the sorted
+    /// uncounted positions
+    fn recount_stack_heights(&mut self, limit: DepthLimit, uncounted_openings: &[(usize, usize)]) {
+        let mut uncounted_openings = uncounted_openings.iter().peekable();
+        // Whether each opening on the stack is matched
+        let mut stack: Vec<bool> = vec![];
+        for (line, match_) in self
+            .matches_by_line
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(line, matches)| matches.iter_mut().map(move |match_| (line, match_)))
+        {
+            let is_uncounted = uncounted_openings
+                .next_if_eq(&&(line, match_.col))
+                .is_some();
+            match (match_.kind, match_.stack_height.as_mut()) {
+                (Kind::Opening, Some(stack_height)) => {
+                    *stack_height = stack.len().min(limit.max_depth);
+                    stack.push(true);
+                }
+                (Kind::Opening, None) if !is_uncounted => stack.push(false),
+                (Kind::Closing, Some(stack_height)) => {
+                    while stack.pop() == Some(false) {}
+                    *stack_height = stack.len().min(limit.max_depth);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn line_matches(&self, line_number: usize) -> Option<Vec<Match>> {
+        self.line_matches_ref(line_number).map(<[Match]>::to_vec)
+    }
+
+    /// Same as [`ParsedBuffer::line_matches`] but borrows the matches rather than cloning
+    /// them, i.e. for reading the visible lines on every redraw
+    pub fn line_matches_ref(&self, line_number: usize) -> Option<&[Match]> {
+        self.matches_by_line.get(line_number).map(Vec::as_slice)
+    }
+
+    /// Gets the rainbow highlights of the delimiters within the lines as a flat list of
+    /// `line, col, len, level` for passing them to Lua at once, where the level is the stack
+    /// height modulo `num_colors`, or `num_colors` for unmatched delimiters
+    pub fn highlights(&self, lines: Range<usize>, num_colors: usize) -> Vec<usize> {
+        let num_colors = num_colors.max(1);
+        let end_line = lines.end.min(self.matches_by_line.len());
+        let start_line = lines.start.min(end_line);
+        let mut highlights = vec![];
+        for (line, matches) in self.matches_by_line[start_line..end_line]
+            .iter()
+            .enumerate()
+        {
+            for match_ in matches
+                .iter()
+                .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
+            {
+                highlights.extend([
+                    start_line + line,
+                    match_.col,
+                    match_.len(),
+                    match_
+                        .stack_height
+                        .map_or(num_colors, |stack_height| stack_height % num_colors),
+                ]);
+            }
+        }
+        highlights
+    }
+
+    pub fn get_indent_levels(&self, start_line: usize, end_line: usize) -> Vec<u8> {
+        let start_idx = start_line.min(self.indent_levels.len());
+        let end_idx = end_line.min(self.indent_levels.len());
+
+        if start_idx >= end_idx {
+            return vec![];
+        }
+
+        self.indent_levels[start_idx..end_idx].to_vec()
+    }
+
+    /// Same as [`ParsedBuffer::get_indent_levels`] but blank lines get the higher indent level
+    /// of the nearest non-blank lines above and below, rather than the one above, so that
+    /// indent guides may be drawn continuously through empty lines in a scope
+    pub fn get_virtual_indent_levels(&self, start_line: usize, end_line: usize) -> Vec<u8> {
+        let mut levels = self.get_indent_levels(start_line, end_line);
+        let start_idx = start_line.min(self.indent_levels.len());
+        let end_idx = start_idx + levels.len();
+
+        let mut next_indent = (end_idx..self.blank_indents.len())
+            .find(|&line| self.blank_indents[line].is_none())
+            .map(|line| self.indent_levels[line]);
+        for (line, level) in (start_idx..end_idx).zip(levels.iter_mut()).rev() {
+            if self.blank_indents[line].is_none() {
+                next_indent = Some(*level);
+            }
+            // Blank lines already hold the indent level of the line above
+            else if let Some(next_indent) = next_indent {
+                *level = (*level).max(next_indent);
+            }
+        }
+        levels
+    }
+
+    pub fn iter_from(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> impl Iterator<Item = MatchWithLine> + '_ {
+        self.matches_by_line[line_number..]
+            .iter()
+            .enumerate()
+            .flat_map(move |(offset, matches)| {
+                let current_line = line_number + offset;
+                let start = match offset {
+                    0 => matches.partition_point(|match_| match_.col < col),
+                    _ => 0,
+                };
+                matches[start..]
+                    .iter()
+                    .map(move |match_| match_.with_line(current_line))
+            })
+    }
+
+    pub fn iter_to(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> impl Iterator<Item = MatchWithLine> + '_ {
+        self.matches_by_line[0..=line_number.min(self.matches_by_line.len() - 1)]
+            .iter()
+            .enumerate()
+            .rev()
+            .flat_map(move |(current_line, matches)| {
+                let end = match current_line == line_number {
+                    true => matches.partition_point(|match_| match_.col < col),
+                    false => matches.len(),
+                };
+                matches[..end]
+                    .iter()
+                    .map(move |match_| match_.with_line(current_line))
+            })
+    }
+
+    pub fn span_at(&self, line_number: usize, col: usize) -> Option<String> {
+        let line_matches = self.matches_by_line.get(line_number)?;
+        let line_state = self.state_by_line.get(line_number)?;
+
+        // Look for spans starting in the current line before the desired column
+
+        let matching_span = line_matches
+            .iter()
+            .rev()
+            // Get all opening matches before the cursor on the current line
+            .filter(|match_| match_.kind == Kind::Opening && match_.col <= col)
+            // Find closing match on the same line or no match (overflows to next line)
+            .find_map(|opening| {
+                match opening.token {
+                    Token::InlineSpan(span, _, _) | Token::BlockSpan(span, _, _) => {
+                        let closing = line_matches.iter().find(|closing| {
+                            closing.kind == Kind::Closing
+                                && closing.col > opening.col
+                                && closing.token == opening.token
+                                && closing.stack_height == opening.stack_height
+                        });
+
+                        match closing {
+                            // Ends before desired column
+                            Some(closing) if closing.end_col() <= col => None,
+                            // Extends to end of line or found closing after desired column
+                            _ => Some(span),
+                        }
+                    }
+                    _ => None,
+                }
+            });
+
+        if let Some(span) = matching_span {
+            return Some(span.to_string());
+        }
+
+        // Look for spans that started before the current line
+        match line_state {
+            // TODO: check that the span doesn't end before the cursor
+            State::InInlineSpan(span) | State::InBlockSpan(span) => Some(span.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Same as [`ParsedBuffer::span_at`] but classifies the string, comment or span at the
+    /// position, i.e. for allowing auto pairs in docstrings but not in regexes. Like
+    /// [`ParsedBuffer::state_at`], the closing of a string is in it but the opening isn't.
+    /// The text of the line starting a block string is read with `line_text`, to tell
+    /// whether it's a docstring
+    pub fn span_kind_at<L: AsRef<[u8]>>(
+        &self,
+        filetype: &str,
+        line_number: usize,
+        col: usize,
+        line_text: impl FnOnce(usize) -> Option<L>,
+    ) -> Option<SpanKind> {
+        let token_sets = filetype_token_sets(filetype).unwrap_or_default();
+        match self.state_at(line_number, col) {
+            State::InLineComment | State::InBlockComment(_) => Some(SpanKind::Comment),
+            State::InInlineSpan(span) | State::InBlockSpan(span) => Some(SpanKind::Span(span)),
+            State::InRegexClass(_) => Some(SpanKind::Regex),
+            State::InString(open) if token_sets.regexes.contains(&open) => Some(SpanKind::Regex),
+            State::InString(_) => Some(SpanKind::String),
+            State::InBlockString(open) if token_sets.docstrings.contains(&open) => {
+                let opening = self.rfind_before(line_number, col, |match_| {
+                    match_.kind == Kind::Opening
+                        && matches!(match_.token, Token::BlockString(opening, _) if opening == open)
+                });
+                let starts_line = opening.and_then(|opening| {
+                    let text = line_text(opening.line)?;
+                    let before = text.as_ref().get(..opening.col)?;
+                    Some(before.iter().all(u8::is_ascii_whitespace))
+                });
+                match starts_line {
+                    Some(true) => Some(SpanKind::Docstring),
+                    _ => Some(SpanKind::String),
+                }
+            }
+            State::InBlockString(_) => Some(SpanKind::String),
+            _ => None,
+        }
+    }
+
+    pub fn match_at(&self, line_number: usize, col: usize) -> Option<Match> {
+        let matches = self.matches_by_line.get(line_number)?;
+        matches.get(match_idx(matches, col)?).cloned()
+    }
+
+    /// Same as [`ParsedBuffer::match_at`] but also gets the byte of the token the position is
+    /// on, i.e. 1 on the `*` of `/*`
+    pub fn match_at_with_offset(&self, line_number: usize, col: usize) -> Option<(Match, usize)> {
+        let match_ = self.match_at(line_number, col)?;
+        let offset = col - match_.col;
+        Some((match_, offset))
+    }
+
+    pub fn match_at_mut(&mut self, line_number: usize, col: usize) -> Option<&mut Match> {
+        let matches = self.matches_by_line.get_mut(line_number)?;
+        let idx = match_idx(matches, col)?;
+        matches.get_mut(idx)
+    }
+
+    /// Gets the start of the token at the position, or the position itself when it isn't on
+    /// a token, so that positions within a token spanning multiple bytes behave like its start
+    fn token_start(&self, line_number: usize, col: usize) -> usize {
+        self.match_at(line_number, col)
+            .map_or(col, |match_| match_.col)
+    }
+
+    /// Gets the matches of the line starting within the columns, so unlike
+    /// [`ParsedBuffer::matches_in`], a multibyte token such as `/*` starting just before the
+    /// columns is excluded even when it ends inside them
+    pub fn line_matches_in(&self, line_number: usize, cols: Range<usize>) -> &[Match] {
+        self.matches_before(line_number, cols.end, |match_| match_.col < cols.start)
+    }
+
+    /// Gets the matches of the line overlapping the columns, including those which start
+    /// before the columns but end inside them, i.e. for redrawing part of a line
+    pub fn matches_in(&self, line_number: usize, cols: Range<usize>) -> &[Match] {
+        self.matches_before(line_number, cols.end, |match_| {
+            match_.end_col() <= cols.start
+        })
+    }
+
+    /// Gets the matches of the line starting before the end column, without the leading
+    /// matches for which `is_skipped` returns true
+    fn matches_before(
+        &self,
+        line_number: usize,
+        end_col: usize,
+        is_skipped: impl FnMut(&Match) -> bool,
+    ) -> &[Match] {
+        let Some(matches) = self.matches_by_line.get(line_number) else {
+            return &[];
+        };
+        let start = matches.partition_point(is_skipped);
+        let end = matches.partition_point(|match_| match_.col < end_col);
+        &matches[start..end.max(start)]
+    }
+
+    pub fn match_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        let match_at_pos = self.match_at(line_number, col)?.with_line(line_number);
+        // The position may be within a token spanning multiple bytes, i.e. on the `*` of `/*`
+        let col = match_at_pos.col;
+
+        // Ignore unmatched delimiter
+        if matches!(match_at_pos.token, Token::Delimiter(_, _))
+            && match_at_pos.stack_height.is_none()
+        {
+            return None;
+        }
+
+        // Opening match
+        if match_at_pos.kind == Kind::Opening {
+            let closing_match = self.matches_by_line[line_number..]
+                .iter()
+                .enumerate()
+                .map(|(matches_line_number, matches)| (matches_line_number + line_number, matches))
+                .find_map(|(matches_line_number, matches)| {
+                    matches
+                        .iter()
+                        .find(|match_| {
+                            (line_number != matches_line_number || match_.col > col)
+                                && match_.kind == Kind::Closing
+                                && match_at_pos.token == match_.token
+                                && match_at_pos.stack_height == match_.stack_height
+                        })
+                        .map(|match_| match_.with_line(matches_line_number))
+                })?;
+
+            Some((match_at_pos, closing_match))
+        }
+        // Closing match
+        else if match_at_pos.kind == Kind::Closing {
+            let opening_match = self.matches_by_line[0..=line_number]
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(matches_line_number, matches)| {
+                    matches
+                        .iter()
+                        .rev()
+                        .find(|match_| {
+                            (line_number != matches_line_number || match_.col < col)
+                                && match_.kind == Kind::Opening
+                                && match_at_pos.token == match_.token
+                                && match_at_pos.stack_height == match_.stack_height
+                        })
+                        .map(|match_| match_.with_line(matches_line_number))
+                })?;
+
+            Some((opening_match, match_at_pos))
+        } else {
+            None
+        }
+    }
+
+    /// Gets the opening and closing of the string, block comment or span at the position,
+    /// i.e. the `/*` and `*/` of a comment, with the position on either token or between
+    /// them. Unterminated strings and comments have no pair
+    pub fn span_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        let is_span = |match_: &Match| {
+            !matches!(match_.token, Token::Delimiter(_, _) | Token::LineComment(_))
+        };
+        if let Some(match_) = self.match_at(line_number, col) {
+            return match is_span(&match_) {
+                true => self.match_pair(line_number, col),
+                false => None,
+            };
+        }
+
+        match self.state_at(line_number, col) {
+            State::InString(_)
+            | State::InBlockString(_)
+            | State::InBlockComment(_)
+            | State::InInlineSpan(_)
+            | State::InBlockSpan(_)
+            | State::InRegexClass(_) => {
+                let opening = self.rfind_before(line_number, col, |match_| {
+                    match_.kind == Kind::Opening && is_span(match_)
+                })?;
+                self.match_pair(opening.line, opening.col)
+            }
+            _ => None,
+        }
+    }
+
+    /// Gets the opening, middles (i.e. `else`) and closing of the keyword pair or delimiter
+    /// at the position, in order
+    fn construct_matches(&self, line_number: usize, col: usize) -> Option<Vec<MatchWithLine>> {
+        let match_at_pos = self.match_at(line_number, col)?;
+        let (opening, closing) = match match_at_pos.kind {
+            Kind::Middle(_) => {
+                let stack_height = match_at_pos.stack_height?;
+                let opening = self.rfind_before(line_number, col, |match_| {
+                    match_.kind == Kind::Opening
+                        && match_.token == match_at_pos.token
+                        && match_.stack_height == Some(stack_height)
+                })?;
+                self.match_pair(opening.line, opening.col)?
+            }
+            _ => self.match_pair(line_number, col)?,
+        };
+
+        let mut matches = vec![opening.clone()];
+        matches.extend(
+            self.iter_from(opening.line, opening.col + 1)
+                .take_while(|match_| (match_.line, match_.col) < (closing.line, closing.col))
+                .filter(|match_| {
+                    matches!(match_.kind, Kind::Middle(_))
+                        && match_.token == opening.token
+                        && match_.stack_height == opening.stack_height
+                }),
+        );
+        matches.push(closing);
+        Some(matches)
+    }
+
+    /// Gets the ranges of every token of the construct at the position, such as `if`, `else`
+    /// and `end if`, for highlighting the whole construct
+    pub fn construct_ranges(&self, line_number: usize, col: usize) -> Option<Vec<TokenRange>> {
+        let matches = self.construct_matches(line_number, col)?;
+        Some(
+            matches
+                .iter()
+                .map(|match_| TokenRange {
+                    line: match_.line,
+                    start_col: match_.col,
+                    end_col: match_.end_col(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Gets the next part of the construct at the position, going from the opening through
+    /// the middles (i.e. `else`) to the closing, and then back to the opening
+    pub fn cycle_pair(&self, line_number: usize, col: usize) -> Option<MatchWithLine> {
+        let col = self.match_at(line_number, col)?.col;
+        let matches = self.construct_matches(line_number, col)?;
+        let idx = matches
+            .iter()
+            .position(|match_| match_.line == line_number && match_.col == col)?;
+        Some(matches[(idx + 1) % matches.len()].clone())
+    }
+
+    pub fn stack_height_at_forward(&self, line_number: usize, col: usize) -> Option<usize> {
+        let mut unmatched_opening_count: usize = 0;
+        self.iter_from(line_number, col)
+            .find_map(|match_| match match_.stack_height {
+                Some(stack_height) => Some(
+                    stack_height
+                        .saturating_add(match match_.kind {
+                            Kind::Closing | Kind::Middle(_) => 1,
+                            _ => 0,
+                        })
+                        .saturating_sub(unmatched_opening_count),
+                ),
+                None => {
+                    if matches!(match_.token, Token::Delimiter(_, _)) {
+                        match match_.kind {
+                            Kind::Opening => {
+                                unmatched_opening_count = unmatched_opening_count.saturating_add(1)
+                            }
+                            Kind::Closing => {
+                                unmatched_opening_count = unmatched_opening_count.saturating_sub(1)
+                            }
+                            Kind::NonPair | Kind::Middle(_) => {}
+                        };
+                    }
+                    None
+                }
+            })
+    }
+
+    pub fn stack_height_at_backward(&self, line_number: usize, col: usize) -> Option<usize> {
+        let mut unmatched_opening_count: usize = 0;
+        self.iter_to(line_number, col)
+            .find_map(|match_| match match_.stack_height {
+                Some(stack_height) => Some(
+                    stack_height
+                        .saturating_add(match match_.kind {
+                            Kind::Opening | Kind::Middle(_) => 1,
+                            _ => 0,
+                        })
+                        .saturating_sub(unmatched_opening_count),
+                ),
+                None => {
+                    if matches!(match_.token, Token::Delimiter(_, _)) {
+                        match match_.kind {
+                            Kind::Opening => {
+                                unmatched_opening_count = unmatched_opening_count.saturating_add(1)
+                            }
+                            Kind::Closing => {
+                                unmatched_opening_count = unmatched_opening_count.saturating_sub(1)
+                            }
+                            Kind::NonPair | Kind::Middle(_) => {}
+                        };
+                    }
+                    None
+                }
+            })
+    }
+
+    /// Gets the number of pairs, strings and comments surrounding the start of each line, i.e.
+    /// for scope highlighting and indent guides
+    pub fn depth_by_line(&self) -> &[usize] {
+        &self.depth_at_line_start
+    }
+
+    /// Gets the number of pairs, strings and comments surrounding the position, from the stack
+    /// height at the start of the line and the matches before the position
+    pub fn stack_height_at(&self, line_number: usize, col: usize) -> usize {
+        let col = self.token_start(line_number, col);
+        match (
+            self.depth_at_line_start.get(line_number),
+            self.depth_at_line_start.len() == self.matches_by_line.len(),
+        ) {
+            (Some(&depth), true) => self.matches_by_line[line_number]
+                .iter()
+                .take_while(|match_| match_.col < col)
+                .fold(depth, depth_after),
+            _ => self.scan_stack_height_at(line_number, col),
+        }
+    }
+
+    /// Gets the stack heights at the start and end of the line and the highest one within, i.e.
+    /// for drawing the changes in depth in the gutter. Like [`ParsedBuffer::stack_height_at`],
+    /// strings and comments count towards the stack height
+    pub fn line_depth_profile(&self, line_number: usize) -> Option<DepthProfile> {
+        let entry = *self.depth_at_line_start.get(line_number)?;
+        let mut profile = DepthProfile {
+            entry,
+            exit: entry,
+            max: entry,
+        };
+        for match_ in &self.matches_by_line[line_number] {
+            profile.exit = depth_after(profile.exit, match_);
+            profile.max = profile.max.max(profile.exit);
+        }
+        Some(profile)
+    }
+
+    /// Same as [`ParsedBuffer::stack_height_at`] but scans the matches around the position
+    /// instead of using the index, which is only valid once the stack heights are calculated
+    fn scan_stack_height_at(&self, line_number: usize, col: usize) -> usize {
+        let col = self.token_start(line_number, col);
+        self.stack_height_at_forward(line_number, col)
+            .or_else(|| self.stack_height_at_backward(line_number, col))
+            .unwrap_or(0)
+    }
+
+    /// Counts the matched pairs and unmatched delimiters of each delimiter type, excluding
+    /// strings and comments, along with the deepest nesting
+    pub fn stats(&self) -> PairStats {
+        let mut stats = PairStats::default();
+        for match_ in self.matches_by_line.iter().flatten() {
+            let Token::Delimiter(opening, closing) = match_.token else {
+                continue;
+            };
+            let idx = match stats
+                .delimiters
+                .iter()
+                .position(|stats| stats.opening == opening && stats.closing == closing)
+            {
+                Some(idx) => idx,
+                None => {
+                    stats.delimiters.push(DelimiterStats {
+                        opening,
+                        closing,
+                        matched: 0,
+                        unmatched: 0,
+                    });
+                    stats.delimiters.len() - 1
+                }
+            };
+            match (match_.kind, match_.stack_height) {
+                (Kind::Opening, Some(stack_height)) => {
+                    stats.delimiters[idx].matched += 1;
+                    stats.max_depth = stats.max_depth.max(stack_height + 1);
+                }
+                (Kind::Opening | Kind::Closing, None) => stats.delimiters[idx].unmatched += 1,
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    /// Returns the deepest nesting for each region of lines between the buffer returning to the
+    /// top level, such as function bodies
+    pub fn nesting_regions(&self) -> Vec<NestingRegion> {
+        let mut regions = vec![];
+        let mut current: Option<NestingRegion> = None;
+        let mut depth = 0;
+
+        for (line, matches) in self.matches_by_line.iter().enumerate() {
+            for match_ in matches
+                .iter()
+                .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
+            {
+                let Some(stack_height) = match_.stack_height else {
+                    continue;
+                };
+                depth = match match_.kind {
+                    Kind::Opening | Kind::Middle(_) => stack_height + 1,
+                    _ => stack_height,
+                };
+
+                let region = current.get_or_insert(NestingRegion {
+                    start_line: line,
+                    end_line: line,
+                    max_depth: 0,
+                });
+                region.max_depth = region.max_depth.max(stack_height + 1);
+            }
+
+            if depth == 0 {
+                if let Some(mut region) = current.take() {
+                    region.end_line = line;
+                    regions.push(region);
+                }
+            }
+        }
+
+        regions
+    }
+
+    /// Gets the innermost matched delimiter opening enclosing the position
+    fn enclosing_opening(&self, line_number: usize, col: usize) -> Option<MatchWithLine> {
+        // Strings and comments have stack heights too, so skip over them to the delimiter
+        // enclosing them
+        (0..self.stack_height_at(line_number, col))
+            .rev()
+            .map_while(|stack_height| {
+                self.rfind_before(line_number, col, |match_| {
+                    match_.kind == Kind::Opening && match_.stack_height == Some(stack_height)
+                })
+            })
+            .find(|opening| matches!(opening.token, Token::Delimiter(_, _)))
+    }
+
+    /// Gets the delimiters without a counterpart, in order
+    pub fn unmatched_delimiters(&self) -> impl Iterator<Item = MatchWithLine> + '_ {
+        self.iter_from(0, 0).filter(|match_| {
+            is_unmatched_delimiter(match_.kind, &match_.token, match_.stack_height)
+        })
+    }
+
+    /// Gets the unmatched delimiters and the strings, comments and spans which are never
+    /// closed, in order, for rendering them as errors
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.iter_from(0, 0)
+            .filter(|match_| match_.stack_height.is_none())
+            .filter_map(|match_| {
+                let kind = match (match_.kind, &match_.token) {
+                    (Kind::Opening, Token::Delimiter(_, _)) => DiagnosticKind::UnmatchedOpening,
+                    (Kind::Opening, Token::String(_) | Token::BlockString(_, _)) => {
+                        DiagnosticKind::UnterminatedString
+                    }
+                    (Kind::Opening, Token::BlockComment(_, _)) => {
+                        DiagnosticKind::UnterminatedComment
+                    }
+                    (Kind::Opening, Token::InlineSpan(..) | Token::BlockSpan(..)) => {
+                        DiagnosticKind::UnterminatedSpan
+                    }
+                    (Kind::Closing, _) => DiagnosticKind::UnmatchedClosing,
+                    _ => return None,
+                };
+                Some(Diagnostic {
+                    line: match_.line,
+                    col: match_.col,
+                    len: match_.len(),
+                    text: match_.text(),
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    /// Gets the nearest delimiter without a counterpart after the position, of any token
+    pub fn next_unmatched_after(&self, line_number: usize, col: usize) -> Option<MatchWithLine> {
+        self.iter_from(line_number, col + 1)
+            .find(|match_| is_unmatched_delimiter(match_.kind, &match_.token, match_.stack_height))
+    }
+
+    /// Gets the nearest opening delimiter with the text after the position, i.e. `{`, for
+    /// jumping to it. Delimiters in strings and comments aren't matched
+    pub fn next_opening(
+        &self,
+        opening: &str,
+        line_number: usize,
+        col: usize,
+    ) -> Option<MatchWithLine> {
+        self.iter_from(line_number, col + 1).find(|match_| {
+            match_.kind == Kind::Opening
+                && matches!(match_.token, Token::Delimiter(_, _))
+                && match_.token.opening() == opening
+        })
+    }
+
+    /// Gets the nearest closing delimiter with the text before the position, i.e. `}`, for
+    /// jumping to it. Delimiters in strings and comments aren't matched
+    pub fn prev_closing(
+        &self,
+        closing: &str,
+        line_number: usize,
+        col: usize,
+    ) -> Option<MatchWithLine> {
+        self.rfind_before(line_number, col, |match_| {
+            match_.kind == Kind::Closing
+                && matches!(match_.token, Token::Delimiter(_, _))
+                && match_.token.closing() == Some(closing)
+        })
+    }
+
+    /// Gets the nearest delimiter without a counterpart before the position, of any token
+    pub fn prev_unmatched_before(&self, line_number: usize, col: usize) -> Option<MatchWithLine> {
+        self.rfind_before(line_number, col, |match_| {
+            is_unmatched_delimiter(match_.kind, &match_.token, match_.stack_height)
+        })
+    }
+
+    /// Gets the delimiters inside the strings and comments of the lines starting at
+    /// `start_line`, such as the brackets in doc comments, for each line. They're paired on a
+    /// stack of their own, starting empty at the first line, so the stack heights are
+    /// independent of the code's and the delimiters never affect the code's pairs.
+    ///
+    /// The lines must be the ones the buffer was parsed from
+    pub fn inner_matches<L: AsRef<[u8]>>(
+        &self,
+        filetype: &str,
+        lines: &[L],
+        start_line: usize,
+    ) -> Option<Vec<Vec<Match>>> {
+        let initial_state = match start_line {
+            0 => State::Normal,
+            _ => self
+                .state_by_line
+                .get(start_line - 1)
+                .copied()
+                .unwrap_or(State::Normal),
+        };
+        let mut matches_by_line = parse_filetype_inner(filetype, lines, initial_state)?;
+        pair_closest(&mut matches_by_line);
+        Some(matches_by_line)
+    }
+
+    /// Gets the innermost delimiter pair whose opening, closing or the text between them
+    /// contains the position, for highlighting the block under the mouse
+    pub fn pairs_intersecting_point(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        if let Some(match_) = self.match_at(line_number, col) {
+            if matches!(match_.token, Token::Delimiter(_, _)) {
+                if let Some(pair) = self.match_pair(line_number, match_.col) {
+                    return Some(pair);
+                }
+            }
+        }
+        let opening = self.enclosing_opening(line_number, col)?;
+        self.match_pair(opening.line, opening.col)
+    }
+
+    /// Gets the innermost delimiter pair surrounding the position, where the opening is before
+    /// the position and the closing after it, so the delimiters of a pair at the position
+    /// aren't surrounded by that pair. Unlike [`ParsedBuffer::match_pair`], the position may be
+    /// anywhere between the delimiters
+    pub fn enclosing_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        let col = self.token_start(line_number, col);
+        let opening = self.enclosing_opening(line_number, col)?;
+        let (opening, closing) = self.match_pair(opening.line, opening.col)?;
+        // On a closing, the enclosing opening is its own
+        if (closing.line, closing.col) == (line_number, col) {
+            let opening = self.enclosing_opening(opening.line, opening.col)?;
+            return self.match_pair(opening.line, opening.col);
+        }
+        Some((opening, closing))
+    }
+
+    /// Gets the state before the character at the position, i.e. [`State::InString`] between
+    /// the quotes of a string. The matches of the line are replayed from the state at its
+    /// start, so the position must be within the parsed lines
+    pub fn state_at(&self, line_number: usize, col: usize) -> State {
+        let mut state = match line_number {
+            0 => State::Normal,
+            _ => self
+                .state_by_line
+                .get(line_number - 1)
+                .copied()
+                .unwrap_or(State::Normal),
+        };
+        let Some(matches) = self.matches_by_line.get(line_number) else {
+            return state;
+        };
+
+        // Tokens covering the position, such as `/*` with the position on the `*`, haven't
+        // taken effect yet
+        for match_ in matches.iter().take_while(|match_| match_.end_col() <= col) {
+            state = match (match_.kind, &match_.token) {
+                (_, Token::LineComment(_)) => State::InLineComment,
+                (Kind::Opening, Token::String(open)) => State::InString(open),
+                (Kind::Opening, Token::BlockString(open, _)) => State::InBlockString(open),
+                (Kind::Opening, Token::BlockComment(open, _)) => State::InBlockComment(open),
+                (Kind::Opening, Token::InlineSpan(span, _, _)) => State::InInlineSpan(span),
+                (Kind::Opening, Token::BlockSpan(span, _, _)) => State::InBlockSpan(span),
+                // Interpolation in a string, i.e. `{$expr}` in PHP
+                (Kind::Opening, Token::Delimiter(_, _)) => match state {
+                    State::InString(string) => State::InInterpolation(string),
+                    state => state,
+                },
+                (Kind::Closing, Token::Delimiter(_, _)) => match state {
+                    State::InInterpolation(string) => State::InString(string),
+                    state => state,
+                },
+                (Kind::Closing, _) => State::Normal,
+                _ => state,
+            };
+        }
+        state
+    }
+
+    /// Whether the position is inside of a line or block comment, excluding the comment's
+    /// delimiters
+    pub fn is_in_comment(&self, line_number: usize, col: usize) -> bool {
+        matches!(
+            self.state_at(line_number, col),
+            State::InLineComment | State::InBlockComment(_)
+        ) && self.match_at(line_number, col).is_none()
+    }
+
+    /// Whether the position is inside of a string, excluding the string's delimiters and any
+    /// interpolations in it, i.e. `{$expr}` in PHP
+    pub fn is_in_string(&self, line_number: usize, col: usize) -> bool {
+        matches!(
+            self.state_at(line_number, col),
+            State::InString(_) | State::InBlockString(_) | State::InRegexClass(_)
+        ) && self.match_at(line_number, col).is_none()
+    }
+
+    /// Whether typing the opening at the position shouldn't insert its closing, due to the
+    /// filetype's [`crate::parser::AutoPairExclusion`]s or the `extra` contexts, i.e. registered at runtime.
+    /// The line is the text of the line at the position
+    pub fn is_auto_pair_excluded(
+        &self,
+        filetype: &str,
+        opening: &str,
+        line_number: usize,
+        line: &[u8],
+        col: usize,
+        extra: &[ExclusionContext],
+    ) -> bool {
+        let state = self.state_at(line_number, col);
+        filetype_auto_pair_exclusions(filetype)
+            .iter()
+            .filter(|exclusion| exclusion.opening == opening)
+            .map(|exclusion| exclusion.context)
+            .chain(extra.iter().copied())
+            .any(|context| context.applies(line, col, state))
+    }
+
+    /// Whether inserting text, which may span multiple lines, at the position would keep the
+    /// strings, comments and tokens around it intact. Inserting inside of a string or comment,
+    /// or between the characters of a token such as `/*`, would split it
+    pub fn is_safe_insertion_point(&self, line_number: usize, col: usize) -> bool {
+        let splits_token = self
+            .matches_by_line
+            .get(line_number)
+            .is_some_and(|matches| {
+                matches
+                    .iter()
+                    .any(|match_| match_.col < col && col < match_.end_col())
+            });
+        !splits_token && self.state_at(line_number, col) == State::Normal
+    }
+
+    /// Parses the text as if it were pasted at the position, reporting the delimiters it leaves
+    /// unpaired, i.e. a pasted block bringing 2 extra `}`, and whether it leaves a string or
+    /// comment open. The delimiters are paired within the text only, on the closest opening
+    pub fn analyze_paste<L: AsRef<[u8]>>(
+        &self,
+        filetype: &str,
+        line_number: usize,
+        col: usize,
+        lines: &[L],
+    ) -> Option<PasteAnalysis> {
+        let start_state = self.state_at(line_number, col);
+        // Lines after the first start at the beginning of the line, so only the state
+        // carried over by the first line's end matters
+        let (mut matches_by_line, states): (Vec<_>, Vec<_>) =
+            parse_filetype_iter(filetype, lines, start_state)?.unzip();
+        pair_closest(&mut matches_by_line);
+
+        let mut analysis = PasteAnalysis {
+            extra_closings: vec![],
+            unclosed_openings: vec![],
+            start_state,
+            end_state: states.last().copied().unwrap_or(start_state),
+        };
+        for (i, matches) in matches_by_line.into_iter().enumerate() {
+            let offset = if i == 0 { col } else { 0 };
+            for match_ in matches {
+                if !matches!(match_.token, Token::Delimiter(_, _)) || match_.stack_height.is_some()
+                {
+                    continue;
+                }
+                let match_ = Match {
+                    col: match_.col + offset,
+                    ..match_
+                };
+                match match_.kind {
+                    Kind::Opening => analysis
+                        .unclosed_openings
+                        .push(match_.with_line(line_number + i)),
+                    Kind::Closing => analysis
+                        .extra_closings
+                        .push(match_.with_line(line_number + i)),
+                    Kind::NonPair | Kind::Middle(_) => {}
+                }
+            }
+        }
+        Some(analysis)
+    }
+
+    /// Gets the lines whose indentation no longer matches the depth of the pairs after a
+    /// delimiter was inserted or removed at the position, i.e. by the auto-pair mappings, for
+    /// reindenting them. The pair of the delimiter at the position, or the pair surrounding
+    /// the position when it was removed, is checked.
+    ///
+    /// Each line within the pair is expected to be indented by one level more than the opening
+    /// line for each pair opened at the end of a line, where closings at the start of the line
+    /// count as closed. Lines continuing a pair opened in the middle of a line, i.e. the
+    /// arguments of a call, may be indented in any way. The lines must be the buffer's lines
+    pub fn stale_indent_range<L: AsRef<[u8]>>(
+        &self,
+        lines: &[L],
+        tab_width: u8,
+        line_number: usize,
+        col: usize,
+    ) -> Option<Range<usize>> {
+        let (opening, closing) = match self.match_at(line_number, col) {
+            Some(match_) if matches!(match_.token, Token::Delimiter(_, _)) => {
+                self.match_pair(line_number, col)?
+            }
+            _ => self.enclosing_pair(line_number, col)?,
+        };
+        let is_paired_delimiter = |match_: &&Match| {
+            matches!(match_.token, Token::Delimiter(_, _))
+                && matches!(match_.kind, Kind::Opening | Kind::Closing)
+                && match_.stack_height.is_some()
+        };
+        let line_text = |line: usize| lines.get(line).map_or(&[][..], |line| line.as_ref());
+        let base_indent = *self.indent_levels.get(opening.line)?;
+
+        // Whether each pair opened since the opening ends its line, i.e. `{` of a block
+        let mut open_pairs: Vec<bool> = vec![];
+        let mut stale: Option<Range<usize>> = None;
+        for line in opening.line..=closing.line.min(self.matches_by_line.len() - 1) {
+            let matches = self.matches_by_line[line]
+                .iter()
+                .filter(is_paired_delimiter)
+                .filter(|match_| line != opening.line || match_.col >= opening.col)
+                .filter(|match_| line != closing.line || match_.col <= closing.col)
+                .collect::<Vec<_>>();
+            let text = line_text(line);
+
+            if line != opening.line && self.blank_indents.get(line).is_some_and(Option::is_none) {
+                // Closings at the start of the line, such as `})`
+                let mut end = text.len() - text.trim_ascii_start().len();
+                let leading_closings = matches
+                    .iter()
+                    .take_while(|match_| {
+                        let is_leading = match_.kind == Kind::Closing && match_.col == end;
+                        end += match_.len();
+                        is_leading
+                    })
+                    .count();
+                let depth = &open_pairs[..open_pairs.len().saturating_sub(leading_closings)];
+
+                let is_continuation = depth.last() == Some(&false);
+                let expected = base_indent as usize
+                    + depth.iter().filter(|ends_line| **ends_line).count() * tab_width as usize;
+                if !is_continuation && self.indent_levels[line] as usize != expected {
+                    let start = stale.as_ref().map_or(line, |stale| stale.start);
+                    stale = Some(start..line + 1);
+                }
+            }
+
+            for match_ in matches {
+                match match_.kind {
+                    Kind::Opening => {
+                        open_pairs.push(self.ends_line(text, line, match_.end_col()));
+                    }
+                    _ => {
+                        open_pairs.pop();
+                    }
+                }
+            }
+        }
+        stale
+    }
+
+    /// Whether only whitespace or a comment follows the column on the line, i.e. after the `{`
+    /// of a block
+    fn ends_line(&self, text: &[u8], line_number: usize, col: usize) -> bool {
+        let end = self
+            .line_matches_in(line_number, col..usize::MAX)
+            .first()
+            .filter(|later| {
+                matches!(
+                    later.token,
+                    Token::LineComment(_) | Token::BlockComment(_, _)
+                )
+            })
+            .map_or(text.len(), |comment| comment.col);
+        text.get(col..end)
+            .unwrap_or_default()
+            .trim_ascii()
+            .is_empty()
+    }
+
+    /// Gets the indentation for the line in columns, for an `indentexpr`, derived from the
+    /// innermost pair open at its start. The text of the lines is read with `line_text`.
+    ///
+    /// Lines within a pair opened at the end of its line are indented by one level more than
+    /// the opening line, while lines within a pair opened in the middle of a line are aligned
+    /// with the first character after the opening, i.e. the first argument of a call. Lines
+    /// starting with the closing of the pair get the indentation of the opening line. Returns
+    /// `None` when the line starts inside of a string or comment, whose indentation should be
+    /// kept
+    pub fn suggested_indent<L: AsRef<[u8]>>(
+        &self,
+        line_number: usize,
+        tab_width: u8,
+        mut line_text: impl FnMut(usize) -> Option<L>,
+    ) -> Option<usize> {
+        if line_number >= self.matches_by_line.len() {
+            return None;
+        }
+        if line_number > 0 && self.state_by_line[line_number - 1] != State::Normal {
+            return None;
+        }
+        let Some(opening) = self.enclosing_opening(line_number, 0) else {
+            return Some(0);
+        };
+        let opening_indent = *self.indent_levels.get(opening.line)? as usize;
+
+        // Closing of the pair at the start of the line, such as `}`
+        let text = line_text(line_number)?;
+        let first_col = text.as_ref().len() - text.as_ref().trim_ascii_start().len();
+        let starts_with_closing = self.matches_by_line[line_number]
+            .iter()
+            .find(|match_| match_.col >= first_col)
+            .is_some_and(|match_| {
+                match_.col == first_col
+                    && match_.kind == Kind::Closing
+                    && match_.token == opening.token
+                    && match_.stack_height == opening.stack_height
+            });
+        if starts_with_closing {
+            return Some(opening_indent);
+        }
+
+        let opening_text = line_text(opening.line)?;
+        let opening_text = opening_text.as_ref();
+        let after_opening = opening.end_col();
+        if self.ends_line(opening_text, opening.line, after_opening) {
+            return Some(opening_indent + tab_width as usize);
+        }
+        let rest = opening_text.get(after_opening..).unwrap_or_default();
+        let first_char = after_opening + rest.len() - rest.trim_ascii_start().len();
+        Some(byte_to_display_col(opening_text, first_char, tab_width))
+    }
+
+    /// Gets the matched delimiter pairs whose opening or closing is within the lines, i.e. the
+    /// viewport, including the partners outside of the lines, ordered by the opening. Scans
+    /// outwards from the lines only as far as the partners
+    pub fn pairs_in_range(&self, lines: Range<usize>) -> Vec<(MatchWithLine, MatchWithLine)> {
+        let end_line = lines.end.min(self.matches_by_line.len());
+        let start_line = lines.start.min(end_line);
+        let is_paired_delimiter = |match_: &MatchWithLine| {
+            matches!(match_.token, Token::Delimiter(_, _))
+                && matches!(match_.kind, Kind::Opening | Kind::Closing)
+                && match_.stack_height.is_some()
+        };
+        let same_pair = |a: &MatchWithLine, b: &MatchWithLine| {
+            a.token == b.token && a.stack_height == b.stack_height
+        };
+
+        // Pair forwards from the first line, where the closings without an opening within the
+        // lines were opened before them
+        let mut pairs = vec![];
+        let mut openings: Vec<MatchWithLine> = vec![];
+        let mut closings: Vec<MatchWithLine> = vec![];
+        for match_ in self.iter_from(start_line, 0).filter(is_paired_delimiter) {
+            let in_lines = match_.line < end_line;
+            if !in_lines && openings.is_empty() {
+                break;
+            }
+            if match_.kind == Kind::Opening {
+                if in_lines {
+                    openings.push(match_);
+                }
+                continue;
+            }
+            match openings
+                .iter()
+                .rposition(|opening| same_pair(opening, &match_))
+            {
+                Some(idx) => pairs.push((openings.remove(idx), match_)),
+                None if in_lines => closings.push(match_),
+                None => {}
+            }
+        }
+
+        // Then backwards from the first line for their openings
+        let before = self.matches_by_line[..start_line]
+            .iter()
+            .enumerate()
+            .rev()
+            .flat_map(|(line, matches)| {
+                matches
+                    .iter()
+                    .rev()
+                    .map(move |match_| match_.with_line(line))
+            });
+        for match_ in before.filter(is_paired_delimiter) {
+            if closings.is_empty() {
+                break;
+            }
+            if match_.kind != Kind::Opening {
+                continue;
+            }
+            if let Some(idx) = closings
+                .iter()
+                .position(|closing| same_pair(&match_, closing))
+            {
+                pairs.push((match_, closings.remove(idx)));
+            }
+        }
+
+        pairs.sort_by_key(|(opening, _)| (opening.line, opening.col));
+        pairs
+    }
+
+    /// Gets the `(start_line, end_line, level)` fold ranges of the matched delimiter pairs
+    /// spanning at least `min_lines` lines, inclusive and ordered by the start line. The level
+    /// starts from 1 and counts the ranges containing the range. Pairs spanning the same lines
+    /// as their parent, such as `({` and `})`, share the range
+    pub fn fold_ranges(&self, min_lines: usize) -> Vec<(usize, usize, usize)> {
+        let mut ranges: Vec<(usize, usize, usize)> = vec![];
+        // End lines of the ranges containing the current range
+        let mut parents: Vec<usize> = vec![];
+        let pairs = self.pairs_in_range(0..self.matches_by_line.len());
+        for (opening, closing) in pairs {
+            let (start, end) = (opening.line, closing.line);
+            if end + 1 - start < min_lines.max(1)
+                || ranges
+                    .last()
+                    .is_some_and(|last| (last.0, last.1) == (start, end))
+            {
+                continue;
+            }
+            while parents.last().is_some_and(|&parent_end| parent_end < end) {
+                parents.pop();
+            }
+            parents.push(end);
+            ranges.push((start, end, parents.len()));
+        }
+        ranges
+    }
+
+    /// Gets the edits for joining the line with the next, i.e. for a `J` mapping, given the
+    /// text of both lines. Joining `foo(` with `a,` gives `foo(a,`, and `[a,` with `]` gives
+    /// `[a]`, while braces keep a space inside of them, such as `{ a }`. Returns `None` when the
+    /// line ends inside of a string or comment, where the lines should be joined as text
+    pub fn join_adjustment(
+        &self,
+        line_number: usize,
+        line: &[u8],
+        next_line: &[u8],
+    ) -> Option<JoinAdjustment> {
+        if line_number + 1 >= self.matches_by_line.len()
+            || *self.state_by_line.get(line_number)? != State::Normal
+        {
+            return None;
+        }
+        let matches = &self.matches_by_line[line_number];
+        if matches
+            .iter()
+            .any(|match_| matches!(match_.token, Token::LineComment(_)))
+        {
+            return None;
+        }
+
+        let line_end = line.trim_ascii_end().len();
+        let next_line_start = next_line.len() - next_line.trim_ascii_start().len();
+        let is_delimiter = |match_: &&Match, kind: Kind| {
+            match_.kind == kind && matches!(match_.token, Token::Delimiter(_, _))
+        };
+        let opening = matches
+            .iter()
+            .rfind(|match_| match_.end_col() == line_end)
+            .filter(|match_| is_delimiter(match_, Kind::Opening));
+        let closing = self.matches_by_line[line_number + 1]
+            .iter()
+            .find(|match_| match_.col == next_line_start)
+            .filter(|match_| is_delimiter(match_, Kind::Closing));
+
+        let trailing_comma =
+            (closing.is_some() && line[..line_end].ends_with(b",")).then(|| line_end - 1);
+        let separator = match (opening, closing) {
+            _ if next_line_start == next_line.len() => "",
+            // Empty pair, i.e. `()`
+            (Some(opening), Some(closing))
+                if opening.token == closing.token
+                    && opening.stack_height == closing.stack_height =>
+            {
+                ""
+            }
+            (Some(match_), _) | (_, Some(match_)) if match_.token.opening() != "{" => "",
+            _ => " ",
+        };
+        Some(JoinAdjustment {
+            trailing_comma,
+            line_end,
+            next_line_start,
+            separator,
+        })
+    }
+
+    /// Same as [`ParsedBuffer::match_pair`] but when the position isn't on a delimiter, pairs
+    /// the one next to it depending on the adjacency, i.e. [`Adjacency::Before`] for
+    /// highlighting the pair of a `)` right before the cursor in insert mode
+    pub fn match_pair_adjacent(
+        &self,
+        line_number: usize,
+        col: usize,
+        adjacency: Adjacency,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        if let Some(pair) = self.match_pair(line_number, col) {
+            return Some(pair);
+        }
+        match adjacency {
+            Adjacency::Exact => None,
+            Adjacency::Before => self.match_pair(line_number, col.checked_sub(1)?),
+            Adjacency::After => self.match_pair(line_number, col + 1),
+            Adjacency::NearestOnLine => self
+                .matches_by_line
+                .get(line_number)?
+                .iter()
+                .filter_map(|match_| {
+                    let distance = match match_.col > col {
+                        true => (match_.col - col, true),
+                        false => ((col + 1).saturating_sub(match_.end_col()), false),
+                    };
+                    Some((distance, self.match_pair(line_number, match_.col)?))
+                })
+                .min_by_key(|(distance, _)| *distance)
+                .map(|(_, pair)| pair),
+        }
+    }
+
+    /// Gets the next or previous pair at the same depth within the same parent pair, i.e. the
+    /// following block, argument or array element, for structural motions. When the position
+    /// is on a delimiter, its pair's siblings are searched from its opening or closing
+    pub fn sibling_block(
+        &self,
+        line_number: usize,
+        col: usize,
+        direction: Direction,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        let (stack_height, from) = match self.match_at(line_number, col) {
+            Some(match_)
+                if matches!(match_.token, Token::Delimiter(_, _))
+                    && matches!(match_.kind, Kind::Opening | Kind::Closing)
+                    && match_.stack_height.is_some() =>
+            {
+                let (opening, closing) = self.match_pair(line_number, match_.col)?;
+                let from = match direction {
+                    Direction::Forward => (closing.line, closing.col + 1),
+                    Direction::Backward => (opening.line, opening.col),
+                };
+                (opening.stack_height?, from)
+            }
+            _ => (self.stack_height_at(line_number, col), (line_number, col)),
+        };
+
+        // Stops at the parent's delimiter, which is below the stack height
+        let is_candidate = |token: &Token, kind: Kind, height: Option<usize>| {
+            matches!(token, Token::Delimiter(_, _))
+                && matches!(kind, Kind::Opening | Kind::Closing)
+                && height.is_some_and(|height| height <= stack_height)
+        };
+        let sibling = match direction {
+            Direction::Forward => self
+                .iter_from(from.0, from.1)
+                .find(|match_| is_candidate(&match_.token, match_.kind, match_.stack_height))?,
+            Direction::Backward => self.rfind_before(from.0, from.1, |match_| {
+                is_candidate(&match_.token, match_.kind, match_.stack_height)
+            })?,
+        };
+        if sibling.stack_height != Some(stack_height) {
+            return None;
+        }
+        self.match_pair(sibling.line, sibling.col)
+    }
+
+    /// Same as [`ParsedBuffer::sibling_block`] searching forward, i.e. for jumping to the next
+    /// argument group or block
+    pub fn next_sibling_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        self.sibling_block(line_number, col, Direction::Forward)
+    }
+
+    /// Same as [`ParsedBuffer::sibling_block`] searching backward
+    pub fn prev_sibling_pair(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> Option<(MatchWithLine, MatchWithLine)> {
+        self.sibling_block(line_number, col, Direction::Backward)
+    }
+
+    /// Gets the range of the pair at the position for `ib` and `ab` style text objects, inside
+    /// of the delimiters or including them when `around`. The pair is the one whose delimiter
+    /// is at the position, or otherwise the innermost pair surrounding it, so delimiters in
+    /// strings and comments are never selected
+    pub fn textobject_at(
+        &self,
+        line_number: usize,
+        col: usize,
+        around: bool,
+    ) -> Option<SourceRange> {
+        let (opening, closing) = match self.match_at(line_number, col) {
+            Some(match_)
+                if matches!(match_.token, Token::Delimiter(_, _))
+                    && matches!(match_.kind, Kind::Opening | Kind::Closing) =>
+            {
+                self.match_pair(line_number, match_.col)?
+            }
+            _ => self.enclosing_pair(line_number, col)?,
+        };
+        Some(SourceRange::of_pair(&opening, &closing, around))
+    }
+
+    /// Gets the smallest range of a pair which strictly contains the range, inside of the
+    /// pair's delimiters or including them, for growing a selection like tree-sitter's
+    /// incremental selection. Strings and comments aren't selected
+    pub fn expand_selection(&self, range: SourceRange) -> Option<SourceRange> {
+        self.pairs_containing(range)
+            .flat_map(|(opening, closing)| {
+                [false, true].map(|around| SourceRange::of_pair(&opening, &closing, around))
+            })
+            .find(|pair_range| pair_range.contains(&range) && *pair_range != range)
+    }
+
+    /// Gets the delimiter pairs whose range, including the delimiters, contains the range,
+    /// from the innermost to the outermost, i.e. for finding the blocks around a selection
+    pub fn pairs_containing(
+        &self,
+        range: SourceRange,
+    ) -> impl Iterator<Item = (MatchWithLine, MatchWithLine)> + '_ {
+        // The pair whose delimiter is at the start of the range, followed by the ones around it
+        let pair_at_start = match self.match_at(range.start_line, range.start_col) {
+            Some(match_)
+                if matches!(match_.token, Token::Delimiter(_, _))
+                    && matches!(match_.kind, Kind::Opening | Kind::Closing) =>
+            {
+                self.match_pair(range.start_line, match_.col)
+            }
+            _ => None,
+        };
+        pair_at_start
+            .into_iter()
+            .chain(self.enclosing_pairs(range.start_line, range.start_col))
+            .filter(move |(opening, closing)| {
+                SourceRange::of_pair(opening, closing, true).contains(&range)
+            })
+    }
+
+    /// Gets the largest range of a pair strictly inside of the range, for stepping back down
+    /// after [`ParsedBuffer::expand_selection`]. When the range is a pair including its
+    /// delimiters, it's the range inside of them. Otherwise, it's the outermost pair within
+    /// the range which contains the cursor, or is the closest one to it
+    pub fn shrink_selection(
+        &self,
+        range: SourceRange,
+        cursor: (usize, usize),
+    ) -> Option<SourceRange> {
+        let end = (range.end_line, range.end_col);
+        let mut pairs = vec![];
+        let mut skip_until = (range.start_line, range.start_col);
+        for match_ in self
+            .iter_from(range.start_line, range.start_col)
+            .take_while(|match_| (match_.line, match_.col) < end)
+        {
+            if (match_.line, match_.col) < skip_until
+                || !matches!(match_.token, Token::Delimiter(_, _))
+                || match_.kind != Kind::Opening
+            {
+                continue;
+            }
+            let Some((opening, closing)) = self.match_pair(match_.line, match_.col) else {
+                continue;
+            };
+            if (closing.line, closing.end_col()) > end {
+                continue;
+            }
+            // Pairs inside of this one are smaller
+            skip_until = (closing.line, closing.end_col());
+            pairs.push(match SourceRange::of_pair(&opening, &closing, true) {
+                around if around == range => SourceRange::of_pair(&opening, &closing, false),
+                around => around,
+            });
+        }
+
+        let distance = |pair_range: &SourceRange| {
+            let start = (pair_range.start_line, pair_range.start_col);
+            let end = (pair_range.end_line, pair_range.end_col);
+            let nearest = match cursor {
+                cursor if cursor < start => start,
+                cursor if cursor > end => end,
+                _ => return (0, 0),
+            };
+            (
+                nearest.0.abs_diff(cursor.0),
+                match nearest.0 == cursor.0 {
+                    true => nearest.1.abs_diff(cursor.1),
+                    false => 0,
+                },
+            )
+        };
+        pairs.into_iter().min_by_key(distance)
+    }
+
+    /// Gets the range of the string, comment or span containing the position, inside of its
+    /// delimiters or including them when `around`, i.e. for selecting inside of a string.
+    /// Line comments end at `line_len`, the length of the line, while unterminated strings and
+    /// comments have no range
+    pub fn span_range_at(
+        &self,
+        line_number: usize,
+        col: usize,
+        line_len: usize,
+        around: bool,
+    ) -> Option<SourceRange> {
+        if let Some((opening, closing)) = self.span_pair(line_number, col) {
+            return Some(SourceRange::of_pair(&opening, &closing, around));
+        }
+
+        let comment = self
+            .matches_by_line
+            .get(line_number)?
+            .iter()
+            .find(|match_| matches!(match_.token, Token::LineComment(_)) && match_.col <= col)?;
+        Some(SourceRange {
+            start_line: line_number,
+            start_col: match around {
+                true => comment.col,
+                false => comment.end_col(),
+            },
+            end_line: line_number,
+            end_col: line_len.max(comment.end_col()),
+        })
+    }
+
+    /// Gets the pairs, strings, comments and spans surrounding the position, including when
+    /// it's on their delimiters, from the innermost to the outermost. Line comments end at
+    /// `line_len`, the length of the line, like [`ParsedBuffer::span_range_at`]
+    pub fn surroundings_at(
+        &self,
+        line_number: usize,
+        col: usize,
+        line_len: usize,
+    ) -> Vec<Surrounding> {
+        let position = SourceRange {
+            start_line: line_number,
+            start_col: col,
+            end_line: line_number,
+            end_col: col,
+        };
+        let mut surroundings = self
+            .pairs_containing(position)
+            .map(|(opening, closing)| Surrounding {
+                kind: SurroundingKind::Pair,
+                range: SourceRange::of_pair(&opening, &closing, true),
+            })
+            .collect::<Vec<_>>();
+
+        let span = self
+            .span_range_at(line_number, col, line_len, true)
+            .and_then(|range| {
+                let kind = match self.match_at(range.start_line, range.start_col)?.token {
+                    Token::String(_) | Token::BlockString(_, _) => SurroundingKind::String,
+                    Token::LineComment(_) | Token::BlockComment(_, _) => SurroundingKind::Comment,
+                    Token::InlineSpan(span, _, _) | Token::BlockSpan(span, _, _) => {
+                        SurroundingKind::Span(span)
+                    }
+                    Token::Delimiter(_, _) => return None,
+                };
+                Some(Surrounding { kind, range })
+            });
+        if let Some(span) = span {
+            // Pairs may surround the span, or be inside of it, i.e. in a block span
+            let idx = surroundings.partition_point(|pair| {
+                (pair.range.start_line, pair.range.start_col)
+                    > (span.range.start_line, span.range.start_col)
+            });
+            surroundings.insert(idx, span);
+        }
+        surroundings
+    }
+
+    /// Gets the ranges of the pairs at the top level spanning multiple lines, including their
+    /// delimiters, such as functions, classes and tables, for outlining the buffer
+    pub fn top_level_blocks(&self) -> Vec<SourceRange> {
+        let mut blocks = vec![];
+        let mut opening: Option<MatchWithLine> = None;
+        for match_ in self.iter_from(0, 0).filter(|match_| {
+            matches!(match_.token, Token::Delimiter(_, _)) && match_.stack_height == Some(0)
+        }) {
+            match (match_.kind, &opening) {
+                (Kind::Opening, _) => opening = Some(match_),
+                (Kind::Closing, Some(open)) if open.token == match_.token => {
+                    if open.line != match_.line {
+                        blocks.push(SourceRange {
+                            start_line: open.line,
+                            start_col: open.col,
+                            end_line: match_.line,
+                            end_col: match_.end_col(),
+                        });
+                    }
+                    opening = None;
+                }
+                _ => {}
+            }
+        }
+        blocks
+    }
+
+    /// Digest of the pair structure, covering the opening and closing delimiters of each line
+    /// with their stack heights but not their columns, the text around them or the strings
+    /// and comments. Folds and outlines derived from the pairs only need to be recomputed when
+    /// the digest changes
+    pub fn structure_hash(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        for matches in &self.matches_by_line {
+            let pairs = matches.iter().filter(|match_| {
+                matches!(match_.token, Token::Delimiter(_, _))
+                    && matches!(match_.kind, Kind::Opening | Kind::Closing)
+            });
+            hasher.write_usize(pairs.clone().count());
+            for match_ in pairs {
+                (match_.kind == Kind::Opening).hash(&mut hasher);
+                match_.token.opening().hash(&mut hasher);
+                match_.stack_height.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Gets the delimiter pairs surrounding the position, from the innermost to the outermost,
+    /// see [`ParsedBuffer::enclosing_pair`]
+    pub fn enclosing_pairs(
+        &self,
+        line_number: usize,
+        col: usize,
+    ) -> impl Iterator<Item = (MatchWithLine, MatchWithLine)> + '_ {
+        core::iter::successors(self.enclosing_pair(line_number, col), |(opening, _)| {
+            self.enclosing_pair(opening.line, opening.col)
+        })
+    }
+
+    /// Gets the closings which would balance the unmatched openings before the position, from
+    /// the innermost to the outermost. Openings inside of pairs closed before the position are
+    /// skipped, since a closing at the position can't balance them, and so are the openings
+    /// outside of a pair closed after the position, since its closing would come first
+    pub fn pending_closings(&self, line_number: usize, col: usize) -> Vec<PendingClosing> {
+        let mut closings = vec![];
+        // Number of pairs closed before the position that the matches are inside of
+        let mut closed_depth = 0usize;
+
+        let last_line = line_number.min(self.matches_by_line.len().saturating_sub(1));
+        for (matches_line_number, matches) in
+            self.matches_by_line[..=last_line].iter().enumerate().rev()
+        {
+            let end = match matches_line_number == line_number {
+                true => matches.partition_point(|match_| match_.end_col() <= col),
+                false => matches.len(),
+            };
+            for match_ in matches[..end].iter().rev() {
+                let Token::Delimiter(_, closing) = match_.token else {
+                    continue;
+                };
+                match (match_.kind, match_.stack_height) {
+                    (Kind::Closing, Some(_)) => closed_depth += 1,
+                    // Surrounds the position, i.e. the `[` of `( [ | ]`
+                    (Kind::Opening, Some(_)) if closed_depth == 0 => return closings,
+                    (Kind::Opening, Some(_)) => closed_depth -= 1,
+                    (Kind::Opening, None) if closed_depth == 0 => closings.push(PendingClosing {
+                        closing,
+                        opening_line: matches_line_number,
+                        opening_col: match_.col,
+                        depth: closings.len(),
+                    }),
+                    _ => {}
+                }
+            }
+        }
+        closings
+    }
+
+    /// Finds the closest match ending before the position, unlike [`ParsedBuffer::iter_to`]
+    /// which yields the matches of each line from left to right. Tokens covering the position,
+    /// such as `/*` with the position on the `*`, aren't before it
+    fn rfind_before(
+        &self,
+        line_number: usize,
+        col: usize,
+        predicate: impl Fn(&Match) -> bool,
+    ) -> Option<MatchWithLine> {
+        let last_line = line_number.min(self.matches_by_line.len().checked_sub(1)?);
+        self.matches_by_line[0..=last_line]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(matches_line_number, matches)| {
+                let end = match matches_line_number == line_number {
+                    true => matches.partition_point(|match_| match_.end_col() <= col),
+                    false => matches.len(),
+                };
+                matches[..end]
+                    .iter()
+                    .rev()
+                    .find(|match_| predicate(match_))
+                    .map(|match_| match_.with_line(matches_line_number))
+            })
+    }
+
+    /// Gets the lines of the scope containing the position, for dimming the code outside of
+    /// it. The scope is the innermost pair spanning multiple lines, or when outside of any,
+    /// the surrounding lines indented at least as much as the line, with the line above them
+    /// as the header. At the top level, the scope ends at blank lines
+    pub fn current_scope(&self, line_number: usize, col: usize) -> Range<usize> {
+        let line_count = self.matches_by_line.len();
+        let line_number = line_number.min(line_count.saturating_sub(1));
+
+        let (mut line, mut col) = (line_number, col);
+        while let Some(opening) = self.enclosing_opening(line, col) {
+            if let Some((opening, closing)) = self.match_pair(opening.line, opening.col) {
+                if opening.line != closing.line {
+                    return opening.line..closing.line + 1;
+                }
+            }
+            (line, col) = (opening.line, opening.col);
+        }
+
+        let indent_levels = self.get_virtual_indent_levels(0, line_count);
+        let Some(&base) = indent_levels.get(line_number) else {
+            return 0..line_count;
+        };
+        let in_scope = |line: usize| match self.blank_indents[line] {
+            Some(_) => base > 0,
+            None => indent_levels[line] >= base,
+        };
+
+        let mut start = line_number;
+        while start > 0 && in_scope(start - 1) {
+            start -= 1;
+        }
+        let mut end = line_number + 1;
+        while end < line_count && in_scope(end) {
+            end += 1;
+        }
+        // Header, such as `def foo():` in Python
+        if base > 0 && start > 0 {
+            start -= 1;
+        }
+        start..end
+    }
+
+    /// Gets the line ranges outside of [`ParsedBuffer::current_scope`]
+    pub fn out_of_scope_ranges(&self, line_number: usize, col: usize) -> Vec<Range<usize>> {
+        let scope = self.current_scope(line_number, col);
+        [0..scope.start, scope.end..self.matches_by_line.len()]
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .collect()
+    }
+
+    /// Gets the line of the closing's opening and the range of its text without the leading
+    /// and trailing whitespace, for previewing the opening when it's offscreen. The text of
+    /// the line is read with `line_text`
+    pub fn opening_context<L: AsRef<[u8]>>(
+        &self,
+        closing: &MatchWithLine,
+        line_text: impl FnOnce(usize) -> Option<L>,
+    ) -> Option<(usize, Range<usize>)> {
+        if closing.kind != Kind::Closing {
+            return None;
+        }
+        let (opening, _) = self.match_pair(closing.line, closing.col)?;
+        let text = line_text(opening.line)?;
+        Some((opening.line, trimmed_range(text.as_ref())))
+    }
+
+    /// Gets the annotations for the closings between the lines whose pair spans at least
+    /// `min_lines` lines, with the opening line's text before the opening, truncated to
+    /// `max_len` characters. Pairs opened at the start of their line are skipped. The lines must
+    /// be the ones the buffer was parsed from
+    pub fn closing_annotations<L: AsRef<[u8]>>(
+        &self,
+        lines: &[L],
+        start_line: usize,
+        end_line: usize,
+        min_lines: usize,
+        max_len: usize,
+    ) -> Vec<ClosingAnnotation> {
+        let end_line = end_line.min(self.matches_by_line.len());
+        let mut annotations = vec![];
+        for line_number in start_line.min(end_line)..end_line {
+            for match_ in &self.matches_by_line[line_number] {
+                if match_.kind != Kind::Closing
+                    || match_.stack_height.is_none()
+                    || !matches!(match_.token, Token::Delimiter(_, _))
+                {
+                    continue;
+                }
+                let Some((opening, _)) = self.match_pair(line_number, match_.col) else {
+                    continue;
+                };
+                if line_number - opening.line + 1 < min_lines {
+                    continue;
+                }
+
+                let opening_text = lines.get(opening.line).map_or(&[][..], |l| l.as_ref());
+                let before_opening = &opening_text[..opening.col.min(opening_text.len())];
+                let text = String::from_utf8_lossy(&before_opening[trimmed_range(before_opening)]);
+                if text.is_empty() {
+                    continue;
+                }
+                annotations.push(ClosingAnnotation {
+                    line: line_number,
+                    col: match_.end_col(),
+                    opening_line: opening.line,
+                    text: truncate(&text, max_len),
+                });
+            }
+        }
+        annotations
+    }
+
+    /// Gets the elements directly inside the innermost pair enclosing the position, such as
+    /// list items or object entries, for sorting them. Elements are separated by `,` or `;`
+    /// outside of nested pairs, strings and comments, and exclude the surrounding whitespace.
+    /// The lines must be the ones the buffer was parsed from
+    pub fn sortable_ranges<L: AsRef<[u8]>>(
+        &self,
+        lines: &[L],
+        line_number: usize,
+        col: usize,
+    ) -> Option<Vec<SourceRange>> {
+        let opening = self.enclosing_opening(line_number, col)?;
+        let (opening, closing) = self.match_pair(opening.line, opening.col)?;
+        let line = |line_number: usize| lines.get(line_number).map_or(&[][..], |l| l.as_ref());
+
+        let start = (opening.line, opening.end_col());
+        let end = (closing.line, closing.col);
+        let mut separators = vec![];
+        let mut depth = 0usize;
+        for line_number in start.0..=end.0 {
+            let text = line(line_number);
+            let mut col = if line_number == start.0 { start.1 } else { 0 };
+            let end_col = if line_number == end.0 {
+                end.1
+            } else {
+                text.len()
+            };
+            let mut matches = self
+                .line_matches_in(line_number, col..end_col)
+                .iter()
+                .peekable();
+
+            while col < end_col {
+                let Some(match_) = matches.next_if(|match_| match_.col == col) else {
+                    if depth == 0 && matches!(text.get(col), Some(b',' | b';')) {
+                        separators.push(((line_number, col), (line_number, col + 1)));
+                    }
+                    col += 1;
+                    continue;
+                };
+                match (match_.kind, &match_.token, match_.stack_height) {
+                    (_, Token::LineComment(_), _) => {
+                        // Comments after a separator belong to the element before it
+                        if let Some((_, after)) = separators.last_mut().filter(|(_, after)| {
+                            after.0 == line_number
+                                && text[after.1..col].iter().all(u8::is_ascii_whitespace)
+                        }) {
+                            *after = (line_number + 1, 0);
+                        }
+                        break;
+                    }
+                    (Kind::Opening, _, Some(_)) => depth += 1,
+                    (Kind::Closing, _, Some(_)) => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                col += match_.len().max(1);
+            }
+        }
+
+        let mut bounds = vec![start];
+        for (separator, after) in separators {
+            bounds.push(separator);
+            bounds.push(after.min(end));
+        }
+        bounds.push(end);
+        Some(
+            bounds
+                .chunks(2)
+                .filter_map(|bounds| trim_whitespace(&line, bounds[0], bounds[1]))
+                .collect(),
+        )
+    }
+
+    /// Gets the `(line, col)` of the comma ending the last element of the pair whose opening
+    /// or closing is at the position, i.e. the `,` in `[a, b,]`, skipping over the whitespace
+    /// and comments before the closing. The lines must be the ones the buffer was parsed from
+    pub fn trailing_comma_position<L: AsRef<[u8]>>(
+        &self,
+        lines: &[L],
+        line_number: usize,
+        col: usize,
+    ) -> Option<(usize, usize)> {
+        let (opening, closing) = self.match_pair(line_number, col)?;
+        let (mut line_number, mut end_col) = (closing.line, closing.col);
+        loop {
+            let text = lines.get(line_number)?.as_ref();
+            let start_col = match line_number == opening.line {
+                true => opening.end_col(),
+                false => 0,
+            };
+            // Skip over line comments, whose text may be anything
+            if let Some(comment) = self
+                .line_matches_in(line_number, start_col..end_col)
+                .iter()
+                .find(|match_| matches!(match_.token, Token::LineComment(_)))
+            {
+                end_col = comment.col;
+            }
+
+            match text[start_col.min(end_col)..end_col.min(text.len())]
+                .iter()
+                .rposition(|byte| !byte.is_ascii_whitespace())
+                .map(|idx| start_col + idx)
+            {
+                Some(col) if text[col] == b',' && self.match_at(line_number, col).is_none() => {
+                    return Some((line_number, col));
+                }
+                // Skip over block comments
+                Some(col) => {
+                    let comment = self.match_at(line_number, col).filter(|match_| {
+                        match_.kind == Kind::Closing
+                            && matches!(match_.token, Token::BlockComment(_, _))
+                    })?;
+                    let (comment, _) = self.match_pair(line_number, comment.col)?;
+                    (line_number, end_col) = (comment.line, comment.col);
+                }
+                None if line_number > opening.line => {
+                    line_number -= 1;
+                    end_col = lines.get(line_number)?.as_ref().len();
+                }
+                None => return None,
+            }
+        }
+    }
+
+    /// Whether the last element of the pair whose opening or closing is at the position ends
+    /// with a comma, see [`ParsedBuffer::trailing_comma_position`]
+    pub fn has_trailing_comma<L: AsRef<[u8]>>(
+        &self,
+        lines: &[L],
+        line_number: usize,
+        col: usize,
+    ) -> bool {
+        self.trailing_comma_position(lines, line_number, col)
+            .is_some()
+    }
+
+    pub fn unmatched_opening_before(
+        &self,
+        opening: &str,
+        closing: &str,
+        line_number: usize,
+        col: usize,
+    ) -> Option<MatchWithLine> {
+        let cursor_stack_height = self.stack_height_at(line_number, col);
+        let mut lowest_stack_height = cursor_stack_height;
+        let mut current_stack_height = cursor_stack_height;
+
+        for match_ in self
+            .iter_to(line_number, col)
+            .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
+        {
+            if let Some(stack_height) = match_.stack_height {
+                // Stack height higher than cursor
+                if stack_height < lowest_stack_height {
+                    // For example: ( [] ( | )
+                    // Stack:            ^   ^
+                    // Cursor stack height: 1
+                    // We can close the outer pair by adding a closing pair at the cursor
+                    if match_.kind == Kind::Opening
+                        && match_.token.closing() == Some(closing)
+                        && match_.token.opening() == opening
+                    {
+                        lowest_stack_height = stack_height;
+                    }
+                    // In this example: ( [ ( | ) ] )
+                    // Stack:             ^ ^   ^ ^
+                    // Cursor stack height: 2
+                    // Inserting a closing pair would not close the outer pair, so we exit
+                    else {
+                        return None;
+                    }
+                }
+
+                current_stack_height =
+                    stack_height + if match_.kind == Kind::Closing { 1 } else { 0 };
+            }
+
+            // Unmatched opening with the same stack height
+            if match_.kind == Kind::Opening
+                && match_.token.opening() == opening
+                && match_.token.closing() == Some(closing)
+                && match_.stack_height.is_none()
+                && current_stack_height == lowest_stack_height
+            {
+                return Some(match_);
+            }
+        }
+
+        None
+    }
+
+    pub fn unmatched_closing_after(
+        &self,
+        opening: &str,
+        closing: &str,
+        line_number: usize,
+        col: usize,
+    ) -> Option<MatchWithLine> {
+        let cursor_stack_height = self.stack_height_at(line_number, col);
+        let mut lowest_stack_height = cursor_stack_height;
+        let mut current_stack_height = cursor_stack_height;
+
+        for match_ in self
+            .iter_from(line_number, col)
+            .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
+        {
+            if let Some(stack_height) = match_.stack_height {
+                // Stack height higher than cursor
+                if stack_height < lowest_stack_height {
+                    // For example: ( | ) )
+                    // Stack:       ^   ^
+                    // Cursor stack height: 1
+                    // We can close the outer pair by adding a closing pair at the cursor
+                    if match_.kind == Kind::Closing
+                        && match_.token.closing() == Some(closing)
+                        && match_.token.opening() == opening
+                    {
+                        lowest_stack_height = stack_height;
+                    }
+                    // In this example: [ ( | ) ] )
+                    // Stack:           ^ ^   ^ ^
+                    // Cursor stack height: 2
+                    // Inserting a closing pair would not close the outer pair, so we exit
+                    else {
+                        return None;
+                    }
+                }
+
+                current_stack_height =
+                    stack_height + if match_.kind == Kind::Opening { 1 } else { 0 };
+            }
+
+            // Unmatched closing with the same stack height
+            if match_.kind == Kind::Closing
+                && match_.token.opening() == opening
+                && match_.token.closing() == Some(closing)
+                && match_.stack_height.is_none()
+                && current_stack_height == lowest_stack_height
+            {
+                return Some(match_);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(all(test, feature = "lang-rust"))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    fn parse(filetype: &str, lines: &[&str]) -> ParsedBuffer {
+        ParsedBuffer::parse(filetype, 4, lines).unwrap()
+    }
+
+    #[test]
+    fn test_unmatched_opening_before() {
+        let buffer = parse("rust", &["("]);
+        assert_eq!(buffer.unmatched_opening_before("(", ")", 0, 0), None);
+        assert_eq!(
+            buffer.unmatched_opening_before("(", ")", 0, 1),
+            Some(Match::delimiter('(', 0, None).with_line(0))
+        );
+
+        let buffer = parse("rust", &["( ( )"]);
+        assert_eq!(
+            buffer.unmatched_opening_before("(", ")", 0, 4),
+            Some(Match::delimiter('(', 0, None).with_line(0))
+        );
+    }
+
+    #[test]
+    fn test_get_unmatched_closing_at() {
+        let buffer = parse("rust", &[")"]);
+        assert_eq!(
+            buffer.unmatched_closing_after("(", ")", 0, 0),
+            Some(Match::delimiter(')', 0, None).with_line(0))
+        );
+        assert_eq!(buffer.unmatched_closing_after("(", ")", 0, 1), None);
+        assert_eq!(buffer.unmatched_closing_after("(", ")", 1, 1), None);
+
+        let buffer = parse("rust", &[" )"]);
+        assert_eq!(
+            buffer.unmatched_closing_after("(", ")", 0, 0),
+            Some(Match::delimiter(')', 1, None).with_line(0))
+        );
+        assert_eq!(
+            buffer.unmatched_closing_after("(", ")", 0, 1),
+            Some(Match::delimiter(')', 1, None).with_line(0))
+        );
+        assert_eq!(buffer.unmatched_closing_after("(", ")", 0, 2), None);
+        assert_eq!(buffer.unmatched_closing_after("(", ")", 1, 0), None);
+
+        let buffer = parse("rust", &["( ] )"]);
+        assert_eq!(buffer.unmatched_closing_after("[", "]", 0, 0), None);
+        assert_eq!(
+            buffer.unmatched_closing_after("[", "]", 0, 1),
+            Some(Match::delimiter(']', 2, None).with_line(0))
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let mut buffer = parse("rust", &["{", "}"]);
+        let checkpoint = buffer.checkpoint();
+
+        buffer.reparse_range("rust", 4, &["{ ("], Some(0), Some(1), Some(1));
+        assert_eq!(
+            buffer.matches_by_line[0],
+            vec![
+                Match::delimiter('{', 0, Some(0)),
+                Match::delimiter('(', 2, None)
+            ]
+        );
+
+        buffer.restore(checkpoint);
+        assert_eq!(
+            buffer.matches_by_line,
+            vec![
+                vec![Match::delimiter('{', 0, Some(0))],
+                vec![Match::delimiter('}', 0, Some(0))],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_indent_changes() {
+        let lines = ["fn a() {", "", "    b();", "", "", "}"];
+        let mut buffer = parse("rust", &lines);
+        assert_eq!(buffer.indent_levels, vec![0, 0, 4, 4, 4, 0]);
+        assert_eq!(buffer.indent_changes(), 0..6);
+
+        // Blank lines after the range follow the new indent level
+        buffer.reparse_range("rust", 4, &["        b();"], Some(2), Some(3), Some(3));
+        assert_eq!(buffer.indent_levels, vec![0, 0, 8, 8, 8, 0]);
+        assert_eq!(buffer.indent_changes(), 2..5);
+
+        buffer.reparse_range("rust", 4, &["fn c() {"], Some(0), Some(1), Some(1));
+        assert!(buffer.indent_changes().is_empty());
+
+        buffer.reparse_range("rust", 4, &["  x"], Some(1), Some(1), Some(2));
+        assert_eq!(buffer.indent_levels, vec![0, 2, 2, 8, 8, 8, 0]);
+        assert_eq!(buffer.indent_changes(), 1..3);
+
+        let lines = ["fn c() {", "  x", "", "        b();", "", "", "}"];
+        assert_eq!(buffer.indent_levels, parse("rust", &lines).indent_levels);
+    }
+
+    #[test]
+    fn test_virtual_indent_levels() {
+        let lines = [
+            "fn a() {",
+            "    b();",
+            "",
+            "    if c {",
+            "",
+            "        d();",
+            "",
+            "    }",
+            "}",
+            "",
+        ];
+        let buffer = parse("rust", &lines);
+        assert_eq!(
+            buffer.get_virtual_indent_levels(0, 10),
+            vec![0, 4, 4, 4, 8, 8, 8, 4, 0, 0]
+        );
+        assert_eq!(buffer.get_virtual_indent_levels(4, 5), vec![8]);
+        assert_eq!(buffer.get_indent_levels(4, 5), vec![4]);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-python")]
+    fn test_current_scope() {
+        let lines = [
+            "fn a() {",
+            "    let b = c(d, [",
+            "        e,",
+            "    ]);",
+            "    f(g);",
+            "}",
+            "",
+            "fn h() {}",
+        ];
+        let buffer = parse("rust", &lines);
+        assert_eq!(buffer.current_scope(2, 8), 1..4);
+        // Single line pairs are skipped
+        assert_eq!(buffer.current_scope(4, 6), 0..6);
+        assert_eq!(buffer.out_of_scope_ranges(4, 6), vec![6..8]);
+        assert_eq!(buffer.current_scope(7, 0), 7..8);
+
+        let lines = ["def a():", "    b()", "", "    if c:", "        d()", "e()"];
+        let buffer = parse("python", &lines);
+        assert_eq!(buffer.current_scope(4, 8), 3..5);
+        assert_eq!(buffer.current_scope(1, 4), 0..5);
+        assert_eq!(buffer.out_of_scope_ranges(1, 4), vec![5..6]);
+    }
+
+    #[test]
+    fn test_clip_long_lines() {
+        let lines = ["{", "a(b[c]) ( [x] ) (d{e}", "f)", "}"];
+        let mut buffer = parse("rust", &lines);
+        let expected = buffer.matches_by_line[1]
+            .iter()
+            .filter(|match_| [8, 10, 12, 14, 16].contains(&match_.col))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let window = ColumnWindow {
+            cols: 8..15,
+            min_line_len: 10,
+        };
+        assert_eq!(
+            buffer.clip_long_lines(&lines, &window),
+            vec![ClippedLine {
+                line: 1,
+                dropped_before: 4,
+                dropped_after: 2,
+            }]
+        );
+        assert_eq!(buffer.matches_by_line[1], expected);
+        assert_eq!(buffer.matches_by_line[2][0].stack_height, Some(1));
+
+        // Stack heights are the same after reparsing
+        buffer.reparse_range("rust", 4, &["}"], Some(3), Some(4), Some(4));
+        assert_eq!(buffer.matches_by_line[1], expected);
+        assert_eq!(buffer.matches_by_line[2][0].stack_height, Some(1));
+
+        // Comments and strings starting before the window cover it
+        let comment = format!("a // {}", "(x) ".repeat(30));
+        let string = format!("a = \"{}\" // (", "(x) ".repeat(30));
+        let lines = [comment.as_str(), string.as_str()];
+        let mut buffer = parse("rust", &lines);
+        let window = ColumnWindow {
+            cols: 50..80,
+            min_line_len: 10,
+        };
+        buffer.clip_long_lines(&lines, &window);
+        assert_eq!(buffer.state_at(0, 60), State::InLineComment);
+        assert!(buffer.is_in_comment(0, 60));
+        assert_eq!(buffer.state_at(1, 60), State::InBlockString("\""));
+        assert!(buffer.is_in_string(1, 60));
+        assert_eq!(buffer.state_at(1, string.len()), State::InLineComment);
+    }
+
+    #[test]
+    fn test_parse_with_budget() {
+        let text = include_str!("../benches/languages/rust.rs");
+        let lines = text.lines().collect::<Vec<_>>();
+        let expected = parse("rust", &lines);
+
+        let mut resumed = 0;
+        let mut result = ParsedBuffer::parse_with_budget("rust", 4, &lines, Duration::ZERO);
+        let parsed = loop {
+            match result.unwrap() {
+                BudgetedParse::Done(parsed) => break parsed,
+                BudgetedParse::Pending(continuation) => {
+                    resumed += 1;
+                    result = Some(continuation.resume(&lines, Duration::ZERO));
+                }
+            }
+        };
+
+        assert!(resumed > 1);
+        assert_eq!(parsed.matches_by_line, expected.matches_by_line);
+        assert_eq!(parsed.state_by_line, expected.state_by_line);
+        assert_eq!(parsed.indent_levels, expected.indent_levels);
+    }
+
+    #[test]
+    fn test_owned_lines() {
+        use std::borrow::Cow;
+
+        let expected = parse("rust", &["(", ")"]).matches_by_line;
+
+        let lines = vec!["(".to_string(), ")".to_string()];
+        let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+        assert_eq!(buffer.matches_by_line, expected);
+
+        let lines: Vec<Cow<str>> = vec![Cow::Borrowed("("), Cow::Owned(")".to_string())];
+        let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+        assert_eq!(buffer.matches_by_line, expected);
+
+        let buffer = ParsedBuffer::parse_lines("rust", 4, "(\n)".lines()).unwrap();
+        assert_eq!(buffer.matches_by_line, expected);
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        let buffer = ParsedBuffer::parse_bytes("rust", 4, &[b"\xff(".as_slice(), b")"]).unwrap();
+        assert_eq!(
+            buffer.matches_by_line,
+            vec![
+                vec![Match::delimiter('(', 1, Some(0))],
+                vec![Match::delimiter(')', 0, Some(0))],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nesting_regions() {
+        let buffer = parse(
+            "rust",
+            &["fn a() {", "    if b { c(d[0]) }", "}", "fn e() {}", "("],
+        );
+        assert_eq!(
+            buffer.nesting_regions(),
+            vec![
+                NestingRegion {
+                    start_line: 0,
+                    end_line: 2,
+                    max_depth: 4
+                },
+                NestingRegion {
+                    start_line: 3,
+                    end_line: 3,
+                    max_depth: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rebalanced_matching() {
+        let buffer = parse("rust", &["{", "\t{", "\t", "}"]);
+        assert_eq!(
+            buffer.matches_by_line,
+            vec![
+                vec![Match::delimiter('{', 0, Some(0))],
+                vec![Match::delimiter('{', 1, None)],
+                vec![],
+                vec![Match::delimiter('}', 0, Some(0))],
+            ]
+        );
+
+        let buffer = parse("rust", &["{", "\t{", "\t}"]);
+        assert_eq!(
+            buffer.matches_by_line,
+            vec![
+                vec![Match::delimiter('{', 0, None)],
+                vec![Match::delimiter('{', 1, Some(1))],
+                vec![Match::delimiter('}', 1, Some(1))],
+            ]
+        );
+
+        let buffer = parse("rust", &["{", "\t{", "\t}", "}"]);
+        assert_eq!(
+            buffer.matches_by_line,
+            vec![
+                vec![Match::delimiter('{', 0, Some(0))],
+                vec![Match::delimiter('{', 1, Some(1))],
+                vec![Match::delimiter('}', 1, Some(1))],
+                vec![Match::delimiter('}', 0, Some(0))],
+            ]
+        );
+
+        let buffer = parse("rust", &["{", "\t{", "\t\t{", "\t\t}", "}"]);
+        assert_eq!(
+            buffer.matches_by_line,
+            vec![
+                vec![Match::delimiter('{', 0, Some(0))],
+                vec![Match::delimiter('{', 1, None)],
+                vec![Match::delimiter('{', 2, Some(2))],
+                vec![Match::delimiter('}', 2, Some(2))],
+                vec![Match::delimiter('}', 0, Some(0))],
+            ]
+        );
+
+        let buffer = parse("rust", &["{", "\t{", "\t\t{", "\t\t}", "\t}"]);
+        assert_eq!(
+            buffer.matches_by_line,
+            vec![
+                vec![Match::delimiter('{', 0, None)],
+                vec![Match::delimiter('{', 1, Some(1))],
+                vec![Match::delimiter('{', 2, Some(2))],
+                vec![Match::delimiter('}', 2, Some(2))],
+                vec![Match::delimiter('}', 1, Some(1))],
+            ]
+        );
+
+        let buffer = parse("rust", &["{", "\t{", "\t\t{", "\t\t}", "\t{", "}"]);
+        assert_eq!(
+            buffer.matches_by_line,
+            vec![
+                vec![Match::delimiter('{', 0, Some(0))],
+                vec![Match::delimiter('{', 1, None)],
+                vec![Match::delimiter('{', 2, Some(2))],
+                vec![Match::delimiter('}', 2, Some(2))],
+                vec![Match::delimiter('{', 1, None)],
+                vec![Match::delimiter('}', 0, Some(0))],
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "lang-fortran")]
+    fn test_cycle_pair() {
+        let buffer = parse(
+            "fortran",
+            &[
+                "if (a) then",
+                "  x = 1",
+                "else if (b) then",
+                "  do i = 1, n",
+                "  end do",
+                "else",
+                "end if",
+            ],
+        );
+        let middle = buffer.match_at(2, 0).unwrap();
+        assert_eq!(middle.kind, Kind::Middle("else if"));
+        assert_eq!(middle.stack_height, Some(0));
+        assert_eq!(buffer.match_at(3, 2).unwrap().stack_height, Some(1));
+
+        let cycle = |line, col| {
+            buffer
+                .cycle_pair(line, col)
+                .map(|match_| (match_.line, match_.col))
+        };
+        assert_eq!(cycle(0, 0), Some((2, 0)));
+        assert_eq!(cycle(2, 3), Some((5, 0)));
+        assert_eq!(cycle(5, 0), Some((6, 0)));
+        assert_eq!(cycle(6, 4), Some((0, 0)));
+        assert_eq!(cycle(3, 2), Some((4, 2)));
+        assert_eq!(cycle(0, 3), Some((0, 5)));
+        assert_eq!(cycle(1, 0), None);
+
+        assert_eq!(
+            buffer.construct_ranges(5, 2).unwrap(),
+            vec![
+                TokenRange {
+                    line: 0,
+                    start_col: 0,
+                    end_col: 2
+                },
+                TokenRange {
+                    line: 2,
+                    start_col: 0,
+                    end_col: 7
+                },
+                TokenRange {
+                    line: 5,
+                    start_col: 0,
+                    end_col: 4
+                },
+                TokenRange {
+                    line: 6,
+                    start_col: 0,
+                    end_col: 6
+                },
+            ]
+        );
+        assert_eq!(buffer.construct_ranges(0, 3).unwrap().len(), 2);
+
+        // Middles don't change which closing is matched
+        let (_, closing) = buffer.match_pair(0, 0).unwrap();
+        assert_eq!((closing.line, closing.col), (6, 0));
+    }
+
+    #[test]
+    fn test_sorted_access() {
+        let buffer = parse("rust", &["f(\"a\", [b]) // c"]);
+        let cols = |matches: &[Match]| matches.iter().map(|m| m.col).collect::<Vec<_>>();
+        assert_eq!(
+            cols(&buffer.matches_by_line[0]),
+            vec![1, 2, 4, 7, 9, 10, 12]
+        );
+
+        assert_eq!(buffer.match_at(0, 0), None);
+        assert_eq!(buffer.match_at(0, 1).unwrap().col, 1);
+        assert_eq!(buffer.match_at(0, 3), None);
+        // Line comments cover the rest of the line
+        assert_eq!(buffer.match_at(0, 13).unwrap().col, 12);
+        assert_eq!(buffer.match_at(1, 0), None);
+
+        assert_eq!(
+            cols(buffer.line_matches_ref(0).unwrap()),
+            vec![1, 2, 4, 7, 9, 10, 12]
+        );
+        assert_eq!(buffer.line_matches_ref(1), None);
+
+        assert_eq!(cols(buffer.line_matches_in(0, 2..10)), vec![2, 4, 7, 9]);
+        assert_eq!(cols(buffer.line_matches_in(0, 5..5)), Vec::<usize>::new());
+        assert_eq!(cols(buffer.line_matches_in(1, 0..10)), Vec::<usize>::new());
+
+        // Tokens starting before the columns but ending inside them are included
+        assert_eq!(cols(buffer.matches_in(0, 3..8)), vec![4, 7]);
+        assert_eq!(cols(buffer.matches_in(0, 13..20)), vec![12]);
+        assert_eq!(cols(buffer.matches_in(0, 5..5)), Vec::<usize>::new());
+        assert_eq!(cols(buffer.matches_in(1, 0..10)), Vec::<usize>::new());
+    }
+
+    #[test]
+    #[cfg(feature = "lang-javascript")]
+    fn test_sortable_ranges() {
+        let range = |start_line, start_col, end_line, end_col| SourceRange {
+            start_line,
+            start_col,
+            end_line,
+            end_col,
+        };
+
+        let lines = ["f(b, [c, d], \"e, f\", g(h))"];
+        let buffer = parse("rust", &lines);
+        assert_eq!(
+            buffer.sortable_ranges(&lines, 0, 3),
+            Some(vec![
+                range(0, 2, 0, 3),
+                range(0, 5, 0, 11),
+                range(0, 13, 0, 19),
+                range(0, 21, 0, 25),
+            ])
+        );
+        // Inside of the nested list
+        assert_eq!(
+            buffer.sortable_ranges(&lines, 0, 7),
+            Some(vec![range(0, 6, 0, 7), range(0, 9, 0, 10)])
+        );
+        assert_eq!(buffer.sortable_ranges(&lines, 0, 0), None);
+
+        let lines = [
+            "let x = {",
+            "    a: 1, // b, c",
+            "    d: [2,",
+            "        3],",
+            "};",
+        ];
+        let buffer = parse("javascript", &lines);
+        assert_eq!(
+            buffer.sortable_ranges(&lines, 1, 4),
+            Some(vec![range(1, 4, 1, 8), range(2, 4, 3, 10)])
+        );
+    }
+
+    #[test]
+    fn test_closing_annotations() {
+        let lines = [
+            "impl Foo {",
+            "    fn bar(&self) -> Result<Vec<u8>, Error> {",
+            "        baz(",
+            "            1,",
+            "        )",
+            "    }",
+            "}",
+            "{",
+            "}",
+        ];
+        let buffer = parse("rust", &lines);
+        let annotations = buffer.closing_annotations(&lines, 0, lines.len(), 4, 20);
+        assert_eq!(
+            annotations,
+            vec![
+                ClosingAnnotation {
+                    line: 5,
+                    col: 5,
+                    opening_line: 1,
+                    text: "fn bar(&self) -> Re…".to_string(),
+                },
+                ClosingAnnotation {
+                    line: 6,
+                    col: 1,
+                    opening_line: 0,
+                    text: "impl Foo".to_string(),
+                },
+            ]
+        );
+        assert_eq!(buffer.closing_annotations(&lines, 0, 6, 3, 20).len(), 2);
+    }
+
+    #[test]
+    fn test_opening_context() {
+        let lines = ["  if x {  ", "    y();", "  }", ""];
+        let buffer = parse("rust", &lines);
+        let closing = buffer.match_at(2, 2).unwrap().with_line(2);
+        assert_eq!(
+            buffer.opening_context(&closing, |line| lines.get(line)),
+            Some((0, 2..8))
+        );
+
+        let opening = buffer.match_at(0, 7).unwrap().with_line(0);
+        assert_eq!(
+            buffer.opening_context(&opening, |line| lines.get(line)),
+            None
+        );
+        assert_eq!(trimmed_range(b"   "), 3..3);
+    }
+
+    #[test]
+    fn test_pairs_intersecting_point() {
+        let buffer = parse("rust", &["fn a() {", "    b(\"c\", [d]);", "}"]);
+        let pair_cols = |line, col| {
+            buffer
+                .pairs_intersecting_point(line, col)
+                .map(|(opening, closing)| {
+                    ((opening.line, opening.col), (closing.line, closing.col))
+                })
+        };
+
+        assert_eq!(pair_cols(0, 7), Some(((0, 7), (2, 0))));
+        assert_eq!(pair_cols(2, 0), Some(((0, 7), (2, 0))));
+        assert_eq!(pair_cols(1, 2), Some(((0, 7), (2, 0))));
+        assert_eq!(pair_cols(1, 5), Some(((1, 5), (1, 14))));
+        // Inside of a string
+        assert_eq!(pair_cols(1, 7), Some(((1, 5), (1, 14))));
+        assert_eq!(pair_cols(1, 12), Some(((1, 11), (1, 13))));
+        assert_eq!(pair_cols(0, 0), None);
+    }
+
+    #[test]
+    fn test_enclosing_pair() {
+        let buffer = parse("rust", &["fn a() {", "    b(\"c\", [d]);", "}"]);
+        let pair_cols = |line, col| {
+            buffer.enclosing_pair(line, col).map(|(opening, closing)| {
+                ((opening.line, opening.col), (closing.line, closing.col))
+            })
+        };
+
+        assert_eq!(pair_cols(1, 2), Some(((0, 7), (2, 0))));
+        assert_eq!(pair_cols(1, 12), Some(((1, 11), (1, 13))));
+        // Inside of a string
+        assert_eq!(pair_cols(1, 7), Some(((1, 5), (1, 14))));
+        // On the delimiters of a pair
+        assert_eq!(pair_cols(1, 5), Some(((0, 7), (2, 0))));
+        assert_eq!(pair_cols(1, 14), Some(((0, 7), (2, 0))));
+        assert_eq!(pair_cols(1, 11), Some(((1, 5), (1, 14))));
+        assert_eq!(pair_cols(1, 13), Some(((1, 5), (1, 14))));
+        assert_eq!(pair_cols(0, 7), None);
+        assert_eq!(pair_cols(2, 0), None);
+        assert_eq!(pair_cols(0, 0), None);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-php")]
+    fn test_state_at() {
+        let buffer = parse("rust", &["a(\"b\", /* c", "d */ e); // f"]);
+        assert_eq!(buffer.state_at(0, 2), State::Normal);
+        assert_eq!(buffer.state_at(0, 3), State::InBlockString("\""));
+        assert_eq!(buffer.state_at(0, 5), State::Normal);
+        assert_eq!(buffer.state_at(0, 9), State::InBlockComment("/*"));
+        assert_eq!(buffer.state_at(1, 0), State::InBlockComment("/*"));
+        assert_eq!(buffer.state_at(1, 4), State::Normal);
+        assert_eq!(buffer.state_at(1, 11), State::InLineComment);
+
+        let buffer = parse("php", &["$a = \"b {$c} d\";"]);
+        assert_eq!(buffer.state_at(0, 10), State::InInterpolation("\""));
+        assert_eq!(buffer.state_at(0, 13), State::InString("\""));
+    }
+
+    #[test]
+    fn test_is_safe_insertion_point() {
+        let buffer = parse("rust", &["a(\"b\", /* c */);"]);
+        let safe = (0..=16)
+            .filter(|&col| buffer.is_safe_insertion_point(0, col))
+            .collect::<Vec<_>>();
+        assert_eq!(safe, vec![0, 1, 2, 5, 6, 7, 14, 15, 16]);
+    }
+
+    #[test]
+    fn test_analyze_paste() {
+        let buffer = parse("rust", &["fn a() {", "    b(\"c\");", "}"]);
+        let positions = |matches: &[MatchWithLine]| {
+            matches
+                .iter()
+                .map(|match_| (match_.line, match_.col, match_.text()))
+                .collect::<Vec<_>>()
+        };
+
+        let analysis = buffer
+            .analyze_paste("rust", 1, 4, &["}", "    }", "d(["])
+            .unwrap();
+        assert_eq!(
+            positions(&analysis.extra_closings),
+            vec![(1, 4, "}"), (2, 4, "}")]
+        );
+        assert_eq!(
+            positions(&analysis.unclosed_openings),
+            vec![(3, 1, "("), (3, 2, "[")]
+        );
+        assert!(!analysis.is_balanced());
+
+        assert!(buffer
+            .analyze_paste("rust", 1, 4, &["if a {", "    b(c);", "}"])
+            .unwrap()
+            .is_balanced());
+
+        // Pasting into a string
+        let analysis = buffer.analyze_paste("rust", 1, 7, &["(\""]).unwrap();
+        assert_eq!(analysis.start_state, State::InBlockString("\""));
+        assert_eq!(analysis.end_state, State::Normal);
+        assert_eq!(positions(&analysis.unclosed_openings), vec![]);
+        assert!(!analysis.is_balanced());
+
+        assert_eq!(buffer.analyze_paste("unknown", 1, 4, &["}"]), None);
+    }
+
+    #[test]
+    fn test_next_opening_prev_closing() {
+        let buffer = parse("rust", &["{ \"{\" }", "// }", "({[]})"]);
+        let pos = |match_: Option<MatchWithLine>| match_.map(|match_| (match_.line, match_.col));
+        assert_eq!(pos(buffer.next_opening("{", 0, 0)), Some((2, 1)));
+        assert_eq!(pos(buffer.next_opening("[", 0, 0)), Some((2, 2)));
+        assert_eq!(pos(buffer.next_opening("{", 2, 1)), None);
+        assert_eq!(pos(buffer.prev_closing("}", 2, 4)), Some((0, 6)));
+        assert_eq!(pos(buffer.prev_closing("}", 2, 5)), Some((2, 4)));
+        assert_eq!(pos(buffer.prev_closing("]", 2, 3)), None);
+    }
+
+    #[test]
+    fn test_fold_ranges() {
+        let buffer = parse(
+            "rust",
+            &[
+                "fn a() {",
+                "    if b {",
+                "        c([",
+                "            d,",
+                "        ]);",
+                "    } else {",
+                "        e(f);",
+                "    }",
+                "}",
+            ],
+        );
+        assert_eq!(
+            buffer.fold_ranges(2),
+            vec![(0, 8, 1), (1, 5, 2), (2, 4, 3), (5, 7, 2)]
+        );
+        assert_eq!(buffer.fold_ranges(5), vec![(0, 8, 1), (1, 5, 2)]);
+    }
+
+    #[test]
+    fn test_join_adjustment() {
+        let lines = [
+            "foo(", "    [a,", "    ],", "    { b", "    },", "    \"c", "\", // d", "    {",
+            "    }", ")",
+        ];
+        let buffer = parse("rust", &lines);
+        let joined = |line: usize| {
+            let adjustment =
+                buffer.join_adjustment(line, lines[line].as_bytes(), lines[line + 1].as_bytes())?;
+            let mut text = lines[line][..adjustment.line_end].to_string();
+            if let Some(comma) = adjustment.trailing_comma {
+                text.remove(comma);
+            }
+            Some(text + adjustment.separator + &lines[line + 1][adjustment.next_line_start..])
+        };
+        assert_eq!(joined(0), Some("foo([a,".to_string()));
+        assert_eq!(joined(1), Some("    [a],".to_string()));
+        assert_eq!(joined(2), Some("    ], { b".to_string()));
+        assert_eq!(joined(3), Some("    { b },".to_string()));
+        assert_eq!(joined(5), None);
+        assert_eq!(joined(6), None);
+        assert_eq!(joined(7), Some("    {}".to_string()));
+        assert_eq!(joined(8), Some("    })".to_string()));
+        assert_eq!(buffer.join_adjustment(9, b")", b""), None);
+    }
+
+    #[test]
+    fn test_sibling_block() {
+        let buffer = parse(
+            "rust",
+            &[
+                "fn a() {",
+                "    b(c, [d], (e));",
+                "    if f { g() }",
+                "}",
+                "h()",
+            ],
+        );
+        let sibling = |line, col, direction| {
+            buffer
+                .sibling_block(line, col, direction)
+                .map(|(opening, closing)| {
+                    ((opening.line, opening.col), (closing.line, closing.col))
+                })
+        };
+        assert_eq!(sibling(1, 5, Direction::Forward), Some(((2, 9), (2, 15))));
+        assert_eq!(sibling(2, 15, Direction::Backward), Some(((1, 5), (1, 17))));
+        assert_eq!(sibling(1, 7, Direction::Forward), Some(((1, 9), (1, 11))));
+        assert_eq!(sibling(1, 9, Direction::Forward), Some(((1, 14), (1, 16))));
+        assert_eq!(sibling(1, 14, Direction::Forward), None);
+        assert_eq!(sibling(1, 9, Direction::Backward), None);
+        assert_eq!(sibling(0, 7, Direction::Forward), Some(((4, 1), (4, 2))));
+        assert_eq!(sibling(4, 0, Direction::Backward), Some(((0, 7), (3, 0))));
+
+        assert_eq!(
+            buffer.next_sibling_pair(1, 7),
+            buffer.sibling_block(1, 7, Direction::Forward)
+        );
+        let (opening, closing) = buffer.prev_sibling_pair(1, 14).unwrap();
+        assert_eq!((opening.col, closing.col), (9, 11));
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-c", feature = "lang-lua"))]
+    fn test_diagnostics() {
+        let buffer = parse("rust", &["fn a() {", "    b(\"c", "    d]);", "/* e"]);
+        assert_eq!(
+            buffer
+                .diagnostics()
+                .into_iter()
+                .map(|diagnostic| (diagnostic.line, diagnostic.col, diagnostic.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, 7, DiagnosticKind::UnmatchedOpening),
+                (1, 5, DiagnosticKind::UnmatchedOpening),
+                (1, 6, DiagnosticKind::UnterminatedString),
+            ]
+        );
+
+        let buffer = parse("lua", &["a = 'b'", "c = 'd", "e)", "--[[ f"]);
+        assert_eq!(
+            buffer
+                .diagnostics()
+                .into_iter()
+                .map(|diagnostic| (diagnostic.line, diagnostic.col, diagnostic.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, 4, DiagnosticKind::UnterminatedString),
+                (2, 1, DiagnosticKind::UnmatchedClosing),
+                (3, 0, DiagnosticKind::UnterminatedComment),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_textobject_at() {
+        let buffer = parse("rust", &["a(b, \")\", [c]", ")"]);
+        let range = |line, col, around| {
+            buffer.textobject_at(line, col, around).map(|range| {
+                (
+                    range.start_line,
+                    range.start_col,
+                    range.end_line,
+                    range.end_col,
+                )
+            })
+        };
+        assert_eq!(range(0, 6, false), Some((0, 2, 1, 0)));
+        assert_eq!(range(0, 6, true), Some((0, 1, 1, 1)));
+        assert_eq!(range(0, 10, false), Some((0, 11, 0, 12)));
+        assert_eq!(range(0, 10, true), Some((0, 10, 0, 13)));
+        assert_eq!(range(1, 0, false), Some((0, 2, 1, 0)));
+        assert_eq!(range(0, 0, false), None);
+    }
+
+    #[test]
+    fn test_top_level_blocks() {
+        let buffer = parse(
+            "rust",
+            &[
+                "struct A {",
+                "    b: [u8; 2],",
+                "}",
+                "const C: u8 = f(1);",
+                "fn d() {",
+                "    e({",
+                "    })",
+                "}",
+                "g!(h, || {",
+                "});",
+            ],
+        );
+        assert_eq!(
+            buffer
+                .top_level_blocks()
+                .into_iter()
+                .map(|range| (
+                    range.start_line,
+                    range.start_col,
+                    range.end_line,
+                    range.end_col
+                ))
+                .collect::<Vec<_>>(),
+            vec![(0, 9, 2, 1), (4, 7, 7, 1), (8, 2, 9, 2)]
+        );
+    }
+
+    #[test]
+    fn test_highlights() {
+        let buffer = parse("rust", &["a(", "  [{(\"b\")}]", ")]"]);
+        assert_eq!(
+            buffer.highlights(1..3, 3),
+            vec![
+                1, 2, 1, 1, //
+                1, 3, 1, 2, //
+                1, 4, 1, 0, //
+                1, 8, 1, 0, //
+                1, 9, 1, 2, //
+                1, 10, 1, 1, //
+                2, 0, 1, 0, //
+                2, 1, 1, 3, //
+            ]
+        );
+        assert_eq!(buffer.highlights(3..5, 3), Vec::<usize>::new());
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-python", feature = "lang-twig"))]
+    fn test_multibyte_tokens() {
+        let pos = |match_: MatchWithLine| (match_.line, match_.col);
+
+        // Block comments
+        let buffer = parse("rust", &["a(/* b */)"]);
+        for col in [2, 3] {
+            assert_eq!(buffer.match_at(0, col).map(|match_| match_.col), Some(2));
+            assert_eq!(
+                buffer
+                    .match_pair(0, col)
+                    .map(|(open, close)| (pos(open), pos(close))),
+                Some(((0, 2), (0, 7)))
+            );
+            assert_eq!(buffer.state_at(0, col), State::Normal);
+            assert_eq!(buffer.stack_height_at(0, col), 1);
+            assert_eq!(
+                buffer.enclosing_pair(0, col).map(|(open, _)| pos(open)),
+                Some((0, 1))
+            );
+        }
+        assert_eq!(buffer.state_at(0, 4), State::InBlockComment("/*"));
+        assert_eq!(buffer.state_at(0, 8), State::InBlockComment("/*"));
+        assert_eq!(buffer.state_at(0, 9), State::Normal);
+        assert_eq!(
+            buffer
+                .match_pair(0, 8)
+                .map(|(open, close)| (pos(open), pos(close))),
+            Some(((0, 2), (0, 7)))
+        );
+
+        // Triple quoted strings
+        let buffer = parse("python", &["a = \"\"\"b\"\"\""]);
+        for col in 4..7 {
+            assert_eq!(
+                buffer
+                    .match_pair(0, col)
+                    .map(|(open, close)| (pos(open), pos(close))),
+                Some(((0, 4), (0, 8)))
+            );
+            assert_eq!(buffer.state_at(0, col), State::Normal);
+        }
+        assert_eq!(buffer.state_at(0, 7), State::InBlockString("\"\"\""));
+        assert_eq!(buffer.state_at(0, 10), State::InBlockString("\"\"\""));
+        assert_eq!(buffer.state_at(0, 11), State::Normal);
+
+        // Tags
+        let buffer = parse("twig", &["{% if (a) %}", "{%- b -%}", "{% endif %}"]);
+        assert_eq!(
+            buffer
+                .match_pair(0, 11)
+                .map(|(open, close)| (pos(open), pos(close))),
+            Some(((0, 0), (0, 10)))
+        );
+        assert_eq!(buffer.enclosing_pair(0, 11), None);
+        assert_eq!(buffer.prev_closing("%}", 1, 1).map(pos), Some((0, 10)));
+        assert_eq!(buffer.prev_closing("%}", 0, 11), None);
+        assert_eq!(
+            buffer
+                .pending_closings(0, 1)
+                .iter()
+                .map(|closing| closing.closing)
+                .collect::<Vec<_>>(),
+            Vec::<&str>::new()
+        );
+        assert_eq!(buffer.unmatched_delimiters().count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-c")]
+    fn test_depth_limit() {
+        let heights = |buffer: &ParsedBuffer| {
+            buffer.matches_by_line[0]
+                .iter()
+                .map(|match_| match_.stack_height)
+                .collect::<Vec<_>>()
+        };
+        let mut buffer = parse("rust", &["(([a])) ()"]);
+        let expected = [0, 1, 2, 2, 1, 0, 0, 0].map(Some);
+        assert_eq!(heights(&buffer), expected);
+
+        let limit = DepthLimit {
+            max_depth: 1,
+            overflow: DepthOverflow::Clamp,
+        };
+        buffer.set_depth_limit(limit, 4);
+        assert_eq!(heights(&buffer), [0, 1, 1, 1, 1, 0, 0, 0].map(Some));
+
+        // The limit is kept when reparsing
+        buffer.reparse_range("rust", 4, &["(([b])) ()"], Some(0), Some(1), Some(1));
+        assert_eq!(buffer.depth_limit(), limit);
+        assert_eq!(heights(&buffer), [0, 1, 1, 1, 1, 0, 0, 0].map(Some));
+
+        let limit = DepthLimit {
+            max_depth: 1,
+            overflow: DepthOverflow::Unmatched,
+        };
+        buffer.set_depth_limit(limit, 4);
+        assert_eq!(
+            heights(&buffer),
+            [Some(0), None, None, None, None, Some(0), Some(0), Some(0)]
+        );
+
+        buffer.set_depth_limit(DepthLimit::default(), 4);
+        assert_eq!(heights(&buffer), expected);
+
+        // Strings and comments past the limit don't count towards the overflowing pairs
+        let mut buffer = parse("c", &["((( \"a", "// (", ")))", "( )"]);
+        let limit = DepthLimit {
+            max_depth: 2,
+            overflow: DepthOverflow::Clamp,
+        };
+        buffer.set_depth_limit(limit, 4);
+        let heights = buffer
+            .matches_by_line
+            .iter()
+            .map(|matches| {
+                matches
+                    .iter()
+                    .map(|match_| match_.stack_height)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            heights,
+            [
+                vec![Some(0), Some(1), Some(2), None],
+                vec![None],
+                vec![Some(2), Some(1), Some(0)],
+                vec![Some(0), Some(0)],
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "lang-python")]
+    fn test_match_at_with_offset() {
+        let buffer = parse("python", &["a = \"\"\"b\"\"\"  # c"]);
+        let at = |col| {
+            buffer
+                .match_at_with_offset(0, col)
+                .map(|(match_, offset)| (match_.range(), offset))
+        };
+        assert_eq!(at(3), None);
+        assert_eq!(at(4), Some((4..7, 0)));
+        assert_eq!(at(6), Some((4..7, 2)));
+        assert_eq!(at(7), None);
+        assert_eq!(at(10), Some((8..11, 2)));
+        assert_eq!(at(13), Some((13..14, 0)));
+    }
+
+    #[test]
+    fn test_stale_indent_range() {
+        let lines = [
+            "fn a() {",
+            "    b(c,",
+            "  d);",
+            "    if e {",
+            "    f();",
+            "    }",
+            "g();",
+            "  }",
+        ];
+        let buffer = parse("rust", &lines);
+        // Continuation lines of `b(` may be indented in any way
+        assert_eq!(buffer.stale_indent_range(&lines, 4, 0, 7), Some(4..8));
+        assert_eq!(buffer.stale_indent_range(&lines, 4, 3, 9), Some(4..5));
+        assert_eq!(buffer.stale_indent_range(&lines, 4, 1, 5), None);
+        assert_eq!(buffer.stale_indent_range(&lines, 4, 2, 2), None);
+        assert_eq!(buffer.stale_indent_range(&lines, 4, 6, 0), Some(4..8));
+
+        let lines = ["a = {", "    b = [ // c", "        d", "    ],", "}"];
+        let buffer = parse("rust", &lines);
+        assert_eq!(buffer.stale_indent_range(&lines, 4, 4, 0), None);
+    }
+
+    #[test]
+    fn test_suggested_indent() {
+        let lines = [
+            "fn a() {",
+            "b(c,",
+            "d);",
+            "    let e = [ // f",
+            "g",
+            "];",
+            "\"h",
+            "i\"",
+            "}",
+            "j",
+        ];
+        let buffer = parse("rust", &lines);
+        let indents = (0..lines.len())
+            .map(|line| buffer.suggested_indent(line, 4, |line| lines.get(line)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            indents,
+            vec![
+                Some(0),
+                Some(4),
+                Some(2),
+                Some(4),
+                Some(8),
+                Some(4),
+                Some(4),
+                None,
+                Some(0),
+                Some(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pairs_in_range() {
+        let buffer = parse(
+            "rust",
+            &[
+                "fn a() {",
+                "    b(",
+                "        [c],",
+                "    );",
+                "    d(e);",
+                "}",
+            ],
+        );
+        let pair_cols = |lines| {
+            buffer
+                .pairs_in_range(lines)
+                .into_iter()
+                .map(|(opening, closing)| {
+                    ((opening.line, opening.col), (closing.line, closing.col))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(pair_cols(2..4), vec![((1, 5), (3, 4)), ((2, 8), (2, 10))]);
+        assert_eq!(pair_cols(4..6), vec![((0, 7), (5, 0)), ((4, 5), (4, 7))]);
+        assert_eq!(
+            pair_cols(0..6),
+            vec![
+                ((0, 4), (0, 5)),
+                ((0, 7), (5, 0)),
+                ((1, 5), (3, 4)),
+                ((2, 8), (2, 10)),
+                ((4, 5), (4, 7))
+            ]
+        );
+        assert_eq!(pair_cols(6..8), vec![]);
+        assert_eq!(pair_cols(7..9), vec![]);
+        assert_eq!(pair_cols(Range { start: 4, end: 2 }), vec![]);
+    }
+
+    #[test]
+    fn test_enclosing_pairs() {
+        let buffer = parse("rust", &["fn a() {", "    b(\"c\", [d]);", "}"]);
+        let pair_cols = |line, col| {
+            buffer
+                .enclosing_pairs(line, col)
+                .map(|(opening, closing)| {
+                    ((opening.line, opening.col), (closing.line, closing.col))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            pair_cols(1, 12),
+            vec![((1, 11), (1, 13)), ((1, 5), (1, 14)), ((0, 7), (2, 0))]
+        );
+        assert_eq!(pair_cols(1, 11), vec![((1, 5), (1, 14)), ((0, 7), (2, 0))]);
+        assert_eq!(pair_cols(0, 0), vec![]);
+    }
+
+    #[test]
+    fn test_pending_closings() {
+        let buffer = parse("rust", &["fn a() {", "    b(c, [d", "    e{f}", "}"]);
+        let closings = |line, col| {
+            buffer
+                .pending_closings(line, col)
+                .into_iter()
+                .map(|closing| {
+                    (
+                        closing.closing,
+                        closing.opening_line,
+                        closing.opening_col,
+                        closing.depth,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(closings(1, 11), vec![("]", 1, 9, 0), (")", 1, 5, 1)]);
+        // Skips the pair closed before the position
+        assert_eq!(closings(2, 8), vec![("]", 1, 9, 0), (")", 1, 5, 1)]);
+        assert_eq!(closings(1, 6), vec![(")", 1, 5, 0)]);
+        assert_eq!(closings(1, 5), vec![]);
+        assert_eq!(closings(3, 1), vec![]);
+
+        // Stops at the pair surrounding the position
+        let buffer = parse("rust", &["( [ ]", "[ ( ]"]);
+        let closings = |line, col| {
+            buffer
+                .pending_closings(line, col)
+                .into_iter()
+                .map(|closing| (closing.closing, closing.opening_col))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(closings(0, 4), vec![]);
+        assert_eq!(closings(1, 4), vec![(")", 2)]);
+    }
+
+    #[test]
+    fn test_unmatched_delimiters() {
+        let buffer = parse("rust", &["fn a() {", "    b(]);", "\"(\""]);
+        let unmatched = buffer
+            .unmatched_delimiters()
+            .map(|match_| (match_.line, match_.col, match_.text()))
+            .collect::<Vec<_>>();
+        assert_eq!(unmatched, vec![(0, 7, "{"), (1, 6, "]")]);
+    }
+
+    #[test]
+    fn test_unmatched_navigation() {
+        let buffer = parse("rust", &["fn a() {", "    b(]);", "    c[d);", "\"(\""]);
+        let position =
+            |match_: Option<MatchWithLine>| match_.map(|match_| (match_.line, match_.col));
+
+        assert_eq!(position(buffer.next_unmatched_after(0, 0)), Some((0, 7)));
+        assert_eq!(position(buffer.next_unmatched_after(0, 7)), Some((1, 6)));
+        assert_eq!(position(buffer.next_unmatched_after(1, 6)), Some((2, 5)));
+        assert_eq!(position(buffer.next_unmatched_after(2, 7)), None);
+        assert_eq!(position(buffer.prev_unmatched_before(3, 1)), Some((2, 7)));
+        assert_eq!(position(buffer.prev_unmatched_before(1, 6)), Some((0, 7)));
+        assert_eq!(position(buffer.prev_unmatched_before(0, 7)), None);
+    }
+
+    #[test]
+    fn test_inner_matches() {
+        let lines = [
+            "/// Returns (a, [b]",
+            "/// or c)",
+            r#"fn f() { g("\(", ')') }"#,
+            "/* { */ }",
+        ];
+        let buffer = parse("rust", &lines);
+        assert_eq!(
+            buffer.inner_matches("rust", &lines, 0),
+            Some(vec![
+                vec![
+                    Match::delimiter('(', 12, Some(0)),
+                    Match::delimiter('[', 16, Some(1)),
+                    Match::delimiter(']', 18, Some(1)),
+                ],
+                vec![Match::delimiter(')', 8, Some(0))],
+                vec![],
+                vec![Match::delimiter('{', 3, None)],
+            ])
+        );
+        assert_eq!(
+            buffer.matches_by_line[0],
+            vec![Match::line_comment("//", 0)]
+        );
 
-                current_stack_height =
-                    stack_height + if match_.kind == Kind::Closing { 1 } else { 0 };
-            }
+        // Starts from the state of the line before
+        let buffer = parse("rust", &["/*", "a(", "*/"]);
+        assert_eq!(
+            buffer.inner_matches("rust", &["a(", "*/"], 1),
+            Some(vec![vec![Match::delimiter('(', 1, None)], vec![]])
+        );
+    }
 
-            // Unmatched opening with the same stack height
-            if match_.kind == Kind::Opening
-                && match_.token.opening() == opening
-                && match_.token.closing() == Some(closing)
-                && match_.stack_height.is_none()
-                && current_stack_height == lowest_stack_height
-            {
-                return Some(match_);
-            }
-        }
+    #[test]
+    fn test_skip_unchanged_lines() {
+        let lines = ["fn a() {", "    b(1);", "}"];
+        let mut buffer = parse("rust", &lines);
 
-        None
+        // Mark the line to tell whether it was tokenized again
+        buffer.matches_by_line[1].clear();
+        assert!(buffer.reparse_range("rust", 4, &lines[1..2], Some(1), Some(2), Some(2)));
+        assert_eq!(buffer.matches_by_line[1], vec![]);
+        assert_eq!(buffer.indent_changes(), 1..1);
+
+        assert!(buffer.reparse_range("rust", 4, &["    b(2);"], Some(1), Some(2), Some(2)));
+        assert_eq!(buffer.matches_by_line[1].len(), 2);
+
+        // Identical lines after a state change are parsed again
+        let mut buffer = parse("rust", &["/*", "a(", ")"]);
+        buffer.reparse_range("rust", 4, &["x"], Some(0), Some(1), Some(1));
+        assert_eq!(buffer.matches_by_line[1], vec![]);
+        buffer.reparse_range("rust", 4, &["a(", ")"], Some(1), Some(3), Some(3));
+        assert_eq!(
+            buffer.matches_by_line[1],
+            vec![Match::delimiter('(', 1, Some(0))]
+        );
     }
 
-    pub fn unmatched_closing_after(
-        &self,
-        opening: &str,
-        closing: &str,
-        line_number: usize,
-        col: usize,
-    ) -> Option<MatchWithLine> {
-        let cursor_stack_height = self.stack_height_at(line_number, col);
-        let mut lowest_stack_height = cursor_stack_height;
-        let mut current_stack_height = cursor_stack_height;
+    #[test]
+    fn test_whitespace_changes() {
+        let mut buffer = parse("rust", &["fn a() {", "  b(1,  ", "  2);", "}"]);
 
-        for match_ in self
-            .iter_from(line_number, col)
-            .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
-        {
-            if let Some(stack_height) = match_.stack_height {
-                // Stack height higher than cursor
-                if stack_height < lowest_stack_height {
-                    // For example: ( | ) )
-                    // Stack:       ^   ^
-                    // Cursor stack height: 1
-                    // We can close the outer pair by adding a closing pair at the cursor
-                    if match_.kind == Kind::Closing
-                        && match_.token.closing() == Some(closing)
-                        && match_.token.opening() == opening
-                    {
-                        lowest_stack_height = stack_height;
-                    }
-                    // In this example: [ ( | ) ] )
-                    // Stack:           ^ ^   ^ ^
-                    // Cursor stack height: 2
-                    // Inserting a closing pair would not close the outer pair, so we exit
-                    else {
-                        return None;
-                    }
-                }
+        // Mark the line to tell whether it was tokenized again
+        buffer.matches_by_line[1][0].source_len = Some(1);
+        let lines = ["fn a() {", "    b(1,", "        2);", "}"];
+        assert!(buffer.reparse_range("rust", 4, &lines[1..3], Some(1), Some(3), Some(3)));
+        assert_eq!(buffer.matches_by_line[1][0].source_len, Some(1));
+        buffer.matches_by_line[1][0].source_len = None;
 
-                current_stack_height =
-                    stack_height + if match_.kind == Kind::Opening { 1 } else { 0 };
-            }
+        let expected = parse("rust", &lines);
+        assert_eq!(buffer.matches_by_line, expected.matches_by_line);
+        assert_eq!(buffer.indent_levels, expected.indent_levels);
+        assert_eq!(buffer.indent_changes(), 1..3);
+        assert_eq!(buffer.generation(), 1);
 
-            // Unmatched closing with the same stack height
-            if match_.kind == Kind::Closing
-                && match_.token.opening() == opening
-                && match_.token.closing() == Some(closing)
-                && match_.stack_height == None
-                && current_stack_height == lowest_stack_height
-            {
-                return Some(match_);
-            }
+        // Lines losing their indent are parsed again
+        buffer.matches_by_line[1][0].source_len = Some(1);
+        assert!(buffer.reparse_range("rust", 4, &["b(1,"], Some(1), Some(2), Some(2)));
+        assert_eq!(buffer.matches_by_line[1][0].source_len, None);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-python")]
+    fn test_span_pair() {
+        let pos = |(open, close): (MatchWithLine, MatchWithLine)| {
+            ((open.line, open.col), (close.line, close.col))
+        };
+        let buffer = parse("rust", &["f(\"a\", 'b') /* c", "(d) */ // e"]);
+        for col in 2..5 {
+            assert_eq!(buffer.span_pair(0, col).map(pos), Some(((0, 2), (0, 4))));
+        }
+        assert_eq!(buffer.span_pair(0, 8).map(pos), Some(((0, 7), (0, 9))));
+        for (line, col) in [(0, 12), (0, 13), (0, 15), (1, 1), (1, 4), (1, 5)] {
+            assert_eq!(
+                buffer.span_pair(line, col).map(pos),
+                Some(((0, 12), (1, 4)))
+            );
         }
+        assert_eq!(buffer.span_pair(0, 1), None);
+        assert_eq!(buffer.span_pair(0, 11), None);
+        assert_eq!(buffer.span_pair(1, 8), None);
+        assert_eq!(buffer.span_pair(1, 10), None);
 
-        None
+        // Unterminated
+        let buffer = parse("python", &["'a' \"b"]);
+        assert_eq!(buffer.span_pair(0, 1).map(pos), Some(((0, 0), (0, 2))));
+        assert_eq!(buffer.span_pair(0, 5), None);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use pretty_assertions::assert_eq;
+    #[test]
+    fn test_structure_hash() {
+        let hash = parse("rust", &["fn a() {", "    b(1);", "}"]).structure_hash();
+        assert_eq!(
+            parse("rust", &["fn c()  {", "        d(2, 3);", "}"]).structure_hash(),
+            hash
+        );
+        assert_ne!(
+            parse("rust", &["fn a() {", "    b(1);", "", "}"]).structure_hash(),
+            hash
+        );
+        assert_ne!(
+            parse("rust", &["fn a() {", "    b[1];", "}"]).structure_hash(),
+            hash
+        );
+        assert_ne!(
+            parse("rust", &["fn a() {", "    b(1); {", "}"]).structure_hash(),
+            hash
+        );
+        // Strings and comments within the pairs aren't part of the structure
+        assert_eq!(
+            parse("rust", &["fn a() {", "    b(\"1\"); /* c */", "}"]).structure_hash(),
+            hash
+        );
+    }
 
-    fn parse(filetype: &str, lines: &[&str]) -> ParsedBuffer {
-        ParsedBuffer::parse(filetype, 4, lines).unwrap()
+    #[test]
+    fn test_span_range_at() {
+        let range = |start_line, start_col, end_line, end_col| SourceRange {
+            start_line,
+            start_col,
+            end_line,
+            end_col,
+        };
+        let lines = ["f(\"a\") /* b", "c */ // d", "'e"];
+        let buffer = parse("rust", &lines);
+        let span_range_at =
+            |line: usize, col, around| buffer.span_range_at(line, col, lines[line].len(), around);
+        assert_eq!(span_range_at(0, 3, false), Some(range(0, 3, 0, 4)));
+        assert_eq!(span_range_at(0, 4, true), Some(range(0, 2, 0, 5)));
+        assert_eq!(span_range_at(0, 10, false), Some(range(0, 9, 1, 2)));
+        assert_eq!(span_range_at(1, 0, true), Some(range(0, 7, 1, 4)));
+        assert_eq!(span_range_at(1, 8, false), Some(range(1, 7, 1, 9)));
+        assert_eq!(span_range_at(1, 5, true), Some(range(1, 5, 1, 9)));
+        assert_eq!(span_range_at(0, 1, false), None);
+        assert_eq!(span_range_at(1, 4, false), None);
+        assert_eq!(span_range_at(2, 1, false), None);
     }
 
     #[test]
-    fn test_unmatched_opening_before() {
-        let buffer = parse("rust", &["("]);
-        assert_eq!(buffer.unmatched_opening_before("(", ")", 0, 0), None);
+    fn test_is_in_comment_or_string() {
+        let buffer = parse("rust", &["f(\"a\") /* b */ c // d", "/* e", "*/ 'f'"]);
+        let in_comment = |line| {
+            (0..21)
+                .filter(|&col| buffer.is_in_comment(line, col))
+                .collect::<Vec<_>>()
+        };
+        let in_string = |line| {
+            (0..21)
+                .filter(|&col| buffer.is_in_string(line, col))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(in_comment(0), vec![9, 10, 11, 19, 20]);
+        assert_eq!(in_string(0), vec![3]);
+        assert_eq!(in_comment(1), (2..21).collect::<Vec<_>>());
+        assert_eq!(in_comment(2), Vec::<usize>::new());
+        assert_eq!(in_string(2), vec![4]);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-markdown")]
+    fn test_auto_pair_exclusions() {
+        let line = "fn f(a: Vec<u8>) -> bool { a < b } // c";
+        let buffer = parse("rust", &[line]);
+        let excluded = |opening, col, extra: &[ExclusionContext]| {
+            buffer.is_auto_pair_excluded("rust", opening, 0, line.as_bytes(), col, extra)
+        };
+        assert!(!excluded("<", 11, &[]));
+        assert!(excluded("<", 29, &[]));
+        assert!(!excluded("(", 29, &[]));
+        assert!(!excluded("'", 39, &[]));
+        assert!(excluded("'", 39, &[ExclusionContext::InComment]));
+        assert!(!excluded("'", 33, &[ExclusionContext::InComment]));
+
+        let line = "don't";
+        let buffer = parse("markdown", &[line]);
+        assert!(buffer.is_auto_pair_excluded("markdown", "'", 0, line.as_bytes(), 3, &[]));
+        assert!(!buffer.is_auto_pair_excluded("markdown", "'", 0, line.as_bytes(), 0, &[]));
+    }
+
+    #[test]
+    fn test_indexed_stack_height_at() {
+        let lines = [
+            "fn a() {",
+            "    b(\"(\", [1]); /* c",
+            "    */ d(",
+            "}",
+            "e)",
+        ];
+        let buffer = parse("rust", &lines);
+        let heights = |line_number: usize| {
+            (0..=lines[line_number].len())
+                .map(|col| buffer.stack_height_at(line_number, col))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(heights(0), vec![0, 0, 0, 0, 0, 1, 0, 0, 1]);
         assert_eq!(
-            buffer.unmatched_opening_before("(", ")", 0, 1),
-            Some(Match::delimiter('(', 0, None).with_line(0))
+            heights(1),
+            vec![1, 1, 1, 1, 1, 1, 2, 3, 3, 2, 2, 2, 3, 3, 2, 1, 1, 1, 1, 2, 2, 2]
         );
+        // The unmatched `(` doesn't change the stack height
+        assert_eq!(heights(2), vec![2, 2, 2, 2, 2, 2, 1, 1, 1, 1]);
+        assert_eq!(heights(3), vec![1, 0]);
+        assert_eq!(heights(4), vec![0, 0, 0]);
+        assert_eq!(buffer.depth_by_line(), &[0, 1, 2, 1, 0]);
 
-        let buffer = parse("rust", &["( ( )"]);
+        // Same as scanning the matches around the position, apart from the unmatched opening
+        // which the scan subtracts from the stack height of the matches after it
+        for (line_number, line) in lines.iter().enumerate().filter(|(line, _)| *line != 2) {
+            for col in 0..=line.len() {
+                assert_eq!(
+                    buffer.stack_height_at(line_number, col),
+                    buffer.scan_stack_height_at(line_number, col)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_trailing_comma() {
+        let lines = [
+            "f(a, [b, c,], \"d,\")",
+            "let e = {",
+            "    g: 1, // h,",
+            "    /* i */",
+            "};",
+            "j(k /* l, */)",
+        ];
+        let buffer = parse("rust", &lines);
+        assert_eq!(buffer.trailing_comma_position(&lines, 0, 1), None);
+        assert_eq!(buffer.trailing_comma_position(&lines, 0, 5), Some((0, 10)));
+        assert_eq!(buffer.trailing_comma_position(&lines, 0, 11), Some((0, 10)));
+        assert_eq!(buffer.trailing_comma_position(&lines, 1, 8), Some((2, 8)));
+        assert_eq!(buffer.trailing_comma_position(&lines, 5, 1), None);
+        assert!(buffer.has_trailing_comma(&lines, 4, 0));
+        assert!(!buffer.has_trailing_comma(&lines, 0, 20));
+
+        let lines = ["()", "[", "]"];
+        let buffer = parse("rust", &lines);
+        assert!(!buffer.has_trailing_comma(&lines, 0, 0));
+        assert!(!buffer.has_trailing_comma(&lines, 1, 0));
+    }
+
+    #[test]
+    fn test_line_depth_profile() {
+        let buffer = parse("rust", &["fn a() {", "    b([c], {", "    }) }", "d("]);
+        let profile = |entry, exit, max| Some(DepthProfile { entry, exit, max });
+        assert_eq!(buffer.line_depth_profile(0), profile(0, 1, 1));
+        assert_eq!(buffer.line_depth_profile(1), profile(1, 3, 3));
+        assert_eq!(buffer.line_depth_profile(2), profile(3, 0, 3));
+        // Unmatched delimiters don't change the depth
+        assert_eq!(buffer.line_depth_profile(3), profile(0, 0, 0));
+        assert_eq!(buffer.line_depth_profile(4), None);
+    }
+
+    #[test]
+    fn test_stats() {
+        let buffer = parse(
+            "rust",
+            &["fn a() {", "    b([c], \"(\");", "    d(", "}", "]"],
+        );
+        let stats = buffer.stats();
         assert_eq!(
-            buffer.unmatched_opening_before("(", ")", 0, 4),
-            Some(Match::delimiter('(', 0, None).with_line(0))
+            stats.delimiters,
+            vec![
+                DelimiterStats {
+                    opening: "(",
+                    closing: ")",
+                    matched: 2,
+                    unmatched: 1
+                },
+                DelimiterStats {
+                    opening: "{",
+                    closing: "}",
+                    matched: 1,
+                    unmatched: 0
+                },
+                DelimiterStats {
+                    opening: "[",
+                    closing: "]",
+                    matched: 1,
+                    unmatched: 1
+                },
+            ]
         );
+        assert_eq!(stats.max_depth, 3);
+        assert_eq!(stats.unmatched(), 2);
+        assert_eq!(parse("rust", &[""]).stats(), PairStats::default());
     }
 
     #[test]
-    fn test_get_unmatched_closing_at() {
-        let buffer = parse("rust", &[")"]);
+    #[cfg(feature = "lang-lua")]
+    fn test_parse_many() {
+        let rust: &[&str] = &["fn f() {", "    g([1]);", "}"];
+        let lua: &[&str] = &["f({ a = '(' })"];
+        let unknown: &[&str] = &["()"];
+        let buffers = [("rust", 4, rust), ("unknown", 4, unknown), ("lua", 2, lua)];
+
+        for threads in [0, 1, 2, 8] {
+            let parsed = ParsedBuffer::parse_many(&buffers, threads);
+            assert_eq!(parsed.len(), 3);
+            assert_eq!(
+                parsed[0].as_ref().unwrap().0.matches_by_line,
+                parse("rust", rust).matches_by_line
+            );
+            assert!(parsed[1].is_none());
+            assert_eq!(
+                parsed[2].as_ref().unwrap().0.matches_by_line,
+                ParsedBuffer::parse("lua", 2, lua).unwrap().matches_by_line
+            );
+            assert_eq!(parsed[2].as_ref().unwrap().1.lines, 1);
+        }
+        assert!(ParsedBuffer::parse_many::<&str>(&[], 4).is_empty());
+    }
+
+    #[test]
+    fn test_furthest_matching() {
+        let heights = |buffer: &ParsedBuffer| {
+            buffer
+                .matches_by_line
+                .iter()
+                .flatten()
+                .map(|match_| match_.stack_height)
+                .collect::<Vec<_>>()
+        };
+
+        let buffer = parse("rust", &["[ ( ( [] ( ) ]"]);
         assert_eq!(
-            buffer.unmatched_closing_after("(", ")", 0, 0),
-            Some(Match::delimiter(')', 0, None).with_line(0))
+            heights(&buffer),
+            vec![
+                Some(0),
+                Some(1),
+                None,
+                Some(2),
+                Some(2),
+                None,
+                Some(1),
+                Some(0)
+            ]
         );
-        assert_eq!(buffer.unmatched_closing_after("(", ")", 0, 1), None);
-        assert_eq!(buffer.unmatched_closing_after("(", ")", 1, 1), None);
+        let (opening, closing) = buffer.match_pair(0, 11).unwrap();
+        assert_eq!((opening.col, closing.col), (2, 11));
 
-        let buffer = parse("rust", &[" )"]);
+        let buffer = parse("rust", &["[ ( ( ( ) ]"]);
         assert_eq!(
-            buffer.unmatched_closing_after("(", ")", 0, 0),
-            Some(Match::delimiter(')', 1, None).with_line(0))
+            heights(&buffer),
+            vec![Some(0), Some(1), None, None, Some(1), Some(0)]
         );
+
+        // Pairs of other types aren't crossed
+        let buffer = parse("rust", &["{ ( [ ( ) ] }"]);
         assert_eq!(
-            buffer.unmatched_closing_after("(", ")", 0, 1),
-            Some(Match::delimiter(')', 1, None).with_line(0))
+            heights(&buffer),
+            vec![Some(0), None, Some(1), Some(2), Some(2), Some(1), Some(0)]
         );
-        assert_eq!(buffer.unmatched_closing_after("(", ")", 0, 2), None);
-        assert_eq!(buffer.unmatched_closing_after("(", ")", 1, 0), None);
 
-        let buffer = parse("rust", &["( ] )"]);
-        assert_eq!(buffer.unmatched_closing_after("[", "]", 0, 0), None);
+        // Mismatched closings still match the closest opening
+        let buffer = parse("rust", &["[ ( ) ) ]"]);
         assert_eq!(
-            buffer.unmatched_closing_after("[", "]", 0, 1),
-            Some(Match::delimiter(']', 2, None).with_line(0))
+            heights(&buffer),
+            vec![Some(0), Some(1), Some(1), None, Some(0)]
         );
     }
 
     #[test]
-    fn test_rebalanced_matching() {
-        let buffer = parse("rust", &["{", "\t{", "\t", "}"]);
+    fn test_expand_selection() {
+        let buffer = parse("rust", &["fn a() {", "    b(c, [d]);", "}"]);
+        let range = |start_line, start_col, end_line, end_col| SourceRange {
+            start_line,
+            start_col,
+            end_line,
+            end_col,
+        };
+
+        let mut selection = range(1, 10, 1, 10);
+        let mut selections = vec![];
+        while let Some(expanded) = buffer.expand_selection(selection) {
+            selections.push(expanded);
+            selection = expanded;
+        }
         assert_eq!(
-            buffer.matches_by_line,
+            selections,
             vec![
-                vec![Match::delimiter('{', 0, Some(0))],
-                vec![Match::delimiter('{', 1, None)],
-                vec![],
-                vec![Match::delimiter('}', 0, Some(0))],
+                range(1, 10, 1, 11),
+                range(1, 9, 1, 12),
+                range(1, 6, 1, 12),
+                range(1, 5, 1, 13),
+                range(0, 8, 2, 0),
+                range(0, 7, 2, 1),
             ]
         );
 
-        let buffer = parse("rust", &["{", "\t{", "\t}"]);
+        // Ranges which don't line up with the pairs
         assert_eq!(
-            buffer.matches_by_line,
-            vec![
-                vec![Match::delimiter('{', 0, None)],
-                vec![Match::delimiter('{', 1, Some(1))],
-                vec![Match::delimiter('}', 1, Some(1))],
-            ]
+            buffer.expand_selection(range(1, 7, 1, 11)),
+            Some(range(1, 6, 1, 12))
         );
+        assert_eq!(buffer.expand_selection(range(0, 0, 1, 4)), None);
+    }
+
+    #[test]
+    fn test_shrink_selection() {
+        let buffer = parse("rust", &["fn a() {", "    b(c, [d]);", "    e(f);", "}"]);
+        let range = |start_line, start_col, end_line, end_col| SourceRange {
+            start_line,
+            start_col,
+            end_line,
+            end_col,
+        };
 
-        let buffer = parse("rust", &["{", "\t{", "\t}", "}"]);
         assert_eq!(
-            buffer.matches_by_line,
-            vec![
-                vec![Match::delimiter('{', 0, Some(0))],
-                vec![Match::delimiter('{', 1, Some(1))],
-                vec![Match::delimiter('}', 1, Some(1))],
-                vec![Match::delimiter('}', 0, Some(0))],
-            ]
+            buffer.shrink_selection(range(0, 7, 3, 1), (1, 10)),
+            Some(range(0, 8, 3, 0))
+        );
+        // Picks the pair around the cursor, or the closest one to it
+        assert_eq!(
+            buffer.shrink_selection(range(0, 8, 3, 0), (1, 10)),
+            Some(range(1, 5, 1, 13))
+        );
+        assert_eq!(
+            buffer.shrink_selection(range(0, 8, 3, 0), (2, 9)),
+            Some(range(2, 5, 2, 8))
         );
+        assert_eq!(
+            buffer.shrink_selection(range(0, 8, 3, 0), (0, 8)),
+            Some(range(1, 5, 1, 13))
+        );
+        assert_eq!(
+            buffer.shrink_selection(range(1, 6, 1, 12), (1, 6)),
+            Some(range(1, 9, 1, 12))
+        );
+        assert_eq!(
+            buffer.shrink_selection(range(1, 9, 1, 12), (1, 10)),
+            Some(range(1, 10, 1, 11))
+        );
+        assert_eq!(buffer.shrink_selection(range(1, 10, 1, 11), (1, 10)), None);
+
+        // Steps back through the ranges of expand_selection
+        let mut selection = range(0, 7, 3, 1);
+        let mut selections = vec![selection];
+        while let Some(shrunk) = buffer.shrink_selection(selection, (1, 10)) {
+            selections.push(shrunk);
+            selection = shrunk;
+        }
+        let mut expanded = vec![selection];
+        while let Some(grown) = buffer.expand_selection(selection) {
+            expanded.push(grown);
+            selection = grown;
+        }
+        expanded.reverse();
+        assert_eq!(selections, expanded);
+    }
+
+    #[test]
+    fn test_pairs_containing() {
+        let buffer = parse("rust", &["fn a() {", "    b(c, [d]);", "    e(f);", "}"]);
+        let pairs = |start_line, start_col, end_line, end_col| {
+            buffer
+                .pairs_containing(SourceRange {
+                    start_line,
+                    start_col,
+                    end_line,
+                    end_col,
+                })
+                .map(|(opening, closing)| {
+                    ((opening.line, opening.col), (closing.line, closing.col))
+                })
+                .collect::<Vec<_>>()
+        };
 
-        let buffer = parse("rust", &["{", "\t{", "\t\t{", "\t\t}", "}"]);
         assert_eq!(
-            buffer.matches_by_line,
+            pairs(1, 10, 1, 11),
+            vec![((1, 9), (1, 11)), ((1, 5), (1, 12)), ((0, 7), (3, 0))]
+        );
+        // Includes the pair whose delimiters are the range
+        assert_eq!(
+            pairs(1, 9, 1, 12),
+            vec![((1, 9), (1, 11)), ((1, 5), (1, 12)), ((0, 7), (3, 0))]
+        );
+        assert_eq!(pairs(1, 9, 2, 6), vec![((0, 7), (3, 0))]);
+        assert_eq!(pairs(0, 0, 1, 4), vec![]);
+    }
+
+    #[test]
+    fn test_match_pair_adjacent() {
+        let buffer = parse("rust", &["a(b) [c] d  {", "}"]);
+        let pair = |col, adjacency| {
+            buffer
+                .match_pair_adjacent(0, col, adjacency)
+                .map(|(opening, closing)| (opening.col, closing.col))
+        };
+
+        assert_eq!(pair(3, Adjacency::Exact), Some((1, 3)));
+        assert_eq!(pair(4, Adjacency::Exact), None);
+        assert_eq!(pair(4, Adjacency::Before), Some((1, 3)));
+        assert_eq!(pair(4, Adjacency::After), Some((5, 7)));
+        assert_eq!(pair(0, Adjacency::Before), None);
+        // On a delimiter, its own pair is preferred
+        assert_eq!(pair(5, Adjacency::Before), Some((5, 7)));
+
+        assert_eq!(pair(9, Adjacency::NearestOnLine), Some((5, 7)));
+        assert_eq!(pair(10, Adjacency::NearestOnLine), Some((12, 0)));
+        assert_eq!(pair(0, Adjacency::NearestOnLine), Some((1, 3)));
+
+        // Ties prefer the delimiter before the position
+        let buffer = parse("rust", &["(a) b (c)"]);
+        let (opening, closing) = buffer
+            .match_pair_adjacent(0, 4, Adjacency::NearestOnLine)
+            .unwrap();
+        assert_eq!((opening.col, closing.col), (0, 2));
+    }
+
+    #[test]
+    fn test_surroundings_at() {
+        let line = "fn a() { b(\"c(d)\", [e]); /* f */ } // g";
+        let buffer = parse("rust", &[line]);
+        let surroundings = |col| {
+            buffer
+                .surroundings_at(0, col, line.len())
+                .into_iter()
+                .map(|surrounding| {
+                    (
+                        surrounding.kind,
+                        surrounding.range.start_col,
+                        surrounding.range.end_col,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            surroundings(13),
             vec![
-                vec![Match::delimiter('{', 0, Some(0))],
-                vec![Match::delimiter('{', 1, None)],
-                vec![Match::delimiter('{', 2, Some(2))],
-                vec![Match::delimiter('}', 2, Some(2))],
-                vec![Match::delimiter('}', 0, Some(0))],
+                (SurroundingKind::String, 11, 17),
+                (SurroundingKind::Pair, 10, 23),
+                (SurroundingKind::Pair, 7, 34),
             ]
         );
-
-        let buffer = parse("rust", &["{", "\t{", "\t\t{", "\t\t}", "\t}"]);
+        // On the delimiters of a pair
         assert_eq!(
-            buffer.matches_by_line,
+            surroundings(19),
             vec![
-                vec![Match::delimiter('{', 0, None)],
-                vec![Match::delimiter('{', 1, Some(1))],
-                vec![Match::delimiter('{', 2, Some(2))],
-                vec![Match::delimiter('}', 2, Some(2))],
-                vec![Match::delimiter('}', 1, Some(1))],
+                (SurroundingKind::Pair, 19, 22),
+                (SurroundingKind::Pair, 10, 23),
+                (SurroundingKind::Pair, 7, 34),
             ]
         );
-
-        let buffer = parse("rust", &["{", "\t{", "\t\t{", "\t\t}", "\t{", "}"]);
         assert_eq!(
-            buffer.matches_by_line,
+            surroundings(28),
             vec![
-                vec![Match::delimiter('{', 0, Some(0))],
-                vec![Match::delimiter('{', 1, None)],
-                vec![Match::delimiter('{', 2, Some(2))],
-                vec![Match::delimiter('}', 2, Some(2))],
-                vec![Match::delimiter('{', 1, None)],
-                vec![Match::delimiter('}', 0, Some(0))],
+                (SurroundingKind::Comment, 25, 32),
+                (SurroundingKind::Pair, 7, 34),
             ]
         );
+        assert_eq!(
+            surroundings(39),
+            vec![(SurroundingKind::Comment, 35, line.len())]
+        );
+        assert_eq!(surroundings(2), vec![]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-javascript", feature = "lang-python"))]
+    fn test_span_kind_at() {
+        let python = [
+            "def f():",
+            "    \"\"\"Doc (",
+            "    \"\"\"",
+            "    x = \"\"\"a\"\"\"",
+            "    # b",
+        ];
+        let buffer = parse("python", &python);
+        let kind = |line, col| {
+            buffer.span_kind_at("python", line, col, |line| {
+                python.get(line).map(|text| text.as_bytes())
+            })
+        };
+        assert_eq!(kind(1, 9), Some(SpanKind::Docstring));
+        assert_eq!(kind(2, 4), Some(SpanKind::Docstring));
+        assert_eq!(kind(3, 11), Some(SpanKind::String));
+        assert_eq!(kind(4, 6), Some(SpanKind::Comment));
+        assert_eq!(kind(0, 4), None);
+
+        let javascript = ["a = /[(]/ + \"b\";"];
+        let buffer = parse("javascript", &javascript);
+        let kind = |col| buffer.span_kind_at("javascript", 0, col, |_| None::<&[u8]>);
+        assert_eq!(kind(6), Some(SpanKind::Regex));
+        assert_eq!(kind(13), Some(SpanKind::String));
+        // The opening isn't in the span
+        assert_eq!(kind(4), None);
     }
 }
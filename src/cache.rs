@@ -0,0 +1,427 @@
+//! On-disk cache of parsed buffers, so that reopening a large file restores its matches
+//! without tokenizing it again.
+//!
+//! Each file gets a cache entry under the cache directory, named after a hash of its path.
+//! The entry stores the hash of the content it was parsed from, and is only used while the
+//! content, filetype and tab width are the same. Callers should still reparse the buffer
+//! afterwards to verify the entry, i.e. after the first redraw.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use blink_pairs::buffer::ParsedBuffer;
+//! use blink_pairs::cache::ParseCache;
+//!
+//! let cache = ParseCache::new("/tmp/blink.pairs");
+//! let path = Path::new("src/main.rs");
+//! let lines = std::fs::read_to_string(path).unwrap();
+//! let lines = lines.lines().collect::<Vec<_>>();
+//!
+//! let buffer = match cache.load(path, "rust", 4, &lines) {
+//!     Some(buffer) => buffer,
+//!     None => {
+//!         let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+//!         cache.store(path, "rust", 4, &lines, &buffer).unwrap();
+//!         buffer
+//!     }
+//! };
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::buffer::{line_hashes, DepthLimit, ParsedBuffer};
+use crate::history::History;
+use crate::interned::intern;
+use crate::parser::{Kind, Match, State, Token};
+
+/// Identifies the format of the entries, bumped whenever it changes
+const MAGIC: &[u8; 8] = b"blinkpc1";
+
+/// Directory of cached parse results
+#[derive(Debug, Clone)]
+pub struct ParseCache {
+    dir: PathBuf,
+}
+
+impl ParseCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Gets the cached buffer of the file, when it was parsed from the same lines with the
+    /// same filetype and tab width
+    pub fn load<L: AsRef<[u8]>>(
+        &self,
+        path: &Path,
+        filetype: &str,
+        tab_width: u8,
+        lines: &[L],
+    ) -> Option<ParsedBuffer> {
+        let bytes = fs::read(self.entry_path(path)).ok()?;
+        let mut reader = Reader {
+            bytes: &bytes,
+            pos: 0,
+        };
+        if reader.take(MAGIC.len())? != MAGIC
+            || reader.u64()? != content_hash(filetype, tab_width, lines)
+        {
+            return None;
+        }
+        let mut buffer = decode(&mut reader)?;
+        if buffer.matches_by_line.len() != lines.len() {
+            return None;
+        }
+        buffer.line_hashes = line_hashes(lines, State::Normal, &buffer.state_by_line);
+        buffer.calculate_line_depths();
+        Some(buffer)
+    }
+
+    /// Writes the buffer parsed from the lines of the file to the cache, replacing the
+    /// previous entry
+    pub fn store<L: AsRef<[u8]>>(
+        &self,
+        path: &Path,
+        filetype: &str,
+        tab_width: u8,
+        lines: &[L],
+        buffer: &ParsedBuffer,
+    ) -> io::Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(content_hash(filetype, tab_width, lines).to_le_bytes());
+        encode(buffer, &mut bytes);
+
+        // Write to a temporary file first so that readers never see a partial entry
+        fs::create_dir_all(&self.dir)?;
+        let entry_path = self.entry_path(path);
+        let tmp_path = entry_path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(tmp_path, entry_path)
+    }
+
+    /// Removes the entry of the file, if any
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        match fs::remove_file(self.entry_path(path)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_path(&self, path: &Path) -> PathBuf {
+        let hash = fnv1a(FNV_OFFSET, path.as_os_str().as_encoded_bytes());
+        self.dir.join(format!("{hash:016x}.bin"))
+    }
+}
+
+/// Hashes the lines along with the filetype and tab width they're parsed with
+pub fn content_hash<L: AsRef<[u8]>>(filetype: &str, tab_width: u8, lines: &[L]) -> u64 {
+    let mut hash = fnv1a(FNV_OFFSET, filetype.as_bytes());
+    hash = fnv1a(hash, &[0, tab_width]);
+    for line in lines {
+        hash = fnv1a(hash, line.as_ref());
+        hash = fnv1a(hash, b"\n");
+    }
+    hash
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Writes the token texts as a table, followed by the lines which refer to them by index
+fn encode(buffer: &ParsedBuffer, bytes: &mut Vec<u8>) {
+    let mut texts = Texts::default();
+    let mut body = vec![];
+    write_u64(&mut body, buffer.matches_by_line.len() as u64);
+    for line in 0..buffer.matches_by_line.len() {
+        encode_state(buffer.state_by_line[line], &mut texts, &mut body);
+        body.push(buffer.indent_levels[line]);
+        match buffer.blank_indents[line] {
+            Some(indent) => body.extend([1, indent]),
+            None => body.push(0),
+        }
+
+        let matches = &buffer.matches_by_line[line];
+        write_u64(&mut body, matches.len() as u64);
+        for match_ in matches {
+            encode_match(match_, &mut texts, &mut body);
+        }
+    }
+
+    write_u64(bytes, texts.list.len() as u64);
+    for text in texts.list {
+        write_u64(bytes, text.len() as u64);
+        bytes.extend(text.as_bytes());
+    }
+    bytes.extend(body);
+}
+
+fn decode(reader: &mut Reader) -> Option<ParsedBuffer> {
+    let texts = (0..reader.u64()?)
+        .map(|_| {
+            let len = reader.u64()? as usize;
+            Some(intern(core::str::from_utf8(reader.take(len)?).ok()?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let line_count = reader.u64()? as usize;
+    // Each line takes multiple bytes, so avoid allocating for a corrupted count
+    if line_count > reader.bytes.len() {
+        return None;
+    }
+    let mut buffer = ParsedBuffer {
+        matches_by_line: Vec::with_capacity(line_count),
+        state_by_line: Vec::with_capacity(line_count),
+        indent_levels: Vec::with_capacity(line_count),
+        blank_indents: Vec::with_capacity(line_count),
+        indent_changes: 0..line_count,
+        line_hashes: vec![],
+        history: History::default(),
+        depth_at_line_start: vec![],
+        depth_limit: DepthLimit::DEFAULT,
+    };
+    for _ in 0..line_count {
+        buffer.state_by_line.push(decode_state(reader, &texts)?);
+        buffer.indent_levels.push(reader.u8()?);
+        buffer.blank_indents.push(match reader.u8()? {
+            0 => None,
+            _ => Some(reader.u8()?),
+        });
+        let match_count = reader.u64()? as usize;
+        let matches = (0..match_count)
+            .map(|_| decode_match(reader, &texts))
+            .collect::<Option<Vec<_>>>()?;
+        if !matches.is_sorted_by_key(|match_| match_.col) {
+            return None;
+        }
+        buffer.matches_by_line.push(matches);
+    }
+    (reader.pos == reader.bytes.len()).then_some(buffer)
+}
+
+fn encode_state(state: State, texts: &mut Texts, bytes: &mut Vec<u8>) {
+    let (tag, text) = match state {
+        State::Normal => (0, None),
+        State::InString(text) => (1, Some(text)),
+        State::InBlockString(text) => (2, Some(text)),
+        State::InLineComment => (3, None),
+        State::InBlockComment(text) => (4, Some(text)),
+        State::InInlineSpan(text) => (5, Some(text)),
+        State::InBlockSpan(text) => (6, Some(text)),
+        State::InRegexClass(text) => (7, Some(text)),
+        State::InInterpolation(text) => (8, Some(text)),
+    };
+    bytes.push(tag);
+    if let Some(text) = text {
+        texts.write(text, bytes);
+    }
+}
+
+fn decode_state(reader: &mut Reader, texts: &[&'static str]) -> Option<State> {
+    Some(match reader.u8()? {
+        0 => State::Normal,
+        1 => State::InString(reader.text(texts)?),
+        2 => State::InBlockString(reader.text(texts)?),
+        3 => State::InLineComment,
+        4 => State::InBlockComment(reader.text(texts)?),
+        5 => State::InInlineSpan(reader.text(texts)?),
+        6 => State::InBlockSpan(reader.text(texts)?),
+        7 => State::InRegexClass(reader.text(texts)?),
+        8 => State::InInterpolation(reader.text(texts)?),
+        _ => return None,
+    })
+}
+
+fn encode_match(match_: &Match, texts: &mut Texts, bytes: &mut Vec<u8>) {
+    match match_.kind {
+        Kind::Opening => bytes.push(0),
+        Kind::Closing => bytes.push(1),
+        Kind::NonPair => bytes.push(2),
+        Kind::Middle(text) => {
+            bytes.push(3);
+            texts.write(text, bytes);
+        }
+    }
+
+    let (tag, token_texts): (u8, &[&'static str]) = match &match_.token {
+        Token::Delimiter(opening, closing) => (0, &[opening, closing]),
+        Token::String(opening) => (1, &[opening]),
+        Token::BlockString(opening, closing) => (2, &[opening, closing]),
+        Token::LineComment(opening) => (3, &[opening]),
+        Token::BlockComment(opening, closing) => (4, &[opening, closing]),
+        Token::InlineSpan(span, opening, closing) => (5, &[span, opening, closing]),
+        Token::BlockSpan(span, opening, closing) => (6, &[span, opening, closing]),
+    };
+    bytes.push(tag);
+    for text in token_texts {
+        texts.write(text, bytes);
+    }
+
+    write_u64(bytes, match_.col as u64);
+    write_option(bytes, match_.stack_height);
+    write_option(bytes, match_.source_len);
+}
+
+fn decode_match(reader: &mut Reader, texts: &[&'static str]) -> Option<Match> {
+    let kind = match reader.u8()? {
+        0 => Kind::Opening,
+        1 => Kind::Closing,
+        2 => Kind::NonPair,
+        3 => Kind::Middle(reader.text(texts)?),
+        _ => return None,
+    };
+    let token = match reader.u8()? {
+        0 => Token::Delimiter(reader.text(texts)?, reader.text(texts)?),
+        1 => Token::String(reader.text(texts)?),
+        2 => Token::BlockString(reader.text(texts)?, reader.text(texts)?),
+        3 => Token::LineComment(reader.text(texts)?),
+        4 => Token::BlockComment(reader.text(texts)?, reader.text(texts)?),
+        5 => Token::InlineSpan(
+            reader.text(texts)?,
+            reader.text(texts)?,
+            reader.text(texts)?,
+        ),
+        6 => Token::BlockSpan(
+            reader.text(texts)?,
+            reader.text(texts)?,
+            reader.text(texts)?,
+        ),
+        _ => return None,
+    };
+
+    let mut match_ = Match::new(kind, token, reader.u64()? as usize);
+    match_.stack_height = reader.option()?;
+    match_.source_len = reader.option()?;
+    Some(match_)
+}
+
+/// Token texts of an entry, written once and referred to by index
+#[derive(Default)]
+struct Texts {
+    list: Vec<&'static str>,
+    indices: HashMap<&'static str, usize>,
+}
+
+impl Texts {
+    fn write(&mut self, text: &'static str, bytes: &mut Vec<u8>) {
+        let idx = *self.indices.entry(text).or_insert_with(|| {
+            self.list.push(text);
+            self.list.len() - 1
+        });
+        write_u64(bytes, idx as u64);
+    }
+}
+
+fn write_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend(value.to_le_bytes());
+}
+
+fn write_option(bytes: &mut Vec<u8>, value: Option<usize>) {
+    match value {
+        Some(value) => {
+            bytes.push(1);
+            write_u64(bytes, value as u64);
+        }
+        None => bytes.push(0),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn option(&mut self) -> Option<Option<usize>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => Some(Some(self.u64()? as usize)),
+            _ => None,
+        }
+    }
+
+    fn text(&mut self, texts: &[&'static str]) -> Option<&'static str> {
+        texts.get(self.u64()? as usize).copied()
+    }
+}
+
+#[cfg(all(test, feature = "lang-rust"))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("blink-pairs-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = cache_dir("round-trip");
+        let cache = ParseCache::new(&dir);
+        let path = Path::new("/src/main.rs");
+        let lines = ["fn a() {", "    /* (", "    */ b(\"c\", [d]);", "  ", "}"];
+        let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+
+        assert!(cache.load(path, "rust", 4, &lines).is_none());
+        cache.store(path, "rust", 4, &lines, &buffer).unwrap();
+
+        let cached = cache.load(path, "rust", 4, &lines).unwrap();
+        assert_eq!(cached.matches_by_line, buffer.matches_by_line);
+        assert_eq!(cached.state_by_line, buffer.state_by_line);
+        assert_eq!(cached.indent_levels, buffer.indent_levels);
+        assert_eq!(cached.blank_indents, buffer.blank_indents);
+
+        // Any change to the key misses
+        assert!(cache.load(path, "rust", 2, &lines).is_none());
+        assert!(cache.load(path, "c", 4, &lines).is_none());
+        assert!(cache.load(path, "rust", 4, &lines[1..]).is_none());
+        assert!(cache
+            .load(Path::new("/src/lib.rs"), "rust", 4, &lines)
+            .is_none());
+
+        cache.remove(path).unwrap();
+        assert!(cache.load(path, "rust", 4, &lines).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_entry() {
+        let dir = cache_dir("corrupted");
+        let cache = ParseCache::new(&dir);
+        let path = Path::new("/src/main.rs");
+        let lines = ["f(x)"];
+        let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+        cache.store(path, "rust", 4, &lines, &buffer).unwrap();
+
+        let entry_path = cache.entry_path(path);
+        let bytes = fs::read(&entry_path).unwrap();
+        for len in [0, 12, bytes.len() - 1] {
+            fs::write(&entry_path, &bytes[..len]).unwrap();
+            assert!(cache.load(path, "rust", 4, &lines).is_none());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
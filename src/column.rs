@@ -0,0 +1,137 @@
+//! Conversions between the byte columns used throughout the crate, display columns which
+//! expand tabs to the next tab stop, and character columns which count UTF-8 characters.
+//!
+//! Every character besides a tab is assumed to take up a single cell, and columns past the
+//! end of the line are clamped to it. Byte columns inside of a multibyte character are
+//! treated as the start of that character.
+//!
+//! # Examples
+//!
+//! ```
+//! use blink_pairs::column::{byte_to_display_col, display_to_byte_col};
+//!
+//! let line = "\tif x {";
+//! assert_eq!(byte_to_display_col(line.as_bytes(), 4, 4), 7);
+//! assert_eq!(display_to_byte_col(line.as_bytes(), 7, 4), 4);
+//! ```
+
+/// Gets the display column of the character at the byte column, expanding tabs to the next
+/// multiple of the tab width
+pub fn byte_to_display_col(line: &[u8], byte_col: usize, tab_width: u8) -> usize {
+    let tab_width = (tab_width as usize).max(1);
+    let mut display_col = 0;
+    for &byte in &line[..char_start(line, byte_col)] {
+        match byte {
+            b'\t' => display_col = (display_col / tab_width + 1) * tab_width,
+            _ if is_continuation(byte) => {}
+            _ => display_col += 1,
+        }
+    }
+    display_col
+}
+
+/// Gets the byte column of the character covering the display column, which is the tab
+/// itself when the display column falls within a tab's expansion
+pub fn display_to_byte_col(line: &[u8], display_col: usize, tab_width: u8) -> usize {
+    let tab_width = (tab_width as usize).max(1);
+    let mut current = 0;
+    for (byte_col, &byte) in line.iter().enumerate() {
+        if is_continuation(byte) {
+            continue;
+        }
+        let next = match byte {
+            b'\t' => (current / tab_width + 1) * tab_width,
+            _ => current + 1,
+        };
+        if display_col < next {
+            return byte_col;
+        }
+        current = next;
+    }
+    line.len()
+}
+
+/// Gets the number of characters before the byte column
+pub fn byte_to_char_col(line: &[u8], byte_col: usize) -> usize {
+    line[..char_start(line, byte_col)]
+        .iter()
+        .filter(|&&byte| !is_continuation(byte))
+        .count()
+}
+
+/// Gets the byte column of the character at the character column
+pub fn char_to_byte_col(line: &[u8], char_col: usize) -> usize {
+    line.iter()
+        .enumerate()
+        .filter(|(_, &byte)| !is_continuation(byte))
+        .nth(char_col)
+        .map(|(byte_col, _)| byte_col)
+        .unwrap_or(line.len())
+}
+
+/// Gets the number of UTF-16 code units before the byte column, as used by LSP positions by
+/// default
+pub fn byte_to_utf16_col(line: &[u8], byte_col: usize) -> usize {
+    line[..char_start(line, byte_col)]
+        .iter()
+        .map(|&byte| match byte {
+            // Lead byte of a 4 byte character, encoded as a surrogate pair
+            0xF0.. => 2,
+            _ if is_continuation(byte) => 0,
+            _ => 1,
+        })
+        .sum()
+}
+
+/// Moves the byte column back to the start of the character containing it
+fn char_start(line: &[u8], byte_col: usize) -> usize {
+    let mut byte_col = byte_col.min(line.len());
+    while byte_col > 0
+        && line
+            .get(byte_col)
+            .is_some_and(|&byte| is_continuation(byte))
+    {
+        byte_col -= 1;
+    }
+    byte_col
+}
+
+/// Whether the byte continues a multibyte UTF-8 character
+fn is_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_display_col() {
+        let line = b"a\tb\t\tc";
+        let display_cols = (0..=line.len())
+            .map(|col| byte_to_display_col(line, col, 4))
+            .collect::<Vec<_>>();
+        assert_eq!(display_cols, vec![0, 1, 4, 5, 8, 12, 13]);
+
+        let byte_cols = (0..14)
+            .map(|col| display_to_byte_col(line, col, 4))
+            .collect::<Vec<_>>();
+        assert_eq!(byte_cols, vec![0, 1, 1, 1, 2, 3, 3, 3, 4, 4, 4, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_multibyte() {
+        let line = "é\t(".as_bytes();
+        assert_eq!(byte_to_display_col(line, 3, 4), 4);
+        assert_eq!(display_to_byte_col(line, 4, 4), 3);
+        assert_eq!(byte_to_display_col(line, 1, 4), 0);
+        assert_eq!(byte_to_char_col(line, 3), 2);
+        assert_eq!(char_to_byte_col(line, 2), 3);
+        assert_eq!(char_to_byte_col(line, 10), line.len());
+
+        let line = "é😀(".as_bytes();
+        assert_eq!(byte_to_utf16_col(line, 6), 3);
+        assert_eq!(byte_to_char_col(line, 6), 2);
+    }
+}
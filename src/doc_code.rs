@@ -0,0 +1,319 @@
+//! Code examples inside doc comments, such as the fenced blocks in Rust's `///` comments and
+//! JSDoc's `/** */` comments. The comment's prefix is stripped from each line and the code is
+//! parsed as the host language, so that the examples get pairs like the code around them.
+//!
+//! ```
+//! use blink_pairs::doc_code::doc_code_matches;
+//! # if cfg!(not(feature = "lang-rust")) { return; }
+//!
+//! let lines = ["/// ```", "/// let a = f(b);", "/// ```", "fn f() {}"];
+//! let matches_by_line = doc_code_matches("rust", 4, &lines).unwrap();
+//! assert_eq!(matches_by_line[1][0].col, 13);
+//! ```
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::buffer::ParsedBuffer;
+use crate::parser::Match;
+
+/// How the lines of a doc comment are marked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocComments {
+    /// Each line starts with one of the markers, i.e. `///` and `//!` in Rust
+    Line(&'static [&'static str]),
+    /// Block comment whose lines may start with the prefix, i.e. `/**`, `*` and `*/`
+    Block {
+        open: &'static str,
+        prefix: &'static str,
+        close: &'static str,
+    },
+}
+
+/// Doc comment syntax of a filetype
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DocSyntax {
+    pub comments: DocComments,
+    /// Words of a fence's info string which keep the block in the host language, such as
+    /// rustdoc's `no_run`. Blocks without an info string are always in the host language
+    pub info_words: &'static [&'static str],
+}
+
+/// Gets the doc comment syntax of the filetype, if its doc comments may contain code
+pub fn doc_syntax(filetype: &str) -> Option<DocSyntax> {
+    match filetype {
+        "rust" => Some(DocSyntax {
+            comments: DocComments::Line(&["///", "//!"]),
+            info_words: &[
+                "rust",
+                "ignore",
+                "no_run",
+                "should_panic",
+                "compile_fail",
+                "test_harness",
+                "standalone_crate",
+                "edition2015",
+                "edition2018",
+                "edition2021",
+                "edition2024",
+            ],
+        }),
+        "javascript" | "typescript" | "javascriptreact" | "typescriptreact" => Some(DocSyntax {
+            comments: DocComments::Block {
+                open: "/**",
+                prefix: "*",
+                close: "*/",
+            },
+            info_words: &["js", "javascript", "jsx", "ts", "typescript", "tsx"],
+        }),
+        _ => None,
+    }
+}
+
+/// Finds the code blocks in the doc comments of the lines and parses them as the filetype,
+/// returning their matches for each line with the columns of the original lines. Stack
+/// heights start from 0 in each block, apart from the code around the comments.
+///
+/// Both fenced blocks and blocks indented by four spaces are found. Since the blocks are only
+/// found from their start, the lines should start outside of any doc comment, i.e. be the
+/// whole buffer. Returns `None` when the filetype has no doc comment syntax
+pub fn doc_code_matches<L: AsRef<[u8]>>(
+    filetype: &str,
+    tab_width: u8,
+    lines: &[L],
+) -> Option<Vec<Vec<Match>>> {
+    let syntax = doc_syntax(filetype)?;
+    let mut matches_by_line = vec![vec![]; lines.len()];
+
+    // Lines of the current code block, with the offset of the code in the line
+    let mut block: Vec<(usize, usize, &[u8])> = vec![];
+    let mut fence: Option<(&[u8], bool)> = None;
+    let mut prev_blank = true;
+    let mut in_block_comment = false;
+
+    let mut flush = |block: &mut Vec<(usize, usize, &[u8])>| {
+        if block.is_empty() {
+            return;
+        }
+        let code = block.iter().map(|(_, _, code)| *code).collect::<Vec<_>>();
+        if let Some(parsed) = ParsedBuffer::parse_bytes(filetype, tab_width, &code) {
+            for ((line, offset, _), matches) in block.iter().zip(parsed.matches_by_line) {
+                matches_by_line[*line] = matches
+                    .into_iter()
+                    .map(|match_| Match {
+                        col: match_.col + offset,
+                        ..match_
+                    })
+                    .collect();
+            }
+        }
+        block.clear();
+    };
+
+    for (line, text) in lines.iter().enumerate() {
+        let Some((offset, content)) =
+            doc_content(&syntax.comments, text.as_ref(), &mut in_block_comment)
+        else {
+            // Blocks end with the comment
+            flush(&mut block);
+            fence = None;
+            prev_blank = true;
+            continue;
+        };
+
+        let trimmed = content.trim_ascii_start();
+        let fence_len = fence_len(trimmed);
+        match fence {
+            // Closing fence
+            Some((marker, _))
+                if trimmed.starts_with(marker)
+                    && trimmed[marker.len()..].trim_ascii().is_empty() =>
+            {
+                flush(&mut block);
+                fence = None;
+            }
+            Some((_, true)) => block.push((line, offset, content)),
+            Some((_, false)) => {}
+            // Opening fence
+            None if fence_len > 0 => {
+                flush(&mut block);
+                let info = trimmed[fence_len..].trim_ascii();
+                let is_host = info
+                    .split(|&byte| byte == b',' || byte == b' ')
+                    .filter(|word| !word.is_empty())
+                    .all(|word| {
+                        syntax
+                            .info_words
+                            .iter()
+                            .any(|info_word| info_word.as_bytes() == word)
+                    });
+                fence = Some((&trimmed[..fence_len], is_host));
+            }
+            // Indented block, which must follow a blank line unless it continues
+            None if content.starts_with(b"    ") && (prev_blank || !block.is_empty()) => {
+                block.push((line, offset + 4, &content[4..]));
+            }
+            None if !block.is_empty() && trimmed.is_empty() => block.push((line, offset, content)),
+            None => flush(&mut block),
+        }
+        prev_blank = trimmed.is_empty();
+    }
+    flush(&mut block);
+
+    Some(matches_by_line)
+}
+
+/// Length of the fence at the start of the text, i.e. 3 for ```` ```rust ````
+fn fence_len(text: &[u8]) -> usize {
+    let Some(&marker @ (b'`' | b'~')) = text.first() else {
+        return 0;
+    };
+    let len = text.iter().take_while(|&&byte| byte == marker).count();
+    if len >= 3 {
+        len
+    } else {
+        0
+    }
+}
+
+/// Gets the text of the doc comment on the line, after the comment's prefix and a single
+/// space, with its offset in the line
+fn doc_content<'a>(
+    comments: &DocComments,
+    line: &'a [u8],
+    in_block_comment: &mut bool,
+) -> Option<(usize, &'a [u8])> {
+    let indent = line.len() - line.trim_ascii_start().len();
+    let rest = &line[indent..];
+    let (offset, content) = match *comments {
+        DocComments::Line(markers) => {
+            let marker = markers
+                .iter()
+                .find(|marker| rest.starts_with(marker.as_bytes()))?;
+            // `////` is a regular comment
+            if rest[marker.len()..].starts_with(&marker.as_bytes()[marker.len() - 1..]) {
+                return None;
+            }
+            (indent + marker.len(), &rest[marker.len()..])
+        }
+        DocComments::Block {
+            open,
+            prefix,
+            close,
+        } => {
+            let contains_close = rest
+                .windows(close.len())
+                .any(|window| window == close.as_bytes());
+            if !*in_block_comment {
+                // `/**/` is an empty regular comment
+                *in_block_comment = rest.starts_with(open.as_bytes())
+                    && !rest[open.len()..].starts_with(b"/")
+                    && !contains_close;
+                return None;
+            }
+            if contains_close {
+                *in_block_comment = false;
+                return None;
+            }
+            match rest.strip_prefix(prefix.as_bytes()) {
+                Some(content) => (indent + prefix.len(), content),
+                None => (indent, rest),
+            }
+        }
+    };
+
+    match content.strip_prefix(b" ") {
+        Some(content) => Some((offset + 1, content)),
+        None => Some((offset, content)),
+    }
+}
+
+#[cfg(all(test, feature = "lang-rust"))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn cols(matches_by_line: &[Vec<Match>]) -> Vec<Vec<(usize, Option<usize>)>> {
+        matches_by_line
+            .iter()
+            .map(|matches| {
+                matches
+                    .iter()
+                    .map(|match_| (match_.col, match_.stack_height))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rust_doc_code() {
+        let lines = [
+            "/// Calls `f`",
+            "///",
+            "/// ```no_run",
+            "/// f(|| {",
+            "/// });",
+            "/// ```",
+            "///",
+            "///     g([1]);",
+            "/// ```text",
+            "/// (",
+            "/// ```",
+            "//// ```",
+            "//// (",
+            "    //! ```",
+            "    //! (",
+        ];
+        assert_eq!(
+            cols(&doc_code_matches("rust", 4, &lines).unwrap()),
+            vec![
+                vec![],
+                vec![],
+                vec![],
+                vec![(5, Some(0)), (9, Some(1))],
+                vec![(4, Some(1)), (5, Some(0))],
+                vec![],
+                vec![],
+                vec![(9, Some(0)), (10, Some(1)), (12, Some(1)), (13, Some(0))],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![(8, None)],
+            ]
+        );
+        assert_eq!(doc_code_matches("c", 4, &lines), None);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-javascript")]
+    fn test_jsdoc_code() {
+        let lines = [
+            "/**",
+            " * @example",
+            " * ```",
+            " * f([a]);",
+            " * ```",
+            " */",
+            "/**/",
+            " * ```",
+            " * f(",
+        ];
+        assert_eq!(
+            cols(&doc_code_matches("javascript", 4, &lines).unwrap()),
+            vec![
+                vec![],
+                vec![],
+                vec![],
+                vec![(4, Some(0)), (5, Some(1)), (7, Some(1)), (8, Some(0))],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+            ]
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Random buffers and edits for fuzzing the incremental parser, along with an oracle which
+//! checks that [`ParsedBuffer::reparse_range_bytes`] gives the same result as parsing the
+//! edited buffer from scratch.
+//!
+//! With the `arbitrary` feature, [`FuzzCase`] implements `Arbitrary`, so a fuzz target is
+//! simply:
+//!
+//! ```ignore
+//! fuzz_target!(|case: FuzzCase| {
+//!     if let Err(divergence) = case.check() {
+//!         panic!("{divergence:?}");
+//!     }
+//! });
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::buffer::ParsedBuffer;
+use crate::parser::State;
+
+/// Filetypes the cases are generated for
+pub const FILETYPES: &[&str] = &[
+    "c",
+    "rust",
+    "lua",
+    "python",
+    "fortran",
+    "markdown",
+    "php",
+    "javascript",
+];
+
+/// Pieces the lines are built from, covering the tokens of [`FILETYPES`]
+pub const FRAGMENTS: &[&str] = &[
+    "(", ")", "[", "]", "{", "}", "\"", "'", "`", "\\", "//", "/*", "*/", "--", "[[", "]]", "#",
+    "\"\"\"", "$", "{$", "r#\"", "\"#", " ", "  ", "\t", "a", "x = 1", "if", "then", "end if",
+    "else", "do", "end do", "/", "<", ">",
+];
+
+/// Replaces the lines from `start_line` to `old_end_line` with the new lines, as reported by
+/// `nvim_buf_attach`'s `on_lines`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub start_line: usize,
+    pub old_end_line: usize,
+    pub new_lines: Vec<Vec<u8>>,
+}
+
+impl Edit {
+    /// Clamps the edit to the lines and applies it, keeping at least one line like Neovim.
+    /// Returns the end of the new lines
+    pub fn apply(&mut self, lines: &mut Vec<Vec<u8>>) -> usize {
+        self.start_line = self.start_line.min(lines.len());
+        self.old_end_line = self.old_end_line.clamp(self.start_line, lines.len());
+        if self.new_lines.is_empty() && self.old_end_line - self.start_line == lines.len() {
+            self.new_lines.push(Vec::new());
+        }
+
+        lines.splice(
+            self.start_line..self.old_end_line,
+            self.new_lines.iter().cloned(),
+        );
+        self.start_line + self.new_lines.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzCase {
+    pub filetype: &'static str,
+    pub tab_width: u8,
+    pub lines: Vec<Vec<u8>>,
+    pub edits: Vec<Edit>,
+}
+
+/// First difference between the incremental and full parse, after applying the edit at
+/// `edit_index`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub edit_index: usize,
+    pub line: usize,
+    pub field: &'static str,
+}
+
+impl FuzzCase {
+    /// Applies each edit to the lines and reparses the edited range, comparing the matches,
+    /// states and indent levels to a full parse. Like a well-behaved client, the lines after
+    /// the edit are reparsed as well when the state at the end of the edit changed, i.e.
+    /// when a block comment was opened
+    pub fn check(&self) -> Result<(), Divergence> {
+        let mut lines = self.lines.clone();
+        if lines.is_empty() {
+            lines.push(Vec::new());
+        }
+        let Some(mut buffer) = ParsedBuffer::parse_bytes(self.filetype, self.tab_width, &lines)
+        else {
+            return Ok(());
+        };
+
+        for (edit_index, edit) in self.edits.iter().enumerate() {
+            let mut edit = edit.clone();
+            let new_end_line = edit.apply(&mut lines);
+            let old_end_state = end_state(&buffer, edit.old_end_line);
+
+            buffer.reparse_range_bytes(
+                self.filetype,
+                self.tab_width,
+                &lines[edit.start_line..new_end_line],
+                Some(edit.start_line),
+                Some(edit.old_end_line),
+                Some(new_end_line),
+            );
+            if new_end_line < lines.len() && end_state(&buffer, new_end_line) != old_end_state {
+                buffer.reparse_range_bytes(
+                    self.filetype,
+                    self.tab_width,
+                    &lines[new_end_line..],
+                    Some(new_end_line),
+                    Some(buffer.matches_by_line.len()),
+                    Some(lines.len()),
+                );
+            }
+
+            let expected = ParsedBuffer::parse_bytes(self.filetype, self.tab_width, &lines)
+                .expect("filetype parsed before");
+            compare(&buffer, &expected).map_err(|(line, field)| Divergence {
+                edit_index,
+                line,
+                field,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// State at the end of the line before `end_line`
+fn end_state(buffer: &ParsedBuffer, end_line: usize) -> State {
+    end_line
+        .checked_sub(1)
+        .and_then(|line| buffer.state_by_line.get(line))
+        .copied()
+        .unwrap_or(State::Normal)
+}
+
+fn compare(actual: &ParsedBuffer, expected: &ParsedBuffer) -> Result<(), (usize, &'static str)> {
+    fn first_difference<T: PartialEq>(a: &[T], b: &[T]) -> Option<usize> {
+        a.iter()
+            .zip(b)
+            .position(|(a, b)| a != b)
+            .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))
+    }
+
+    let fields = [
+        (
+            first_difference(&actual.matches_by_line, &expected.matches_by_line),
+            "matches_by_line",
+        ),
+        (
+            first_difference(&actual.state_by_line, &expected.state_by_line),
+            "state_by_line",
+        ),
+        (
+            first_difference(&actual.indent_levels, &expected.indent_levels),
+            "indent_levels",
+        ),
+    ];
+    match fields
+        .into_iter()
+        .find_map(|(line, field)| Some((line?, field)))
+    {
+        Some(difference) => Err(difference),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for FuzzCase {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        fn line(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<Vec<u8>> {
+            let mut line = Vec::new();
+            for _ in 0..u.int_in_range(0..=8)? {
+                line.extend_from_slice(u.choose(FRAGMENTS)?.as_bytes());
+            }
+            Ok(line)
+        }
+        fn random_lines(
+            u: &mut arbitrary::Unstructured<'_>,
+            max: usize,
+        ) -> arbitrary::Result<Vec<Vec<u8>>> {
+            (0..u.int_in_range(0..=max)?).map(|_| line(u)).collect()
+        }
+
+        let filetype = *u.choose(FILETYPES)?;
+        let tab_width = u.int_in_range(1..=8)?;
+        let lines = random_lines(u, 32)?;
+        let edits = (0..u.int_in_range(1..=8)?)
+            .map(|_| {
+                let start_line = u.int_in_range(0..=32)?;
+                Ok(Edit {
+                    start_line,
+                    old_end_line: start_line + u.int_in_range(0..=4)?,
+                    new_lines: random_lines(u, 4)?,
+                })
+            })
+            .collect::<arbitrary::Result<_>>()?;
+
+        Ok(Self {
+            filetype,
+            tab_width,
+            lines,
+            edits,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use pretty_assertions::assert_eq;
+
+    fn lines(text: &[&str]) -> Vec<Vec<u8>> {
+        text.iter().map(|line| line.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let case = FuzzCase {
+            filetype: "rust",
+            tab_width: 4,
+            lines: lines(&["fn a() {", "", "    b();", "}"]),
+            edits: vec![
+                Edit {
+                    start_line: 1,
+                    old_end_line: 2,
+                    new_lines: lines(&["    /* (", "  "]),
+                },
+                Edit {
+                    start_line: 0,
+                    old_end_line: 10,
+                    new_lines: vec![],
+                },
+                Edit {
+                    start_line: 1,
+                    old_end_line: 1,
+                    new_lines: lines(&["\t{ */ [", ""]),
+                },
+            ],
+        };
+        assert_eq!(case.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_unterminated_string_on_last_line() {
+        let case = FuzzCase {
+            filetype: "c",
+            tab_width: 4,
+            lines: lines(&["f(\"a"]),
+            edits: vec![Edit {
+                start_line: 1,
+                old_end_line: 1,
+                new_lines: lines(&[")"]),
+            }],
+        };
+        assert_eq!(case.check(), Ok(()));
+    }
+}
@@ -0,0 +1,116 @@
+//! Bounded history of the lines replaced by recent reparses, so that an undo or redo which
+//! brings back recently replaced lines restores their matches instead of tokenizing them again
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::buffer::{line_hashes, LineHash};
+use crate::parser::{Match, State};
+
+/// Number of reparses to remember
+const HISTORY_LEN: usize = 16;
+/// Number of replaced lines to remember across all entries, bounding the memory used after
+/// large edits such as reformatting the whole buffer
+const HISTORY_LINES: usize = 100_000;
+
+/// Lines replaced by a reparse, with their parse results
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replaced {
+    pub matches_by_line: Vec<Vec<Match>>,
+    pub state_by_line: Vec<State>,
+    pub line_hashes: Vec<LineHash>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    entries: VecDeque<(u64, Replaced)>,
+    /// Number of lines across all entries
+    lines: usize,
+    generation: u64,
+}
+
+impl History {
+    /// Remembers the replaced lines, forgetting the oldest entry when full
+    pub fn push(&mut self, replaced: Replaced) {
+        self.generation += 1;
+        if replaced.line_hashes.is_empty() || replaced.line_hashes.len() > HISTORY_LINES {
+            return;
+        }
+
+        self.lines += replaced.line_hashes.len();
+        self.entries.push_back((self.generation, replaced));
+        while self.entries.len() > HISTORY_LEN || self.lines > HISTORY_LINES {
+            if let Some((_, oldest)) = self.entries.pop_front() {
+                self.lines -= oldest.line_hashes.len();
+            }
+        }
+    }
+
+    /// Takes the most recent entry whose lines are the same as the given lines when parsed
+    /// from the initial state. Since the hashes include the state at the start of each line,
+    /// the entry's matches and states are the same as parsing the lines would give
+    pub fn take<L: AsRef<[u8]>>(&mut self, lines: &[L], initial_state: State) -> Option<Replaced> {
+        let first_hash = *line_hashes(&lines[..lines.len().min(1)], initial_state, &[]).first()?;
+        let idx = self.entries.iter().rposition(|(_, replaced)| {
+            replaced.line_hashes.len() == lines.len()
+                && replaced.line_hashes[0] == first_hash
+                && line_hashes(lines, initial_state, &replaced.state_by_line)
+                    == replaced.line_hashes
+        })?;
+        let (_, replaced) = self.entries.remove(idx)?;
+        self.lines -= replaced.line_hashes.len();
+        Some(replaced)
+    }
+
+    /// Incremented by each push, including the ones which weren't remembered
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+#[cfg(all(test, feature = "lang-rust"))]
+mod tests {
+    use super::*;
+    use crate::buffer::ParsedBuffer;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_undo_restores_replaced_lines() {
+        let lines = ["fn a() {", "    b(1);", "}"];
+        let mut buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+        let original = buffer.matches_by_line.clone();
+
+        buffer.reparse_range("rust", 4, &["    b(", "    1);"], Some(1), Some(2), Some(3));
+        assert_eq!(buffer.generation(), 1);
+
+        // Mark the replaced line to tell whether the undo tokenized it again
+        buffer.history.entries[0].1.matches_by_line[0].pop();
+        buffer.reparse_range("rust", 4, &["    b(1);"], Some(1), Some(3), Some(2));
+        assert_eq!(buffer.matches_by_line[1].len(), 1);
+        assert_eq!(buffer.matches_by_line[0], original[0]);
+
+        // Redo restores the lines replaced by the undo
+        let redo_matches = buffer.history.entries[0].1.matches_by_line.clone();
+        buffer.reparse_range("rust", 4, &["    b(", "    1);"], Some(1), Some(2), Some(3));
+        assert_eq!(buffer.matches_by_line[1..3], redo_matches[..]);
+    }
+
+    #[test]
+    fn test_state_mismatch() {
+        let mut history = History::default();
+        let buffer = ParsedBuffer::parse("rust", 4, &["a(", ")"]).unwrap();
+        history.push(Replaced {
+            matches_by_line: buffer.matches_by_line.clone(),
+            state_by_line: buffer.state_by_line.clone(),
+            line_hashes: buffer.line_hashes.clone(),
+        });
+
+        assert_eq!(
+            history.take(&["a(", ")"], State::InBlockComment("/*")),
+            None
+        );
+        assert_eq!(history.take(&["a("], State::Normal), None);
+        assert!(history.take(&["a(", ")"], State::Normal).is_some());
+        assert_eq!(history.take(&["a(", ")"], State::Normal), None);
+    }
+}
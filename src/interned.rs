@@ -0,0 +1,207 @@
+//! Interning of the token texts of parse results read from outside of the process, since
+//! matches hold `&'static str`. With the `serde` feature, the types holding texts deserialize
+//! via owned copies, whose texts are interned afterwards
+
+use std::collections::HashSet;
+use std::sync::{LazyLock, Mutex};
+
+#[cfg(feature = "serde")]
+use crate::parser::{Kind, State, Token};
+
+/// Token texts read from outside of the process, leaked once and shared by all buffers
+static INTERNED: LazyLock<Mutex<HashSet<&'static str>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+pub(crate) fn intern(text: &str) -> &'static str {
+    let mut interned = INTERNED.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(text) = interned.get(text) {
+        return text;
+    }
+    let text: &'static str = Box::leak(text.into());
+    interned.insert(text);
+    text
+}
+
+/// Same as [`Kind`] with owned texts
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "Kind")]
+enum OwnedKind {
+    Opening,
+    Closing,
+    NonPair,
+    Middle(String),
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Kind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        OwnedKind::deserialize(deserializer).map(Kind::from)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<OwnedKind> for Kind {
+    fn from(kind: OwnedKind) -> Self {
+        match kind {
+            OwnedKind::Opening => Kind::Opening,
+            OwnedKind::Closing => Kind::Closing,
+            OwnedKind::NonPair => Kind::NonPair,
+            OwnedKind::Middle(middle) => Kind::Middle(intern(&middle)),
+        }
+    }
+}
+
+/// Same as [`Token`] with owned texts
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "Token")]
+enum OwnedToken {
+    Delimiter(String, String),
+    String(String),
+    BlockString(String, String),
+    LineComment(String),
+    BlockComment(String, String),
+    InlineSpan(String, String, String),
+    BlockSpan(String, String, String),
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Token {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        OwnedToken::deserialize(deserializer).map(Token::from)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<OwnedToken> for Token {
+    fn from(token: OwnedToken) -> Self {
+        match token {
+            OwnedToken::Delimiter(open, close) => Token::Delimiter(intern(&open), intern(&close)),
+            OwnedToken::String(open) => Token::String(intern(&open)),
+            OwnedToken::BlockString(open, close) => {
+                Token::BlockString(intern(&open), intern(&close))
+            }
+            OwnedToken::LineComment(open) => Token::LineComment(intern(&open)),
+            OwnedToken::BlockComment(open, close) => {
+                Token::BlockComment(intern(&open), intern(&close))
+            }
+            OwnedToken::InlineSpan(span, open, close) => {
+                Token::InlineSpan(intern(&span), intern(&open), intern(&close))
+            }
+            OwnedToken::BlockSpan(span, open, close) => {
+                Token::BlockSpan(intern(&span), intern(&open), intern(&close))
+            }
+        }
+    }
+}
+
+/// Same as [`State`] with owned texts
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "State")]
+enum OwnedState {
+    Normal,
+    InString(String),
+    InBlockString(String),
+    InLineComment,
+    InBlockComment(String),
+    InInlineSpan(String),
+    InBlockSpan(String),
+    InRegexClass(String),
+    InInterpolation(String),
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for State {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        OwnedState::deserialize(deserializer).map(State::from)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<OwnedState> for State {
+    fn from(state: OwnedState) -> Self {
+        match state {
+            OwnedState::Normal => State::Normal,
+            OwnedState::InString(open) => State::InString(intern(&open)),
+            OwnedState::InBlockString(open) => State::InBlockString(intern(&open)),
+            OwnedState::InLineComment => State::InLineComment,
+            OwnedState::InBlockComment(open) => State::InBlockComment(intern(&open)),
+            OwnedState::InInlineSpan(span) => State::InInlineSpan(intern(&span)),
+            OwnedState::InBlockSpan(span) => State::InBlockSpan(intern(&span)),
+            OwnedState::InRegexClass(open) => State::InRegexClass(intern(&open)),
+            OwnedState::InInterpolation(open) => State::InInterpolation(intern(&open)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use crate::buffer::{DepthLimit, DepthOverflow, ParsedBuffer};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_serde_round_trip() {
+        let lines = ["fn a() {", "    /* (", "    */ b(\"c\", [d]);", "}"];
+        let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+
+        let bytes = postcard::to_stdvec(&buffer).unwrap();
+        let mut decoded: ParsedBuffer = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.matches_by_line, buffer.matches_by_line);
+        assert_eq!(decoded.state_by_line, buffer.state_by_line);
+        assert_eq!(decoded.indent_levels, buffer.indent_levels);
+        assert_eq!(decoded.line_hashes, buffer.line_hashes);
+
+        // Still reparses incrementally
+        let lines = [
+            "fn a() {",
+            "    /* (",
+            "    */ b(\"c\", [d]);",
+            "    e()",
+            "}",
+        ];
+        assert!(decoded.reparse_range("rust", 4, &lines[3..4], Some(3), Some(3), Some(4)));
+        let reparsed = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+        assert_eq!(decoded.matches_by_line, reparsed.matches_by_line);
+    }
+
+    #[test]
+    fn test_serde_rejects_malformed() {
+        let lines = ["fn a() {", "    b(\"c\", [d]);", "}"];
+        let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+        let decode = |buffer: &ParsedBuffer| {
+            postcard::from_bytes::<ParsedBuffer>(&postcard::to_stdvec(buffer).unwrap()).is_ok()
+        };
+        assert!(decode(&buffer));
+        // Including pairs nested past the depth limit
+        for overflow in [DepthOverflow::Clamp, DepthOverflow::Unmatched] {
+            let lines = ["(((((", "[", "])))))", ")"];
+            let mut deep = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+            deep.set_depth_limit(
+                DepthLimit {
+                    max_depth: 2,
+                    overflow,
+                },
+                4,
+            );
+            assert!(decode(&deep));
+        }
+
+        let mut missing_line = buffer.clone();
+        missing_line.indent_levels.pop();
+        assert!(!decode(&missing_line));
+
+        let mut unsorted = buffer.clone();
+        unsorted.matches_by_line[1].reverse();
+        assert!(!decode(&unsorted));
+
+        let mut wrong_height = buffer.clone();
+        wrong_height.matches_by_line[0][1].stack_height = Some(3);
+        assert!(!decode(&wrong_height));
+
+        let mut indent_changes = buffer.clone();
+        indent_changes.indent_changes = 0..4;
+        assert!(!decode(&indent_changes));
+    }
+}
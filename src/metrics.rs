@@ -0,0 +1,160 @@
+//! Timing and throughput of parse calls, see [`ParsedBuffer::parse_bytes_with_metrics`] and
+//! [`ParsedBuffer::reparse_range_bytes_with_metrics`]
+
+use core::ops::{Add, AddAssign};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "lua")]
+use mlua::IntoLua;
+
+use crate::buffer::ParsedBuffer;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Metrics {
+    /// Number of lines (re)parsed
+    pub lines: usize,
+    /// Number of bytes (re)parsed, excluding newlines
+    pub bytes: usize,
+    /// Number of matches stored for the (re)parsed lines. Excludes the tokens which don't
+    /// become matches, such as the delimiters inside of strings and comments
+    pub stored_matches: usize,
+    pub duration: Duration,
+}
+
+impl Metrics {
+    fn measure<L: AsRef<[u8]>, T>(lines: &[L], f: impl FnOnce() -> T) -> (T, Self) {
+        let start_time = Instant::now();
+        let result = f();
+        let duration = start_time.elapsed();
+        let metrics = Metrics {
+            lines: lines.len(),
+            bytes: lines.iter().map(|line| line.as_ref().len()).sum(),
+            stored_matches: 0,
+            duration,
+        };
+        (result, metrics)
+    }
+
+    /// Bytes parsed per second, or 0 when no time has passed
+    pub fn bytes_per_second(&self) -> f64 {
+        match self.duration.as_secs_f64() {
+            0.0 => 0.0,
+            secs => self.bytes as f64 / secs,
+        }
+    }
+}
+
+impl Add for Metrics {
+    type Output = Metrics;
+
+    fn add(self, other: Self) -> Self::Output {
+        Metrics {
+            lines: self.lines + other.lines,
+            bytes: self.bytes + other.bytes,
+            stored_matches: self.stored_matches + other.stored_matches,
+            duration: self.duration + other.duration,
+        }
+    }
+}
+
+impl AddAssign for Metrics {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+#[cfg(feature = "lua")]
+impl IntoLua for Metrics {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        table.set("lines", self.lines)?;
+        table.set("bytes", self.bytes)?;
+        table.set("stored_matches", self.stored_matches)?;
+        table.set("micros", self.duration.as_micros() as u64)?;
+        (&table).into_lua(lua)
+    }
+}
+
+impl ParsedBuffer {
+    /// Same as [`ParsedBuffer::parse_bytes`] but also measures the parse
+    pub fn parse_bytes_with_metrics<L: AsRef<[u8]>>(
+        filetype: &str,
+        tab_width: u8,
+        lines: &[L],
+    ) -> Option<(Self, Metrics)> {
+        let (parsed, mut metrics) =
+            Metrics::measure(lines, || Self::parse_bytes(filetype, tab_width, lines));
+        let parsed = parsed?;
+        metrics.stored_matches = parsed.matches_by_line.iter().map(Vec::len).sum();
+        Some((parsed, metrics))
+    }
+
+    /// Same as [`ParsedBuffer::reparse_range_bytes`] but also measures the reparse
+    pub fn reparse_range_bytes_with_metrics<L: AsRef<[u8]>>(
+        &mut self,
+        filetype: &str,
+        tab_width: u8,
+        lines: &[L],
+        start_line: Option<usize>,
+        old_end_line: Option<usize>,
+        new_end_line: Option<usize>,
+    ) -> (bool, Metrics) {
+        let (reparsed, mut metrics) = Metrics::measure(lines, || {
+            self.reparse_range_bytes(
+                filetype,
+                tab_width,
+                lines,
+                start_line,
+                old_end_line,
+                new_end_line,
+            )
+        });
+
+        let start_line = start_line.unwrap_or(0).min(self.matches_by_line.len());
+        let end_line = new_end_line
+            .unwrap_or(start_line + lines.len())
+            .min(self.matches_by_line.len());
+        if reparsed {
+            metrics.stored_matches = self.matches_by_line[start_line..end_line.max(start_line)]
+                .iter()
+                .map(Vec::len)
+                .sum();
+        }
+        (reparsed, metrics)
+    }
+}
+
+#[cfg(all(test, feature = "lang-rust"))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_metrics() {
+        let (mut buffer, metrics) =
+            ParsedBuffer::parse_bytes_with_metrics("rust", 4, &["fn a() {", "}"]).unwrap();
+        assert_eq!(
+            (metrics.lines, metrics.bytes, metrics.stored_matches),
+            (2, 9, 4)
+        );
+
+        let (reparsed, metrics) = buffer.reparse_range_bytes_with_metrics(
+            "rust",
+            4,
+            &["fn b() { [] }"],
+            Some(0),
+            Some(1),
+            Some(1),
+        );
+        assert!(reparsed);
+        assert_eq!(
+            (metrics.lines, metrics.bytes, metrics.stored_matches),
+            (1, 13, 6)
+        );
+
+        // New end before the start
+        let (_, metrics) =
+            buffer.reparse_range_bytes_with_metrics("rust", 4, &["}"], Some(1), Some(2), Some(0));
+        assert_eq!(metrics.stored_matches, 0);
+    }
+}
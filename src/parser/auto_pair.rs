@@ -0,0 +1,72 @@
+//! Contexts where typing an opening shouldn't insert its closing, such as a quote right after a
+//! word, i.e. `don't`, declared by the matchers and checked by
+//! [`crate::buffer::ParsedBuffer::is_auto_pair_excluded`]
+
+use super::State;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExclusionContext {
+    /// The character before the cursor is a letter, digit or `_`
+    AfterWord,
+    /// The cursor is at the start of the line or after whitespace, i.e. `<` as a comparison
+    AfterWhitespace,
+    /// The character after the cursor is a letter, digit or `_`
+    BeforeWord,
+    InString,
+    InComment,
+}
+
+impl ExclusionContext {
+    /// Whether the cursor at the column of the line is in the context, where the state is the
+    /// one at the cursor, see [`crate::buffer::ParsedBuffer::state_at`]
+    pub fn applies(self, line: &[u8], col: usize, state: State) -> bool {
+        let is_word = |byte: &u8| byte.is_ascii_alphanumeric() || *byte == b'_' || *byte >= 0x80;
+        match self {
+            ExclusionContext::AfterWord => col
+                .checked_sub(1)
+                .and_then(|col| line.get(col))
+                .is_some_and(is_word),
+            ExclusionContext::AfterWhitespace => col
+                .checked_sub(1)
+                .and_then(|col| line.get(col))
+                .is_none_or(u8::is_ascii_whitespace),
+            ExclusionContext::BeforeWord => line.get(col).is_some_and(is_word),
+            ExclusionContext::InString => matches!(
+                state,
+                State::InString(_) | State::InBlockString(_) | State::InRegexClass(_)
+            ),
+            ExclusionContext::InComment => {
+                matches!(state, State::InLineComment | State::InBlockComment(_))
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for ExclusionContext {
+    type Error = ();
+
+    fn try_from(context: &str) -> Result<Self, Self::Error> {
+        match context {
+            "after_word" => Ok(ExclusionContext::AfterWord),
+            "after_whitespace" => Ok(ExclusionContext::AfterWhitespace),
+            "before_word" => Ok(ExclusionContext::BeforeWord),
+            "in_string" => Ok(ExclusionContext::InString),
+            "in_comment" => Ok(ExclusionContext::InComment),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Context where typing the opening shouldn't insert its closing, i.e. `'` after a word in
+/// prose, declared by a matcher with `auto_pair_exclusions`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoPairExclusion {
+    pub opening: &'static str,
+    pub context: ExclusionContext,
+}
+
+impl AutoPairExclusion {
+    pub const fn new(opening: &'static str, context: ExclusionContext) -> Self {
+        Self { opening, context }
+    }
+}
@@ -1,5 +1,10 @@
 //! Module for calculating indentation levels in source code.
 
+use alloc::vec::Vec;
+
+#[cfg(feature = "lua")]
+use mlua::IntoLua;
+
 /// Calculate indentation levels with a custom tab width.
 ///
 /// Returns a vector where each element represents the indentation level
@@ -9,18 +14,28 @@
 /// # Examples
 ///
 /// ```
-/// use blink_pairs::indent::indent_levels;
+/// use blink_pairs::parser::indent::indent_levels;
 ///
 /// let src = ["fn main() {", "\tprintln!(\"hello\");", "}"];
 /// let indents = indent_levels(&src, 8);
-/// assert_eq!(indents, vec![0, 4, 0]);
+/// assert_eq!(indents, vec![0, 8, 0]);
 /// ```
-pub fn indent_levels(lines: &[&str], tab_width: u8) -> Vec<u8> {
-    let mut last_indent = None;
+pub fn indent_levels<L: AsRef<[u8]>>(lines: &[L], tab_width: u8) -> Vec<u8> {
+    indent_levels_after(lines, tab_width, None)
+}
+
+/// Same as [`indent_levels`] but continues from the indentation of the line before the
+/// first line, which leading whitespace-only lines receive. Used when reparsing a range of
+/// lines
+pub fn indent_levels_after<L: AsRef<[u8]>>(
+    lines: &[L],
+    tab_width: u8,
+    mut last_indent: Option<u8>,
+) -> Vec<u8> {
     let mut indents = Vec::with_capacity(lines.len());
     'outer: for line in lines {
         let mut indent: u8 = 0;
-        for c in line.as_bytes() {
+        for c in line.as_ref() {
             match c {
                 b' ' => indent = indent.saturating_add(1),
                 b'\t' => indent = indent.saturating_add(tab_width),
@@ -37,9 +52,131 @@ pub fn indent_levels(lines: &[&str], tab_width: u8) -> Vec<u8> {
     indents
 }
 
+/// Gets the width of the whitespace when the line is entirely whitespace, in which case its
+/// indentation level is inherited from the previous line, or this width on the first line
+pub fn blank_indent<L: AsRef<[u8]>>(line: L, tab_width: u8) -> Option<u8> {
+    line.as_ref()
+        .iter()
+        .try_fold(0u8, |indent, byte| match byte {
+            b' ' => Some(indent.saturating_add(1)),
+            b'\t' => Some(indent.saturating_add(tab_width)),
+            _ => None,
+        })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces,
+}
+
+/// Dominant indentation style of some lines, see [`detect_indent`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedIndent {
+    pub style: IndentStyle,
+    /// Number of characters per indent level, which is always 1 for tabs
+    pub width: u8,
+    /// Share of the indented lines which follow the style, from 0 to 1
+    pub confidence: f32,
+    /// Indented lines which don't follow the style, including lines mixing tabs and spaces
+    pub deviating_lines: Vec<usize>,
+}
+
+#[cfg(feature = "lua")]
+impl IntoLua for DetectedIndent {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let table = lua.create_table()?;
+        let style = match self.style {
+            IndentStyle::Tabs => "tabs",
+            IndentStyle::Spaces => "spaces",
+        };
+        table.set("style", style)?;
+        table.set("width", self.width)?;
+        table.set("confidence", self.confidence)?;
+        table.set("deviating_lines", self.deviating_lines)?;
+        (&table).into_lua(lua)
+    }
+}
+
+/// Detects whether the lines are indented with tabs or spaces, and with how many spaces per
+/// level, by counting the style of each indented line. Returns `None` when no line is indented
+///
+/// # Examples
+///
+/// ```
+/// use blink_pairs::parser::indent::{detect_indent, IndentStyle};
+///
+/// let src = ["fn main() {", "  if x {", "    y();", "\t}", "}"];
+/// let indent = detect_indent(&src).unwrap();
+/// assert_eq!((indent.style, indent.width), (IndentStyle::Spaces, 2));
+/// assert_eq!(indent.deviating_lines, vec![3]);
+/// ```
+pub fn detect_indent<L: AsRef<[u8]>>(lines: &[L]) -> Option<DetectedIndent> {
+    // Styles of the indented lines, where `None` is a mix of tabs and spaces
+    let mut styles = Vec::new();
+    // Occurrences of each increase in the number of leading spaces between lines
+    let mut space_deltas = [0usize; 9];
+    let mut last_spaces = 0;
+
+    for (line_number, line) in lines.iter().enumerate() {
+        let line = line.as_ref();
+        let Some(len) = line.iter().position(|byte| !matches!(byte, b' ' | b'\t')) else {
+            continue;
+        };
+        let leading = &line[..len];
+
+        let style = if leading.iter().all(|&byte| byte == b' ') {
+            if len > last_spaces && len - last_spaces < space_deltas.len() {
+                space_deltas[len - last_spaces] += 1;
+            }
+            last_spaces = len;
+            Some(IndentStyle::Spaces)
+        } else if leading.iter().all(|&byte| byte == b'\t') {
+            Some(IndentStyle::Tabs)
+        } else {
+            None
+        };
+        if len > 0 {
+            styles.push((line_number, style));
+        }
+    }
+
+    if styles.is_empty() {
+        return None;
+    }
+
+    let count = |style| styles.iter().filter(|(_, s)| *s == Some(style)).count();
+    let (tabs, spaces) = (count(IndentStyle::Tabs), count(IndentStyle::Spaces));
+    let (style, matching) = match tabs > spaces {
+        true => (IndentStyle::Tabs, tabs),
+        false => (IndentStyle::Spaces, spaces),
+    };
+
+    let width = match style {
+        IndentStyle::Tabs => 1,
+        // Most common increase, preferring the smaller one on ties
+        IndentStyle::Spaces => (1..space_deltas.len())
+            .rev()
+            .max_by_key(|&delta| space_deltas[delta])
+            .filter(|&delta| space_deltas[delta] > 0)
+            .unwrap_or(4) as u8,
+    };
+
+    Some(DetectedIndent {
+        style,
+        width,
+        confidence: matching as f32 / styles.len() as f32,
+        deviating_lines: styles
+            .iter()
+            .filter(|(_, s)| *s != Some(style))
+            .map(|(line_number, _)| *line_number)
+            .collect(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use super::indent_levels;
+    use super::{detect_indent, indent_levels, IndentStyle};
 
     #[test]
     fn test_basic_indentation() {
@@ -138,4 +275,30 @@ mod tests {
         let result = indent_levels(&src, 4);
         assert_eq!(result, vec![0, 4, 4]);
     }
+
+    #[test]
+    fn test_detect_indent() {
+        let src = [
+            "fn main() {",
+            "    if x {",
+            "        y();",
+            "",
+            "    }",
+            "\tz();",
+            "  \tw();",
+            "}",
+        ];
+        let indent = detect_indent(&src).unwrap();
+        assert_eq!(indent.style, IndentStyle::Spaces);
+        assert_eq!(indent.width, 4);
+        assert_eq!(indent.confidence, 0.6);
+        assert_eq!(indent.deviating_lines, vec![5, 6]);
+
+        let src = ["a {", "\tb {", "\t\tc", "\t}", "}"];
+        let indent = detect_indent(&src).unwrap();
+        assert_eq!((indent.style, indent.width), (IndentStyle::Tabs, 1));
+        assert_eq!(indent.confidence, 1.0);
+
+        assert_eq!(detect_indent(&["a", "", "b"]), None);
+    }
 }
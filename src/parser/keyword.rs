@@ -0,0 +1,322 @@
+//! Keyword pairs, such as `do`/`end do`, which can't be found by the tokenizer since they're
+//! made of letters. Instead, each line is scanned for keywords after the matcher has run,
+//! skipping over the strings and comments it found
+
+use alloc::vec::Vec;
+
+use super::{Kind, Match, State, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyword {
+    /// Text to find, where a space matches any amount of blanks, including none, so that
+    /// `end do` matches both `end do` and `enddo`
+    pub pattern: &'static str,
+    pub kind: Kind,
+    /// Canonical opening and closing text of the pair, used for the emitted token
+    pub opening: &'static str,
+    pub closing: &'static str,
+    pub ignore_case: bool,
+    /// Only match as the first word of a statement, optionally after a numeric label or a
+    /// `name:` construct label
+    pub statement_start: bool,
+    /// Only match when the code on the line ends with the given word, i.e. `then`
+    pub line_end: Option<&'static str>,
+}
+
+impl Keyword {
+    pub const fn opening(opening: &'static str, closing: &'static str) -> Self {
+        Self {
+            pattern: opening,
+            kind: Kind::Opening,
+            opening,
+            closing,
+            ignore_case: false,
+            statement_start: false,
+            line_end: None,
+        }
+    }
+
+    pub const fn closing(opening: &'static str, closing: &'static str) -> Self {
+        Self {
+            pattern: closing,
+            kind: Kind::Closing,
+            ..Self::opening(opening, closing)
+        }
+    }
+
+    /// Part of the construct between the opening and closing, such as `else`
+    pub const fn middle(
+        opening: &'static str,
+        closing: &'static str,
+        middle: &'static str,
+    ) -> Self {
+        Self {
+            pattern: middle,
+            kind: Kind::Middle(middle),
+            ..Self::opening(opening, closing)
+        }
+    }
+
+    pub const fn ignore_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+
+    pub const fn statement_start(mut self) -> Self {
+        self.statement_start = true;
+        self
+    }
+
+    pub const fn line_end(mut self, word: &'static str) -> Self {
+        self.line_end = Some(word);
+        self
+    }
+
+    pub fn token(&self) -> Token {
+        Token::Delimiter(self.opening, self.closing)
+    }
+
+    /// Gets the length of the keyword if it matches at the start of the text
+    fn match_len(&self, text: &[u8]) -> Option<usize> {
+        let mut len = 0;
+        for &expected in self.pattern.as_bytes() {
+            if expected == b' ' {
+                while text.get(len).is_some_and(|byte| is_blank(*byte)) {
+                    len += 1;
+                }
+                continue;
+            }
+
+            let byte = *text.get(len)?;
+            let equal = match self.ignore_case {
+                true => byte.eq_ignore_ascii_case(&expected),
+                false => byte == expected,
+            };
+            if !equal {
+                return None;
+            }
+            len += 1;
+        }
+
+        // Must end on a word boundary
+        match text.get(len) {
+            Some(byte) if is_word(*byte) => None,
+            _ => Some(len),
+        }
+    }
+}
+
+fn is_word(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+fn is_blank(byte: u8) -> bool {
+    byte == b' ' || byte == b'\t'
+}
+
+/// Finds the keywords in the code of the line and inserts them into the line's matches, where
+/// the state is the one from the end of the previous line
+pub fn match_keywords(
+    keywords: &[Keyword],
+    line: &[u8],
+    state: State,
+    line_matches: &mut Vec<Match>,
+) {
+    let code_ranges = code_ranges(line, state, line_matches);
+    let Some(code_end) = code_ranges.last().map(|range| range.1) else {
+        return;
+    };
+    let code = &line[..code_end];
+    let mut keyword_matches = Vec::new();
+
+    for &(start, end) in &code_ranges {
+        // End of the last keyword
+        let mut keyword_end = start;
+        for col in start..end {
+            // Keywords must start on a word boundary
+            if col < keyword_end || (col > 0 && is_word(line[col - 1])) {
+                continue;
+            }
+
+            let keyword = keywords.iter().find_map(|keyword| {
+                let len = keyword.match_len(&line[col..end])?;
+                if keyword.statement_start && !is_statement_start(&line[..col]) {
+                    return None;
+                }
+                if let Some(word) = keyword.line_end {
+                    if !ends_with_word(code, word, keyword.ignore_case) {
+                        return None;
+                    }
+                }
+                Some((keyword, len))
+            });
+            if let Some((keyword, len)) = keyword {
+                let mut match_ = Match::new(keyword.kind, keyword.token(), col);
+                match_.source_len = (len != match_.text().len()).then_some(len);
+                keyword_matches.push(match_);
+                keyword_end = col + len;
+            }
+        }
+    }
+
+    if !keyword_matches.is_empty() {
+        line_matches.extend(keyword_matches);
+        line_matches.sort_by_key(|match_| match_.col);
+    }
+}
+
+/// Gets the `(start, end)` byte ranges of the line which aren't in a string or comment
+fn code_ranges(line: &[u8], state: State, line_matches: &[Match]) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut code_start = match state {
+        State::InString(_)
+        | State::InBlockString(_)
+        | State::InLineComment
+        | State::InBlockComment(_) => None,
+        _ => Some(0),
+    };
+
+    for match_ in line_matches {
+        match (&match_.token, match_.kind) {
+            (
+                Token::String(_) | Token::BlockString(_, _) | Token::BlockComment(_, _),
+                Kind::Opening,
+            )
+            | (Token::LineComment(_), _) => {
+                if let Some(start) = code_start.take() {
+                    ranges.push((start, match_.col));
+                }
+                if matches!(match_.token, Token::LineComment(_)) {
+                    return ranges;
+                }
+            }
+            (
+                Token::String(_) | Token::BlockString(_, _) | Token::BlockComment(_, _),
+                Kind::Closing,
+            ) => {
+                code_start = Some(match_.end_col());
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(start) = code_start {
+        ranges.push((start, line.len()));
+    }
+    ranges
+}
+
+/// Whether the text before the keyword only contains blanks, a numeric label (`10 continue`),
+/// a construct label (`outer: do`) or the end of a previous statement (`;`)
+fn is_statement_start(before: &[u8]) -> bool {
+    let before = match before.iter().rposition(|&byte| byte == b';') {
+        Some(idx) => &before[idx + 1..],
+        None => before,
+    };
+    let before = before.trim_ascii();
+
+    // Construct label
+    let before = match before.strip_suffix(b":") {
+        Some(label) if !label.ends_with(b":") => {
+            let label = label.trim_ascii_end();
+            let word_start = label
+                .iter()
+                .rposition(|&byte| !is_word(byte))
+                .map(|idx| idx + 1)
+                .unwrap_or(0);
+            label[..word_start].trim_ascii()
+        }
+        _ => before,
+    };
+
+    before.iter().all(|byte| byte.is_ascii_digit())
+}
+
+fn ends_with_word(code: &[u8], word: &str, ignore_case: bool) -> bool {
+    let code = code.trim_ascii_end();
+    let Some(start) = code.len().checked_sub(word.len()) else {
+        return false;
+    };
+    let equal = match ignore_case {
+        true => code[start..].eq_ignore_ascii_case(word.as_bytes()),
+        false => &code[start..] == word.as_bytes(),
+    };
+    equal && (start == 0 || !is_word(code[start - 1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const KEYWORDS: &[Keyword] = &[
+        Keyword::closing("do", "end do").ignore_case(),
+        Keyword::opening("do", "end do")
+            .ignore_case()
+            .statement_start(),
+        Keyword::closing("if", "end if").ignore_case(),
+        Keyword::opening("if", "end if")
+            .ignore_case()
+            .statement_start()
+            .line_end("then"),
+    ];
+
+    fn keyword_cols(line: &str) -> Vec<(Kind, usize)> {
+        let mut line_matches = Vec::new();
+        match_keywords(KEYWORDS, line.as_bytes(), State::Normal, &mut line_matches);
+        line_matches
+            .iter()
+            .map(|match_| (match_.kind, match_.col))
+            .collect()
+    }
+
+    #[test]
+    fn test_match_keywords() {
+        assert_eq!(keyword_cols("  do i = 1, n"), vec![(Kind::Opening, 2)]);
+        assert_eq!(keyword_cols("10 DO i = 1, n"), vec![(Kind::Opening, 3)]);
+        assert_eq!(keyword_cols("outer: do"), vec![(Kind::Opening, 7)]);
+        assert_eq!(keyword_cols("  enddo"), vec![(Kind::Closing, 2)]);
+        assert_eq!(keyword_cols("  End  Do outer"), vec![(Kind::Closing, 2)]);
+        assert_eq!(keyword_cols("x = do_thing(1)"), vec![]);
+        assert_eq!(keyword_cols("call do(1)"), vec![]);
+    }
+
+    #[test]
+    fn test_source_text() {
+        for (line, source) in [
+            ("  enddo", "enddo"),
+            ("  End  Do outer", "End  Do"),
+            ("  end do", "end do"),
+        ] {
+            let mut line_matches = Vec::new();
+            match_keywords(KEYWORDS, line.as_bytes(), State::Normal, &mut line_matches);
+            assert_eq!(line_matches[0].text(), "end do");
+            assert_eq!(line_matches[0].len(), source.len());
+            assert_eq!(line_matches[0].source(line.as_bytes()), source.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_line_end() {
+        assert_eq!(keyword_cols("if (x > 1) then"), vec![(Kind::Opening, 0)]);
+        assert_eq!(keyword_cols("if (x > 1) y = 2"), vec![]);
+        assert_eq!(keyword_cols("else if (x > 1) then"), vec![]);
+        assert_eq!(keyword_cols("end if"), vec![(Kind::Closing, 0)]);
+    }
+
+    #[test]
+    fn test_skips_strings_and_comments() {
+        let mut line_matches = vec![
+            Match::new(Kind::Opening, Token::String("'"), 4),
+            Match::new(Kind::Closing, Token::String("'"), 10),
+            Match::line_comment("!", 12),
+        ];
+        match_keywords(
+            KEYWORDS,
+            b"x = 'do if' ! do",
+            State::Normal,
+            &mut line_matches,
+        );
+        assert_eq!(line_matches.len(), 3);
+    }
+}
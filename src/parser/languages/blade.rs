@@ -0,0 +1,95 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+const KEYWORDS: &[Keyword] = &[
+    Keyword::closing("@if", "@endif"),
+    Keyword::closing("@unless", "@endunless"),
+    Keyword::closing("@isset", "@endisset"),
+    Keyword::closing("@empty", "@endempty"),
+    Keyword::closing("@auth", "@endauth"),
+    Keyword::closing("@guest", "@endguest"),
+    Keyword::closing("@can", "@endcan"),
+    Keyword::closing("@switch", "@endswitch"),
+    Keyword::closing("@for", "@endfor"),
+    Keyword::closing("@foreach", "@endforeach"),
+    Keyword::closing("@forelse", "@endforelse"),
+    Keyword::closing("@while", "@endwhile"),
+    Keyword::closing("@push", "@endpush"),
+    Keyword::closing("@once", "@endonce"),
+    Keyword::closing("@php", "@endphp"),
+    Keyword::closing("@verbatim", "@endverbatim"),
+    Keyword::closing("@component", "@endcomponent"),
+    Keyword::closing("@slot", "@endslot"),
+    Keyword::middle("@if", "@endif", "@elseif"),
+    Keyword::middle("@if", "@endif", "@else"),
+    Keyword::middle("@switch", "@endswitch", "@case"),
+    Keyword::middle("@switch", "@endswitch", "@default"),
+    Keyword::opening("@if", "@endif"),
+    Keyword::opening("@unless", "@endunless"),
+    Keyword::opening("@isset", "@endisset"),
+    Keyword::opening("@empty", "@endempty"),
+    Keyword::opening("@auth", "@endauth"),
+    Keyword::opening("@guest", "@endguest"),
+    Keyword::opening("@can", "@endcan"),
+    Keyword::opening("@switch", "@endswitch"),
+    Keyword::opening("@for", "@endfor"),
+    Keyword::opening("@foreach", "@endforeach"),
+    Keyword::opening("@forelse", "@endforelse"),
+    Keyword::opening("@while", "@endwhile"),
+    Keyword::opening("@push", "@endpush"),
+    Keyword::opening("@once", "@endonce"),
+    Keyword::opening("@php", "@endphp"),
+    Keyword::opening("@verbatim", "@endverbatim"),
+    Keyword::opening("@component", "@endcomponent"),
+    Keyword::opening("@slot", "@endslot"),
+    // `@section` is excluded since `@section('title', 'Home')` has no `@endsection`
+];
+
+define_matcher!(Blade {
+    delimiters: [
+        "{{" => "}}",
+        "{!!" => "!!}",
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    block_comment: ["{{--" => "--}}"],
+    keywords: KEYWORDS
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_directives() {
+        let if_ = Token::Delimiter("@if", "@endif");
+        let echo = Token::Delimiter("{!!", "!!}");
+        let comment = Token::BlockComment("{{--", "--}}");
+        assert_eq!(
+            parse_filetype(
+                "blade",
+                4,
+                &["@if($a) {!! $b !!}", "{{-- @endif --}}", "@endif"],
+                State::Normal
+            )
+            .unwrap()
+            .matches_by_line,
+            vec![
+                vec![
+                    Match::new(Kind::Opening, if_.clone(), 0),
+                    Match::delimiter('(', 3, None),
+                    Match::delimiter(')', 6, None),
+                    Match::new(Kind::Opening, echo.clone(), 8),
+                    Match::new(Kind::Closing, echo, 15),
+                ],
+                vec![
+                    Match::new(Kind::Opening, comment.clone(), 0),
+                    Match::new(Kind::Closing, comment, 12),
+                ],
+                vec![Match::new(Kind::Closing, if_, 0)],
+            ]
+        );
+    }
+}
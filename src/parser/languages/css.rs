@@ -0,0 +1,90 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Css {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    block_comment: ["/*" => "*/"],
+    string: ["\"", "'"],
+    // Unquoted urls may contain anything, i.e. `url(data:image/png;base64,...)`
+    inline_span: {
+        url: "url(" => ")"
+    }
+});
+
+define_matcher!(Scss {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: ["//"],
+    block_comment: ["/*" => "*/"],
+    string: ["\"", "'"],
+    interpolation: [
+        "\"": "#{" => "}",
+        "'": "#{" => "}"
+    ],
+    inline_span: {
+        url: "url(" => ")"
+    }
+});
+
+define_matcher!(Less {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: ["//"],
+    block_comment: ["/*" => "*/"],
+    string: ["\"", "'"],
+    inline_span: {
+        url: "url(" => ")"
+    }
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    fn parse(filetype: &str, text: &str) -> Vec<Match> {
+        parse_filetype(filetype, 4, &[text], State::Normal)
+            .unwrap()
+            .matches_by_line
+            .remove(0)
+    }
+
+    #[test]
+    fn test_url() {
+        let url = Token::InlineSpan("url", "url(", ")");
+        assert_eq!(
+            parse("css", "a { b: url(x//y[) }"),
+            vec![
+                Match::delimiter('{', 2, None),
+                Match::new(Kind::Opening, url.clone(), 7),
+                Match::new(Kind::Closing, url, 16),
+                Match::delimiter('}', 18, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let string = Token::String("\"");
+        assert_eq!(
+            parse("scss", "a: \"#{$b}\" // {"),
+            vec![
+                Match::new(Kind::Opening, string.clone(), 3),
+                Match::delimiter('{', 5, None),
+                Match::delimiter('}', 8, None),
+                Match::new(Kind::Closing, string, 9),
+                Match::line_comment("//", 11),
+            ]
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+// Headers are treated as comments so that only the content of hunk lines is matched. The
+// `+`, `-` and ` ` markers in the first column are never tokens
+define_matcher!(Diff {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_start_comment: ["diff", "index", "---", "+++", "@@"]
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Match, State};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_headers() {
+        assert_eq!(
+            parse_filetype(
+                "diff",
+                4,
+                &[
+                    "--- a/f(",
+                    "+++ b/f(",
+                    "@@ -1 +1 @@ fn f() {",
+                    "-  a(",
+                    "+  b()"
+                ],
+                State::Normal
+            )
+            .unwrap()
+            .matches_by_line,
+            vec![
+                vec![Match::line_comment("---", 0)],
+                vec![Match::line_comment("+++", 0)],
+                vec![Match::line_comment("@@", 0)],
+                vec![Match::delimiter('(', 4, None)],
+                vec![
+                    Match::delimiter('(', 4, None),
+                    Match::delimiter(')', 5, None)
+                ],
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+// Closings come first so that `end do` isn't matched as `do`
+const KEYWORDS: &[Keyword] = &[
+    Keyword::closing("program", "end program").ignore_case(),
+    Keyword::closing("module", "end module").ignore_case(),
+    Keyword::closing("subroutine", "end subroutine").ignore_case(),
+    Keyword::closing("function", "end function").ignore_case(),
+    Keyword::closing("interface", "end interface").ignore_case(),
+    Keyword::closing("do", "end do").ignore_case(),
+    Keyword::closing("if", "end if").ignore_case(),
+    Keyword::closing("select", "end select").ignore_case(),
+    Keyword::closing("associate", "end associate").ignore_case(),
+    Keyword::closing("block", "end block").ignore_case(),
+    // Longest first so that `else if` isn't matched as `else`
+    Keyword::middle("if", "end if", "else if")
+        .ignore_case()
+        .statement_start()
+        .line_end("then"),
+    Keyword::middle("if", "end if", "else")
+        .ignore_case()
+        .statement_start(),
+    Keyword::middle("select", "end select", "case")
+        .ignore_case()
+        .statement_start(),
+    Keyword::opening("program", "end program")
+        .ignore_case()
+        .statement_start(),
+    Keyword::opening("module", "end module")
+        .ignore_case()
+        .statement_start(),
+    // May be preceded by prefixes such as `pure` or `integer`
+    Keyword::opening("subroutine", "end subroutine").ignore_case(),
+    Keyword::opening("function", "end function").ignore_case(),
+    Keyword::opening("interface", "end interface").ignore_case(),
+    Keyword::opening("do", "end do")
+        .ignore_case()
+        .statement_start(),
+    // Excludes single line `if (x) y = 1`
+    Keyword::opening("if", "end if")
+        .ignore_case()
+        .statement_start()
+        .line_end("then"),
+    Keyword::opening("select", "end select")
+        .ignore_case()
+        .statement_start(),
+    Keyword::opening("associate", "end associate")
+        .ignore_case()
+        .statement_start(),
+    Keyword::opening("block", "end block")
+        .ignore_case()
+        .statement_start(),
+];
+
+define_matcher!(Fortran {
+    delimiters: [
+        "(" => ")",
+        "[" => "]"
+    ],
+    line_comment: ["!"],
+    doubled_string: ["'", "\""],
+    keywords: KEYWORDS
+});
+
+define_matcher!(FortranFixed {
+    delimiters: [
+        "(" => ")",
+        "[" => "]"
+    ],
+    line_comment: ["!"],
+    line_start_comment: ["C", "c", "*"],
+    doubled_string: ["'", "\""],
+    keywords: KEYWORDS
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, State, Token};
+    use pretty_assertions::assert_eq;
+
+    fn parse(filetype: &str, text: &str) -> Vec<Vec<(Kind, Token, usize)>> {
+        parse_filetype(
+            filetype,
+            4,
+            &text.split('\n').collect::<Vec<_>>(),
+            State::Normal,
+        )
+        .unwrap()
+        .matches_by_line
+        .into_iter()
+        .map(|matches| {
+            matches
+                .into_iter()
+                .map(|match_| (match_.kind, match_.token, match_.col))
+                .collect()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_keywords() {
+        let do_ = Token::Delimiter("do", "end do");
+        let if_ = Token::Delimiter("if", "end if");
+        let paren = Token::Delimiter("(", ")");
+        assert_eq!(
+            parse(
+                "fortran",
+                "do i = 1, n\n  if (i > 1) then\n    x = 'end if ( ''do'''\n  endif\nend do"
+            ),
+            vec![
+                vec![(Kind::Opening, do_.clone(), 0)],
+                vec![
+                    (Kind::Opening, if_.clone(), 2),
+                    (Kind::Opening, paren.clone(), 5),
+                    (Kind::Closing, paren, 11)
+                ],
+                vec![
+                    (Kind::Opening, Token::String("'"), 8),
+                    (Kind::Closing, Token::String("'"), 24)
+                ],
+                vec![(Kind::Closing, if_, 2)],
+                vec![(Kind::Closing, do_, 0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fixed_form_comments() {
+        let text = "C     comment (\n      call foo()\n* comment [";
+        assert_eq!(
+            parse("fortran_fixed", text),
+            vec![
+                vec![(Kind::NonPair, Token::LineComment("C"), 0)],
+                vec![
+                    (Kind::Opening, Token::Delimiter("(", ")"), 14),
+                    (Kind::Closing, Token::Delimiter("(", ")"), 15)
+                ],
+                vec![(Kind::NonPair, Token::LineComment("*"), 0)],
+            ]
+        );
+
+        // Code in free form
+        assert_eq!(parse("fortran", "call foo()")[0].len(), 2);
+    }
+}
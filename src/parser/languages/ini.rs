@@ -0,0 +1,44 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+// Values are free-form text, so only `[section]` headers are matched
+define_matcher!(Ini {
+    delimiters: [
+        "[" => "]"
+    ],
+    line_comment: [";", "#"],
+    string: ["\""]
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_gitconfig() {
+        let string = Token::String("\"");
+        assert_eq!(
+            parse_filetype(
+                "gitconfig",
+                4,
+                &["[alias]", "  lg = \"log \\\"#[\" ; (", "# [x"],
+                State::Normal
+            )
+            .unwrap()
+            .matches_by_line,
+            vec![
+                vec![
+                    Match::delimiter('[', 0, None),
+                    Match::delimiter(']', 6, None)
+                ],
+                vec![
+                    Match::new(Kind::Opening, string.clone(), 7),
+                    Match::new(Kind::Closing, string, 16),
+                    Match::line_comment(";", 18),
+                ],
+                vec![Match::line_comment("#", 0)],
+            ]
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Janet {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: ["#"],
+    string: ["\""],
+    // Long strings close on the same number of backticks, longest first so that they take
+    // priority
+    block_string: [
+        "````" => "````",
+        "```" => "```",
+        "``" => "``",
+        "`" => "`"
+    ]
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_long_strings() {
+        let long_string = Token::BlockString("``", "``");
+        assert_eq!(
+            parse_filetype("janet", 4, &["(print ``a ` (", "``)"], State::Normal)
+                .unwrap()
+                .matches_by_line,
+            vec![
+                vec![
+                    Match::delimiter('(', 0, None),
+                    Match::new(Kind::Opening, long_string.clone(), 7)
+                ],
+                vec![
+                    Match::new(Kind::Closing, long_string, 0),
+                    Match::delimiter(')', 2, None)
+                ]
+            ]
+        );
+    }
+}
@@ -10,5 +10,42 @@ define_matcher!(JavaScript {
     line_comment: ["//"],
     block_comment: ["/*" => "*/"],
     string: ["\"", "'"],
+    regex: ["/"],
     block_string: ["`" => "`"]
 });
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    fn parse(text: &str) -> Vec<Match> {
+        parse_filetype("javascript", 4, &[text], State::Normal)
+            .unwrap()
+            .matches_by_line
+            .remove(0)
+    }
+
+    #[test]
+    fn test_regex() {
+        let regex = Token::String("/");
+        assert_eq!(
+            parse("f(/[/(]+/g)"),
+            vec![
+                Match::delimiter('(', 1, None),
+                Match::new(Kind::Opening, regex.clone(), 2),
+                Match::new(Kind::Closing, regex, 8),
+                Match::delimiter(')', 10, None),
+            ]
+        );
+        assert_eq!(
+            parse("a[0] / b / (c)"),
+            vec![
+                Match::delimiter('[', 1, None),
+                Match::delimiter(']', 3, None),
+                Match::delimiter('(', 11, None),
+                Match::delimiter(')', 13, None),
+            ]
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use crate::parser::*;
 use matcher_macros::define_matcher;
 
+// Apostrophes, i.e. "don't"
+const AUTO_PAIR_EXCLUSIONS: &[AutoPairExclusion] =
+    &[AutoPairExclusion::new("'", ExclusionContext::AfterWord)];
+
 define_matcher!(Markdown {
     inline_span: {
         math: "$" => "$",
@@ -13,5 +17,6 @@ define_matcher!(Markdown {
         math: "$$" => "$$",
         code: "```" => "```"
     },
+    auto_pair_exclusions: AUTO_PAIR_EXCLUSIONS
 });
 
@@ -1,73 +1,189 @@
+#[cfg(feature = "lang-blade")]
+mod blade;
+#[cfg(feature = "lang-c")]
 mod c;
+#[cfg(feature = "lang-clojure")]
 mod clojure;
+#[cfg(feature = "lang-cpp")]
 mod cpp;
+#[cfg(feature = "lang-csharp")]
 mod csharp;
+#[cfg(feature = "lang-css")]
+mod css;
+#[cfg(feature = "lang-dart")]
 mod dart;
+#[cfg(feature = "lang-diff")]
+mod diff;
+#[cfg(feature = "lang-elixir")]
 mod elixir;
+#[cfg(feature = "lang-erlang")]
 mod erlang;
+#[cfg(feature = "lang-fennel")]
+mod fennel;
+#[cfg(feature = "lang-fortran")]
+mod fortran;
+#[cfg(feature = "lang-fsharp")]
 mod fsharp;
+#[cfg(feature = "lang-go")]
 mod go;
+#[cfg(feature = "lang-haskell")]
 mod haskell;
+#[cfg(feature = "lang-haxe")]
 mod haxe;
+#[cfg(feature = "lang-ini")]
+mod ini;
+#[cfg(feature = "lang-janet")]
+mod janet;
+#[cfg(feature = "lang-java")]
 mod java;
+#[cfg(feature = "lang-javascript")]
 mod javascript;
+#[cfg(feature = "lang-json")]
 mod json;
+#[cfg(feature = "lang-kotlin")]
 mod kotlin;
+#[cfg(feature = "lang-latex")]
 mod latex;
+#[cfg(feature = "lang-lean")]
 mod lean;
+#[cfg(feature = "lang-lua")]
 mod lua;
+#[cfg(feature = "lang-markdown")]
 mod markdown;
+#[cfg(feature = "lang-nix")]
 mod nix;
+#[cfg(feature = "lang-objc")]
 mod objc;
+#[cfg(feature = "lang-ocaml")]
 mod ocaml;
+#[cfg(feature = "lang-perl")]
 mod perl;
+#[cfg(feature = "lang-php")]
 mod php;
+#[cfg(feature = "lang-prolog")]
+mod prolog;
+#[cfg(feature = "lang-prose")]
+mod prose;
+#[cfg(feature = "lang-python")]
 mod python;
+#[cfg(feature = "lang-r")]
 mod r;
+#[cfg(feature = "lang-ruby")]
 mod ruby;
+#[cfg(feature = "lang-rust")]
 mod rust;
+#[cfg(feature = "lang-scala")]
 mod scala;
+#[cfg(feature = "lang-shell")]
 mod shell;
+#[cfg(feature = "lang-smalltalk")]
+mod smalltalk;
+#[cfg(feature = "lang-sql")]
 mod sql;
+#[cfg(feature = "lang-swift")]
 mod swift;
+#[cfg(feature = "lang-toml")]
 mod toml;
+#[cfg(feature = "lang-twig")]
+mod twig;
+#[cfg(feature = "lang-typst")]
 mod typst;
+#[cfg(feature = "lang-vim")]
 mod vim;
+#[cfg(feature = "lang-zig")]
 mod zig;
 
+#[cfg(feature = "lang-blade")]
+pub use blade::Blade;
+#[cfg(feature = "lang-c")]
 pub use c::C;
+#[cfg(feature = "lang-clojure")]
 pub use clojure::Clojure;
+#[cfg(feature = "lang-cpp")]
 pub use cpp::Cpp;
+#[cfg(feature = "lang-csharp")]
 pub use csharp::CSharp;
+#[cfg(feature = "lang-css")]
+pub use css::{Css, Less, Scss};
+#[cfg(feature = "lang-dart")]
 pub use dart::Dart;
+#[cfg(feature = "lang-diff")]
+pub use diff::Diff;
+#[cfg(feature = "lang-elixir")]
 pub use elixir::Elixir;
+#[cfg(feature = "lang-erlang")]
 pub use erlang::Erlang;
+#[cfg(feature = "lang-fennel")]
+pub use fennel::Fennel;
+#[cfg(feature = "lang-fortran")]
+pub use fortran::{Fortran, FortranFixed};
+#[cfg(feature = "lang-fsharp")]
 pub use fsharp::FSharp;
+#[cfg(feature = "lang-go")]
 pub use go::Go;
+#[cfg(feature = "lang-haskell")]
 pub use haskell::Haskell;
+#[cfg(feature = "lang-haxe")]
 pub use haxe::Haxe;
+#[cfg(feature = "lang-ini")]
+pub use ini::Ini;
+#[cfg(feature = "lang-janet")]
+pub use janet::Janet;
+#[cfg(feature = "lang-java")]
 pub use java::Java;
+#[cfg(feature = "lang-javascript")]
 pub use javascript::JavaScript;
+#[cfg(feature = "lang-json")]
 pub use json::Json;
+#[cfg(feature = "lang-kotlin")]
 pub use kotlin::Kotlin;
+#[cfg(feature = "lang-latex")]
 pub use latex::Latex;
+#[cfg(feature = "lang-lean")]
 pub use lean::Lean;
+#[cfg(feature = "lang-lua")]
 pub use lua::Lua;
+#[cfg(feature = "lang-markdown")]
 pub use markdown::Markdown;
+#[cfg(feature = "lang-nix")]
 pub use nix::Nix;
+#[cfg(feature = "lang-objc")]
 pub use objc::ObjC;
+#[cfg(feature = "lang-ocaml")]
 pub use ocaml::OCaml;
+#[cfg(feature = "lang-perl")]
 pub use perl::Perl;
+#[cfg(feature = "lang-php")]
 pub use php::Php;
+#[cfg(feature = "lang-prolog")]
+pub use prolog::Prolog;
+#[cfg(feature = "lang-prose")]
+pub use prose::{Prose, VimHelp};
+#[cfg(feature = "lang-python")]
 pub use python::Python;
+#[cfg(feature = "lang-r")]
 pub use r::R;
+#[cfg(feature = "lang-ruby")]
 pub use ruby::Ruby;
+#[cfg(feature = "lang-rust")]
 pub use rust::Rust;
+#[cfg(feature = "lang-scala")]
 pub use scala::Scala;
+#[cfg(feature = "lang-shell")]
 pub use shell::Shell;
+#[cfg(feature = "lang-smalltalk")]
+pub use smalltalk::Smalltalk;
+#[cfg(feature = "lang-sql")]
 pub use sql::Sql;
+#[cfg(feature = "lang-swift")]
 pub use swift::Swift;
+#[cfg(feature = "lang-toml")]
 pub use toml::Toml;
+#[cfg(feature = "lang-twig")]
+pub use twig::Twig;
+#[cfg(feature = "lang-typst")]
 pub use typst::Typst;
+#[cfg(feature = "lang-vim")]
 pub use vim::Vim;
+#[cfg(feature = "lang-zig")]
 pub use zig::Zig;
@@ -8,6 +8,53 @@ define_matcher!(Php {
         "{" => "}"
     ],
     line_comment: ["//", "#"],
+    // Attributes, i.e. `#[Route("/")]`
+    not_comment: ["#["],
     block_comment: ["/*" => "*/"],
-    string: ["\"", "'"]
+    string: ["\"", "'"],
+    interpolation: [
+        "\"": "{$" => "}",
+        "\"": "${" => "}"
+    ]
 });
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    fn parse(text: &str) -> Vec<Match> {
+        parse_filetype("php", 4, &[text], State::Normal)
+            .unwrap()
+            .matches_by_line
+            .remove(0)
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let string = Token::String("\"");
+        assert_eq!(
+            parse(r#""a {$b} ${c} \{$d}""#),
+            vec![
+                Match::new(Kind::Opening, string.clone(), 0),
+                Match::delimiter('{', 3, None),
+                Match::delimiter('}', 6, None),
+                Match::delimiter('{', 9, None),
+                Match::delimiter('}', 11, None),
+                Match::new(Kind::Closing, string, 18),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attribute() {
+        assert_eq!(
+            parse("#[Attr] # (comment"),
+            vec![
+                Match::delimiter('[', 1, None),
+                Match::delimiter(']', 6, None),
+                Match::line_comment("#", 8),
+            ]
+        );
+    }
+}
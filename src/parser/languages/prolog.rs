@@ -0,0 +1,41 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Prolog {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    line_comment: ["%"],
+    block_comment: ["/*" => "*/"],
+    // Quoted atoms are treated as strings
+    string: ["\"", "'", "`"],
+    // Character codes such as `0'(`
+    char_prefix: ["0'"]
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Match, State};
+    use pretty_assertions::assert_eq;
+
+    fn parse(text: &str) -> Vec<Match> {
+        parse_filetype("prolog", 4, &[text], State::Normal)
+            .unwrap()
+            .matches_by_line
+            .remove(0)
+    }
+
+    #[test]
+    fn test_char_codes() {
+        assert_eq!(
+            parse("open(X) :- X = 0'(."),
+            vec![
+                Match::delimiter('(', 4, None),
+                Match::delimiter(')', 6, None)
+            ]
+        );
+        assert_eq!(parse("X = 0''"), vec![]);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+// Apostrophes, i.e. "don't"
+const AUTO_PAIR_EXCLUSIONS: &[AutoPairExclusion] =
+    &[AutoPairExclusion::new("'", ExclusionContext::AfterWord)];
+
+// Parentheses in prose are often unbalanced, i.e. "1) first", so only explicit code spans are
+// matched
+define_matcher!(Prose {
+    inline_span: {
+        code: "`" => "`"
+    },
+    auto_pair_exclusions: AUTO_PAIR_EXCLUSIONS
+});
+
+define_matcher!(VimHelp {
+    inline_span: {
+        code: "`" => "`",
+        tag: "|" => "|"
+    },
+    auto_pair_exclusions: AUTO_PAIR_EXCLUSIONS
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_help() {
+        let tag = Token::InlineSpan("tag", "|", "|");
+        assert_eq!(
+            parse_filetype("help", 4, &["1) See |foo()| (or"], State::Normal)
+                .unwrap()
+                .matches_by_line,
+            vec![vec![
+                Match::new(Kind::Opening, tag.clone(), 7),
+                Match::new(Kind::Closing, tag, 13),
+            ]]
+        );
+    }
+}
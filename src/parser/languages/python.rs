@@ -10,5 +10,6 @@ define_matcher!(Python {
     line_comment: ["#"],
     block_comment: [],
     string: ["\"", "'"],
-    block_string: ["\"\"\"" => "\"\"\"", "'''" => "'''"]
+    block_string: ["\"\"\"" => "\"\"\"", "'''" => "'''"],
+    docstring: ["\"\"\"", "'''"]
 });
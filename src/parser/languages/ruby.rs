@@ -9,5 +9,6 @@ define_matcher!(Ruby {
     ],
     line_comment: ["#"],
     block_comment: ["=begin" => "end"],
-    string: ["\"", "'"]
+    string: ["\"", "'"],
+    regex: ["/"]
 });
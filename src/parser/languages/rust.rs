@@ -1,6 +1,12 @@
 use crate::parser::*;
 use matcher_macros::define_matcher;
 
+// Generics follow a name, i.e. `Vec<T>` and `fn f<T>`, while comparisons are surrounded by spaces
+const AUTO_PAIR_EXCLUSIONS: &[AutoPairExclusion] = &[AutoPairExclusion::new(
+    "<",
+    ExclusionContext::AfterWhitespace,
+)];
+
 define_matcher!(Rust {
     delimiters: [
         "(" => ")",
@@ -15,5 +21,89 @@ define_matcher!(Rust {
         "r#\"" => "\"#",
         "r##\"" => "\"##",
         "r###\"" => "\"###"
-    ]
+    ],
+    auto_pair_exclusions: AUTO_PAIR_EXCLUSIONS
 });
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{filetype_token_sets, parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    fn delimiters(text: &str) -> Vec<(&'static str, usize)> {
+        parse_filetype("rust", 4, &[text], State::Normal)
+            .unwrap()
+            .matches_by_line
+            .remove(0)
+            .into_iter()
+            .filter(|match_| matches!(match_.token, Token::Delimiter(_, _)))
+            .map(|match_| match match_.kind {
+                Kind::Opening => (match_.token.opening(), match_.col),
+                _ => (match_.token.closing().unwrap(), match_.col),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_token_sets() {
+        let token_sets = filetype_token_sets("rust").unwrap();
+        assert_eq!(token_sets.delimiters, &[("(", ")"), ("[", "]"), ("{", "}")]);
+        assert_eq!(token_sets.strings, &["'"]);
+        assert_eq!(
+            token_sets.block_strings[..2],
+            [("\"", "\""), ("r#\"", "\"#")]
+        );
+        assert_eq!(token_sets.line_comments, &["//"]);
+        assert_eq!(token_sets.block_comments, &[("/*", "*/")]);
+        assert_eq!(token_sets.inline_spans, &[]);
+        assert_eq!(filetype_token_sets("unknown"), None);
+    }
+
+    #[test]
+    fn test_attributes() {
+        assert_eq!(
+            delimiters("#![allow(x)] #[doc = \"(\"] let c = '#';"),
+            vec![
+                ("[", 2),
+                ("(", 8),
+                (")", 10),
+                ("]", 11),
+                ("[", 14),
+                ("]", 24)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_macro_repetition() {
+        assert_eq!(
+            delimiters("($($x:expr),*) => { $(f(r#type, '$');)* }"),
+            vec![
+                ("(", 0),
+                ("(", 2),
+                (")", 10),
+                (")", 13),
+                ("{", 18),
+                ("(", 21),
+                ("(", 23),
+                (")", 35),
+                (")", 37),
+                ("}", 40)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        assert_eq!(
+            parse_filetype("rust", 4, &["r#\"(\"# ["], State::Normal)
+                .unwrap()
+                .matches_by_line[0],
+            vec![
+                Match::new(Kind::Opening, Token::BlockString("r#\"", "\"#"), 0),
+                Match::new(Kind::Closing, Token::BlockString("r#\"", "\"#"), 4),
+                Match::delimiter('[', 7, None)
+            ]
+        );
+    }
+}
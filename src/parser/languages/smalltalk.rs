@@ -0,0 +1,44 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Smalltalk {
+    delimiters: [
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    // Double quotes are comments rather than strings
+    block_comment: ["\"" => "\""],
+    doubled_string: ["'"],
+    // Character literals such as `$(`
+    char_prefix: ["$"]
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    fn parse(lines: &[&str]) -> Vec<Vec<Match>> {
+        parse_filetype("st", 4, lines, State::Normal)
+            .unwrap()
+            .matches_by_line
+    }
+
+    #[test]
+    fn test_comments_and_strings() {
+        let comment = Token::BlockComment("\"", "\"");
+        let string = Token::String("'");
+        assert_eq!(
+            parse(&["\"a ( comment", "\" x := 'it''s ['. y := $("]),
+            vec![
+                vec![Match::new(Kind::Opening, comment.clone(), 0)],
+                vec![
+                    Match::new(Kind::Closing, comment, 0),
+                    Match::new(Kind::Opening, string.clone(), 7),
+                    Match::new(Kind::Closing, string, 15),
+                ]
+            ]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use crate::parser::*;
+use matcher_macros::define_matcher;
+
+define_matcher!(Twig {
+    delimiters: [
+        "{{" => "}}",
+        "{%" => "%}",
+        "(" => ")",
+        "[" => "]",
+        "{" => "}"
+    ],
+    block_comment: ["{#" => "#}"]
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_filetype, Kind, Match, State, Token};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_tags() {
+        let tag = Token::Delimiter("{%", "%}");
+        let expr = Token::Delimiter("{{", "}}");
+        assert_eq!(
+            parse_filetype("twig", 4, &["{% if a %}{{ b(1) }}{# ( #}"], State::Normal)
+                .unwrap()
+                .matches_by_line,
+            vec![vec![
+                Match::new(Kind::Opening, tag.clone(), 0),
+                Match::new(Kind::Closing, tag, 8),
+                Match::new(Kind::Opening, expr.clone(), 10),
+                Match::delimiter('(', 14, None),
+                Match::delimiter(')', 16, None),
+                Match::new(Kind::Closing, expr, 18),
+                Match::new(Kind::Opening, Token::BlockComment("{#", "#}"), 20),
+                Match::new(Kind::Closing, Token::BlockComment("{#", "#}"), 25),
+            ]]
+        );
+    }
+}
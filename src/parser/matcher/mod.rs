@@ -1,4 +1,7 @@
+use alloc::vec::Vec;
+use core::ops::Range;
 use itertools::MultiPeek;
+#[cfg(feature = "lua")]
 use mlua::IntoLua;
 
 mod token;
@@ -7,19 +10,37 @@ mod token_type;
 pub use token::*;
 pub use token_type::*;
 
-use crate::parser::{CharPos, State};
+use crate::parser::{AutoPairExclusion, CharPos, Keyword, State};
 
 pub trait Matcher {
     const TOKENS: &[u8];
+    /// Keyword pairs found after matching each line, see [`Keyword`]
+    const KEYWORDS: &[Keyword] = &[];
+    /// Contexts where typing an opening shouldn't insert its closing, see [`AutoPairExclusion`]
+    const AUTO_PAIR_EXCLUSIONS: &[AutoPairExclusion] = &[];
+    /// Texts of the tokens by their type, see [`TokenSets`]
+    const TOKEN_SETS: TokenSets = TokenSets::EMPTY;
     #[inline(always)]
     fn tokens(&self) -> &'static [u8] {
         Self::TOKENS
     }
 
+    fn auto_pair_exclusions(&self) -> &'static [AutoPairExclusion] {
+        Self::AUTO_PAIR_EXCLUSIONS
+    }
+
+    fn token_sets(&self) -> TokenSets {
+        Self::TOKEN_SETS
+    }
+
+    /// Pushes the matches of the token to `matches`, and the delimiters found inside strings
+    /// and comments to `inner_matches`, see [`crate::parser::parse_inner`]
+    #[allow(clippy::too_many_arguments)]
     fn call<I>(
         &mut self,
-        matches_by_line: &mut Vec<Vec<Match>>,
         matches: &mut Vec<Match>,
+        inner_matches: &mut Vec<Match>,
+        line: &[u8],
         tokens: &mut MultiPeek<I>,
         state: State,
         token: CharPos,
@@ -30,11 +51,15 @@ pub trait Matcher {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Match {
     pub kind: Kind,
     pub token: Token,
     pub col: usize,
     pub stack_height: Option<usize>,
+    /// Length of the source text when it differs from the token's text, such as keywords
+    /// matched with other blanks like `enddo` for `end do`
+    pub source_len: Option<usize>,
 }
 
 impl Match {
@@ -44,6 +69,7 @@ impl Match {
             token,
             col,
             stack_height: None,
+            source_len: None,
         }
     }
 
@@ -53,6 +79,7 @@ impl Match {
             token,
             col,
             stack_height: Some(stack_height),
+            source_len: None,
         }
     }
 
@@ -63,6 +90,7 @@ impl Match {
             line,
             col: self.col,
             stack_height: self.stack_height,
+            source_len: self.source_len,
         }
     }
 
@@ -72,18 +100,34 @@ impl Match {
             token: Token::LineComment(text),
             col,
             stack_height: None,
+            source_len: None,
         }
     }
 
+    /// Canonical text of the token that was matched, i.e. the closing for closings
+    pub fn text(&self) -> &'static str {
+        match_text(self.kind, &self.token)
+    }
+
+    /// Length of the source text in bytes
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
-        match self.kind {
-            Kind::Opening | Kind::NonPair => self.token.opening().len(),
-            Kind::Closing => self
-                .token
-                .closing()
-                .unwrap_or_else(|| self.token.opening())
-                .len(),
-        }
+        self.source_len.unwrap_or(self.text().len())
+    }
+
+    /// Column after the last byte of the token, i.e. 2 for `/*` at column 0
+    pub fn end_col(&self) -> usize {
+        self.col + self.len()
+    }
+
+    /// Columns covered by the token, i.e. `0..2` for `/*` at column 0
+    pub fn range(&self) -> Range<usize> {
+        self.col..self.end_col()
+    }
+
+    /// Gets the exact source text of the match from the line it was parsed from
+    pub fn source<'a>(&self, line: &'a [u8]) -> &'a [u8] {
+        match_source(line, self.col, self.len())
     }
 }
 
@@ -105,6 +149,7 @@ impl Match {
             token,
             col,
             stack_height,
+            source_len: None,
         }
     }
 
@@ -119,10 +164,12 @@ impl Match {
             token,
             col,
             stack_height: None,
+            source_len: None,
         }
     }
 }
 
+#[cfg(feature = "lua")]
 impl IntoLua for Match {
     fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
         let table = lua.create_table()?;
@@ -139,21 +186,66 @@ impl IntoLua for Match {
         }
 
         table.set("col", self.col)?;
+        table.set("len", self.len())?;
+        table.set("end_col", self.end_col())?;
         table.set("stack_height", self.stack_height)?;
 
         (&table).into_lua(lua)
     }
 }
 
+fn match_text(kind: Kind, token: &Token) -> &'static str {
+    match kind {
+        Kind::Opening | Kind::NonPair => token.opening(),
+        Kind::Middle(text) => text,
+        Kind::Closing => token.closing().unwrap_or_else(|| token.opening()),
+    }
+}
+
+fn match_source(line: &[u8], col: usize, len: usize) -> &[u8] {
+    let start = col.min(line.len());
+    &line[start..(col + len).min(line.len())]
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MatchWithLine {
     pub kind: Kind,
     pub token: Token,
     pub line: usize,
     pub col: usize,
     pub stack_height: Option<usize>,
+    pub source_len: Option<usize>,
+}
+
+impl MatchWithLine {
+    /// See [`Match::text`]
+    pub fn text(&self) -> &'static str {
+        match_text(self.kind, &self.token)
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.source_len.unwrap_or(self.text().len())
+    }
+
+    /// See [`Match::end_col`]
+    pub fn end_col(&self) -> usize {
+        self.col + self.len()
+    }
+
+    /// See [`Match::range`]
+    pub fn range(&self) -> Range<usize> {
+        self.col..self.end_col()
+    }
+
+    /// Gets the exact source text of the match from its line
+    pub fn source<'a>(&self, line: &'a [u8]) -> &'a [u8] {
+        match_source(line, self.col, self.len())
+    }
 }
 
+#[cfg(feature = "lua")]
 impl IntoLua for MatchWithLine {
     fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
         let table = lua.create_table()?;
@@ -168,11 +260,55 @@ impl IntoLua for MatchWithLine {
             }
             _ => {}
         }
+        if let Kind::Middle(middle) = self.kind {
+            table.set("middle", middle)?;
+        }
 
         table.set("line", self.line)?;
         table.set("col", self.col)?;
+        table.set("len", self.len())?;
+        table.set("end_col", self.end_col())?;
         table.set("stack_height", self.stack_height)?;
 
         (&table).into_lua(lua)
     }
 }
+
+/// Pairs are `{ opening, closing }` like the matches, with the name of spans as `span`
+#[cfg(feature = "lua")]
+impl IntoLua for TokenSets {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        let pairs = |pairs: &[(&str, &str)]| {
+            lua.create_sequence_from(
+                pairs
+                    .iter()
+                    .map(|(open, close)| lua.create_sequence_from([*open, *close]))
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            )
+        };
+        let spans = |spans: &[(&str, &str, &str)]| {
+            lua.create_sequence_from(
+                spans
+                    .iter()
+                    .map(|(span, open, close)| {
+                        let table = lua.create_sequence_from([*open, *close])?;
+                        table.set("span", *span)?;
+                        Ok(table)
+                    })
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            )
+        };
+
+        let table = lua.create_table()?;
+        table.set("delimiters", pairs(self.delimiters)?)?;
+        table.set("strings", self.strings)?;
+        table.set("block_strings", pairs(self.block_strings)?)?;
+        table.set("regexes", self.regexes)?;
+        table.set("docstrings", self.docstrings)?;
+        table.set("line_comments", self.line_comments)?;
+        table.set("block_comments", pairs(self.block_comments)?)?;
+        table.set("inline_spans", spans(self.inline_spans)?)?;
+        table.set("block_spans", spans(self.block_spans)?)?;
+        (&table).into_lua(lua)
+    }
+}
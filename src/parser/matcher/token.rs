@@ -1,13 +1,18 @@
 // TODO: rework with variants that make more sense for usage
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[repr(u8)]
 pub enum Kind {
     Opening,
     Closing,
     NonPair,
+    /// Belongs to the enclosing pair with the same token, such as `else` in `if`/`end if`,
+    /// with the text of the middle
+    Middle(&'static str),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Token {
     Delimiter(&'static str, &'static str),
 
@@ -24,13 +29,13 @@ pub enum Token {
 impl Token {
     pub fn opening(&self) -> &'static str {
         match self {
-            Token::Delimiter(open, _) => *open,
-            Token::String(open) => *open,
-            Token::BlockString(open, _) => *open,
-            Token::LineComment(open) => *open,
-            Token::BlockComment(open, _) => *open,
-            Token::InlineSpan(_, open, _) => *open,
-            Token::BlockSpan(_, open, _) => *open,
+            Token::Delimiter(open, _) => open,
+            Token::String(open) => open,
+            Token::BlockString(open, _) => open,
+            Token::LineComment(open) => open,
+            Token::BlockComment(open, _) => open,
+            Token::InlineSpan(_, open, _) => open,
+            Token::BlockSpan(_, open, _) => open,
         }
     }
 
@@ -46,3 +51,37 @@ impl Token {
         }
     }
 }
+
+/// Texts of a matcher's tokens by their type, as declared with `define_matcher!`, i.e. for
+/// choosing the comment string of a filetype, see [`crate::parser::filetype_token_sets`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TokenSets {
+    pub delimiters: &'static [(&'static str, &'static str)],
+    /// Strings closed by the same text, including chars and regexes
+    pub strings: &'static [&'static str],
+    pub block_strings: &'static [(&'static str, &'static str)],
+    /// Strings which are regexes, also in `strings`
+    pub regexes: &'static [&'static str],
+    /// Openings of the block strings which are docstrings when they start their line
+    pub docstrings: &'static [&'static str],
+    /// Including the comments which must be at the start of the line
+    pub line_comments: &'static [&'static str],
+    pub block_comments: &'static [(&'static str, &'static str)],
+    /// Name, opening and closing of each span, sorted by name
+    pub inline_spans: &'static [(&'static str, &'static str, &'static str)],
+    pub block_spans: &'static [(&'static str, &'static str, &'static str)],
+}
+
+impl TokenSets {
+    pub const EMPTY: TokenSets = TokenSets {
+        delimiters: &[],
+        strings: &[],
+        block_strings: &[],
+        regexes: &[],
+        docstrings: &[],
+        line_comments: &[],
+        block_comments: &[],
+        inline_spans: &[],
+        block_spans: &[],
+    };
+}
@@ -1,64 +1,194 @@
-pub type SimdVec = std::simd::Simd<u8, 16>;
+pub type SimdVec = core::simd::Simd<u8, 16>;
 
+pub mod auto_pair;
 pub mod indent;
+pub mod keyword;
 pub mod languages;
 pub mod matcher;
 pub mod parse;
+pub mod regex;
 pub mod tokenize;
 
+pub use auto_pair::{AutoPairExclusion, ExclusionContext};
 pub use itertools::MultiPeek;
-pub use matcher::{Kind, Match, MatchWithLine, Matcher, Token};
-pub use parse::{parse, State};
-pub use tokenize::{tokenize, CharPos};
+pub use keyword::Keyword;
+pub use matcher::{Kind, Match, MatchWithLine, Matcher, Token, TokenSets};
+pub use parse::{parse, parse_inner, parse_iter, parse_with_tokenizer, ParseIter, State};
+pub use regex::regex_allowed;
+pub use tokenize::{
+    simd_lanes, tokenize, unsupported_simd_features, Backend, CharPos, MemchrTokenizer,
+    SimdTokenizer, SwarTokenizer, Tokenizer,
+};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 
 use crate::buffer::ParsedBuffer;
 
+/// Runs the body with the matcher for the filetype, if the filetype is supported and its
+/// `lang-*` cargo feature is enabled
 #[rustfmt::skip]
-pub fn parse_filetype(
+macro_rules! match_filetype {
+    ($filetype:expr, $matcher:ident => $body:expr) => {
+        match $filetype {
+            #[cfg(feature = "lang-blade")]
+            "blade" => { let $matcher = languages::Blade {}; Some($body) }
+            #[cfg(feature = "lang-c")]
+            "c" => { let $matcher = languages::C {}; Some($body) }
+            #[cfg(feature = "lang-clojure")]
+            "clojure" => { let $matcher = languages::Clojure {}; Some($body) }
+            #[cfg(feature = "lang-cpp")]
+            "cpp" => { let $matcher = languages::Cpp {}; Some($body) }
+            #[cfg(feature = "lang-csharp")]
+            "csharp" => { let $matcher = languages::CSharp {}; Some($body) }
+            #[cfg(feature = "lang-css")]
+            "css" => { let $matcher = languages::Css {}; Some($body) }
+            #[cfg(feature = "lang-css")]
+            "scss" => { let $matcher = languages::Scss {}; Some($body) }
+            #[cfg(feature = "lang-css")]
+            "less" => { let $matcher = languages::Less {}; Some($body) }
+            #[cfg(feature = "lang-dart")]
+            "dart" => { let $matcher = languages::Dart {}; Some($body) }
+            #[cfg(feature = "lang-diff")]
+            "diff" => { let $matcher = languages::Diff {}; Some($body) }
+            #[cfg(feature = "lang-elixir")]
+            "elixir" => { let $matcher = languages::Elixir {}; Some($body) }
+            #[cfg(feature = "lang-erlang")]
+            "erlang" => { let $matcher = languages::Erlang {}; Some($body) }
+            #[cfg(feature = "lang-fennel")]
+            "fennel" => { let $matcher = languages::Fennel {}; Some($body) }
+            #[cfg(feature = "lang-fortran")]
+            "fortran" => { let $matcher = languages::Fortran {}; Some($body) }
+            #[cfg(feature = "lang-fortran")]
+            "fortran_fixed" => { let $matcher = languages::FortranFixed {}; Some($body) }
+            #[cfg(feature = "lang-fsharp")]
+            "fsharp" => { let $matcher = languages::FSharp {}; Some($body) }
+            #[cfg(feature = "lang-go")]
+            "go" => { let $matcher = languages::Go {}; Some($body) }
+            #[cfg(feature = "lang-haskell")]
+            "haskell" => { let $matcher = languages::Haskell {}; Some($body) }
+            #[cfg(feature = "lang-haxe")]
+            "haxe" => { let $matcher = languages::Haxe {}; Some($body) }
+            #[cfg(feature = "lang-ini")]
+            "dosini" | "confini" | "ini" | "gitconfig" => { let $matcher = languages::Ini {}; Some($body) }
+            #[cfg(feature = "lang-janet")]
+            "janet" => { let $matcher = languages::Janet {}; Some($body) }
+            #[cfg(feature = "lang-java")]
+            "java" => { let $matcher = languages::Java {}; Some($body) }
+            #[cfg(feature = "lang-javascript")]
+            "typescript" | "javascript" | "typescriptreact" | "javascriptreact" => { let $matcher = languages::JavaScript {}; Some($body) }
+            #[cfg(feature = "lang-json")]
+            "json" => { let $matcher = languages::Json {}; Some($body) }
+            #[cfg(feature = "lang-kotlin")]
+            "kotlin" => { let $matcher = languages::Kotlin {}; Some($body) }
+            #[cfg(feature = "lang-latex")]
+            "latex" | "tex" | "bib" => { let $matcher = languages::Latex {}; Some($body) }
+            #[cfg(feature = "lang-lean")]
+            "lean" => { let $matcher = languages::Lean {}; Some($body) }
+            #[cfg(feature = "lang-lua")]
+            "lua" => { let $matcher = languages::Lua {}; Some($body) }
+            #[cfg(feature = "lang-markdown")]
+            "markdown" => { let $matcher = languages::Markdown {}; Some($body) }
+            #[cfg(feature = "lang-nix")]
+            "nix" => { let $matcher = languages::Nix {}; Some($body) }
+            #[cfg(feature = "lang-objc")]
+            "objc" => { let $matcher = languages::ObjC {}; Some($body) }
+            #[cfg(feature = "lang-ocaml")]
+            "ocaml" => { let $matcher = languages::OCaml {}; Some($body) }
+            #[cfg(feature = "lang-perl")]
+            "perl" => { let $matcher = languages::Perl {}; Some($body) }
+            #[cfg(feature = "lang-php")]
+            "php" => { let $matcher = languages::Php {}; Some($body) }
+            #[cfg(feature = "lang-prolog")]
+            "prolog" => { let $matcher = languages::Prolog {}; Some($body) }
+            #[cfg(feature = "lang-prose")]
+            "text" | "man" => { let $matcher = languages::Prose {}; Some($body) }
+            #[cfg(feature = "lang-prose")]
+            "help" => { let $matcher = languages::VimHelp {}; Some($body) }
+            #[cfg(feature = "lang-python")]
+            "python" => { let $matcher = languages::Python {}; Some($body) }
+            #[cfg(feature = "lang-r")]
+            "r" => { let $matcher = languages::R {}; Some($body) }
+            #[cfg(feature = "lang-ruby")]
+            "ruby" => { let $matcher = languages::Ruby {}; Some($body) }
+            #[cfg(feature = "lang-rust")]
+            "rust" => { let $matcher = languages::Rust {}; Some($body) }
+            #[cfg(feature = "lang-scala")]
+            "scala" => { let $matcher = languages::Scala {}; Some($body) }
+            #[cfg(feature = "lang-shell")]
+            "shell" => { let $matcher = languages::Shell {}; Some($body) }
+            #[cfg(feature = "lang-smalltalk")]
+            "st" | "smalltalk" => { let $matcher = languages::Smalltalk {}; Some($body) }
+            #[cfg(feature = "lang-sql")]
+            "sql" => { let $matcher = languages::Sql {}; Some($body) }
+            #[cfg(feature = "lang-swift")]
+            "swift" => { let $matcher = languages::Swift {}; Some($body) }
+            #[cfg(feature = "lang-toml")]
+            "toml" => { let $matcher = languages::Toml {}; Some($body) }
+            #[cfg(feature = "lang-twig")]
+            "twig" => { let $matcher = languages::Twig {}; Some($body) }
+            #[cfg(feature = "lang-typst")]
+            "typst" => { let $matcher = languages::Typst {}; Some($body) }
+            #[cfg(feature = "lang-vim")]
+            "vim" => { let $matcher = languages::Vim {}; Some($body) }
+            #[cfg(feature = "lang-zig")]
+            "zig" => { let $matcher = languages::Zig {}; Some($body) }
+
+            _ => None,
+        }
+    };
+}
+
+/// Parses the lines with the matcher for the filetype, if the filetype is supported and its
+/// `lang-*` cargo feature is enabled
+#[cfg_attr(not(feature = "all-languages"), allow(unused_variables))]
+pub fn parse_filetype<L: AsRef<[u8]>>(
     filetype: &str,
     tab_width: u8,
-    lines: &[&str],
+    lines: &[L],
     initial_state: State,
 ) -> Option<ParsedBuffer> {
-    match filetype {
-        "c" => Some(parse(tab_width, lines, initial_state, languages::C {})),
-        "clojure" => Some(parse(tab_width, lines, initial_state, languages::Clojure {})),
-        "cpp" => Some(parse(tab_width, lines, initial_state, languages::Cpp {})),
-        "csharp" => Some(parse(tab_width, lines, initial_state, languages::CSharp {})),
-        "dart" => Some(parse(tab_width, lines, initial_state, languages::Dart {})),
-        "elixir" => Some(parse(tab_width, lines, initial_state, languages::Elixir {})),
-        "erlang" => Some(parse(tab_width, lines, initial_state, languages::Erlang {})),
-        "fsharp" => Some(parse(tab_width, lines, initial_state, languages::FSharp {})),
-        "go" => Some(parse(tab_width, lines, initial_state, languages::Go {})),
-        "haskell" => Some(parse(tab_width, lines, initial_state, languages::Haskell {})),
-        "haxe" => Some(parse(tab_width, lines, initial_state, languages::Haxe {})),
-        "java" => Some(parse(tab_width, lines, initial_state, languages::Java {})),
-        "typescript" | "javascript" | "typescriptreact" | "javascriptreact" =>
-            Some(parse(tab_width, lines, initial_state, languages::JavaScript {})),
-        "json" => Some(parse(tab_width, lines, initial_state, languages::Json {})),
-        "kotlin" => Some(parse(tab_width, lines, initial_state, languages::Kotlin {})),
-        "latex" | "tex" | "bib" => Some(parse(tab_width, lines, initial_state, languages::Latex {})),
-        "lean" => Some(parse(tab_width, lines, initial_state, languages::Lean {})),
-        "lua" => Some(parse(tab_width, lines, initial_state, languages::Lua {})),
-        "markdown" => Some(parse(tab_width, lines, initial_state, languages::Markdown {})),
-        "nix" => Some(parse(tab_width, lines, initial_state, languages::Nix {})),
-        "objc" => Some(parse(tab_width, lines, initial_state, languages::ObjC {})),
-        "ocaml" => Some(parse(tab_width, lines, initial_state, languages::OCaml {})),
-        "perl" => Some(parse(tab_width, lines, initial_state, languages::Perl {})),
-        "php" => Some(parse(tab_width, lines, initial_state, languages::Php {})),
-        "python" => Some(parse(tab_width, lines, initial_state, languages::Python {})),
-        "r" => Some(parse(tab_width, lines, initial_state, languages::R {})),
-        "ruby" => Some(parse(tab_width, lines, initial_state, languages::Ruby {})),
-        "rust" => Some(parse(tab_width, lines, initial_state, languages::Rust {})),
-        "scala" => Some(parse(tab_width, lines, initial_state, languages::Scala {})),
-        "shell" => Some(parse(tab_width, lines, initial_state, languages::Shell {})),
-        "sql" => Some(parse(tab_width, lines, initial_state, languages::Sql {})),
-        "swift" => Some(parse(tab_width, lines, initial_state, languages::Swift {})),
-        "toml" => Some(parse(tab_width, lines, initial_state, languages::Toml {})),
-        "typst" => Some(parse(tab_width, lines, initial_state, languages::Typst {})),
-        "vim" => Some(parse(tab_width, lines, initial_state, languages::Vim {})),
-        "zig" => Some(parse(tab_width, lines, initial_state, languages::Zig {})),
+    match_filetype!(filetype, matcher => parse(tab_width, lines, initial_state, matcher))
+}
+
+/// Same as [`parse_iter`] but with the matcher for the filetype
+#[cfg_attr(not(feature = "all-languages"), allow(unused_variables))]
+pub fn parse_filetype_iter<'a, I>(
+    filetype: &str,
+    lines: I,
+    initial_state: State,
+) -> Option<Box<dyn Iterator<Item = (Vec<Match>, State)> + 'a>>
+where
+    I: IntoIterator + 'a,
+    I::Item: AsRef<[u8]>,
+{
+    match_filetype!(filetype, matcher => Box::new(parse_iter(lines, initial_state, matcher)) as Box<dyn Iterator<Item = _>>)
+}
+
+/// Same as [`parse_inner`] but with the matcher for the filetype
+#[cfg_attr(not(feature = "all-languages"), allow(unused_variables))]
+pub fn parse_filetype_inner<L: AsRef<[u8]>>(
+    filetype: &str,
+    lines: &[L],
+    initial_state: State,
+) -> Option<Vec<Vec<Match>>> {
+    match_filetype!(filetype, matcher => parse_inner(lines, initial_state, matcher))
+}
+
+/// Gets the contexts where the filetype's openings shouldn't be auto-paired, see
+/// [`AutoPairExclusion`]
+pub fn filetype_auto_pair_exclusions(filetype: &str) -> &'static [AutoPairExclusion] {
+    match_filetype!(filetype, matcher => matcher.auto_pair_exclusions()).unwrap_or(&[])
+}
+
+/// Gets the texts of the filetype's strings, comments, spans and delimiters, see
+/// [`TokenSets`]
+pub fn filetype_token_sets(filetype: &str) -> Option<TokenSets> {
+    match_filetype!(filetype, matcher => matcher.token_sets())
+}
 
-        _ => None,
-    }
+/// Gets the bytes which the tokenizer must find for the filetype's matcher, for use with
+/// [`tokenize`] or any [`Tokenizer`]
+pub fn filetype_tokens(filetype: &str) -> Option<&'static [u8]> {
+    match_filetype!(filetype, matcher => matcher.tokens())
 }
@@ -1,10 +1,21 @@
+use alloc::vec;
+use alloc::vec::Vec;
 use itertools::Itertools;
 
-use crate::{buffer::ParsedBuffer, parser::indent::indent_levels};
+use crate::{
+    buffer::{line_hashes, DepthLimit, ParsedBuffer},
+    history::History,
+    parser::indent::{blank_indent, indent_levels},
+};
 
-use super::{matcher::Matcher, tokenize::tokenize};
+use super::{
+    keyword::match_keywords,
+    matcher::{Match, Matcher},
+    tokenize::{Backend, MemchrTokenizer, SimdTokenizer, SwarTokenizer, Tokenizer},
+};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum State {
     Normal,
     InString(&'static str),
@@ -13,35 +24,73 @@ pub enum State {
     InBlockComment(&'static str),
     InInlineSpan(&'static str),
     InBlockSpan(&'static str),
+    /// In a character class (`[...]`) of a regex literal, where `/` doesn't close the regex
+    InRegexClass(&'static str),
+    /// In the interpolation of a string, i.e. `{$expr}` in PHP, with the string's delimiter
+    InInterpolation(&'static str),
+}
+
+impl State {
+    /// Gets the state carried over to the next line, where strings, line comments and other
+    /// single line constructs end
+    pub fn at_line_end(self) -> State {
+        match self {
+            State::InString(_)
+            | State::InRegexClass(_)
+            | State::InInterpolation(_)
+            | State::InLineComment
+            | State::InInlineSpan(_) => State::Normal,
+            state => state,
+        }
+    }
 }
 
 /// Given a matcher, runs the tokenizer on the lines and keeps track
-/// of the state and matches for each line
-pub fn parse<M: Matcher>(
+/// of the state and matches for each line. Lines may be `&str`, `String` or raw bytes,
+/// where the columns are always byte offsets into the line.
+///
+/// Uses the tokenizer selected by [`Backend::set`]
+pub fn parse<M: Matcher, L: AsRef<[u8]>>(
     tab_width: u8,
-    lines: &[&str],
+    lines: &[L],
+    initial_state: State,
+    matcher: M,
+) -> ParsedBuffer {
+    match Backend::current() {
+        Backend::Simd => {
+            parse_with_tokenizer(tab_width, lines, initial_state, matcher, SimdTokenizer)
+        }
+        Backend::Memchr => {
+            parse_with_tokenizer(tab_width, lines, initial_state, matcher, MemchrTokenizer)
+        }
+        Backend::Swar => {
+            parse_with_tokenizer(tab_width, lines, initial_state, matcher, SwarTokenizer)
+        }
+    }
+}
+
+/// Same as [`parse`] but with the given tokenizer
+pub fn parse_with_tokenizer<M: Matcher, L: AsRef<[u8]>, T: Tokenizer>(
+    tab_width: u8,
+    lines: &[L],
     initial_state: State,
     mut matcher: M,
+    tokenizer: T,
 ) -> ParsedBuffer {
     // State
     let mut matches_by_line = Vec::with_capacity(lines.len());
     let mut line_matches = vec![];
+    // Delimiters inside strings and comments aren't kept, see [`parse_inner`]
+    let mut inner_matches = vec![];
 
     let mut state_by_line = Vec::with_capacity(lines.len());
     let mut state = initial_state;
 
     let mut escaped_col: Option<usize> = None;
 
-    let text = lines.join("\n");
-
-    #[cfg(target_feature = "avx512f")]
-    const N: usize = 64;
-    #[cfg(all(target_feature = "avx2", not(target_feature = "avx512f")))]
-    const N: usize = 32;
-    #[cfg(not(any(target_feature = "avx2", target_feature = "avx512f")))]
-    const N: usize = 16;
+    let text = join_lines(lines);
 
-    let tokens = tokenize::<N>(&text, matcher.tokens());
+    let tokens = tokenizer.tokenize(&text, matcher.tokens());
     let indent_levels = indent_levels(lines, tab_width);
 
     let mut tokens = tokens.multipeek();
@@ -49,16 +98,22 @@ pub fn parse<M: Matcher>(
     while let Some(token) = tokens.next() {
         // New line
         if matches!(token.byte, b'\n') {
+            if !M::KEYWORDS.is_empty() {
+                let line = matches_by_line.len();
+                let line_start_state = state_by_line.last().copied().unwrap_or(initial_state);
+                match_keywords(
+                    M::KEYWORDS,
+                    lines[line].as_ref(),
+                    line_start_state,
+                    &mut line_matches,
+                );
+            }
             matches_by_line.push(line_matches);
             line_matches = vec![];
+            inner_matches.clear();
             escaped_col = None;
 
-            if matches!(
-                state,
-                State::InString(_) | State::InLineComment | State::InInlineSpan(_)
-            ) {
-                state = State::Normal;
-            }
+            state = state.at_line_end();
             state_by_line.push(state);
             continue;
         }
@@ -74,28 +129,212 @@ pub fn parse<M: Matcher>(
             continue;
         }
 
+        let line = lines[matches_by_line.len()].as_ref();
         state = matcher.call(
-            &mut matches_by_line,
             &mut line_matches,
+            &mut inner_matches,
+            line,
             &mut tokens,
             state,
             token,
             escaped_col.map(|col| col == token.col - 1).unwrap_or(false),
         );
     }
+    if !M::KEYWORDS.is_empty() {
+        if let Some(line) = lines.get(matches_by_line.len()) {
+            let line_start_state = state_by_line.last().copied().unwrap_or(initial_state);
+            match_keywords(
+                M::KEYWORDS,
+                line.as_ref(),
+                line_start_state,
+                &mut line_matches,
+            );
+        }
+    }
     matches_by_line.push(line_matches);
-    state_by_line.push(state);
+    state_by_line.push(state.at_line_end());
 
     ParsedBuffer {
+        line_hashes: line_hashes(lines, initial_state, &state_by_line),
+        history: History::default(),
         matches_by_line,
         state_by_line,
         indent_levels,
+        blank_indents: lines
+            .iter()
+            .map(|line| blank_indent(line, tab_width))
+            .collect(),
+        indent_changes: 0..lines.len(),
+        depth_at_line_start: vec![],
+        depth_limit: DepthLimit::DEFAULT,
+    }
+}
+
+/// Lazily parses the lines one at a time, yielding the matches and the state at the end of
+/// each line. Unlike [`parse`], only the current line is held in memory so the lines may come
+/// from a stream. Stack heights and indent levels are not calculated.
+///
+/// Uses the tokenizer selected by [`Backend::set`]
+pub fn parse_iter<M: Matcher, I>(
+    lines: I,
+    initial_state: State,
+    matcher: M,
+) -> ParseIter<M, I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    ParseIter {
+        lines: lines.into_iter(),
+        state: initial_state,
+        matcher,
+    }
+}
+
+/// Iterator returned by [`parse_iter`]
+pub struct ParseIter<M, I> {
+    lines: I,
+    state: State,
+    matcher: M,
+}
+
+impl<M, I> ParseIter<M, I> {
+    /// Gets the state at the end of the last yielded line
+    pub fn state(&self) -> State {
+        self.state
     }
 }
 
+impl<M: Matcher, I> Iterator for ParseIter<M, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = (Vec<Match>, State);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        let line = line.as_ref();
+        let matcher = &mut self.matcher;
+        let state = self.state;
+
+        let (line_matches, state) = parse_line(line, state, matcher, &mut vec![]);
+        let state = state.at_line_end();
+        self.state = state;
+
+        Some((line_matches, state))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.lines.size_hint()
+    }
+}
+
+/// Finds the delimiters inside the strings and comments of each line, such as the brackets in
+/// doc comments, which are matched apart from the code. Their stack heights are not
+/// calculated, see [`ParsedBuffer::inner_matches`]
+///
+/// Uses the tokenizer selected by [`Backend::set`]
+pub fn parse_inner<M: Matcher, L: AsRef<[u8]>>(
+    lines: &[L],
+    initial_state: State,
+    mut matcher: M,
+) -> Vec<Vec<Match>> {
+    let mut state = initial_state;
+    lines
+        .iter()
+        .map(|line| {
+            let mut inner_matches = vec![];
+            state = parse_line(line.as_ref(), state, &mut matcher, &mut inner_matches)
+                .1
+                .at_line_end();
+            inner_matches
+        })
+        .collect()
+}
+
+fn parse_line<M: Matcher>(
+    line: &[u8],
+    state: State,
+    matcher: &mut M,
+    inner_matches: &mut Vec<Match>,
+) -> (Vec<Match>, State) {
+    match Backend::current() {
+        Backend::Simd => {
+            parse_line_with_tokenizer(line, state, matcher, inner_matches, SimdTokenizer)
+        }
+        Backend::Memchr => {
+            parse_line_with_tokenizer(line, state, matcher, inner_matches, MemchrTokenizer)
+        }
+        Backend::Swar => {
+            parse_line_with_tokenizer(line, state, matcher, inner_matches, SwarTokenizer)
+        }
+    }
+}
+
+fn parse_line_with_tokenizer<M: Matcher, T: Tokenizer>(
+    line: &[u8],
+    initial_state: State,
+    matcher: &mut M,
+    inner_matches: &mut Vec<Match>,
+    tokenizer: T,
+) -> (Vec<Match>, State) {
+    let mut line_matches = vec![];
+    let mut state = initial_state;
+    let mut escaped_col: Option<usize> = None;
+
+    let mut tokens = tokenizer.tokenize(line, matcher.tokens()).multipeek();
+    while let Some(token) = tokens.next() {
+        if matches!(token.byte, b'\\') {
+            if let Some(col) = escaped_col {
+                if col == token.col - 1 {
+                    escaped_col = None;
+                    continue;
+                }
+            }
+            escaped_col = Some(token.col);
+            continue;
+        }
+
+        state = matcher.call(
+            &mut line_matches,
+            inner_matches,
+            line,
+            &mut tokens,
+            state,
+            token,
+            escaped_col.map(|col| col == token.col - 1).unwrap_or(false),
+        );
+    }
+
+    if !M::KEYWORDS.is_empty() {
+        match_keywords(M::KEYWORDS, line, initial_state, &mut line_matches);
+    }
+    (line_matches, state)
+}
+
+fn join_lines<L: AsRef<[u8]>>(lines: &[L]) -> Vec<u8> {
+    let len = lines
+        .iter()
+        .map(|line| line.as_ref().len() + 1)
+        .sum::<usize>();
+    let mut text = Vec::with_capacity(len);
+    for (i, line) in lines.iter().enumerate() {
+        if i != 0 {
+            text.push(b'\n');
+        }
+        text.extend_from_slice(line.as_ref());
+    }
+    text
+}
+
 // TODO: come up with a better way to do testing
-#[cfg(test)]
+#[cfg(all(test, feature = "lang-c"))]
 mod tests {
+    #[cfg(feature = "lang-rust")]
+    use super::parse_iter;
+    #[cfg(feature = "lang-rust")]
+    use crate::parser::languages::Rust;
     use crate::parser::{parse_filetype, Match, State};
 
     fn parse(filetype: &str, lines: &str) -> Vec<Vec<Match>> {
@@ -114,8 +353,8 @@ mod tests {
         assert_eq!(
             parse("c", "{\n}"),
             vec![
-                vec![Match::delimiter('{', 0, Some(0))],
-                vec![Match::delimiter('}', 0, Some(0))]
+                vec![Match::delimiter('{', 0, None)],
+                vec![Match::delimiter('}', 0, None)]
             ]
         );
 
@@ -123,7 +362,7 @@ mod tests {
             parse("c", "// comment {}\n}"),
             vec![
                 vec![Match::line_comment("//", 0)],
-                vec![Match::delimiter('}', 0, Some(0))],
+                vec![Match::delimiter('}', 0, None)],
             ]
         );
 
@@ -134,22 +373,36 @@ mod tests {
                     Match::block_comment("/*", 0),
                     Match::block_comment("*/", 14)
                 ],
-                vec![Match::delimiter('}', 0, Some(0))]
+                vec![Match::delimiter('}', 0, None)]
             ]
         );
     }
 
     #[test]
+    #[cfg(feature = "lang-latex")]
     fn test_tex() {
         assert_eq!(
             parse("tex", "test 90\\% ( and b )\n%abc"),
             vec![
                 vec![
-                    Match::delimiter('(', 10, Some(0)),
-                    Match::delimiter(')', 18, Some(0))
+                    Match::delimiter('(', 10, None),
+                    Match::delimiter(')', 18, None)
                 ],
                 vec![Match::line_comment("%", 0)]
             ]
         );
     }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_parse_iter() {
+        let text = include_str!("../../benches/languages/rust.rs");
+        let lines = text.lines().collect::<Vec<_>>();
+        let buffer = parse_filetype("rust", 4, &lines, State::Normal).unwrap();
+
+        let (matches_by_line, state_by_line): (Vec<_>, Vec<_>) =
+            parse_iter(text.lines(), State::Normal, Rust {}).unzip();
+        assert_eq!(matches_by_line, buffer.matches_by_line);
+        assert_eq!(state_by_line, buffer.state_by_line);
+    }
 }
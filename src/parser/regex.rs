@@ -0,0 +1,91 @@
+//! Heuristics for telling regex literals (`/[a-z]+/`) apart from division, based on the code
+//! before the `/`
+
+/// Keywords after which an expression, and therefore a regex, may start
+const KEYWORDS: &[&[u8]] = &[
+    // JavaScript
+    b"return",
+    b"typeof",
+    b"instanceof",
+    b"in",
+    b"of",
+    b"new",
+    b"delete",
+    b"void",
+    b"throw",
+    b"case",
+    b"do",
+    b"else",
+    b"yield",
+    b"await",
+    // Ruby
+    b"if",
+    b"elsif",
+    b"unless",
+    b"when",
+    b"while",
+    b"until",
+    b"and",
+    b"or",
+    b"not",
+    b"then",
+];
+
+/// Whether a `/` following the code may start a regex, rather than being a division, i.e.
+/// after an operator, an opening bracket or a keyword such as `return`
+pub fn regex_allowed(before: &[u8]) -> bool {
+    let before = before.trim_ascii_end();
+    let Some(&last) = before.last() else {
+        return true;
+    };
+
+    // Postfix `++` and `--` end an expression, such as in `a++ / 2`, while prefix ones
+    // start one
+    if let Some(operand) = before
+        .strip_suffix(b"++")
+        .or_else(|| before.strip_suffix(b"--"))
+    {
+        return regex_allowed(operand);
+    }
+
+    match last {
+        // Closing brackets, literals and identifiers end an expression
+        b')' | b']' | b'"' | b'\'' | b'`' => false,
+        byte if is_word(byte) => {
+            let word_start = before
+                .iter()
+                .rposition(|&byte| !is_word(byte))
+                .map(|idx| idx + 1)
+                .unwrap_or(0);
+            KEYWORDS.contains(&&before[word_start..])
+        }
+        _ => true,
+    }
+}
+
+fn is_word(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex_allowed() {
+        assert!(regex_allowed(b""));
+        assert!(regex_allowed(b"x = "));
+        assert!(regex_allowed(b"foo("));
+        assert!(regex_allowed(b"  return "));
+        assert!(!regex_allowed(b"a "));
+        assert!(!regex_allowed(b"foo() "));
+        assert!(!regex_allowed(b"x[0]"));
+        assert!(!regex_allowed(b"1"));
+        assert!(!regex_allowed(b"returned "));
+        assert!(!regex_allowed(b"a++ "));
+        assert!(!regex_allowed(b"a-- "));
+        assert!(!regex_allowed(b"x[0]++"));
+        assert!(regex_allowed(b"x = ++"));
+        assert!(regex_allowed(b"return --"));
+    }
+}
@@ -0,0 +1,59 @@
+use alloc::vec::Vec;
+
+use super::{CharPos, Tokenizer};
+
+/// Tokenizer which runs [`memchr::memchr_iter`] once per token and merges the results.
+/// Slower than [`super::SimdTokenizer`] for most matchers, but has no SIMD requirements
+/// beyond those of `memchr`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemchrTokenizer;
+
+impl Tokenizer for MemchrTokenizer {
+    fn tokenize<'s>(
+        &self,
+        text: &'s [u8],
+        tokens: &'static [u8],
+    ) -> impl Iterator<Item = CharPos> + 's {
+        let mut positions = b"\n\\"
+            .iter()
+            .chain(
+                tokens
+                    .iter()
+                    // Enabled by default, ignore
+                    .filter(|&&byte| !matches!(byte, 0 | b'\n' | b'\\')),
+            )
+            .flat_map(|&byte| memchr::memchr_iter(byte, text))
+            .collect::<Vec<_>>();
+        positions.sort_unstable();
+        positions.dedup();
+
+        let mut line_start = 0;
+        positions.into_iter().map(move |idx| match text[idx] {
+            b'\n' => {
+                line_start = idx + 1;
+                CharPos::new(b'\n', 0)
+            }
+            byte => CharPos::new(byte, idx - line_start),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "lang-rust"))]
+mod tests {
+    use super::*;
+    use crate::parser::tokenize::SimdTokenizer;
+    use crate::parser::{languages::Rust, Matcher};
+
+    #[test]
+    fn test_matches_simd() {
+        let text = include_str!("../../../benches/languages/rust.rs").as_bytes();
+        assert_eq!(
+            MemchrTokenizer
+                .tokenize(text, Rust::TOKENS)
+                .collect::<Vec<_>>(),
+            SimdTokenizer
+                .tokenize(text, Rust::TOKENS)
+                .collect::<Vec<_>>()
+        );
+    }
+}
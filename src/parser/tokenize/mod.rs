@@ -0,0 +1,191 @@
+//! Tokenizers find the bytes used by a matcher in the text, see [`Tokenizer`]
+//!
+//! This is the scanning layer underneath [`ParsedBuffer`](crate::buffer::ParsedBuffer) and may
+//! be used on its own, e.g. by formatters which only need the positions of a few bytes. The
+//! items exported here follow semver: the [`Tokenizer`] contract, the fields of [`CharPos`] and
+//! the output of the built-in tokenizers will not change outside of a major release.
+//!
+//! ```
+//! use blink_pairs::parser::{filetype_tokens, CharPos, SimdTokenizer, Tokenizer};
+//!
+//! let text = b"fn main() {\n}";
+//! let positions = SimdTokenizer
+//!     .tokenize(text, b"{}")
+//!     .collect::<Vec<_>>();
+//! assert_eq!(
+//!     positions,
+//!     vec![CharPos::new(b'{', 10), CharPos::new(b'\n', 0), CharPos::new(b'}', 0)]
+//! );
+//!
+//! // Or use the bytes from a built-in language
+//! # #[cfg(feature = "lang-rust")]
+//! assert!(filetype_tokens("rust").unwrap().contains(&b'{'));
+//! ```
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+mod memchr;
+mod simd;
+mod swar;
+
+pub use memchr::MemchrTokenizer;
+pub use simd::{simd_lanes, tokenize, SimdTokenizer};
+pub use swar::SwarTokenizer;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CharPos {
+    /// The byte which was found, one of the requested tokens, `\n` or `\\`
+    pub byte: u8,
+    /// Byte offset from the start of the line, always 0 for newlines
+    pub col: usize,
+}
+
+impl CharPos {
+    pub fn new(byte: u8, col: usize) -> Self {
+        Self { byte, col }
+    }
+}
+
+/// Finds the provided list of tokens in the text, returning the byte and column position of
+/// each token. Newlines (`\n`) and escapes (`\\`) must always be returned, with newlines
+/// using column 0 and resetting the column for the following tokens
+pub trait Tokenizer {
+    fn tokenize<'s>(
+        &self,
+        text: &'s [u8],
+        tokens: &'static [u8],
+    ) -> impl Iterator<Item = CharPos> + 's;
+}
+
+/// Built-in tokenizers which may be selected at runtime via [`Backend::set`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[repr(u8)]
+pub enum Backend {
+    Simd = 0,
+    Memchr = 1,
+    Swar = 2,
+}
+
+static BACKEND: AtomicU8 = AtomicU8::new(Backend::DEFAULT as u8);
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::DEFAULT
+    }
+}
+
+impl Backend {
+    /// SIMD when the target has vector registers, otherwise SWAR since emulated SIMD is
+    /// slower than processing 8 bytes at a time
+    #[cfg(any(
+        target_feature = "sse2",
+        target_feature = "neon",
+        target_feature = "simd128"
+    ))]
+    pub const DEFAULT: Backend = Backend::Simd;
+    #[cfg(not(any(
+        target_feature = "sse2",
+        target_feature = "neon",
+        target_feature = "simd128"
+    )))]
+    pub const DEFAULT: Backend = Backend::Swar;
+
+    /// Sets the tokenizer used by all subsequent parses
+    pub fn set(backend: Backend) {
+        BACKEND.store(backend as u8, Ordering::Relaxed);
+    }
+
+    /// Gets the tokenizer used for parsing
+    pub fn current() -> Backend {
+        match BACKEND.load(Ordering::Relaxed) {
+            0 => Backend::Simd,
+            1 => Backend::Memchr,
+            _ => Backend::Swar,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Simd => "simd",
+            Backend::Memchr => "memchr",
+            Backend::Swar => "swar",
+        }
+    }
+}
+
+impl TryFrom<&str> for Backend {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "simd" => Ok(Backend::Simd),
+            "memchr" => Ok(Backend::Memchr),
+            "swar" => Ok(Backend::Swar),
+            _ => Err(()),
+        }
+    }
+}
+
+/// SIMD features enabled at build time which the CPU doesn't support, i.e. when a build with
+/// `-C target-cpu=native` is copied to an older machine. The default build targets the
+/// baseline CPU and detects the wider lanes at runtime instead, see [`simd_lanes`], so this is
+/// only for reporting builds which need rebuilding. Always empty without `std`, since the CPU
+/// can't be queried
+#[cfg(feature = "std")]
+pub fn unsupported_simd_features() -> &'static [&'static str] {
+    static UNSUPPORTED: std::sync::OnceLock<Vec<&'static str>> = std::sync::OnceLock::new();
+    UNSUPPORTED.get_or_init(detect_unsupported_simd_features)
+}
+
+#[cfg(not(feature = "std"))]
+pub fn unsupported_simd_features() -> &'static [&'static str] {
+    &[]
+}
+
+#[cfg(feature = "std")]
+fn detect_unsupported_simd_features() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut unsupported = vec![];
+
+    // Features which select the SIMD tokenizer or its lane count, see `SimdTokenizer`. SSE2 is
+    // part of the x86_64 baseline, so it's only checked on x86
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        macro_rules! check {
+            ($($feature:tt),*) => {$(
+                if cfg!(target_feature = $feature) && !std::arch::is_x86_feature_detected!($feature) {
+                    unsupported.push($feature);
+                }
+            )*};
+        }
+        #[cfg(target_arch = "x86")]
+        check!("sse2");
+        check!("avx2", "avx512bw");
+    }
+    #[cfg(target_arch = "aarch64")]
+    if cfg!(target_feature = "neon") && !std::arch::is_aarch64_feature_detected!("neon") {
+        unsupported.push("neon");
+    }
+
+    unsupported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unsupported_simd_features() {
+        // Tests are built for the CPU they run on
+        assert_eq!(unsupported_simd_features(), &[] as &[&str]);
+    }
+
+    #[test]
+    fn test_backend_name() {
+        for backend in [Backend::Simd, Backend::Memchr, Backend::Swar] {
+            assert_eq!(Backend::try_from(backend.name()), Ok(backend));
+        }
+        assert_eq!(Backend::try_from("avx"), Err(()));
+    }
+}
@@ -0,0 +1,179 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::{
+    cell::Cell,
+    simd::{cmp::SimdPartialEq, Select, Simd},
+};
+
+use super::{CharPos, Tokenizer};
+
+/// Tokenizer backed by [`tokenize`], using the widest SIMD lanes supported by the CPU, see
+/// [`simd_lanes`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimdTokenizer;
+
+impl Tokenizer for SimdTokenizer {
+    fn tokenize<'s>(
+        &self,
+        text: &'s [u8],
+        tokens: &'static [u8],
+    ) -> impl Iterator<Item = CharPos> + 's {
+        #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+        match simd_lanes() {
+            // SAFETY: the lanes are only this wide when the CPU supports the feature
+            64 => return unsafe { tokenize_avx512(text, tokens) }.into_iter(),
+            32 => return unsafe { tokenize_avx2(text, tokens) }.into_iter(),
+            _ => {}
+        }
+        tokenize::<BUILD_LANES>(text, tokens)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+/// Widest SIMD lanes enabled at build time
+#[cfg(target_feature = "avx512bw")]
+const BUILD_LANES: usize = 64;
+#[cfg(all(target_feature = "avx2", not(target_feature = "avx512bw")))]
+const BUILD_LANES: usize = 32;
+#[cfg(not(any(target_feature = "avx2", target_feature = "avx512bw")))]
+const BUILD_LANES: usize = 16;
+
+/// Number of bytes compared at once by the [`SimdTokenizer`]. On x86, the widest of AVX-512
+/// (64) and AVX2 (32) supported by the CPU, so that builds for the baseline CPU still use
+/// them. Otherwise the widest lanes enabled at build time, such as 16 for SSE2 and NEON
+pub fn simd_lanes() -> usize {
+    #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if std::arch::is_x86_feature_detected!("avx512bw") {
+            return 64;
+        }
+        if std::arch::is_x86_feature_detected!("avx2") {
+            return 32;
+        }
+    }
+    BUILD_LANES
+}
+
+// The positions are collected within the functions, so that all of the tokenizing is compiled
+// with the feature
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+#[target_feature(enable = "avx512bw")]
+fn tokenize_avx512(text: &[u8], tokens: &'static [u8]) -> Vec<CharPos> {
+    tokenize::<64>(text, tokens).collect()
+}
+
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+#[target_feature(enable = "avx2")]
+fn tokenize_avx2(text: &[u8], tokens: &'static [u8]) -> Vec<CharPos> {
+    tokenize::<32>(text, tokens).collect()
+}
+
+/// Takes input text and uses SIMD to find the provided list of tokens in the text
+/// returning the byte and column position of each token. You can get the row by counting
+/// every incoming `\n` token
+pub fn tokenize<'s, const N: usize>(
+    text: &'s [u8],
+    tokens: &'static [u8],
+) -> impl Iterator<Item = CharPos> + 's {
+    let none = Simd::<u8, N>::splat(0);
+    let new_line = Simd::<u8, N>::splat(b'\n');
+    let escape = Simd::<u8, N>::splat(b'\\');
+
+    let tokens_to_find = tokens
+        .iter()
+        .flat_map(|&c| {
+            match c {
+                // Enabled by default, ignore
+                0 | b'\n' | b'\\' => None,
+
+                _ => Some(Simd::<u8, N>::splat(c)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    //
+
+    // TODO: must use Rc and Cell here since we need to mutate the value inside a closure
+    // which uses `move`, so otherwise we would copy, and the value would be reset on every
+    // chunk
+    let col_offset = Rc::new(Cell::new(0));
+    text.chunks(N)
+        .map(Simd::<u8, N>::load_or_default)
+        .enumerate()
+        .flat_map(move |(chunk_idx, chunk)| {
+            let mut tokens = none;
+            tokens |= new_line.simd_eq(chunk).select(new_line, none);
+            tokens |= escape.simd_eq(chunk).select(escape, none);
+
+            for &char in tokens_to_find.iter() {
+                tokens |= char.simd_eq(chunk).select(char, none);
+            }
+
+            // Apply parsed tokens
+            let chunk_col = chunk_idx * N;
+            let col_offset = col_offset.clone();
+            tokens
+                .to_array()
+                .into_iter()
+                .enumerate()
+                .flat_map(move |(idx_in_chunk, byte)| match byte {
+                    0 => None,
+                    b'\n' => {
+                        col_offset.set(chunk_col + idx_in_chunk + 1);
+
+                        Some(CharPos {
+                            byte: b'\n',
+                            col: 0,
+                        })
+                    }
+                    byte => Some(CharPos {
+                        byte,
+                        col: chunk_col + idx_in_chunk - col_offset.get(),
+                    }),
+                })
+        })
+}
+
+// TODO: come up with a better way to do testing
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simd_lanes() {
+        let text = include_str!("../../../benches/languages/rust.rs").as_bytes();
+        let expected = tokenize::<16>(text, b"(){}[]\"'/*").collect::<Vec<_>>();
+        assert_eq!(
+            SimdTokenizer
+                .tokenize(text, b"(){}[]\"'/*")
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert!([16, 32, 64].contains(&simd_lanes()));
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let text = [
+            "use crate::r#const::*;",
+            "use std::ops::Not;",
+            "use std::simd::cmp::*;",
+            "use std::simd::num::SimdUint;",
+            "use std::simd::{Mask, Simd};",
+        ]
+        .join("\n");
+
+        assert_eq!(
+            tokenize::<16>(text.as_bytes(), b"(){}").collect::<Vec<_>>(),
+            vec![
+                CharPos::new(b'\n', 0),
+                CharPos::new(b'\n', 0),
+                CharPos::new(b'\n', 0),
+                CharPos::new(b'\n', 0),
+                CharPos::new(b'{', 15),
+                CharPos::new(b'}', 26),
+            ]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use alloc::vec::Vec;
+
+use super::{CharPos, Tokenizer};
+
+const LOW_BITS: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+
+/// Tokenizer which processes 8 bytes at a time using u64 bit tricks (SIMD within a register),
+/// for targets without usable SIMD such as some WASM configurations and older ARM cores
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SwarTokenizer;
+
+impl Tokenizer for SwarTokenizer {
+    fn tokenize<'s>(
+        &self,
+        text: &'s [u8],
+        tokens: &'static [u8],
+    ) -> impl Iterator<Item = CharPos> + 's {
+        let tokens_to_find = b"\n\\"
+            .iter()
+            .chain(
+                tokens
+                    .iter()
+                    // Enabled by default, ignore
+                    .filter(|&&byte| !matches!(byte, 0 | b'\n' | b'\\')),
+            )
+            .map(|&byte| splat(byte))
+            .collect::<Vec<_>>();
+
+        SwarTokens {
+            text,
+            tokens_to_find,
+            chunk_start: 0,
+            mask: 0,
+            line_start: 0,
+        }
+    }
+}
+
+struct SwarTokens<'s> {
+    text: &'s [u8],
+    tokens_to_find: Vec<u64>,
+    chunk_start: usize,
+    /// High bit of each byte set for the remaining tokens in the current chunk
+    mask: u64,
+    line_start: usize,
+}
+
+impl SwarTokens<'_> {
+    fn next_chunk(&mut self) -> bool {
+        while self.chunk_start < self.text.len() {
+            let end = (self.chunk_start + 8).min(self.text.len());
+            let mut bytes = [0; 8];
+            bytes[..end - self.chunk_start].copy_from_slice(&self.text[self.chunk_start..end]);
+            let chunk = u64::from_le_bytes(bytes);
+
+            self.mask = self
+                .tokens_to_find
+                .iter()
+                .fold(0, |mask, &token| mask | zero_bytes(chunk ^ token));
+            if self.mask != 0 {
+                return true;
+            }
+            self.chunk_start += 8;
+        }
+        false
+    }
+}
+
+impl Iterator for SwarTokens<'_> {
+    type Item = CharPos;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.mask == 0 && !self.next_chunk() {
+            return None;
+        }
+
+        let idx = self.chunk_start + (self.mask.trailing_zeros() / 8) as usize;
+        // Clear the lowest set bit
+        self.mask &= self.mask - 1;
+        if self.mask == 0 {
+            self.chunk_start += 8;
+        }
+
+        Some(match self.text[idx] {
+            b'\n' => {
+                self.line_start = idx + 1;
+                CharPos::new(b'\n', 0)
+            }
+            byte => CharPos::new(byte, idx - self.line_start),
+        })
+    }
+}
+
+fn splat(byte: u8) -> u64 {
+    u64::from_ne_bytes([byte; 8])
+}
+
+/// Sets the high bit of every byte which is zero, without false positives from carries
+fn zero_bytes(value: u64) -> u64 {
+    !(((value & LOW_BITS) + LOW_BITS) | value) & HIGH_BITS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "lang-c")]
+    use crate::parser::{languages::C, tokenize::SimdTokenizer, Matcher};
+
+    #[test]
+    #[cfg(feature = "lang-c")]
+    fn test_matches_simd() {
+        let text = include_str!("../../../benches/languages/c.c").as_bytes();
+        assert_eq!(
+            SwarTokenizer.tokenize(text, C::TOKENS).collect::<Vec<_>>(),
+            SimdTokenizer.tokenize(text, C::TOKENS).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_unaligned_tail() {
+        assert_eq!(
+            SwarTokenizer
+                .tokenize(b"0123456789(\n)", b"()")
+                .collect::<Vec<_>>(),
+            vec![
+                CharPos::new(b'(', 10),
+                CharPos::new(b'\n', 0),
+                CharPos::new(b')', 0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! Compound filters over the matches of a [`ParsedBuffer`], evaluated in a single pass
+//!
+//! ```
+//! use blink_pairs::{buffer::ParsedBuffer, query::Query};
+//! # if cfg!(not(feature = "lang-rust")) { return; }
+//!
+//! let lines = ["fn main() {", "    // (", "    ("];
+//! let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+//! let unmatched = buffer
+//!     .query(&Query::new().unmatched().token("("))
+//!     .collect::<Vec<_>>();
+//! assert_eq!(unmatched.len(), 1);
+//! assert_eq!((unmatched[0].line, unmatched[0].col), (2, 4));
+//!
+//! // Delimiters inside of comments are only found along with the lines
+//! let commented = buffer
+//!     .query_with_inner("rust", &lines, &Query::new().token("(").in_comments(true))
+//!     .unwrap();
+//! assert_eq!(commented.len(), 1);
+//! assert_eq!((commented[0].line, commented[0].col), (1, 7));
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+#[cfg(feature = "lua")]
+use mlua::{FromLua, Lua};
+
+use crate::buffer::ParsedBuffer;
+use crate::parser::matcher::TokenType;
+use crate::parser::{Kind, Match, MatchWithLine, State};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    token: Option<String>,
+    token_type: Option<TokenType>,
+    kind: Option<Kind>,
+    matched: Option<bool>,
+    in_comments: Option<bool>,
+    in_strings: Option<bool>,
+    lines: Option<Range<usize>>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only include matches with a partner
+    pub fn matched(mut self) -> Self {
+        self.matched = Some(true);
+        self
+    }
+
+    /// Only include openings and closings without a partner
+    pub fn unmatched(mut self) -> Self {
+        self.matched = Some(false);
+        self
+    }
+
+    /// Only include matches whose opening text equals the given text, i.e. `(` matches both
+    /// `(` and `)`
+    pub fn token(mut self, opening: &str) -> Self {
+        self.token = Some(opening.to_string());
+        self
+    }
+
+    pub fn token_type(mut self, token_type: TokenType) -> Self {
+        self.token_type = Some(token_type);
+        self
+    }
+
+    pub fn kind(mut self, kind: Kind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Whether to include (`true`) or exclude (`false`) matches inside of comments, which are
+    /// only found by [`ParsedBuffer::query_with_inner`]
+    pub fn in_comments(mut self, in_comments: bool) -> Self {
+        self.in_comments = Some(in_comments);
+        self
+    }
+
+    /// Whether to include (`true`) or exclude (`false`) matches inside of strings, which are
+    /// only found by [`ParsedBuffer::query_with_inner`]
+    pub fn in_strings(mut self, in_strings: bool) -> Self {
+        self.in_strings = Some(in_strings);
+        self
+    }
+
+    /// Only include matches on the given lines (end exclusive)
+    pub fn lines(mut self, lines: Range<usize>) -> Self {
+        self.lines = Some(lines);
+        self
+    }
+
+    fn matches(&self, match_: &Match, context: Context) -> bool {
+        if let Some(token) = &self.token {
+            if match_.token.opening() != token {
+                return false;
+            }
+        }
+        if let Some(token_type) = self.token_type {
+            if !token_type.matches(&match_.token) {
+                return false;
+            }
+        }
+        if let Some(kind) = self.kind {
+            if match_.kind != kind {
+                return false;
+            }
+        }
+        if let Some(matched) = self.matched {
+            if match_.kind == Kind::NonPair || match_.stack_height.is_some() != matched {
+                return false;
+            }
+        }
+        if let Some(in_comments) = self.in_comments {
+            if (context == Context::Comment) != in_comments {
+                return false;
+            }
+        }
+        if let Some(in_strings) = self.in_strings {
+            if (context == Context::String) != in_strings {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context {
+    Code,
+    String,
+    Comment,
+}
+
+impl From<State> for Context {
+    fn from(state: State) -> Self {
+        match state {
+            State::InString(_) | State::InBlockString(_) => Context::String,
+            State::InLineComment | State::InBlockComment(_) => Context::Comment,
+            _ => Context::Code,
+        }
+    }
+}
+
+impl ParsedBuffer {
+    /// Runs the query over the buffer, returning the matches in order. Delimiters inside of
+    /// strings and comments aren't stored, so they're never returned, see
+    /// [`ParsedBuffer::query_with_inner`]
+    pub fn query<'a>(&'a self, query: &'a Query) -> impl Iterator<Item = MatchWithLine> + 'a {
+        self.query_lines(query).flat_map(move |line| {
+            self.matches_by_line[line]
+                .iter()
+                .filter(|match_| query.matches(match_, Context::Code))
+                .map(move |match_| match_.with_line(line))
+        })
+    }
+
+    /// Same as [`ParsedBuffer::query`] but includes the delimiters inside of strings and
+    /// comments, see [`ParsedBuffer::inner_matches`]. The lines must be the ones the buffer
+    /// was parsed from
+    pub fn query_with_inner<L: AsRef<[u8]>>(
+        &self,
+        filetype: &str,
+        lines: &[L],
+        query: &Query,
+    ) -> Option<Vec<MatchWithLine>> {
+        let query_lines = self.query_lines(query);
+        let start_line = query_lines.start;
+        let lines = &lines[start_line.min(lines.len())..query_lines.end.min(lines.len())];
+        let inner_matches = self.inner_matches(filetype, lines, start_line)?;
+
+        let mut matches = self.query(query).collect::<Vec<_>>();
+        for (offset, line_matches) in inner_matches.into_iter().enumerate() {
+            let line = start_line + offset;
+            matches.extend(
+                line_matches
+                    .into_iter()
+                    .filter(|match_| query.matches(match_, self.state_at(line, match_.col).into()))
+                    .map(|match_| match_.with_line(line)),
+            );
+        }
+        matches.sort_by_key(|match_| (match_.line, match_.col));
+        Some(matches)
+    }
+
+    /// Lines of the query, clamped to the buffer
+    fn query_lines(&self, query: &Query) -> Range<usize> {
+        let len = self.matches_by_line.len();
+        let lines = query.lines.clone().unwrap_or(0..len);
+        let end_line = lines.end.min(len);
+        lines.start.min(end_line)..end_line
+    }
+}
+
+#[cfg(feature = "lua")]
+impl FromLua for Query {
+    fn from_lua(value: mlua::Value, lua: &Lua) -> mlua::Result<Self> {
+        let table = mlua::Table::from_lua(value, lua)?;
+
+        let mut query = Query::new();
+        if let Some(token) = table.get::<Option<String>>("token")? {
+            query = query.token(&token);
+        }
+        if let Some(token_type) = table.get::<Option<u8>>("token_type")? {
+            let token_type = TokenType::try_from(token_type)
+                .map_err(|_| mlua::Error::runtime("invalid token_type"))?;
+            query = query.token_type(token_type);
+        }
+        query = match table.get::<Option<bool>>("matched")? {
+            Some(true) => query.matched(),
+            Some(false) => query.unmatched(),
+            None => query,
+        };
+        if let Some(in_comments) = table.get::<Option<bool>>("in_comments")? {
+            query = query.in_comments(in_comments);
+        }
+        if let Some(in_strings) = table.get::<Option<bool>>("in_strings")? {
+            query = query.in_strings(in_strings);
+        }
+        let start_line = table.get::<Option<usize>>("start_line")?;
+        let end_line = table.get::<Option<usize>>("end_line")?;
+        if start_line.is_some() || end_line.is_some() {
+            query = query.lines(start_line.unwrap_or(0)..end_line.unwrap_or(usize::MAX));
+        }
+        Ok(query)
+    }
+}
+
+#[cfg(all(test, feature = "lang-rust"))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn parse(lines: &[&str]) -> ParsedBuffer {
+        ParsedBuffer::parse("rust", 4, lines).unwrap()
+    }
+
+    #[test]
+    fn test_query_unmatched() {
+        let buffer = parse(&["( [", "]"]);
+        assert_eq!(
+            buffer.query(&Query::new().unmatched()).collect::<Vec<_>>(),
+            vec![Match::delimiter('(', 0, None).with_line(0)]
+        );
+        assert_eq!(
+            buffer
+                .query(&Query::new().matched().token("["))
+                .collect::<Vec<_>>(),
+            vec![
+                Match::delimiter('[', 2, Some(1)).with_line(0),
+                Match::delimiter(']', 0, Some(1)).with_line(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_context() {
+        let lines = ["/* a ( */ [", "\"{\" ]"];
+        let buffer = parse(&lines);
+        let query = |query: Query| {
+            buffer
+                .query_with_inner("rust", &lines, &query.token_type(TokenType::Delimiter))
+                .unwrap()
+                .into_iter()
+                .map(|match_| (match_.line, match_.col))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(query(Query::new()), vec![(0, 5), (0, 10), (1, 1), (1, 4)]);
+        assert_eq!(query(Query::new().in_comments(true)), vec![(0, 5)]);
+        assert_eq!(query(Query::new().in_strings(true)), vec![(1, 1)]);
+        assert_eq!(
+            query(Query::new().in_comments(false).in_strings(false)),
+            vec![(0, 10), (1, 4)]
+        );
+        assert_eq!(
+            query(Query::new().in_strings(true).lines(1..2)),
+            vec![(1, 1)]
+        );
+
+        // Without the lines, only the code's matches are found
+        assert_eq!(
+            buffer
+                .query(&Query::new().token_type(TokenType::Delimiter))
+                .map(|match_| (match_.line, match_.col))
+                .collect::<Vec<_>>(),
+            vec![(0, 10), (1, 4)]
+        );
+    }
+
+    #[test]
+    fn test_query_lines() {
+        let buffer = parse(&["(", ")", "("]);
+        assert_eq!(
+            buffer
+                .query(&Query::new().kind(Kind::Opening).lines(1..10))
+                .collect::<Vec<_>>(),
+            vec![Match::delimiter('(', 0, None).with_line(2)]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Exports the bracket pair highlights in the LSP semantic tokens format, so that language
+//! servers and other integrations may reuse the client's rendering of semantic tokens.
+//!
+//! Matched delimiters get the token type of their stack height modulo the number of levels,
+//! like the highlighter's groups, and unmatched delimiters get the type after the levels. See
+//! [`legend`] for the token type names to register with the client.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::buffer::ParsedBuffer;
+use crate::column::{byte_to_char_col, byte_to_utf16_col};
+use crate::parser::Token;
+
+/// Unit of the `startChar` and `length` fields, negotiated with the client through
+/// `positionEncoding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+/// A single token before relative encoding, see [`encode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub line: u32,
+    pub start_char: u32,
+    pub length: u32,
+    pub token_type: u32,
+    pub token_modifiers: u32,
+}
+
+/// Edit to the previously sent token data, as in `SemanticTokensEdit`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticTokensEdit {
+    pub start: u32,
+    pub delete_count: u32,
+    pub data: Vec<u32>,
+}
+
+/// Names of the token types, where `bracketLevelN` is used for stack heights `N - 1`
+/// modulo `levels`
+pub fn legend(levels: u32) -> Vec<String> {
+    let mut token_types = (1..=levels)
+        .map(|level| format!("bracketLevel{level}"))
+        .collect::<Vec<_>>();
+    token_types.push(String::from("bracketUnmatched"));
+    token_types
+}
+
+/// Gets the tokens of the delimiters in the buffer, in order, where the lines must be the
+/// ones the buffer was parsed from to convert the columns to the encoding
+pub fn semantic_tokens<L: AsRef<[u8]>>(
+    buffer: &ParsedBuffer,
+    lines: &[L],
+    levels: u32,
+    encoding: PositionEncoding,
+) -> Vec<SemanticToken> {
+    let levels = levels.max(1);
+    let mut tokens = Vec::new();
+    for (line_number, matches) in buffer.matches_by_line.iter().enumerate() {
+        let line = lines
+            .get(line_number)
+            .map(|line| line.as_ref())
+            .unwrap_or(&[]);
+        let convert = |col: usize| match encoding {
+            PositionEncoding::Utf8 => col,
+            PositionEncoding::Utf16 => byte_to_utf16_col(line, col),
+            PositionEncoding::Utf32 => byte_to_char_col(line, col),
+        };
+
+        for match_ in matches {
+            if !matches!(match_.token, Token::Delimiter(_, _)) {
+                continue;
+            }
+            let start_char = convert(match_.col);
+            let end_char = convert(match_.end_col());
+            let token_type = match match_.stack_height {
+                Some(stack_height) => stack_height as u32 % levels,
+                None => levels,
+            };
+            tokens.push(SemanticToken {
+                line: line_number as u32,
+                start_char: start_char as u32,
+                length: (end_char - start_char) as u32,
+                token_type,
+                token_modifiers: 0,
+            });
+        }
+    }
+    tokens
+}
+
+/// Encodes the tokens relative to each other, as in the `data` field of the response,
+/// where the tokens must be in order
+pub fn encode(tokens: &[SemanticToken]) -> Vec<u32> {
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let (mut last_line, mut last_start) = (0, 0);
+    for token in tokens {
+        let delta_line = token.line - last_line;
+        let delta_start = match delta_line {
+            0 => token.start_char - last_start,
+            _ => token.start_char,
+        };
+        data.extend([
+            delta_line,
+            delta_start,
+            token.length,
+            token.token_type,
+            token.token_modifiers,
+        ]);
+        (last_line, last_start) = (token.line, token.start_char);
+    }
+    data
+}
+
+/// Gets the edit turning the previously sent data into the new data, for
+/// `textDocument/semanticTokens/full/delta`. Returns `None` when the data is the same
+pub fn edit(previous: &[u32], current: &[u32]) -> Option<SemanticTokensEdit> {
+    let prefix = previous
+        .iter()
+        .zip(current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix == previous.len() && prefix == current.len() {
+        return None;
+    }
+
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    Some(SemanticTokensEdit {
+        start: prefix as u32,
+        delete_count: (previous.len() - prefix - suffix) as u32,
+        data: current[prefix..current.len() - suffix].to_vec(),
+    })
+}
+
+#[cfg(all(test, feature = "lang-rust"))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_encode() {
+        let lines = ["fn a() {", "    \"é\".b(]", "}"];
+        let buffer = ParsedBuffer::parse("rust", 4, &lines).unwrap();
+        let tokens = semantic_tokens(&buffer, &lines, 3, PositionEncoding::Utf16);
+        assert_eq!(
+            encode(&tokens),
+            vec![
+                0, 4, 1, 0, 0, //
+                0, 1, 1, 0, 0, //
+                0, 2, 1, 0, 0, //
+                1, 9, 1, 3, 0, //
+                0, 1, 1, 3, 0, //
+                1, 0, 1, 0, 0, //
+            ]
+        );
+    }
+
+    #[test]
+    fn test_edit() {
+        assert_eq!(edit(&[1, 2, 3], &[1, 2, 3]), None);
+        assert_eq!(
+            edit(&[1, 2, 3, 4], &[1, 5, 6, 4]),
+            Some(SemanticTokensEdit {
+                start: 1,
+                delete_count: 2,
+                data: vec![5, 6],
+            })
+        );
+        assert_eq!(
+            edit(&[1, 2], &[1, 2, 3]),
+            Some(SemanticTokensEdit {
+                start: 2,
+                delete_count: 0,
+                data: vec![3],
+            })
+        );
+    }
+}
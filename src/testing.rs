@@ -0,0 +1,278 @@
+//! Golden tests for matchers: each sample file in a directory is parsed and its matches are
+//! compared against the JSON file committed next to it, named `<sample>.golden.json`.
+//!
+//! By default, the filetype is the extension of the sample, i.e. `strings.rust`. Matchers
+//! which aren't built into the crate may be tested with [`GoldenCorpus::check_with`].
+//! Set `BLINK_PAIRS_UPDATE_GOLDEN=1` to write the golden files instead of comparing them.
+//!
+//! ```no_run
+//! use blink_pairs::testing::GoldenCorpus;
+//!
+//! let corpus = GoldenCorpus::new(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden"));
+//! if let Err(failures) = corpus.check() {
+//!     panic!("{}", failures.iter().map(|f| f.to_string()).collect::<Vec<_>>().join("\n"));
+//! }
+//! ```
+
+use std::fmt::{self, Display, Write as _};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::buffer::ParsedBuffer;
+use crate::parser::{Kind, Token};
+
+const GOLDEN_SUFFIX: &str = ".golden.json";
+
+/// Directory of sample files with their golden files
+#[derive(Debug, Clone)]
+pub struct GoldenCorpus {
+    dir: PathBuf,
+    tab_width: u8,
+    update: bool,
+}
+
+#[derive(Debug)]
+pub enum GoldenFailure {
+    Io(PathBuf, std::io::Error),
+    /// The sample has no golden file, run with `BLINK_PAIRS_UPDATE_GOLDEN=1` to create it
+    Missing(PathBuf),
+    /// No matcher exists for the sample's filetype
+    Unparsed(PathBuf),
+    /// The matches differ from the golden file, starting at the line of the golden file
+    Mismatch {
+        path: PathBuf,
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Display for GoldenFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            Self::Missing(path) => write!(f, "{}: missing golden file", path.display()),
+            Self::Unparsed(path) => write!(f, "{}: no matcher for filetype", path.display()),
+            Self::Mismatch {
+                path,
+                line,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}:{}: expected `{expected}`, got `{actual}`",
+                path.display(),
+                line + 1
+            ),
+        }
+    }
+}
+
+impl GoldenCorpus {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            tab_width: 4,
+            update: std::env::var_os("BLINK_PAIRS_UPDATE_GOLDEN").is_some_and(|var| var != "0"),
+        }
+    }
+
+    pub fn tab_width(mut self, tab_width: u8) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    /// Writes the golden files rather than comparing against them
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Parses the samples with the filetype given by their extension. Returns the number of
+    /// samples checked
+    pub fn check(&self) -> Result<usize, Vec<GoldenFailure>> {
+        self.check_with(|path, lines| {
+            let filetype = path.extension()?.to_str()?;
+            ParsedBuffer::parse_bytes(filetype, self.tab_width, lines)
+        })
+    }
+
+    /// Same as [`GoldenCorpus::check`] but parses the samples with the given function
+    pub fn check_with(
+        &self,
+        parse: impl Fn(&Path, &[&[u8]]) -> Option<ParsedBuffer>,
+    ) -> Result<usize, Vec<GoldenFailure>> {
+        let samples = self.samples().map_err(|err| vec![err])?;
+        let failures = samples
+            .iter()
+            .filter_map(|sample| self.check_sample(sample, &parse).err())
+            .collect::<Vec<_>>();
+        match failures.is_empty() {
+            true => Ok(samples.len()),
+            false => Err(failures),
+        }
+    }
+
+    /// Gets the sample files in the directory, sorted by path
+    fn samples(&self) -> Result<Vec<PathBuf>, GoldenFailure> {
+        let io_err = |err| GoldenFailure::Io(self.dir.clone(), err);
+        let mut samples = fs::read_dir(&self.dir)
+            .map_err(io_err)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io_err)?;
+        samples.retain(|path| {
+            path.is_file()
+                && !path
+                    .to_str()
+                    .is_some_and(|path| path.ends_with(GOLDEN_SUFFIX))
+        });
+        samples.sort();
+        Ok(samples)
+    }
+
+    fn check_sample(
+        &self,
+        sample: &Path,
+        parse: &impl Fn(&Path, &[&[u8]]) -> Option<ParsedBuffer>,
+    ) -> Result<(), GoldenFailure> {
+        let text = fs::read(sample).map_err(|err| GoldenFailure::Io(sample.to_owned(), err))?;
+        let lines = text.split(|&byte| byte == b'\n').collect::<Vec<_>>();
+        let buffer = parse(sample, &lines).ok_or_else(|| GoldenFailure::Unparsed(sample.into()))?;
+        let actual = to_golden_json(&buffer);
+
+        let mut golden = sample.as_os_str().to_owned();
+        golden.push(GOLDEN_SUFFIX);
+        let golden = PathBuf::from(golden);
+        if self.update {
+            return fs::write(&golden, actual).map_err(|err| GoldenFailure::Io(golden, err));
+        }
+
+        let expected = match fs::read_to_string(&golden) {
+            Ok(expected) => expected,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(GoldenFailure::Missing(golden))
+            }
+            Err(err) => return Err(GoldenFailure::Io(golden, err)),
+        };
+        let mut expected_lines = expected.lines();
+        let mut actual_lines = actual.lines();
+        for line in 0.. {
+            match (expected_lines.next(), actual_lines.next()) {
+                (None, None) => return Ok(()),
+                (expected, actual) if expected != actual => {
+                    return Err(GoldenFailure::Mismatch {
+                        path: golden,
+                        line,
+                        expected: expected.unwrap_or_default().to_string(),
+                        actual: actual.unwrap_or_default().to_string(),
+                    })
+                }
+                _ => {}
+            }
+        }
+        unreachable!()
+    }
+}
+
+/// Serializes the matches as a JSON array, with one match per line so that golden files
+/// diff nicely
+pub fn to_golden_json(buffer: &ParsedBuffer) -> String {
+    let mut json = String::from("[\n");
+    let matches = buffer
+        .matches_by_line
+        .iter()
+        .enumerate()
+        .flat_map(|(line, matches)| matches.iter().map(move |match_| (line, match_)));
+    for (i, (line, match_)) in matches.enumerate() {
+        if i > 0 {
+            json.push_str(",\n");
+        }
+        let kind = match match_.kind {
+            Kind::Opening => "opening",
+            Kind::Closing => "closing",
+            Kind::NonPair => "none",
+            Kind::Middle(_) => "middle",
+        };
+        let token = match match_.token {
+            Token::Delimiter(_, _) => "delimiter",
+            Token::String(_) => "string",
+            Token::BlockString(_, _) => "block_string",
+            Token::LineComment(_) => "line_comment",
+            Token::BlockComment(_, _) => "block_comment",
+            Token::InlineSpan(_, _, _) => "inline_span",
+            Token::BlockSpan(_, _, _) => "block_span",
+        };
+        let stack_height = match match_.stack_height {
+            Some(stack_height) => stack_height.to_string(),
+            None => String::from("null"),
+        };
+        let _ = write!(
+            json,
+            r#"  {{"line": {line}, "col": {}, "token": "{token}", "kind": "{kind}", "text": {}, "stack_height": {stack_height}}}"#,
+            match_.col,
+            json_string(match_.text()),
+        );
+    }
+    json.push_str("\n]\n");
+    json
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+    for char in text.chars() {
+        match char {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            char if char.is_control() => {
+                let _ = write!(json, "\\u{:04x}", char as u32);
+            }
+            char => json.push(char),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(all(test, feature = "lang-rust"))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    #[cfg(feature = "all-languages")]
+    fn test_golden_corpus() {
+        let corpus = GoldenCorpus::new(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden"));
+        match corpus.check() {
+            Ok(count) => assert!(count > 0),
+            Err(failures) => panic!(
+                "{}",
+                failures
+                    .iter()
+                    .map(|failure| failure.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+        }
+    }
+
+    #[test]
+    fn test_to_golden_json() {
+        let buffer = ParsedBuffer::parse("rust", 4, &["f(\"\\\\\")"]).unwrap();
+        assert_eq!(
+            to_golden_json(&buffer),
+            [
+                "[",
+                r#"  {"line": 0, "col": 1, "token": "delimiter", "kind": "opening", "text": "(", "stack_height": 0},"#,
+                r#"  {"line": 0, "col": 2, "token": "block_string", "kind": "opening", "text": "\"", "stack_height": 1},"#,
+                r#"  {"line": 0, "col": 5, "token": "block_string", "kind": "closing", "text": "\"", "stack_height": 1},"#,
+                r#"  {"line": 0, "col": 6, "token": "delimiter", "kind": "closing", "text": ")", "stack_height": 0}"#,
+                "]",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}